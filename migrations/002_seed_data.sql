@@ -4,7 +4,12 @@ INSERT INTO users (email, name, password_hash) VALUES
 ('admin@groups.dev', 'Admin User', '$argon2id$v=19$m=65536,t=3,p=4$example$hash')
 ON CONFLICT (email) DO NOTHING;
 
-INSERT INTO groups (name) VALUES 
+INSERT INTO groups (name)
+SELECT seed.name FROM (VALUES
 ('Rust Developers'),
 ('Web Developers'),
-('Database Enthusiasts');
\ No newline at end of file
+('Database Enthusiasts')
+) AS seed(name)
+WHERE NOT EXISTS (SELECT 1 FROM groups WHERE groups.name = seed.name);
+
+UPDATE users SET is_admin = TRUE WHERE email = 'admin@groups.dev' AND NOT is_admin;
@@ -0,0 +1,4 @@
+-- Track who created a group so they can manage it (restore from the trash)
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS owner_id INTEGER REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_groups_owner_id ON groups(owner_id);
@@ -0,0 +1,41 @@
+-- Invitations to join a group, either a shareable link or sent to one email
+CREATE TABLE IF NOT EXISTS group_invitations (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    code VARCHAR(64) NOT NULL UNIQUE,
+    -- Set for invitations sent to one address, NULL for shareable links
+    email VARCHAR(255),
+    invited_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    -- pending, accepted or revoked
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    -- NULL for unlimited
+    max_uses INTEGER,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    accepted_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_invitations_group ON group_invitations(group_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_group_invitations_email ON group_invitations(LOWER(email)) WHERE status = 'pending';
+
+-- Members of a group, the owner is a member with the owner role
+CREATE TABLE IF NOT EXISTS group_members (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- owner or member
+    role VARCHAR(16) NOT NULL DEFAULT 'member',
+    -- The invitation used to join, if any
+    invitation_id INTEGER REFERENCES group_invitations(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (group_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_members_user_id ON group_members(user_id);
+
+-- Owners of existing groups become their first members
+INSERT INTO group_members (group_id, user_id, role, created_at)
+SELECT id, owner_id, 'owner', created_at FROM groups WHERE owner_id IS NOT NULL
+ON CONFLICT (group_id, user_id) DO NOTHING;
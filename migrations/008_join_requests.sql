@@ -0,0 +1,33 @@
+-- How users become members: open (join directly), approval (request to join,
+-- vetted by the group's managers) or invite (invitations only)
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS join_policy VARCHAR(16) NOT NULL DEFAULT 'open';
+
+-- Questions asked to users requesting to join
+CREATE TABLE IF NOT EXISTS group_join_questions (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    question TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_join_questions_group ON group_join_questions(group_id, position);
+
+CREATE TABLE IF NOT EXISTS group_join_requests (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- pending, approved or rejected
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    -- JSON array of {"question", "answer"}, questions copied as asked
+    answers TEXT NOT NULL DEFAULT '[]',
+    decided_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    decided_at TIMESTAMP WITH TIME ZONE,
+    -- Optional message to the applicant
+    decision_note TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- One open request per user and group
+CREATE UNIQUE INDEX IF NOT EXISTS idx_group_join_requests_pending ON group_join_requests(group_id, user_id) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_group_join_requests_queue ON group_join_requests(group_id, status, created_at);
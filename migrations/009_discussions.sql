@@ -0,0 +1,34 @@
+-- Group discussions. A thread is its opening post, with a title. Replies
+-- belong to a thread through thread_id.
+CREATE TABLE IF NOT EXISTS posts (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    thread_id INTEGER REFERENCES posts(id) ON DELETE CASCADE,
+    author_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    title VARCHAR(200),
+    -- Markdown, rendered and sanitized when displayed
+    body TEXT NOT NULL,
+    -- Maintained on threads only: replies not deleted and the latest activity
+    reply_count INTEGER NOT NULL DEFAULT 0,
+    last_activity_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    edited_at TIMESTAMP WITH TIME ZONE,
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_posts_threads ON posts(group_id, last_activity_at DESC) WHERE thread_id IS NULL;
+CREATE INDEX IF NOT EXISTS idx_posts_replies ON posts(thread_id, id) WHERE thread_id IS NOT NULL;
+
+-- Earlier versions of edited posts, one row per edit
+CREATE TABLE IF NOT EXISTS post_revisions (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    title VARCHAR(200),
+    body TEXT NOT NULL,
+    -- When this version was written
+    written_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    edited_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_post_revisions_post ON post_revisions(post_id, id);
@@ -0,0 +1,35 @@
+-- Events organized by a group
+CREATE TABLE IF NOT EXISTS events (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    title VARCHAR(200) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    starts_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    ends_at TIMESTAMP WITH TIME ZONE,
+    -- Free-form address or directions
+    location VARCHAR(255),
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_group_starts_at ON events(group_id, starts_at);
+
+-- Comments and questions on an event. Answers point at the question they
+-- answer through reply_to.
+CREATE TABLE IF NOT EXISTS event_comments (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    author_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reply_to INTEGER REFERENCES event_comments(id) ON DELETE CASCADE,
+    -- Markdown, rendered and sanitized when displayed
+    body TEXT NOT NULL,
+    -- Pinned by an organizer, shown above the other comments
+    pinned_at TIMESTAMP WITH TIME ZONE,
+    -- Hidden by a moderator: only moderators still see it
+    hidden_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_event_comments_event ON event_comments(event_id, id);
@@ -0,0 +1,4 @@
+-- IANA time zone of each user, used to show times in reminders
+ALTER TABLE users ADD COLUMN IF NOT EXISTS time_zone VARCHAR(64) NOT NULL DEFAULT 'UTC';
+
+CREATE INDEX IF NOT EXISTS idx_events_starts_at ON events(starts_at) WHERE deleted_at IS NULL;
@@ -0,0 +1,12 @@
+-- Activity digest emails. Users without a row get the default frequency and
+-- a row, with its unsubscribe token, the first time a digest is sent.
+CREATE TABLE IF NOT EXISTS digest_settings (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    -- daily, weekly or never
+    frequency VARCHAR(16) NOT NULL,
+    -- Unguessable token of the unsubscribe link, which works without logging in
+    unsubscribe_token VARCHAR(64) NOT NULL UNIQUE,
+    last_sent_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_members_created_at ON group_members(group_id, created_at);
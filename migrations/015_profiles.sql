@@ -0,0 +1,8 @@
+-- Profile shown at /users/{id}, and who may see it
+ALTER TABLE users ADD COLUMN IF NOT EXISTS bio TEXT NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS location VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS interests TEXT[] NOT NULL DEFAULT '{}';
+-- everyone, members (of a group the user is in) or private
+ALTER TABLE users ADD COLUMN IF NOT EXISTS profile_visibility VARCHAR(16) NOT NULL DEFAULT 'members';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS show_location BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS show_groups BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+-- Uploaded images, as the base key of their variants in the blob store
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_key VARCHAR(200);
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS cover_key VARCHAR(200);
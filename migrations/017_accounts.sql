@@ -0,0 +1,12 @@
+-- Bumped to log out every session of the user, e.g. after a password change
+ALTER TABLE users ADD COLUMN IF NOT EXISTS session_version INTEGER NOT NULL DEFAULT 0;
+
+-- Email changes waiting for the new address to be confirmed, at most one per user
+CREATE TABLE IF NOT EXISTS email_changes (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    new_email VARCHAR(255) NOT NULL,
+    -- Unguessable token of the confirmation link sent to the new address
+    token VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,18 @@
+-- Archives of everything stored about a user, built by a job on request
+CREATE TABLE IF NOT EXISTS data_exports (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- pending, ready or failed
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    -- Unguessable token of the download link
+    token VARCHAR(64) NOT NULL UNIQUE,
+    -- Where the archive is stored, once it is ready
+    blob_key VARCHAR(200),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE,
+    -- The download link stops working after this and the archive is deleted
+    expires_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_data_exports_user ON data_exports(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_data_exports_expires_at ON data_exports(expires_at) WHERE expires_at IS NOT NULL;
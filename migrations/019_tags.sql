@@ -0,0 +1,30 @@
+-- Top-level categories tags are filed under, curated by site admins
+CREATE TABLE IF NOT EXISTS categories (
+    id SERIAL PRIMARY KEY,
+    slug VARCHAR(50) NOT NULL UNIQUE,
+    name VARCHAR(50) NOT NULL,
+    -- Order on the topics page, lowest first
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Topics groups are tagged with, created as group managers use them
+CREATE TABLE IF NOT EXISTS tags (
+    id SERIAL PRIMARY KEY,
+    -- Lowercase letters, digits and dashes, used in URLs
+    slug VARCHAR(50) NOT NULL UNIQUE,
+    -- As first typed
+    name VARCHAR(50) NOT NULL,
+    category_id INTEGER REFERENCES categories(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_tags_category ON tags(category_id);
+
+CREATE TABLE IF NOT EXISTS group_tags (
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_tags_tag ON group_tags(tag_id);
@@ -0,0 +1,118 @@
+-- Where groups meet and events take place, in WGS84 degrees
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS location VARCHAR(255);
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION;
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
+
+-- Distance queries first narrow down to a band of latitudes
+CREATE INDEX IF NOT EXISTS idx_groups_latitude ON groups(latitude)
+    WHERE latitude IS NOT NULL AND deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_events_latitude ON events(latitude)
+    WHERE latitude IS NOT NULL AND deleted_at IS NULL;
+
+-- Offline geocoding: typed city names to coordinates. Seeded with large
+-- cities, more can be loaded from a GeoNames dump with `groups import-cities`.
+CREATE TABLE IF NOT EXISTS cities (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(200) NOT NULL,
+    -- ISO 3166-1 alpha-2
+    country_code CHAR(2) NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    population BIGINT NOT NULL DEFAULT 0,
+    UNIQUE (name, country_code)
+);
+
+CREATE INDEX IF NOT EXISTS idx_cities_name ON cities(lower(name) text_pattern_ops);
+
+INSERT INTO cities (name, country_code, latitude, longitude, population) VALUES
+    ('Amsterdam', 'NL', 52.37403, 4.88969, 741636),
+    ('Athens', 'GR', 37.98376, 23.72784, 664046),
+    ('Atlanta', 'US', 33.749, -84.38798, 498715),
+    ('Auckland', 'NZ', -36.84853, 174.76349, 1450000),
+    ('Austin', 'US', 30.26715, -97.74306, 961855),
+    ('Bangalore', 'IN', 12.97194, 77.59369, 8443675),
+    ('Bangkok', 'TH', 13.75398, 100.50144, 5104476),
+    ('Barcelona', 'ES', 41.38879, 2.15899, 1620343),
+    ('Beijing', 'CN', 39.9075, 116.39723, 18960744),
+    ('Berlin', 'DE', 52.52437, 13.41053, 3426354),
+    ('Bogotá', 'CO', 4.60971, -74.08175, 7674366),
+    ('Boston', 'US', 42.35843, -71.05977, 675647),
+    ('Brisbane', 'AU', -27.46794, 153.02809, 2514184),
+    ('Brussels', 'BE', 50.85045, 4.34878, 1019022),
+    ('Bucharest', 'RO', 44.43225, 26.10626, 1877155),
+    ('Budapest', 'HU', 47.49835, 19.04045, 1741041),
+    ('Buenos Aires', 'AR', -34.61315, -58.37723, 2891082),
+    ('Cairo', 'EG', 30.06263, 31.24967, 9606916),
+    ('Cape Town', 'ZA', -33.92584, 18.42322, 3433441),
+    ('Chicago', 'US', 41.85003, -87.65005, 2746388),
+    ('Copenhagen', 'DK', 55.67594, 12.56553, 1153615),
+    ('Dallas', 'US', 32.78306, -96.80667, 1304379),
+    ('Delhi', 'IN', 28.65195, 77.23149, 10927986),
+    ('Denver', 'US', 39.73915, -104.9847, 715522),
+    ('Dubai', 'AE', 25.07725, 55.30927, 3478300),
+    ('Dublin', 'IE', 53.33306, -6.24889, 1024027),
+    ('Edinburgh', 'GB', 55.95206, -3.19648, 464990),
+    ('Frankfurt am Main', 'DE', 50.11552, 8.68417, 753056),
+    ('Geneva', 'CH', 46.20222, 6.14569, 203856),
+    ('Hamburg', 'DE', 53.55073, 9.99302, 1845229),
+    ('Helsinki', 'FI', 60.16952, 24.93545, 658864),
+    ('Ho Chi Minh City', 'VN', 10.82302, 106.62965, 8993082),
+    ('Hong Kong', 'HK', 22.27832, 114.17469, 7482500),
+    ('Houston', 'US', 29.76328, -95.36327, 2304580),
+    ('Istanbul', 'TR', 41.01384, 28.94966, 15701602),
+    ('Jakarta', 'ID', -6.21462, 106.84513, 10562088),
+    ('Johannesburg', 'ZA', -26.20227, 28.04363, 5635127),
+    ('Kraków', 'PL', 50.06143, 19.93658, 804237),
+    ('Kuala Lumpur', 'MY', 3.1412, 101.68653, 1982112),
+    ('Kyiv', 'UA', 50.45466, 30.5238, 2952301),
+    ('Lagos', 'NG', 6.45407, 3.39467, 15388000),
+    ('Lima', 'PE', -12.04318, -77.02824, 7737002),
+    ('Lisbon', 'PT', 38.71667, -9.13333, 545245),
+    ('London', 'GB', 51.50853, -0.12574, 8961989),
+    ('Los Angeles', 'US', 34.05223, -118.24368, 3898747),
+    ('Lyon', 'FR', 45.74846, 4.84671, 522969),
+    ('Madrid', 'ES', 40.4165, -3.70256, 3255944),
+    ('Manchester', 'GB', 53.48095, -2.23743, 552858),
+    ('Manila', 'PH', 14.6042, 120.9822, 1846513),
+    ('Melbourne', 'AU', -37.814, 144.96332, 4917750),
+    ('Mexico City', 'MX', 19.42847, -99.12766, 9209944),
+    ('Miami', 'US', 25.77427, -80.19366, 442241),
+    ('Milan', 'IT', 45.46427, 9.18951, 1371498),
+    ('Montréal', 'CA', 45.50884, -73.58781, 1762949),
+    ('Moscow', 'RU', 55.75222, 37.61556, 12506468),
+    ('Mumbai', 'IN', 19.07283, 72.88261, 12691836),
+    ('Munich', 'DE', 48.13743, 11.57549, 1488202),
+    ('Nairobi', 'KE', -1.28333, 36.81667, 4397073),
+    ('New York City', 'US', 40.71427, -74.00597, 8804190),
+    ('Osaka', 'JP', 34.69374, 135.50218, 2753862),
+    ('Oslo', 'NO', 59.91273, 10.74609, 709037),
+    ('Paris', 'FR', 48.85341, 2.3488, 2138551),
+    ('Philadelphia', 'US', 39.95233, -75.16379, 1603797),
+    ('Phoenix', 'US', 33.44838, -112.07404, 1608139),
+    ('Portland', 'US', 45.52345, -122.67621, 652503),
+    ('Prague', 'CZ', 50.08804, 14.42076, 1357326),
+    ('Rio de Janeiro', 'BR', -22.90642, -43.18223, 6747815),
+    ('Rome', 'IT', 41.89193, 12.51133, 2872800),
+    ('Rotterdam', 'NL', 51.9225, 4.47917, 651446),
+    ('San Diego', 'US', 32.71571, -117.16472, 1386932),
+    ('San Francisco', 'US', 37.77493, -122.41942, 873965),
+    ('Santiago', 'CL', -33.45694, -70.64827, 6310000),
+    ('São Paulo', 'BR', -23.5475, -46.63611, 12396372),
+    ('Seattle', 'US', 47.60621, -122.33207, 737015),
+    ('Seoul', 'KR', 37.566, 126.9784, 9776000),
+    ('Shanghai', 'CN', 31.22222, 121.45806, 24874500),
+    ('Singapore', 'SG', 1.28967, 103.85007, 5638700),
+    ('Stockholm', 'SE', 59.32938, 18.06871, 975904),
+    ('Sydney', 'AU', -33.86785, 151.20732, 5312163),
+    ('Taipei', 'TW', 25.04776, 121.53185, 2602418),
+    ('Tel Aviv', 'IL', 32.08088, 34.78057, 460613),
+    ('Tokyo', 'JP', 35.6895, 139.69171, 13960000),
+    ('Toronto', 'CA', 43.70011, -79.4163, 2794356),
+    ('Vancouver', 'CA', 49.24966, -123.11934, 662248),
+    ('Vienna', 'AT', 48.20849, 16.37208, 1897491),
+    ('Warsaw', 'PL', 52.22977, 21.01178, 1860281),
+    ('Washington', 'US', 38.89511, -77.03637, 689545),
+    ('Zürich', 'CH', 47.36667, 8.55, 421878)
+ON CONFLICT (name, country_code) DO NOTHING;
@@ -0,0 +1,26 @@
+-- Places a group meets at, reused across its events
+CREATE TABLE IF NOT EXISTS venues (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    name VARCHAR(200) NOT NULL,
+    address VARCHAR(500) NOT NULL DEFAULT '',
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    accessibility_notes TEXT NOT NULL DEFAULT '',
+    -- People the venue holds, when known
+    capacity INTEGER,
+    directions TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_venues_group ON venues(group_id);
+
+-- Events keep a copy of the venue's name, address and coordinates in
+-- location, latitude and longitude, so they still say where they took place
+-- once the venue is removed
+ALTER TABLE events ADD COLUMN IF NOT EXISTS venue_id INTEGER REFERENCES venues(id) ON DELETE SET NULL;
+-- Set for online events, shown to members going only
+ALTER TABLE events ADD COLUMN IF NOT EXISTS meeting_url VARCHAR(2000);
+
+CREATE INDEX IF NOT EXISTS idx_events_venue ON events(venue_id);
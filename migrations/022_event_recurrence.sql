@@ -0,0 +1,10 @@
+-- How an event repeats: weekly, biweekly or monthly, from its first start.
+-- NULL for events held once.
+ALTER TABLE events ADD COLUMN IF NOT EXISTS recurrence VARCHAR(16);
+-- The last time an occurrence may start, NULL to repeat without end
+ALTER TABLE events ADD COLUMN IF NOT EXISTS recurs_until TIMESTAMP WITH TIME ZONE;
+
+-- Calendars look up the events of a date range by start, and the series
+-- still running apart
+CREATE INDEX IF NOT EXISTS idx_events_recurring ON events(group_id, recurs_until)
+    WHERE recurrence IS NOT NULL AND deleted_at IS NULL;
@@ -0,0 +1,22 @@
+-- Secret of each RSVP, shown to the member as a QR code that hosts scan to
+-- check them in
+ALTER TABLE event_rsvps ADD COLUMN IF NOT EXISTS check_in_token VARCHAR(64)
+    NOT NULL DEFAULT replace(gen_random_uuid()::text, '-', '');
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_event_rsvps_check_in_token ON event_rsvps(check_in_token);
+
+-- Who showed up at an event, kept apart from what they answered: members can
+-- be checked in without having said they were going
+CREATE TABLE IF NOT EXISTS event_attendance (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- The host who checked them in
+    checked_in_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    -- list or qr_code
+    method VARCHAR(16) NOT NULL,
+    checked_in_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (event_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_event_attendance_user ON event_attendance(user_id);
@@ -0,0 +1,3 @@
+-- Workers touch heartbeat_at while a job runs, so a long job is not mistaken
+-- for one whose worker died and run a second time
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS heartbeat_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,3 @@
+-- IANA time zone an event was created in. Recurring events keep their local
+-- time of day in it across daylight saving changes.
+ALTER TABLE events ADD COLUMN IF NOT EXISTS time_zone VARCHAR(64) NOT NULL DEFAULT 'UTC';
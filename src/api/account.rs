@@ -0,0 +1,671 @@
+use super::{hash_password, verify_password};
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::uploads::release_avatar;
+use crate::config::ServerConfig;
+use crate::db::{
+    audit::AuditContext,
+    export::{DataExportError, DataExportService},
+    job::{JobService, NewJob},
+    models::{DataExportStatus, User},
+    user::{UserError, UserService},
+};
+use crate::exports::{EXPORT_USER_DATA_JOB, ExportPayload, delete_user_exports};
+use crate::mail::Email;
+use crate::middleware::auth::current_user_id;
+use crate::middleware::security_headers::CspNonce;
+use crate::middleware::session_validation::SESSION_VERSION_KEY;
+use crate::reminders::local_time;
+use crate::storage::BlobStore;
+use actix_session::Session;
+use actix_web::{HttpResponse, http::header, web};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use tera::Tera;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+const MAX_EMAIL_LENGTH: usize = 255;
+// How long the link confirming a new email address works
+const EMAIL_CHANGE_HOURS: i64 = 24;
+
+#[derive(Deserialize)]
+pub struct AccountQuery {
+    pub notice: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EmailForm {
+    pub current_password: String,
+    pub new_email: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordForm {
+    pub current_password: String,
+    pub new_password: String,
+    pub confirm_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteForm {
+    pub current_password: String,
+    // The "I understand" checkbox
+    pub confirm: Option<String>,
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+fn login_redirect() -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("Location", "/login"))
+        .finish()
+}
+
+// Why a form was not applied: a message shown on the account page, or a
+// response for failures the user cannot fix
+enum Refusal {
+    Message(String),
+    Response(HttpResponse),
+}
+
+impl From<&str> for Refusal {
+    fn from(message: &str) -> Self {
+        Refusal::Message(message.to_string())
+    }
+}
+
+// A plausible address: one @ with something on both sides, a dot in the
+// domain and no spaces. Whether it works is what the confirmation link checks.
+fn valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+    email.len() <= MAX_EMAIL_LENGTH
+        && !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email.chars().any(char::is_whitespace)
+}
+
+fn check_new_password(new_password: &str, confirm_password: &str) -> Result<(), String> {
+    let length = new_password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Passwords must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err(format!(
+            "Passwords are limited to {} characters",
+            MAX_PASSWORD_LENGTH
+        ));
+    }
+    if new_password != confirm_password {
+        return Err("The new passwords do not match".to_string());
+    }
+    Ok(())
+}
+
+fn password_matches(user: &User, password: &str) -> bool {
+    user.password_hash
+        .as_deref()
+        .is_some_and(|hash| verify_password(password.as_bytes(), hash).unwrap_or(false))
+}
+
+async fn logged_in_user(session: &Session, users: &UserService) -> Result<User, HttpResponse> {
+    let Some(user_id) = current_user_id(session) else {
+        return Err(login_redirect());
+    };
+    match users.get_by_id(user_id).await {
+        Ok(Some(user)) if user.deleted_at.is_none() => Ok(user),
+        Ok(_) => Err(login_redirect()),
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn account_response(
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    users: &UserService,
+    exports: &DataExportService,
+    user: &User,
+    notice: Option<&str>,
+    error: Option<&str>,
+) -> HttpResponse {
+    let pending = match users.pending_email_change(user.id).await {
+        Ok(pending) => pending,
+        Err(e) => return database_error(e),
+    };
+    // Expired exports are as good as gone, even before they are purged
+    let now = Utc::now();
+    let export = match exports.latest(user.id).await {
+        Ok(export) => export.filter(|e| e.expires_at.is_none_or(|expires_at| expires_at > now)),
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = create_template_context(session, nonce);
+    context.insert("email", &user.email);
+    context.insert("pending_email", &pending.map(|change| change.new_email));
+    context.insert("export_status", &export.as_ref().map(|e| e.status.as_str()));
+    context.insert(
+        "export_url",
+        &export
+            .as_ref()
+            .filter(|e| e.is_downloadable(now))
+            .map(|e| format!("/account/export/{}", e.token)),
+    );
+    context.insert(
+        "export_expires_at",
+        &export
+            .as_ref()
+            .and_then(|e| e.expires_at)
+            .map(|at| local_time(at, user)),
+    );
+    context.insert("min_password_length", &MIN_PASSWORD_LENGTH);
+    context.insert("notice", &notice);
+    context.insert("error", &error);
+    render(tmpl, "account.html", &context)
+}
+
+async fn refusal_response(
+    refusal: Refusal,
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    users: &UserService,
+    exports: &DataExportService,
+    user: &User,
+) -> HttpResponse {
+    match refusal {
+        Refusal::Message(message) => {
+            account_response(
+                tmpl,
+                session,
+                nonce,
+                users,
+                exports,
+                user,
+                None,
+                Some(&message),
+            )
+            .await
+        }
+        Refusal::Response(response) => response,
+    }
+}
+
+// Email address, password and account deletion
+pub async fn account_page(
+    query: web::Query<AccountQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    users: web::Data<UserService>,
+    exports: web::Data<DataExportService>,
+) -> HttpResponse {
+    let user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    account_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &users,
+        &exports,
+        &user,
+        query.notice.as_deref(),
+        None,
+    )
+    .await
+}
+
+async fn queue_email(jobs: &JobService, email: Result<Email, tera::Error>) -> Result<(), String> {
+    let job = email
+        .map_err(|e| e.to_string())?
+        .job()
+        .map_err(|e| e.to_string())?;
+    jobs.enqueue(job).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn request_email_change(
+    user: &User,
+    form: &EmailForm,
+    tmpl: &Tera,
+    users: &UserService,
+    jobs: &JobService,
+    server: &ServerConfig,
+) -> Result<String, Refusal> {
+    if !password_matches(user, &form.current_password) {
+        return Err("Your current password is not correct".into());
+    }
+    let new_email = form.new_email.trim();
+    if !valid_email(new_email) {
+        return Err("Enter a valid email address".into());
+    }
+    if new_email.eq_ignore_ascii_case(&user.email) {
+        return Err("That is already your email address".into());
+    }
+
+    let change = match users
+        .request_email_change(user.id, new_email, Duration::hours(EMAIL_CHANGE_HOURS))
+        .await
+    {
+        Ok(change) => change,
+        Err(UserError::EmailTaken) => {
+            return Err("That email address is already registered".into());
+        }
+        Err(e) => return Err(Refusal::Response(database_error(e))),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("name", &user.name);
+    ctx.insert("new_email", &change.new_email);
+    ctx.insert(
+        "url",
+        &server.url(&format!("/account/email/{}", change.token)),
+    );
+    ctx.insert("hours", &EMAIL_CHANGE_HOURS);
+    let confirmation = tmpl
+        .render("emails/email_change.txt", &ctx)
+        .map(|text| Email {
+            to: change.new_email.clone(),
+            subject: "Confirm your new email address".to_string(),
+            text,
+            html: None,
+            unsubscribe_url: None,
+        });
+    let warning = tmpl
+        .render("emails/email_change_notice.txt", &ctx)
+        .map(|text| Email {
+            to: user.email.clone(),
+            subject: "Your email address is being changed".to_string(),
+            text,
+            html: None,
+            unsubscribe_url: None,
+        });
+    for email in [confirmation, warning] {
+        if let Err(e) = queue_email(jobs, email).await {
+            return Err(Refusal::Response(
+                HttpResponse::InternalServerError()
+                    .body(format!("The email could not be queued: {}", e)),
+            ));
+        }
+    }
+
+    Ok(change.new_email)
+}
+
+// Send a confirmation link to the new address, and a warning to the current
+// one. The address only changes once the link is followed.
+#[allow(clippy::too_many_arguments)]
+pub async fn change_email(
+    form: web::Form<EmailForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    users: web::Data<UserService>,
+    exports: web::Data<DataExportService>,
+    jobs: web::Data<JobService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match request_email_change(&user, &form, &tmpl, &users, &jobs, &server).await {
+        Ok(new_email) => redirect_with_notice(
+            "/account",
+            &format!(
+                "We sent a confirmation link to {}. Your email address changes once you follow it.",
+                new_email
+            ),
+        ),
+        Err(refusal) => {
+            refusal_response(refusal, &tmpl, &session, &nonce, &users, &exports, &user).await
+        }
+    }
+}
+
+fn confirm_response(tmpl: &Tera, mut context: tera::Context, error: &str) -> HttpResponse {
+    context.insert("error", error);
+    let rendered = tmpl
+        .render("account_email_confirm.html", &context)
+        .unwrap_or_else(|_| error.to_string());
+    HttpResponse::NotFound()
+        .content_type("text/html")
+        .body(rendered)
+}
+
+// The link in the confirmation email. It works without logging in, but only
+// asks for confirmation so link checkers opening it change nothing.
+pub async fn confirm_email_page(
+    path: web::Path<String>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    let token = path.into_inner();
+    let mut context = create_template_context(&session, &nonce);
+    let change = match users.get_email_change(&token).await {
+        Ok(Some(change)) => change,
+        Ok(None) => {
+            return confirm_response(
+                &tmpl,
+                context,
+                "This confirmation link is invalid or has expired.",
+            );
+        }
+        Err(e) => return database_error(e),
+    };
+
+    context.insert("token", &token);
+    context.insert("new_email", &change.new_email);
+    render(&tmpl, "account_email_confirm.html", &context)
+}
+
+pub async fn confirm_email(
+    path: web::Path<String>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    let context = create_template_context(&session, &nonce);
+    let user = match users.confirm_email_change(&path.into_inner(), &audit).await {
+        Ok(user) => user,
+        Err(UserError::EmailChangeNotFound) | Err(UserError::UserNotFound) => {
+            return confirm_response(
+                &tmpl,
+                context,
+                "This confirmation link is invalid or has expired.",
+            );
+        }
+        Err(UserError::EmailTaken) => {
+            return confirm_response(
+                &tmpl,
+                context,
+                "That email address has been registered by someone else in the meantime.",
+            );
+        }
+        Err(e) => return database_error(e),
+    };
+
+    // Shown in the navigation bar when there is no name
+    if current_user_id(&session) == Some(user.id) {
+        let _ = session.insert("user_email", &user.email);
+    }
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("confirmed", &user.email);
+    render(&tmpl, "account_email_confirm.html", &context)
+}
+
+// Returns the new session version
+async fn apply_password_change(
+    user: &User,
+    form: &PasswordForm,
+    audit: &AuditContext,
+    users: &UserService,
+) -> Result<i32, Refusal> {
+    if !password_matches(user, &form.current_password) {
+        return Err("Your current password is not correct".into());
+    }
+    check_new_password(&form.new_password, &form.confirm_password).map_err(Refusal::Message)?;
+    let password_hash = hash_password(form.new_password.as_bytes()).map_err(|_| {
+        Refusal::Response(HttpResponse::InternalServerError().body("Failed to process password"))
+    })?;
+
+    users
+        .change_password(user.id, &password_hash, audit)
+        .await
+        .map_err(|e| Refusal::Response(database_error(e)))
+}
+
+// Change the password and log out every other session
+#[allow(clippy::too_many_arguments)]
+pub async fn change_password(
+    form: web::Form<PasswordForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    users: web::Data<UserService>,
+    exports: web::Data<DataExportService>,
+    jobs: web::Data<JobService>,
+) -> HttpResponse {
+    let user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let version = match apply_password_change(&user, &form, &audit, &users).await {
+        Ok(version) => version,
+        Err(refusal) => {
+            return refusal_response(refusal, &tmpl, &session, &nonce, &users, &exports, &user)
+                .await;
+        }
+    };
+    // This session carries on, with a fresh id and the new version
+    session.renew();
+    let _ = session.insert(SESSION_VERSION_KEY, version);
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("name", &user.name);
+    let email = tmpl
+        .render("emails/password_changed.txt", &ctx)
+        .map(|text| Email {
+            to: user.email.clone(),
+            subject: "Your password was changed".to_string(),
+            text,
+            html: None,
+            unsubscribe_url: None,
+        });
+    // The change is done, a missing warning email does not undo it
+    if let Err(e) = queue_email(&jobs, email).await {
+        eprintln!(
+            "Failed to queue password change email for {}: {}",
+            user.id, e
+        );
+    }
+
+    redirect_with_notice(
+        "/account",
+        "Password changed. Your other sessions have been logged out.",
+    )
+}
+
+// Returns the user as they were before deletion
+async fn apply_deletion(
+    user: &User,
+    form: &DeleteForm,
+    audit: &AuditContext,
+    users: &UserService,
+) -> Result<User, Refusal> {
+    if !password_matches(user, &form.current_password) {
+        return Err("Your current password is not correct".into());
+    }
+    if form.confirm.is_none() {
+        return Err("Tick the box to confirm you want to delete your account".into());
+    }
+
+    users
+        .delete_account(user.id, audit)
+        .await
+        .map_err(|e| Refusal::Response(database_error(e)))
+}
+
+// Delete the account for good: ownership of groups moves to other members
+// and personal data is erased, see UserService::delete_account
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_account(
+    form: web::Form<DeleteForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    users: web::Data<UserService>,
+    exports: web::Data<DataExportService>,
+    store: web::Data<dyn BlobStore>,
+) -> HttpResponse {
+    let user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let deleted = match apply_deletion(&user, &form, &audit, &users).await {
+        Ok(deleted) => deleted,
+        Err(refusal) => {
+            return refusal_response(refusal, &tmpl, &session, &nonce, &users, &exports, &user)
+                .await;
+        }
+    };
+    if let Some(avatar_key) = &deleted.avatar_key {
+        release_avatar(&**store, &users, avatar_key).await;
+    }
+    delete_user_exports(&exports, &**store, user.id).await;
+
+    session.purge();
+    HttpResponse::SeeOther()
+        .append_header(("Location", "/"))
+        .finish()
+}
+
+// Queue an export of everything stored about the user. The job emails a
+// download link once the archive is ready.
+pub async fn request_export(
+    session: Session,
+    audit: AuditContext,
+    users: web::Data<UserService>,
+    exports: web::Data<DataExportService>,
+    jobs: web::Data<JobService>,
+) -> HttpResponse {
+    let user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match exports.latest(user.id).await {
+        Ok(Some(export)) if export.status == DataExportStatus::Pending => {
+            return redirect_with_notice(
+                "/account",
+                "Your export is already being prepared. We will email you when it is ready.",
+            );
+        }
+        Ok(_) => {}
+        Err(e) => return database_error(e),
+    }
+
+    let export = match exports.create(user.id, &audit).await {
+        Ok(export) => export,
+        Err(e) => return database_error(e),
+    };
+    let payload = ExportPayload {
+        export_id: export.id,
+    };
+    let job = match NewJob::new(EXPORT_USER_DATA_JOB, &payload) {
+        Ok(job) => job.unique_key(payload.unique_key()),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    if let Err(e) = jobs.enqueue(job).await {
+        return database_error(e);
+    }
+
+    redirect_with_notice(
+        "/account",
+        "Your export is being prepared. We will email you a download link when it is ready.",
+    )
+}
+
+// The archive of an export, for the user it belongs to while the link works
+pub async fn download_export(
+    path: web::Path<String>,
+    session: Session,
+    users: web::Data<UserService>,
+    exports: web::Data<DataExportService>,
+    store: web::Data<dyn BlobStore>,
+) -> HttpResponse {
+    let user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let export = match exports.get_by_token(&path.into_inner()).await {
+        Ok(export) if export.user_id == user.id => export,
+        Ok(_) | Err(DataExportError::NotFound) => {
+            return HttpResponse::NotFound().body("Export not found");
+        }
+        Err(e) => return database_error(e),
+    };
+    let Some(key) = export
+        .blob_key
+        .as_deref()
+        .filter(|_| export.is_downloadable(Utc::now()))
+    else {
+        return redirect_with_notice(
+            "/account",
+            "That download link has expired. Request a new export below.",
+        );
+    };
+
+    match store.get(key).await {
+        Ok(Some(blob)) => HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"groups-data-{}.zip\"",
+                    export.created_at.format("%Y-%m-%d")
+                ),
+            ))
+            .insert_header((header::CACHE_CONTROL, "private, no-store"))
+            .body(blob.bytes),
+        Ok(None) => HttpResponse::NotFound().body("Export not found"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Storage error: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_email() {
+        assert!(valid_email("ada@example.com"));
+        assert!(valid_email("ada+groups@mail.example.org"));
+        assert!(!valid_email("ada"));
+        assert!(!valid_email("@example.com"));
+        assert!(!valid_email("ada@localhost"));
+        assert!(!valid_email("ada@example.com."));
+        assert!(!valid_email("ada@@example.com"));
+        assert!(!valid_email("ada lovelace@example.com"));
+        assert!(!valid_email(&format!("{}@example.com", "a".repeat(250))));
+    }
+
+    #[test]
+    fn test_check_new_password() {
+        assert!(check_new_password("correct horse", "correct horse").is_ok());
+        assert!(check_new_password("short", "short").is_err());
+        assert!(check_new_password("correct horse", "correct horses").is_err());
+        assert!(check_new_password(&"x".repeat(129), &"x".repeat(129)).is_err());
+    }
+}
@@ -0,0 +1,643 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::db::admin_action::AdminActionService;
+use crate::db::audit::{AuditContext, AuditFilter, AuditService};
+use crate::db::group::{GroupError, GroupFilter, GroupService};
+use crate::db::job::{JobError, JobService};
+use crate::db::models::{AuditEvent, JobStatus};
+use crate::db::tag::{MAX_CATEGORY_LENGTH, TagError, TagService, slugify};
+use crate::db::user::{DeletedFilter, UserError, UserFilter, UserService};
+use crate::middleware::admin::RequireAdmin;
+use crate::middleware::auth::current_user_id;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, Result, error::ErrorInternalServerError, get, post, web};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tera::Tera;
+
+// Number of audit entries shown on the dashboard and audit pages
+const DASHBOARD_AUDIT_ENTRIES: i64 = 10;
+const AUDIT_PAGE_ENTRIES: i64 = 200;
+const JOB_PAGE_ENTRIES: i64 = 100;
+const EVENT_PAGE_ENTRIES: i64 = 200;
+const EVENT_EXPORT_ENTRIES: i64 = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct AdminListQuery {
+    pub q: Option<String>,
+    // active (default), deleted or all
+    pub status: Option<String>,
+    // "on" when the admins-only checkbox is ticked
+    pub admins: Option<String>,
+    // Outcome of the previous action, shown above the list
+    pub notice: Option<String>,
+}
+
+impl AdminListQuery {
+    fn deleted_filter(&self) -> DeletedFilter {
+        match self.status.as_deref() {
+            Some("deleted") => DeletedFilter::Deleted,
+            Some("all") => DeletedFilter::All,
+            _ => DeletedFilter::Active,
+        }
+    }
+}
+
+fn render(tmpl: &Tera, name: &str, ctx: &tera::Context) -> Result<HttpResponse> {
+    let rendered = tmpl
+        .render(name, ctx)
+        .map_err(|e| ErrorInternalServerError(format!("Template error: {}", e)))?;
+
+    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+}
+
+#[get("")]
+pub async fn dashboard(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    users: web::Data<UserService>,
+    groups: web::Data<GroupService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let mut ctx = create_template_context(&session, &nonce);
+
+    let user_count = users.count().await.map_err(ErrorInternalServerError)?;
+    let deleted_users = users
+        .count_deleted()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let group_count = groups.count().await.map_err(ErrorInternalServerError)?;
+    let deleted_groups = groups
+        .count_deleted()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let recent = actions
+        .list_recent(DASHBOARD_AUDIT_ENTRIES)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    ctx.insert("active_users", &(user_count - deleted_users));
+    ctx.insert("deleted_users", &deleted_users);
+    ctx.insert("active_groups", &(group_count - deleted_groups));
+    ctx.insert("deleted_groups", &deleted_groups);
+    ctx.insert("actions", &recent);
+
+    render(&tmpl, "admin.html", &ctx)
+}
+
+#[get("/users")]
+pub async fn list_users(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    query: web::Query<AdminListQuery>,
+    users: web::Data<UserService>,
+) -> Result<HttpResponse> {
+    let mut ctx = create_template_context(&session, &nonce);
+
+    let filter = UserFilter {
+        query: query.q.clone(),
+        deleted: query.deleted_filter(),
+        admins_only: query.admins.is_some(),
+    };
+    let found = users
+        .search(&filter)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    ctx.insert("users", &found);
+    ctx.insert("q", &query.q.clone().unwrap_or_default());
+    ctx.insert("status", query.status.as_deref().unwrap_or("active"));
+    ctx.insert("admins_only", &filter.admins_only);
+    ctx.insert("notice", &query.notice);
+    ctx.insert("current_user_id", &current_user_id(&session));
+
+    render(&tmpl, "admin_users.html", &ctx)
+}
+
+#[post("/users/{id}/{action}")]
+pub async fn user_action(
+    path: web::Path<(i32, String)>,
+    session: Session,
+    audit: AuditContext,
+    users: web::Data<UserService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let (user_id, action) = path.into_inner();
+    let admin_id = current_user_id(&session).unwrap_or_default();
+
+    if user_id == admin_id && action != "restore" {
+        return Ok(redirect_with_notice(
+            "/admin/users",
+            "You cannot delete your own account from the admin area",
+        ));
+    }
+
+    // Capture the email before a hard delete removes the row
+    let email = match users.get_by_id(user_id).await {
+        Ok(Some(user)) => user.email,
+        Ok(None) => return Ok(HttpResponse::NotFound().body("User not found")),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let result = match action.as_str() {
+        "soft-delete" => users.delete(user_id, &audit).await,
+        "restore" => users.restore(user_id, &audit).await,
+        "hard-delete" => users.hard_delete(user_id, &audit).await,
+        _ => return Ok(HttpResponse::NotFound().body("Unknown action")),
+    };
+
+    let notice = match result {
+        Ok(()) => {
+            actions
+                .record(admin_id, &action, "user", user_id, &email)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            format!("User {}: {} done", user_id, action)
+        }
+        Err(UserError::NotSoftDeleted) => {
+            format!("User {} must be soft-deleted before hard deletion", user_id)
+        }
+        // Nothing changed: already deleted, or not deleted when restoring
+        Err(UserError::UserNotFound) => {
+            return Ok(HttpResponse::NotFound().body(format!("No user {} to {}", user_id, action)));
+        }
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(redirect_with_notice("/admin/users", &notice))
+}
+
+#[get("/groups")]
+pub async fn list_groups(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    query: web::Query<AdminListQuery>,
+    groups: web::Data<GroupService>,
+) -> Result<HttpResponse> {
+    let mut ctx = create_template_context(&session, &nonce);
+
+    let filter = GroupFilter {
+        query: query.q.clone(),
+        deleted: query.deleted_filter(),
+    };
+    let found = groups
+        .search(&filter)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    ctx.insert("groups", &found);
+    ctx.insert("q", &query.q.clone().unwrap_or_default());
+    ctx.insert("status", query.status.as_deref().unwrap_or("active"));
+    ctx.insert("notice", &query.notice);
+
+    render(&tmpl, "admin_groups.html", &ctx)
+}
+
+#[post("/groups/{id}/{action}")]
+pub async fn group_action(
+    path: web::Path<(i32, String)>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let (group_id, action) = path.into_inner();
+    let admin_id = current_user_id(&session).unwrap_or_default();
+
+    // Capture the name before a hard delete removes the row
+    let name = match groups.get_by_id(group_id).await {
+        Ok(Some(group)) => group.name,
+        Ok(None) => return Ok(HttpResponse::NotFound().body("Group not found")),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let result = match action.as_str() {
+        "soft-delete" => groups.delete(group_id, &audit).await,
+        "restore" => groups.restore(group_id, &audit).await,
+        "hard-delete" => groups.hard_delete(group_id, &audit).await,
+        _ => return Ok(HttpResponse::NotFound().body("Unknown action")),
+    };
+
+    let notice = match result {
+        Ok(()) => {
+            actions
+                .record(admin_id, &action, "group", group_id, &name)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            format!("Group {}: {} done", group_id, action)
+        }
+        Err(GroupError::NotSoftDeleted) => {
+            format!(
+                "Group {} must be soft-deleted before hard deletion",
+                group_id
+            )
+        }
+        // Nothing changed: already deleted, or not deleted when restoring
+        Err(GroupError::GroupNotFound) => {
+            return Ok(
+                HttpResponse::NotFound().body(format!("No group {} to {}", group_id, action))
+            );
+        }
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(redirect_with_notice("/admin/groups", &notice))
+}
+
+#[get("/audit")]
+pub async fn audit_log(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let mut ctx = create_template_context(&session, &nonce);
+
+    let entries = actions
+        .list_recent(AUDIT_PAGE_ENTRIES)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    ctx.insert("actions", &entries);
+
+    render(&tmpl, "admin_audit.html", &ctx)
+}
+
+// Filters of the audit log viewer and export. Everything arrives as text so
+// the empty fields of a submitted filter form are simply ignored.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct AuditEventQuery {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    // Inclusive dates, YYYY-MM-DD
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+impl AuditEventQuery {
+    fn to_filter(&self, limit: i64) -> AuditFilter {
+        let id = |value: &Option<String>| value.as_deref().and_then(|v| v.trim().parse().ok());
+        let date = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|v| NaiveDate::parse_from_str(v.trim(), "%Y-%m-%d").ok())
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc())
+        };
+
+        AuditFilter {
+            entity_type: self.entity_type.clone(),
+            entity_id: id(&self.entity_id),
+            actor_id: id(&self.actor_id),
+            action: self.action.clone(),
+            since: date(&self.since),
+            // Include the whole `until` day
+            until: date(&self.until).map(|d| d + Duration::days(1)),
+            limit,
+        }
+    }
+}
+
+// An audit event with its changes parsed, for display and export
+#[derive(Debug, Serialize)]
+pub struct AuditEventView {
+    pub id: i32,
+    pub actor_id: Option<i32>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: i32,
+    pub changes: Value,
+    pub ip_address: Option<String>,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<AuditEvent> for AuditEventView {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            changes: serde_json::from_str(&event.changes).unwrap_or(Value::Null),
+            id: event.id,
+            actor_id: event.actor_id,
+            action: event.action,
+            entity_type: event.entity_type,
+            entity_id: event.entity_id,
+            ip_address: event.ip_address,
+            request_id: event.request_id,
+            created_at: event.created_at,
+        }
+    }
+}
+
+async fn find_events(
+    audit: &AuditService,
+    query: &AuditEventQuery,
+    limit: i64,
+) -> Result<Vec<AuditEventView>> {
+    let events = audit
+        .search(&query.to_filter(limit))
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(events.into_iter().map(AuditEventView::from).collect())
+}
+
+// Audit log of every change to users and groups, newest first
+#[get("/events")]
+pub async fn audit_events(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    query: web::Query<AuditEventQuery>,
+    audit: web::Data<AuditService>,
+) -> Result<HttpResponse> {
+    let mut ctx = create_template_context(&session, &nonce);
+
+    let events = find_events(&audit, &query, EVENT_PAGE_ENTRIES).await?;
+
+    ctx.insert("events", &events);
+    ctx.insert("filter", &*query);
+    ctx.insert("limit", &EVENT_PAGE_ENTRIES);
+
+    render(&tmpl, "admin_events.html", &ctx)
+}
+
+// Download the audit events matching the filters as a JSON array
+#[get("/events/export")]
+pub async fn export_audit_events(
+    query: web::Query<AuditEventQuery>,
+    audit: web::Data<AuditService>,
+) -> Result<HttpResponse> {
+    let events = find_events(&audit, &query, EVENT_EXPORT_ENTRIES).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header((
+            "Content-Disposition",
+            "attachment; filename=\"audit-events.json\"",
+        ))
+        .json(events))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobListQuery {
+    pub status: Option<String>,
+    pub notice: Option<String>,
+}
+
+// Job queue status: counts per status, schedules and the most recent jobs
+#[get("/jobs")]
+pub async fn job_status(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    query: web::Query<JobListQuery>,
+    jobs: web::Data<JobService>,
+) -> Result<HttpResponse> {
+    let mut ctx = create_template_context(&session, &nonce);
+
+    let status = JobStatus::ALL
+        .into_iter()
+        .find(|s| Some(s.as_str()) == query.status.as_deref());
+    let stats = jobs.stats().await.map_err(ErrorInternalServerError)?;
+    let schedules = jobs
+        .list_schedules()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let recent = jobs
+        .list_recent(status, JOB_PAGE_ENTRIES)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    ctx.insert("stats", &stats);
+    ctx.insert("schedules", &schedules);
+    ctx.insert("jobs", &recent);
+    ctx.insert("status", &status.map(|s| s.as_str()).unwrap_or("all"));
+    ctx.insert("notice", &query.notice);
+
+    render(&tmpl, "admin_jobs.html", &ctx)
+}
+
+#[post("/jobs/{id}/retry")]
+pub async fn retry_job(
+    path: web::Path<i32>,
+    session: Session,
+    jobs: web::Data<JobService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let job_id = path.into_inner();
+    let admin_id = current_user_id(&session).unwrap_or_default();
+
+    let notice = match jobs.retry(job_id).await {
+        Ok(()) => {
+            let kind = match jobs.get_by_id(job_id).await {
+                Ok(Some(job)) => job.kind,
+                _ => String::new(),
+            };
+            actions
+                .record(admin_id, "retry", "job", job_id, &kind)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            format!("Job {} queued again", job_id)
+        }
+        Err(JobError::JobNotFound) => format!("Job {} is not a failed job", job_id),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(redirect_with_notice("/admin/jobs", &notice))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoticeQuery {
+    pub notice: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryForm {
+    pub name: String,
+    pub position: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagCategoryForm {
+    // Empty for no category
+    pub category_id: Option<String>,
+}
+
+// The top-level categories and every tag with the groups using it
+#[get("/tags")]
+pub async fn list_tags(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    query: web::Query<NoticeQuery>,
+    tags: web::Data<TagService>,
+) -> Result<HttpResponse> {
+    let mut ctx = create_template_context(&session, &nonce);
+
+    let categories = tags
+        .list_categories()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let summaries = tags
+        .list_with_counts()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    ctx.insert("categories", &categories);
+    ctx.insert("tags", &summaries);
+    ctx.insert("max_category_length", &MAX_CATEGORY_LENGTH);
+    ctx.insert("notice", &query.notice);
+
+    render(&tmpl, "admin_tags.html", &ctx)
+}
+
+#[post("/tags/categories")]
+pub async fn create_category(
+    form: web::Form<CategoryForm>,
+    session: Session,
+    tags: web::Data<TagService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let admin_id = current_user_id(&session).unwrap_or_default();
+    let name = form.name.trim();
+    if slugify(name).is_empty() || name.chars().count() > MAX_CATEGORY_LENGTH {
+        return Ok(redirect_with_notice(
+            "/admin/tags",
+            &format!(
+                "Category names are 1 to {} characters, with at least one letter or digit",
+                MAX_CATEGORY_LENGTH
+            ),
+        ));
+    }
+
+    let notice = match tags
+        .create_category(name, form.position.unwrap_or_default())
+        .await
+    {
+        Ok(category) => {
+            actions
+                .record(admin_id, "create", "category", category.id, &category.name)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            format!("Category {} created", category.name)
+        }
+        Err(TagError::CategoryExists) => format!("Category {} already exists", name),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(redirect_with_notice("/admin/tags", &notice))
+}
+
+// Its tags are kept, without a category
+#[post("/tags/categories/{id}/delete")]
+pub async fn delete_category(
+    path: web::Path<i32>,
+    session: Session,
+    tags: web::Data<TagService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let category_id = path.into_inner();
+    let admin_id = current_user_id(&session).unwrap_or_default();
+
+    let notice = match tags.delete_category(category_id).await {
+        Ok(category) => {
+            actions
+                .record(admin_id, "delete", "category", category.id, &category.name)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            format!("Category {} deleted", category.name)
+        }
+        Err(TagError::CategoryNotFound) => format!("Category {} not found", category_id),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(redirect_with_notice("/admin/tags", &notice))
+}
+
+#[post("/tags/{id}/category")]
+pub async fn set_tag_category(
+    path: web::Path<i32>,
+    form: web::Form<TagCategoryForm>,
+    session: Session,
+    tags: web::Data<TagService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let tag_id = path.into_inner();
+    let admin_id = current_user_id(&session).unwrap_or_default();
+    let category_id = form
+        .category_id
+        .as_deref()
+        .and_then(|id| id.parse::<i32>().ok());
+
+    let notice = match tags.set_category(tag_id, category_id).await {
+        Ok(tag) => {
+            let details = match category_id {
+                Some(id) => format!("{} -> category {}", tag.slug, id),
+                None => format!("{} -> no category", tag.slug),
+            };
+            actions
+                .record(admin_id, "categorize", "tag", tag.id, &details)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            format!("Tag {} updated", tag.name)
+        }
+        Err(TagError::NotFound) => format!("Tag {} not found", tag_id),
+        Err(TagError::CategoryNotFound) => "That category no longer exists".to_string(),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(redirect_with_notice("/admin/tags", &notice))
+}
+
+// Removes the tag from every group using it
+#[post("/tags/{id}/delete")]
+pub async fn delete_tag(
+    path: web::Path<i32>,
+    session: Session,
+    tags: web::Data<TagService>,
+    actions: web::Data<AdminActionService>,
+) -> Result<HttpResponse> {
+    let tag_id = path.into_inner();
+    let admin_id = current_user_id(&session).unwrap_or_default();
+
+    let notice = match tags.delete_tag(tag_id).await {
+        Ok(tag) => {
+            actions
+                .record(admin_id, "delete", "tag", tag.id, &tag.slug)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            format!("Tag {} deleted", tag.name)
+        }
+        Err(TagError::NotFound) => format!("Tag {} not found", tag_id),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(redirect_with_notice("/admin/tags", &notice))
+}
+
+// Configure the admin area, every route requires a site administrator
+pub fn configure_admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(RequireAdmin)
+            .service(dashboard)
+            .service(list_users)
+            .service(user_action)
+            .service(list_groups)
+            .service(group_action)
+            .service(audit_log)
+            .service(audit_events)
+            .service(export_audit_events)
+            .service(job_status)
+            .service(retry_job)
+            .service(list_tags)
+            .service(create_category)
+            .service(delete_category)
+            .service(set_tag_category)
+            .service(delete_tag),
+    );
+}
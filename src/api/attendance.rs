@@ -0,0 +1,476 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::events::find_event;
+use crate::api::groups_api::{managed_group, readable_group};
+use crate::config::ServerConfig;
+use crate::db::{
+    attendance::{AttendanceService, MemberAttendance},
+    audit::AuditContext,
+    event::EventService,
+    group::GroupService,
+    member::MemberService,
+    models::{CheckInMethod, Event, Group, RsvpStatus},
+    rsvp::RsvpService,
+    user::UserService,
+};
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use qrcode::QrCode;
+use qrcode::render::svg;
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+// Past events listed on a group's attendance page
+const ATTENDANCE_EVENTS: i64 = 50;
+// Side of the QR codes members show at the door, in pixels
+const QR_CODE_SIZE: u32 = 240;
+
+#[derive(Deserialize)]
+pub struct CheckInQuery {
+    // Part of a member's name
+    pub q: Option<String>,
+    pub notice: Option<String>,
+}
+
+// A member's attendance with their no-show rate, for the templates
+#[derive(Serialize)]
+struct MemberAttendanceView {
+    #[serde(flatten)]
+    member: MemberAttendance,
+    no_show_rate: Option<i64>,
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+fn check_in_url(event_id: i32) -> String {
+    format!("/events/{}/check-in", event_id)
+}
+
+// Where the QR code of an RSVP leads: the page checking its member in
+fn scan_path(event_id: i32, token: &str) -> String {
+    format!("/events/{}/check-in/scan/{}", event_id, token)
+}
+
+// The QR code of `data` as an SVG document
+fn qr_code_svg(data: &str) -> Option<String> {
+    let code = QrCode::new(data.as_bytes()).ok()?;
+    Some(
+        code.render::<svg::Color>()
+            .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+            .build(),
+    )
+}
+
+// The event with its group, if the logged in user hosts it: the group's
+// owner or a site admin
+async fn hosted_event(
+    session: &Session,
+    events: &EventService,
+    groups: &GroupService,
+    members: &MemberService,
+    users: &UserService,
+    event_id: i32,
+) -> Result<(Event, Group), HttpResponse> {
+    let event = find_event(events, event_id).await?;
+    let (group, viewer) = readable_group(session, groups, members, users, event.group_id).await?;
+    if !viewer.can_moderate {
+        return Err(HttpResponse::Forbidden().body("Only the event's hosts can check people in"));
+    }
+    Ok((event, group))
+}
+
+// Check-in mode: find people and check them in as they arrive
+#[allow(clippy::too_many_arguments)]
+pub async fn check_in_page(
+    path: web::Path<i32>,
+    query: web::Query<CheckInQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    attendance: web::Data<AttendanceService>,
+) -> HttpResponse {
+    let (event, group) = match hosted_event(
+        &session,
+        &events,
+        &groups,
+        &members,
+        &users,
+        path.into_inner(),
+    )
+    .await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let attendees = match attendance
+        .attendees(event.id, group.id, query.q.as_deref())
+        .await
+    {
+        Ok(attendees) => attendees,
+        Err(e) => return database_error(e),
+    };
+    let summary = match attendance.summary(event.id).await {
+        Ok(summary) => summary,
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("event", &event);
+    context.insert("group", &group);
+    context.insert("attendees", &attendees);
+    context.insert("summary", &summary);
+    context.insert("q", &query.q);
+    context.insert("notice", &query.notice);
+
+    render(&tmpl, "check_in.html", &context)
+}
+
+// htmz fragment: the people matching a search
+#[allow(clippy::too_many_arguments)]
+pub async fn attendees_fragment(
+    path: web::Path<i32>,
+    query: web::Query<CheckInQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    attendance: web::Data<AttendanceService>,
+) -> HttpResponse {
+    let (event, group) = match hosted_event(
+        &session,
+        &events,
+        &groups,
+        &members,
+        &users,
+        path.into_inner(),
+    )
+    .await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let attendees = match attendance
+        .attendees(event.id, group.id, query.q.as_deref())
+        .await
+    {
+        Ok(attendees) => attendees,
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = tera::Context::new();
+    context.insert("event", &event);
+    context.insert("attendees", &attendees);
+
+    render(&tmpl, "check_in_attendees.html", &context)
+}
+
+// htmz fragment: check someone in, or take it back when `undo` is set. The
+// response replaces their line of the list.
+#[allow(clippy::too_many_arguments)]
+async fn set_checked_in(
+    event_id: i32,
+    user_id: i32,
+    undo: bool,
+    tmpl: &Tera,
+    session: &Session,
+    audit: &AuditContext,
+    groups: &GroupService,
+    members: &MemberService,
+    users: &UserService,
+    events: &EventService,
+    attendance: &AttendanceService,
+) -> HttpResponse {
+    let (event, group) = match hosted_event(session, events, groups, members, users, event_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    match attendance.attendee(event.id, group.id, user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Attendee not found"),
+        Err(e) => return database_error(e),
+    }
+
+    let changed = if undo {
+        attendance
+            .undo_check_in(event.id, user_id, audit)
+            .await
+            .map(|_| ())
+    } else {
+        attendance
+            .check_in(event.id, user_id, CheckInMethod::List, audit)
+            .await
+            .map(|_| ())
+    };
+    if let Err(e) = changed {
+        return database_error(e);
+    }
+
+    let attendee = match attendance.attendee(event.id, group.id, user_id).await {
+        Ok(Some(attendee)) => attendee,
+        Ok(None) => return HttpResponse::NotFound().body("Attendee not found"),
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = tera::Context::new();
+    context.insert("event", &event);
+    context.insert("attendee", &attendee);
+
+    render(tmpl, "check_in_attendee.html", &context)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn check_in(
+    path: web::Path<(i32, i32)>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    attendance: web::Data<AttendanceService>,
+) -> HttpResponse {
+    let (event_id, user_id) = path.into_inner();
+    set_checked_in(
+        event_id,
+        user_id,
+        false,
+        &tmpl,
+        &session,
+        &audit,
+        &groups,
+        &members,
+        &users,
+        &events,
+        &attendance,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn undo_check_in(
+    path: web::Path<(i32, i32)>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    attendance: web::Data<AttendanceService>,
+) -> HttpResponse {
+    let (event_id, user_id) = path.into_inner();
+    set_checked_in(
+        event_id,
+        user_id,
+        true,
+        &tmpl,
+        &session,
+        &audit,
+        &groups,
+        &members,
+        &users,
+        &events,
+        &attendance,
+    )
+    .await
+}
+
+// Where a scanned QR code leads: who it belongs to, with a button checking
+// them in
+#[allow(clippy::too_many_arguments)]
+pub async fn scan_page(
+    path: web::Path<(i32, String)>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    attendance: web::Data<AttendanceService>,
+) -> HttpResponse {
+    let (event_id, token) = path.into_inner();
+    let (event, group) =
+        match hosted_event(&session, &events, &groups, &members, &users, event_id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+
+    let attendee = match attendance.find_token(event.id, &token).await {
+        Ok(Some(user_id)) => attendance.attendee(event.id, group.id, user_id).await,
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let attendee = match attendee {
+        Ok(attendee) => attendee,
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("event", &event);
+    context.insert("group", &group);
+    context.insert("attendee", &attendee);
+    context.insert("token", &token);
+
+    render(&tmpl, "check_in_scan.html", &context)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn check_in_scanned(
+    path: web::Path<(i32, String)>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    attendance: web::Data<AttendanceService>,
+) -> HttpResponse {
+    let (event_id, token) = path.into_inner();
+    let (event, group) =
+        match hosted_event(&session, &events, &groups, &members, &users, event_id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let back = check_in_url(event.id);
+
+    let attendee = match attendance.find_token(event.id, &token).await {
+        Ok(Some(user_id)) => attendance.attendee(event.id, group.id, user_id).await,
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let attendee = match attendee {
+        Ok(Some(attendee)) => attendee,
+        Ok(None) => return redirect_with_notice(&back, "This code is not valid for this event"),
+        Err(e) => return database_error(e),
+    };
+
+    match attendance
+        .check_in(event.id, attendee.user_id, CheckInMethod::QrCode, &audit)
+        .await
+    {
+        Ok((_, true)) => redirect_with_notice(&back, &format!("{} checked in", attendee.name)),
+        Ok((_, false)) => {
+            redirect_with_notice(&back, &format!("{} was already checked in", attendee.name))
+        }
+        Err(e) => database_error(e),
+    }
+}
+
+// The QR code of the logged in member's RSVP, to show at the door
+#[allow(clippy::too_many_arguments)]
+pub async fn check_in_code(
+    path: web::Path<i32>,
+    session: Session,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    rsvps: web::Data<RsvpService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let event = match find_event(&events, path.into_inner()).await {
+        Ok(event) => event,
+        Err(response) => return response,
+    };
+    let viewer = match readable_group(&session, &groups, &members, &users, event.group_id).await {
+        Ok((_, viewer)) => viewer,
+        Err(response) => return response,
+    };
+    let Some(user_id) = viewer.user_id else {
+        return HttpResponse::Unauthorized().body("Login required");
+    };
+
+    let rsvp = match rsvps.get(event.id, user_id).await {
+        Ok(Some(rsvp)) if rsvp.status != RsvpStatus::NotGoing => rsvp,
+        Ok(_) => return HttpResponse::NotFound().body("No RSVP to this event"),
+        Err(e) => return database_error(e),
+    };
+
+    match qr_code_svg(&server.url(&scan_path(event.id, &rsvp.check_in_token))) {
+        Some(svg) => HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .insert_header(("Cache-Control", "private, no-store"))
+            .body(svg),
+        None => HttpResponse::InternalServerError().body("Could not draw the QR code"),
+    }
+}
+
+// Who showed up at the group's past events, and who tends not to
+pub async fn attendance_page(
+    path: web::Path<i32>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    attendance: web::Data<AttendanceService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    let now = Utc::now();
+    let past_events = match attendance.events(group.id, now, ATTENDANCE_EVENTS).await {
+        Ok(past_events) => past_events,
+        Err(e) => return database_error(e),
+    };
+    let member_stats: Vec<MemberAttendanceView> = match attendance.members(group.id, now).await {
+        Ok(member_stats) => member_stats,
+        Err(e) => return database_error(e),
+    }
+    .into_iter()
+    .map(|member| MemberAttendanceView {
+        no_show_rate: member.no_show_rate(),
+        member,
+    })
+    .collect();
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert("events", &past_events);
+    context.insert("members", &member_stats);
+
+    render(&tmpl, "group_attendance.html", &context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qr_code_svg() {
+        let url = format!(
+            "https://groups.example.com{}",
+            scan_path(7, "0123456789abcdef0123456789abcdef")
+        );
+        assert_eq!(
+            url,
+            "https://groups.example.com/events/7/check-in/scan/0123456789abcdef0123456789abcdef"
+        );
+        let svg = qr_code_svg(&url).unwrap();
+        assert!(svg.starts_with("<?xml") && svg.contains("<svg"));
+    }
+}
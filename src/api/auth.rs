@@ -1,5 +1,17 @@
 use super::{hash_password, verify_password};
-use crate::db::user::UserService;
+use crate::api::notifications::notify_member_joined;
+use crate::config::{FeaturesConfig, ServerConfig};
+use crate::db::{
+    audit::AuditContext,
+    group::GroupService,
+    invitation::{InvitationError, InvitationService},
+    job::JobService,
+    models::User,
+    notification::NotificationService,
+    user::UserService,
+};
+use crate::middleware::security_headers::CspNonce;
+use crate::middleware::session_validation::SESSION_VERSION_KEY;
 use actix_session::Session;
 use actix_web::{HttpResponse, Result, web};
 use serde::Deserialize;
@@ -15,6 +27,13 @@ pub struct LoginRequest {
 pub struct RegisterRequest {
     email: String,
     password: String,
+    // Code of the invitation link that led to the registration
+    invite: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterQuery {
+    invite: Option<String>,
 }
 
 pub async fn login_page(tmpl: web::Data<Tera>) -> Result<HttpResponse> {
@@ -30,9 +49,11 @@ pub async fn login(
     form: web::Form<LoginRequest>,
     user_service: web::Data<UserService>,
     session: Session,
+    nonce: CspNonce,
     _tmpl: web::Data<Tera>,
 ) -> Result<HttpResponse> {
     let mut ctx = tera::Context::new();
+    ctx.insert("csp_nonce", nonce.as_str());
 
     // Look up user by email
     match user_service.get_by_email(form.email.clone()).await {
@@ -42,10 +63,16 @@ pub async fn login(
                 match verify_password(form.password.as_bytes(), password_hash) {
                     Ok(true) => {
                         // Password is correct
+                        // Issue a fresh session id to prevent session fixation
+                        session.renew();
                         // Store user info in session
                         session.insert("user_id", user.id).unwrap();
                         session.insert("user_email", &user.email).unwrap();
                         session.insert("user_name", &user.name).unwrap();
+                        session.insert("is_admin", user.is_admin).unwrap();
+                        session
+                            .insert(SESSION_VERSION_KEY, user.session_version)
+                            .unwrap();
 
                         ctx.insert("message", "Login successful!");
                         ctx.insert("success", &true);
@@ -84,7 +111,7 @@ pub async fn login(
         <div id="login-form">
             {% if success %}
                 <div class="alert alert-success">{{ message }}</div>
-                <script>setTimeout(() => window.top.location.href = '/groups', 1500);</script>
+                <script nonce="{{ csp_nonce }}">setTimeout(() => window.top.location.href = '/groups', 1500);</script>
             {% else %}
                 <div class="alert alert-error">{{ message }}</div>
             {% endif %}
@@ -116,8 +143,17 @@ pub async fn login(
     Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
 }
 
-pub async fn register_page(tmpl: web::Data<Tera>) -> Result<HttpResponse> {
-    let ctx = tera::Context::new();
+pub async fn register_page(
+    query: web::Query<RegisterQuery>,
+    tmpl: web::Data<Tera>,
+    features: web::Data<FeaturesConfig>,
+) -> Result<HttpResponse> {
+    if !features.registration {
+        return Ok(HttpResponse::NotFound().body("Registration is disabled"));
+    }
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("invite", &query.invite);
     let rendered = tmpl.render("register.html", &ctx).map_err(|e| {
         actix_web::error::ErrorInternalServerError(format!("Template error: {}", e))
     })?;
@@ -125,12 +161,27 @@ pub async fn register_page(tmpl: web::Data<Tera>) -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn register(
     form: web::Form<RegisterRequest>,
+    audit: AuditContext,
     user_service: web::Data<UserService>,
-    _tmpl: web::Data<Tera>,
+    invitations: web::Data<InvitationService>,
+    groups: web::Data<GroupService>,
+    notifications: web::Data<NotificationService>,
+    jobs: web::Data<JobService>,
+    features: web::Data<FeaturesConfig>,
+    server: web::Data<ServerConfig>,
+    nonce: CspNonce,
+    tmpl: web::Data<Tera>,
 ) -> Result<HttpResponse> {
+    if !features.registration {
+        return Ok(HttpResponse::NotFound().body("Registration is disabled"));
+    }
+
     let mut ctx = tera::Context::new();
+    ctx.insert("csp_nonce", nonce.as_str());
+    ctx.insert("invite", &form.invite);
 
     // Check if email already exists
     match user_service.get_by_email(form.email.clone()).await {
@@ -147,11 +198,32 @@ pub async fn register(
             match hash_password(form.password.as_bytes()) {
                 Ok(password_hash) => {
                     match user_service
-                        .create_with_password(form.email.clone(), name, password_hash)
+                        .create_with_password(form.email.clone(), name, password_hash, &audit)
                         .await
                     {
-                        Ok(_) => {
-                            ctx.insert("message", "Registration successful! Please login.");
+                        Ok(user) => {
+                            let joined =
+                                accept_invitation(&invitations, &user, &form, &audit).await;
+                            if let Some(group_id) = joined {
+                                if let Ok(Some(group)) = groups.get_by_id(group_id).await {
+                                    notify_member_joined(
+                                        &notifications,
+                                        &jobs,
+                                        &tmpl,
+                                        &server,
+                                        &group,
+                                        &user,
+                                    )
+                                    .await;
+                                }
+                            }
+                            let message = match joined {
+                                None => "Registration successful! Please login.",
+                                Some(_) => {
+                                    "Registration successful! You joined the group. Please login."
+                                }
+                            };
+                            ctx.insert("message", &message);
                             ctx.insert("success", &true);
                         }
                         Err(e) => {
@@ -177,7 +249,7 @@ pub async fn register(
         <div id="register-form">
             {% if success %}
                 <div class="alert alert-success">{{ message }}</div>
-                <script>setTimeout(() => window.top.location.href = '/login', 1500);</script>
+                <script nonce="{{ csp_nonce }}">setTimeout(() => window.top.location.href = '/login', 1500);</script>
             {% else %}
                 <div class="alert alert-error">{{ message }}</div>
             {% endif %}
@@ -192,6 +264,10 @@ pub async fn register(
                 <input type="password" id="password" name="password" required autocomplete="new-password" minlength="8">
             </div>
             
+            {% if invite %}
+            <input type="hidden" name="invite" value="{{ invite }}">
+            {% endif %}
+
             <div class="form-actions">
                 <button type="submit">Register</button>
             </div>
@@ -209,6 +285,34 @@ pub async fn register(
     Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
 }
 
+// Join the group of the invitation link the new user registered from. Email
+// addresses are not verified, so invitations sent to the address are only
+// accepted through their link. Returns the id of the group joined; a failure
+// does not undo the registration.
+async fn accept_invitation(
+    invitations: &InvitationService,
+    user: &User,
+    form: &RegisterRequest,
+    audit: &AuditContext,
+) -> Option<i32> {
+    let code = form.invite.as_deref().filter(|c| !c.is_empty())?;
+    // The new user is the one accepting
+    let audit = AuditContext {
+        actor_id: Some(user.id),
+        ..audit.clone()
+    };
+
+    match invitations.accept(code, user, &audit).await {
+        Ok(member) => Some(member.group_id),
+        Err(InvitationError::SqlError(e)) => {
+            eprintln!("Failed to accept invitation for {}: {}", user.email, e);
+            None
+        }
+        // Expired, used up or sent to another address
+        Err(_) => None,
+    }
+}
+
 pub async fn logout(session: Session) -> Result<HttpResponse> {
     // Clear the session
     session.clear();
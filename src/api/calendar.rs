@@ -0,0 +1,249 @@
+use crate::api::context::create_template_context;
+use crate::api::groups_api::readable_group;
+use crate::calendar::{self, Calendar, CalendarView};
+use crate::db::{
+    event::EventService, group::GroupService, member::MemberService, user::UserService,
+};
+use crate::middleware::auth::current_user_id;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use tera::Tera;
+
+#[derive(Deserialize)]
+pub struct CalendarQuery {
+    // month, week or agenda
+    pub view: Option<String>,
+    // A day of the period to show, today by default
+    pub date: Option<String>,
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+// The view and day asked for
+fn parse_query(
+    query: &CalendarQuery,
+    today: NaiveDate,
+) -> Result<(CalendarView, NaiveDate), String> {
+    let view =
+        CalendarView::parse(query.view.as_deref()).ok_or("view must be month, week or agenda")?;
+    let date = match query.date.as_deref().map(str::trim) {
+        None | Some("") => today,
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| "date must be formatted as YYYY-MM-DD")?,
+    };
+    Ok((view, date))
+}
+
+// The logged in user's time zone, UTC for visitors
+async fn viewer_tz(session: &Session, users: &UserService) -> Tz {
+    let Some(user_id) = current_user_id(session) else {
+        return Tz::UTC;
+    };
+    match users.get_by_id(user_id).await {
+        Ok(Some(user)) => user.tz(),
+        _ => Tz::UTC,
+    }
+}
+
+// The calendar of the groups' events for the query, in the time zone
+async fn load_calendar(
+    events: &EventService,
+    group_ids: &[i32],
+    query: &CalendarQuery,
+    tz: Tz,
+) -> Result<Calendar, HttpResponse> {
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let (view, date) =
+        parse_query(query, today).map_err(|message| HttpResponse::BadRequest().body(message))?;
+    let (first, end) = view.range(date);
+    let (from, to) = calendar::day_bounds(first, end, tz);
+
+    let entries = events
+        .list_for_calendar(group_ids, from, to)
+        .await
+        .map_err(database_error)?;
+    let occurrences = entries
+        .iter()
+        .flat_map(|entry| calendar::occurrences(&entry.event, &entry.group_name, from, to))
+        .collect();
+
+    Ok(calendar::build(view, date, today, occurrences, tz))
+}
+
+// A group's events as a month, a week or an agenda
+#[allow(clippy::too_many_arguments)]
+pub async fn group_calendar_page(
+    path: web::Path<i32>,
+    query: web::Query<CalendarQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+) -> HttpResponse {
+    let (group, _) =
+        match readable_group(&session, &groups, &members, &users, path.into_inner()).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let tz = viewer_tz(&session, &users).await;
+    let calendar = match load_calendar(&events, &[group.id], &query, tz).await {
+        Ok(calendar) => calendar,
+        Err(response) => return response,
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert("calendar", &calendar);
+    context.insert("base_url", &format!("/groups/{}/calendar", group.id));
+    context.insert("show_groups", &false);
+
+    render(&tmpl, "group_calendar.html", &context)
+}
+
+// htmz fragment: another period or view of a group's calendar
+#[allow(clippy::too_many_arguments)]
+pub async fn group_calendar_fragment(
+    path: web::Path<i32>,
+    query: web::Query<CalendarQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+) -> HttpResponse {
+    let (group, _) =
+        match readable_group(&session, &groups, &members, &users, path.into_inner()).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let tz = viewer_tz(&session, &users).await;
+    let calendar = match load_calendar(&events, &[group.id], &query, tz).await {
+        Ok(calendar) => calendar,
+        Err(response) => return response,
+    };
+
+    let mut context = tera::Context::new();
+    context.insert("calendar", &calendar);
+    context.insert("base_url", &format!("/groups/{}/calendar", group.id));
+    context.insert("show_groups", &false);
+
+    render(&tmpl, "calendar_view.html", &context)
+}
+
+// Ids of the groups the logged in user belongs to
+async fn member_group_ids(
+    session: &Session,
+    groups: &GroupService,
+) -> Result<Vec<i32>, HttpResponse> {
+    let Some(user_id) = current_user_id(session) else {
+        return Err(HttpResponse::Unauthorized().body("Login required"));
+    };
+    match groups.list_for_member(user_id).await {
+        Ok(groups) => Ok(groups.iter().map(|group| group.id).collect()),
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+// The events of all the user's groups
+pub async fn my_calendar_page(
+    query: web::Query<CalendarQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+) -> HttpResponse {
+    let group_ids = match member_group_ids(&session, &groups).await {
+        Ok(group_ids) => group_ids,
+        Err(response) => return response,
+    };
+    let tz = viewer_tz(&session, &users).await;
+    let calendar = match load_calendar(&events, &group_ids, &query, tz).await {
+        Ok(calendar) => calendar,
+        Err(response) => return response,
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("calendar", &calendar);
+    context.insert("base_url", "/calendar");
+    context.insert("show_groups", &true);
+    context.insert("has_groups", &!group_ids.is_empty());
+
+    render(&tmpl, "my_calendar.html", &context)
+}
+
+// htmz fragment: another period or view of the user's calendar
+pub async fn my_calendar_fragment(
+    query: web::Query<CalendarQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+) -> HttpResponse {
+    let group_ids = match member_group_ids(&session, &groups).await {
+        Ok(group_ids) => group_ids,
+        Err(response) => return response,
+    };
+    let tz = viewer_tz(&session, &users).await;
+    let calendar = match load_calendar(&events, &group_ids, &query, tz).await {
+        Ok(calendar) => calendar,
+        Err(response) => return response,
+    };
+
+    let mut context = tera::Context::new();
+    context.insert("calendar", &calendar);
+    context.insert("base_url", "/calendar");
+    context.insert("show_groups", &true);
+
+    render(&tmpl, "calendar_view.html", &context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(view: Option<&str>, date: Option<&str>) -> CalendarQuery {
+        CalendarQuery {
+            view: view.map(String::from),
+            date: date.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let today = NaiveDate::from_ymd_opt(2026, 3, 18).unwrap();
+        assert_eq!(
+            parse_query(&query(None, None), today),
+            Ok((CalendarView::Month, today))
+        );
+        assert_eq!(
+            parse_query(&query(Some("agenda"), Some("2026-04-01")), today),
+            Ok((
+                CalendarView::Agenda,
+                NaiveDate::from_ymd_opt(2026, 4, 1).unwrap()
+            ))
+        );
+        assert!(parse_query(&query(Some("year"), None), today).is_err());
+        assert!(parse_query(&query(None, Some("18/03/2026")), today).is_err());
+    }
+}
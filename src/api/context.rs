@@ -0,0 +1,62 @@
+use crate::middleware::notifications::unread_notifications;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::HttpResponse;
+
+// Helper function to create template context with session data
+pub fn create_template_context(session: &Session, nonce: &CspNonce) -> tera::Context {
+    let mut context = tera::Context::new();
+    context.insert("csp_nonce", nonce.as_str());
+
+    // Check if user is logged in
+    if let Ok(Some(user_email)) = session.get::<String>("user_email") {
+        context.insert("user_email", &user_email);
+        if let Ok(Some(user_name)) = session.get::<String>("user_name") {
+            context.insert("user_name", &user_name);
+        }
+        context.insert("is_logged_in", &true);
+        // Only used to show the admin link, access is checked by RequireAdmin
+        let is_admin = session
+            .get::<bool>("is_admin")
+            .ok()
+            .flatten()
+            .unwrap_or(false);
+        context.insert("is_admin", &is_admin);
+        context.insert("unread_notifications", &unread_notifications(session));
+    } else {
+        context.insert("is_logged_in", &false);
+        context.insert("is_admin", &false);
+        context.insert("unread_notifications", &0);
+    }
+
+    context
+}
+
+// 303 redirect after a form submission, with the outcome shown on the next page
+pub fn redirect_with_notice(location: &str, notice: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .append_header(("Location", notice_location(location, notice)))
+        .finish()
+}
+
+fn notice_location(location: &str, notice: &str) -> String {
+    let query = serde_urlencoded::to_string([("notice", notice)]).unwrap_or_default();
+    format!("{}?{}", location, query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notice_location_encodes_notice() {
+        assert_eq!(
+            notice_location("/admin/users", "User 3: restore done"),
+            "/admin/users?notice=User+3%3A+restore+done"
+        );
+        assert_eq!(
+            notice_location("/groups/1", "#1 ?&+ café 100%"),
+            "/groups/1?notice=%231+%3F%26%2B+caf%C3%A9+100%25"
+        );
+    }
+}
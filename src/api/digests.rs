@@ -0,0 +1,74 @@
+use crate::api::context::create_template_context;
+use crate::db::{digest::DigestService, models::DigestFrequency};
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use tera::Tera;
+
+fn render(tmpl: &Tera, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl
+        .render("digest_unsubscribe.html", context)
+        .unwrap_or_else(|e| {
+            eprintln!("Template error: {}", e);
+            "Template error".to_string()
+        });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn invalid_link(tmpl: &Tera, mut context: tera::Context) -> HttpResponse {
+    context.insert("error", "This unsubscribe link is not valid.");
+    let rendered = tmpl
+        .render("digest_unsubscribe.html", &context)
+        .unwrap_or_else(|_| "Not found".to_string());
+    HttpResponse::NotFound()
+        .content_type("text/html")
+        .body(rendered)
+}
+
+// The link in digest emails. Following it only asks for confirmation, so
+// link checkers opening it do not unsubscribe anyone.
+pub async fn unsubscribe_page(
+    path: web::Path<String>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    digests: web::Data<DigestService>,
+) -> HttpResponse {
+    let token = path.into_inner();
+    let context = create_template_context(&session, &nonce);
+    let settings = match digests.get_by_token(&token).await {
+        Ok(Some(settings)) => settings,
+        Ok(None) => return invalid_link(&tmpl, context),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let mut context = context;
+    context.insert("token", &token);
+    context.insert(
+        "unsubscribed",
+        &(settings.frequency == DigestFrequency::Never),
+    );
+    render(&tmpl, &context)
+}
+
+// Unsubscribe without logging in, from the page or straight from the mail
+// client's unsubscribe button
+pub async fn unsubscribe(
+    path: web::Path<String>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    digests: web::Data<DigestService>,
+) -> HttpResponse {
+    let mut context = create_template_context(&session, &nonce);
+    match digests.unsubscribe(&path.into_inner()).await {
+        Ok(Some(_)) => {
+            context.insert("unsubscribed", &true);
+            render(&tmpl, &context)
+        }
+        Ok(None) => invalid_link(&tmpl, context),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
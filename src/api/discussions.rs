@@ -0,0 +1,701 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::groups_api::{GroupViewer, readable_group};
+use crate::api::notifications::notify_with_email;
+use crate::config::ServerConfig;
+use crate::db::{
+    audit::AuditContext,
+    group::GroupService,
+    job::JobService,
+    member::MemberService,
+    models::{Group, NotificationKind, Post},
+    notification::{NewNotification, NotificationService},
+    post::{MAX_BODY_LENGTH, MAX_TITLE_LENGTH, PostEntry, PostError, PostService},
+    user::UserService,
+};
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+const THREADS_PER_PAGE: i64 = 20;
+const REPLIES_PER_PAGE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct DiscussionsQuery {
+    pub page: Option<i64>,
+    pub notice: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ThreadQuery {
+    pub notice: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RepliesQuery {
+    // Load the replies following this one
+    pub after: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct PostForm {
+    // Threads only
+    pub title: Option<String>,
+    pub body: String,
+}
+
+fn can_edit(viewer: &GroupViewer, post: &Post) -> bool {
+    post.deleted_at.is_none() && viewer.is_author(post.author_id)
+}
+
+// Authors delete their own posts, owners and admins any post
+fn can_delete(viewer: &GroupViewer, post: &Post) -> bool {
+    post.deleted_at.is_none() && (viewer.is_author(post.author_id) || viewer.can_moderate)
+}
+
+fn post_view(viewer: &GroupViewer, entry: PostEntry) -> PostView {
+    let can_edit = can_edit(viewer, &entry.post);
+    let can_delete = can_delete(viewer, &entry.post);
+    let mut post = entry.post;
+    // Deleted posts keep their place in the thread but not their content
+    if post.deleted_at.is_some() {
+        post.body.clear();
+    }
+
+    PostView {
+        post,
+        author_name: entry
+            .author_name
+            .unwrap_or_else(|| "Deleted user".to_string()),
+        can_edit,
+        can_delete,
+    }
+}
+
+// A post as shown in templates
+#[derive(Serialize)]
+struct PostView {
+    #[serde(flatten)]
+    post: Post,
+    author_name: String,
+    can_edit: bool,
+    can_delete: bool,
+}
+
+// Check the title (threads only) and body of a post
+fn validate_post(title: Option<&str>, body: &str) -> Result<(), String> {
+    if let Some(title) = title {
+        if title.is_empty() {
+            return Err("Title cannot be empty".to_string());
+        }
+        if title.chars().count() > MAX_TITLE_LENGTH {
+            return Err(format!(
+                "Title is limited to {} characters",
+                MAX_TITLE_LENGTH
+            ));
+        }
+    }
+    if body.is_empty() {
+        return Err("Post cannot be empty".to_string());
+    }
+    if body.chars().count() > MAX_BODY_LENGTH {
+        return Err(format!(
+            "Posts are limited to {} characters",
+            MAX_BODY_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+// A post of the group that is not deleted; for threads, the thread itself
+// must not be deleted either
+async fn find_post(posts: &PostService, group: &Group, id: i32) -> Result<PostEntry, HttpResponse> {
+    let not_found = || HttpResponse::NotFound().body("Post not found");
+    let entry = match posts.get(id).await {
+        Ok(Some(entry)) if entry.post.group_id == group.id && entry.post.deleted_at.is_none() => {
+            entry
+        }
+        Ok(_) => return Err(not_found()),
+        Err(e) => return Err(database_error(e)),
+    };
+
+    if let Some(thread_id) = entry.post.thread_id {
+        match posts.get(thread_id).await {
+            Ok(Some(thread)) if thread.post.deleted_at.is_none() => {}
+            Ok(_) => return Err(not_found()),
+            Err(e) => return Err(database_error(e)),
+        }
+    }
+
+    Ok(entry)
+}
+
+fn thread_url(group_id: i32, thread_id: i32) -> String {
+    format!("/groups/{}/discussions/{}", group_id, thread_id)
+}
+
+// One page of replies, with the id to continue from when there are more
+async fn replies_page(
+    posts: &PostService,
+    viewer: &GroupViewer,
+    thread_id: i32,
+    after: Option<i32>,
+) -> Result<(Vec<PostView>, Option<i32>), PostError> {
+    let mut replies = posts
+        .list_replies(thread_id, after, REPLIES_PER_PAGE + 1)
+        .await?;
+    let has_more = replies.len() as i64 > REPLIES_PER_PAGE;
+    replies.truncate(REPLIES_PER_PAGE as usize);
+    let next_after = if has_more {
+        replies.last().map(|entry| entry.post.id)
+    } else {
+        None
+    };
+
+    Ok((
+        replies
+            .into_iter()
+            .map(|entry| post_view(viewer, entry))
+            .collect(),
+        next_after,
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn discussions_response(
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    group: &Group,
+    viewer: &GroupViewer,
+    posts: &PostService,
+    page: i64,
+    notice: Option<&str>,
+    form: Option<(&PostForm, &str)>,
+) -> HttpResponse {
+    let page = page.max(1);
+    let (threads, total) = match (
+        posts
+            .list_threads(group.id, THREADS_PER_PAGE, (page - 1) * THREADS_PER_PAGE)
+            .await,
+        posts.count_threads(group.id).await,
+    ) {
+        (Ok(threads), Ok(total)) => (threads, total),
+        (Err(e), _) | (_, Err(e)) => return database_error(e),
+    };
+    let threads: Vec<PostView> = threads.into_iter().map(|t| post_view(viewer, t)).collect();
+
+    let mut context = create_template_context(session, nonce);
+    context.insert("group", group);
+    context.insert("threads", &threads);
+    context.insert("page", &page);
+    context.insert("has_next", &(page * THREADS_PER_PAGE < total));
+    context.insert("can_post", &viewer.can_post());
+    context.insert("notice", &notice);
+    context.insert("title", &form.and_then(|(f, _)| f.title.as_deref()));
+    context.insert("body", &form.map(|(f, _)| f.body.as_str()));
+    context.insert("error", &form.map(|(_, error)| error));
+
+    render(tmpl, "group_discussions.html", &context)
+}
+
+// Threads of a group, with a form to start one
+#[allow(clippy::too_many_arguments)]
+pub async fn discussions_page(
+    path: web::Path<i32>,
+    query: web::Query<DiscussionsQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group, viewer) =
+        match readable_group(&session, &groups, &members, &users, path.into_inner()).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+
+    discussions_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &group,
+        &viewer,
+        &posts,
+        query.page.unwrap_or(1),
+        query.notice.as_deref(),
+        None,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_thread(
+    path: web::Path<i32>,
+    form: web::Form<PostForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group, viewer) =
+        match readable_group(&session, &groups, &members, &users, path.into_inner()).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let Some(user_id) = viewer.user_id.filter(|_| viewer.can_post()) else {
+        return HttpResponse::Forbidden().body("Only members can post in this group");
+    };
+
+    let title = form.title.as_deref().unwrap_or_default().trim();
+    let body = form.body.trim();
+    if let Err(error) = validate_post(Some(title), body) {
+        return discussions_response(
+            &tmpl,
+            &session,
+            &nonce,
+            &group,
+            &viewer,
+            &posts,
+            1,
+            None,
+            Some((&form, &error)),
+        )
+        .await;
+    }
+
+    match posts
+        .create_thread(group.id, user_id, title, body, &audit)
+        .await
+    {
+        Ok(thread) => HttpResponse::SeeOther()
+            .append_header(("Location", thread_url(group.id, thread.id)))
+            .finish(),
+        Err(e) => database_error(e),
+    }
+}
+
+// A thread with its first page of replies
+#[allow(clippy::too_many_arguments)]
+pub async fn thread_page(
+    path: web::Path<(i32, i32)>,
+    query: web::Query<ThreadQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group_id, thread_id) = path.into_inner();
+    let (group, viewer) = match readable_group(&session, &groups, &members, &users, group_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let thread = match find_post(&posts, &group, thread_id).await {
+        Ok(entry) => entry,
+        Err(response) => return response,
+    };
+    // Links to a reply lead to its place in the thread
+    if let Some(thread_id) = thread.post.thread_id {
+        return HttpResponse::Found()
+            .append_header((
+                "Location",
+                format!(
+                    "{}#post-{}",
+                    thread_url(group.id, thread_id),
+                    thread.post.id
+                ),
+            ))
+            .finish();
+    }
+
+    let (replies, next_after) = match replies_page(&posts, &viewer, thread.post.id, None).await {
+        Ok(page) => page,
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert("thread", &post_view(&viewer, thread));
+    context.insert("replies", &replies);
+    context.insert("next_after", &next_after);
+    context.insert("can_post", &viewer.can_post());
+    context.insert("notice", &query.notice);
+
+    render(&tmpl, "discussion_thread.html", &context)
+}
+
+// htmz fragment: the next page of replies, replacing the "load more" link
+#[allow(clippy::too_many_arguments)]
+pub async fn replies_fragment(
+    path: web::Path<(i32, i32)>,
+    query: web::Query<RepliesQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group_id, thread_id) = path.into_inner();
+    let (group, viewer) = match readable_group(&session, &groups, &members, &users, group_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let thread = match find_post(&posts, &group, thread_id).await {
+        Ok(entry) if entry.post.is_thread() => entry,
+        Ok(_) => return HttpResponse::NotFound().body("Thread not found"),
+        Err(response) => return response,
+    };
+
+    let (replies, next_after) =
+        match replies_page(&posts, &viewer, thread.post.id, query.after).await {
+            Ok(page) => page,
+            Err(e) => return database_error(e),
+        };
+
+    let mut context = tera::Context::new();
+    context.insert("group", &group);
+    context.insert("thread", &post_view(&viewer, thread));
+    context.insert("replies", &replies);
+    context.insert("next_after", &next_after);
+
+    render(&tmpl, "discussion_replies.html", &context)
+}
+
+// htmz fragment: post a reply. The response replaces the reply form with the
+// new reply followed by an empty form, or the form with an error.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_reply(
+    path: web::Path<(i32, i32)>,
+    form: web::Form<PostForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+    notifications: web::Data<NotificationService>,
+    jobs: web::Data<JobService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let (group_id, thread_id) = path.into_inner();
+    let (group, viewer) = match readable_group(&session, &groups, &members, &users, group_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let Some(user_id) = viewer.user_id.filter(|_| viewer.can_post()) else {
+        return HttpResponse::Forbidden().body("Only members can post in this group");
+    };
+    let thread = match find_post(&posts, &group, thread_id).await {
+        Ok(entry) if entry.post.is_thread() => entry,
+        Ok(_) => return HttpResponse::NotFound().body("Thread not found"),
+        Err(response) => return response,
+    };
+    let thread_author = thread.post.author_id;
+    let thread_title = thread.post.title.clone().unwrap_or_default();
+
+    let mut context = tera::Context::new();
+    context.insert("group", &group);
+    context.insert("thread", &post_view(&viewer, thread));
+
+    let body = form.body.trim();
+    match validate_post(None, body) {
+        Err(error) => {
+            context.insert("error", &error);
+            context.insert("body", body);
+        }
+        Ok(()) => match posts.reply(thread_id, user_id, body, &audit).await {
+            Ok(post) => {
+                let entry = PostEntry {
+                    post,
+                    author_name: users
+                        .get_by_id(user_id)
+                        .await
+                        .ok()
+                        .flatten()
+                        .map(|u| u.name),
+                };
+                if let Some(thread_author) = thread_author {
+                    let notification = NewNotification {
+                        kind: NotificationKind::Reply,
+                        actor_id: Some(user_id),
+                        message: format!(
+                            "{} replied to {}",
+                            entry.author_name.as_deref().unwrap_or("Someone"),
+                            thread_title
+                        ),
+                        url: format!(
+                            "/groups/{}/discussions/{}#post-{}",
+                            group.id, thread_id, entry.post.id
+                        ),
+                    };
+                    notify_with_email(
+                        &notifications,
+                        &jobs,
+                        &tmpl,
+                        &server,
+                        &[thread_author],
+                        &notification,
+                    )
+                    .await;
+                }
+                context.insert("post", &post_view(&viewer, entry));
+            }
+            Err(e @ (PostError::NotFound | PostError::Deleted)) => {
+                context.insert("error", &e.to_string());
+            }
+            Err(e) => return database_error(e),
+        },
+    }
+
+    render(&tmpl, "discussion_reply_created.html", &context)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn edit_response(
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    group: &Group,
+    post: &Post,
+    title: Option<&str>,
+    body: &str,
+    error: Option<&str>,
+) -> HttpResponse {
+    let mut context = create_template_context(session, nonce);
+    context.insert("group", group);
+    context.insert("post", post);
+    context.insert("title", &title);
+    context.insert("body", body);
+    context.insert("error", &error);
+    context.insert("thread_url", &thread_url(group.id, post.thread()));
+
+    render(tmpl, "discussion_edit.html", &context)
+}
+
+// Form to edit one of the user's posts
+#[allow(clippy::too_many_arguments)]
+pub async fn edit_post_page(
+    path: web::Path<(i32, i32)>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group_id, post_id) = path.into_inner();
+    let (group, viewer) = match readable_group(&session, &groups, &members, &users, group_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let post = match find_post(&posts, &group, post_id).await {
+        Ok(entry) => entry.post,
+        Err(response) => return response,
+    };
+    if !can_edit(&viewer, &post) {
+        return HttpResponse::Forbidden().body("Only the author can edit this post");
+    }
+
+    edit_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &group,
+        &post,
+        post.title.as_deref(),
+        &post.body,
+        None,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn edit_post(
+    path: web::Path<(i32, i32)>,
+    form: web::Form<PostForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group_id, post_id) = path.into_inner();
+    let (group, viewer) = match readable_group(&session, &groups, &members, &users, group_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let post = match find_post(&posts, &group, post_id).await {
+        Ok(entry) => entry.post,
+        Err(response) => return response,
+    };
+    if !can_edit(&viewer, &post) {
+        return HttpResponse::Forbidden().body("Only the author can edit this post");
+    }
+
+    let title = if post.is_thread() {
+        Some(form.title.as_deref().unwrap_or_default().trim())
+    } else {
+        None
+    };
+    let body = form.body.trim();
+    if let Err(error) = validate_post(title, body) {
+        return edit_response(
+            &tmpl,
+            &session,
+            &nonce,
+            &group,
+            &post,
+            title,
+            body,
+            Some(&error),
+        )
+        .await;
+    }
+
+    // Saving without changes does not add a revision
+    if title == post.title.as_deref() && body == post.body {
+        return HttpResponse::SeeOther()
+            .append_header((
+                "Location",
+                format!("{}#post-{}", thread_url(group.id, post.thread()), post.id),
+            ))
+            .finish();
+    }
+
+    match posts.edit(post.id, title, body, &audit).await {
+        Ok(post) => HttpResponse::SeeOther()
+            .append_header((
+                "Location",
+                format!("{}#post-{}", thread_url(group.id, post.thread()), post.id),
+            ))
+            .finish(),
+        Err(PostError::NotFound | PostError::Deleted) => {
+            HttpResponse::NotFound().body("Post not found")
+        }
+        Err(e) => database_error(e),
+    }
+}
+
+// Soft delete a post; authors delete their own, owners and admins any post
+pub async fn delete_post(
+    path: web::Path<(i32, i32)>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group_id, post_id) = path.into_inner();
+    let (group, viewer) = match readable_group(&session, &groups, &members, &users, group_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let post = match find_post(&posts, &group, post_id).await {
+        Ok(entry) => entry.post,
+        Err(response) => return response,
+    };
+    if !can_delete(&viewer, &post) {
+        return HttpResponse::Forbidden().body("You cannot delete this post");
+    }
+
+    match posts.delete(post.id, &audit).await {
+        Ok(post) if post.is_thread() => redirect_with_notice(
+            &format!("/groups/{}/discussions", group.id),
+            "Thread deleted",
+        ),
+        Ok(post) => redirect_with_notice(&thread_url(group.id, post.thread()), "Reply deleted"),
+        Err(PostError::NotFound | PostError::Deleted) => {
+            HttpResponse::NotFound().body("Post not found")
+        }
+        Err(e) => database_error(e),
+    }
+}
+
+// Earlier versions of an edited post
+#[allow(clippy::too_many_arguments)]
+pub async fn post_history(
+    path: web::Path<(i32, i32)>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    posts: web::Data<PostService>,
+) -> HttpResponse {
+    let (group_id, post_id) = path.into_inner();
+    let (group, viewer) = match readable_group(&session, &groups, &members, &users, group_id).await
+    {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let entry = match find_post(&posts, &group, post_id).await {
+        Ok(entry) => entry,
+        Err(response) => return response,
+    };
+    let revisions = match posts.revisions(entry.post.id).await {
+        Ok(revisions) => revisions,
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert("thread_url", &thread_url(group.id, entry.post.thread()));
+    context.insert("post", &post_view(&viewer, entry));
+    context.insert("revisions", &revisions);
+
+    render(&tmpl, "discussion_history.html", &context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_post() {
+        assert!(validate_post(Some("Hello"), "First post").is_ok());
+        assert!(validate_post(None, "A reply").is_ok());
+        assert!(validate_post(Some(""), "Body").is_err());
+        assert!(validate_post(None, "").is_err());
+        assert!(validate_post(Some(&"x".repeat(MAX_TITLE_LENGTH + 1)), "Body").is_err());
+        assert!(validate_post(None, &"é".repeat(MAX_BODY_LENGTH)).is_ok());
+        assert!(validate_post(None, &"é".repeat(MAX_BODY_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,983 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::groups_api::{GroupViewer, readable_group};
+use crate::api::notifications::{notify, notify_with_email};
+use crate::config::ServerConfig;
+use crate::db::{
+    audit::AuditContext,
+    event::{
+        EventService, MAX_DESCRIPTION_LENGTH, MAX_LOCATION_LENGTH, MAX_MEETING_URL_LENGTH,
+        MAX_TITLE_LENGTH, NewEvent,
+    },
+    event_comment::{
+        EventCommentEntry, EventCommentError, EventCommentService, MAX_COMMENT_LENGTH, Moderation,
+        mentioned_handles,
+    },
+    geo::{GeoError, GeoService},
+    group::GroupService,
+    job::JobService,
+    member::MemberService,
+    models::{Event, EventComment, Group, NotificationKind, Recurrence, RsvpStatus},
+    notification::{NewNotification, NotificationService},
+    rsvp::RsvpService,
+    user::UserService,
+    venue::VenueService,
+};
+use crate::geo::Point;
+use crate::mail::Email;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+// Past events listed on a group's events page
+const PAST_EVENTS: i64 = 20;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    pub notice: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EventForm {
+    pub title: String,
+    pub description: String,
+    // `datetime-local` inputs, in UTC
+    pub starts_at: String,
+    pub ends_at: Option<String>,
+    pub location: Option<String>,
+    // City or coordinates placing the event on the map
+    pub place: Option<String>,
+    // One of the group's venues, empty for none
+    pub venue_id: Option<String>,
+    // Link to the online meeting, for online events
+    pub meeting_url: Option<String>,
+    // How the event repeats, empty when it is held once
+    pub recurrence: Option<String>,
+    // `date` input: the last day an occurrence may start, empty for no end
+    pub recurs_until: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RsvpForm {
+    pub status: RsvpStatus,
+}
+
+#[derive(Deserialize)]
+pub struct CommentForm {
+    // The question being answered
+    pub reply_to: Option<i32>,
+    pub body: String,
+}
+
+// An absolute http(s) link
+fn valid_meeting_url(url: &str) -> bool {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    rest.is_some_and(|rest| !rest.is_empty() && !rest.chars().any(char::is_whitespace))
+}
+
+// Parse the value of a `datetime-local` input
+fn parse_datetime_local(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .map(|naive| naive.and_utc())
+}
+
+fn validate_event(form: &EventForm) -> Result<NewEvent, String> {
+    let title = form.title.trim();
+    if title.is_empty() {
+        return Err("Title cannot be empty".to_string());
+    }
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(format!(
+            "Title is limited to {} characters",
+            MAX_TITLE_LENGTH
+        ));
+    }
+    let description = form.description.trim();
+    if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        return Err(format!(
+            "Description is limited to {} characters",
+            MAX_DESCRIPTION_LENGTH
+        ));
+    }
+    let location = form
+        .location
+        .as_deref()
+        .map(str::trim)
+        .filter(|l| !l.is_empty());
+    if location.is_some_and(|l| l.chars().count() > MAX_LOCATION_LENGTH) {
+        return Err(format!(
+            "Location is limited to {} characters",
+            MAX_LOCATION_LENGTH
+        ));
+    }
+
+    let meeting_url = form
+        .meeting_url
+        .as_deref()
+        .map(str::trim)
+        .filter(|url| !url.is_empty());
+    if let Some(url) = meeting_url {
+        if url.chars().count() > MAX_MEETING_URL_LENGTH {
+            return Err(format!(
+                "Meeting links are limited to {} characters",
+                MAX_MEETING_URL_LENGTH
+            ));
+        }
+        if !valid_meeting_url(url) {
+            return Err("The meeting link must start with https:// or http://".to_string());
+        }
+    }
+    let venue_id = match form.venue_id.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(id) => Some(id.parse::<i32>().map_err(|_| "Unknown venue")?),
+    };
+
+    let starts_at = parse_datetime_local(&form.starts_at).ok_or("Invalid start time")?;
+    let ends_at = match form.ends_at.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(value) => Some(parse_datetime_local(value).ok_or("Invalid end time")?),
+    };
+    if ends_at.is_some_and(|ends_at| ends_at < starts_at) {
+        return Err("The event cannot end before it starts".to_string());
+    }
+
+    let recurrence = match form.recurrence.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(value) => Some(
+            Recurrence::ALL
+                .into_iter()
+                .find(|recurrence| recurrence.as_str() == value)
+                .ok_or("Unknown repetition")?,
+        ),
+    };
+    let recurs_until = match form.recurs_until.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(value) => {
+            let day =
+                NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| "Invalid end date")?;
+            Some(
+                day.and_hms_opt(23, 59, 59)
+                    .ok_or("Invalid end date")?
+                    .and_utc(),
+            )
+        }
+    };
+    if recurs_until.is_some() && recurrence.is_none() {
+        return Err("Choose how often the event repeats".to_string());
+    }
+    if recurs_until.is_some_and(|until| until < starts_at) {
+        return Err("The event cannot stop repeating before it starts".to_string());
+    }
+
+    Ok(NewEvent {
+        title: title.to_string(),
+        description: description.to_string(),
+        starts_at,
+        ends_at,
+        location: location.map(String::from),
+        point: None,
+        venue_id,
+        meeting_url: meeting_url.map(String::from),
+        recurrence,
+        recurs_until,
+        // The creator's, see create_event
+        time_zone: Tz::UTC,
+    })
+}
+
+// One of the ways an event can repeat, for the form
+#[derive(Serialize)]
+struct RecurrenceChoice {
+    value: &'static str,
+    label: &'static str,
+}
+
+fn recurrence_choices() -> Vec<RecurrenceChoice> {
+    Recurrence::ALL
+        .iter()
+        .map(|recurrence| RecurrenceChoice {
+            value: recurrence.as_str(),
+            label: recurrence.label(),
+        })
+        .collect()
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+fn event_url(event_id: i32) -> String {
+    format!("/events/{}", event_id)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn events_response(
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    group: &Group,
+    viewer: &GroupViewer,
+    events: &EventService,
+    venues: &VenueService,
+    notice: Option<&str>,
+    form: Option<(&EventForm, &str)>,
+) -> HttpResponse {
+    let now = Utc::now();
+    let (upcoming, past) = match (
+        events.list_upcoming(group.id, now).await,
+        events.list_past(group.id, now, PAST_EVENTS).await,
+    ) {
+        (Ok(upcoming), Ok(past)) => (upcoming, past),
+        (Err(e), _) | (_, Err(e)) => return database_error(e),
+    };
+    // For the new event form
+    let group_venues = if viewer.can_moderate {
+        match venues.list_for_group(group.id).await {
+            Ok(group_venues) => group_venues,
+            Err(e) => return database_error(e),
+        }
+    } else {
+        Vec::new()
+    };
+
+    let mut context = create_template_context(session, nonce);
+    context.insert("group", group);
+    context.insert("upcoming", &upcoming);
+    context.insert("past", &past);
+    context.insert("can_create", &viewer.can_moderate);
+    context.insert("venues", &group_venues);
+    context.insert("recurrences", &recurrence_choices());
+    context.insert("notice", &notice);
+    context.insert("form", &form.map(|(f, _)| f));
+    context.insert("error", &form.map(|(_, error)| error));
+
+    render(tmpl, "group_events.html", &context)
+}
+
+// Upcoming and past events of a group; organizers can add one
+#[allow(clippy::too_many_arguments)]
+pub async fn events_page(
+    path: web::Path<i32>,
+    query: web::Query<EventsQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    venues: web::Data<VenueService>,
+) -> HttpResponse {
+    let (group, viewer) =
+        match readable_group(&session, &groups, &members, &users, path.into_inner()).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+
+    events_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &group,
+        &viewer,
+        &events,
+        &venues,
+        query.notice.as_deref(),
+        None,
+    )
+    .await
+}
+
+// Why an event cannot be created: a message for the form, or a response
+// for errors that are not the user's
+enum FormError {
+    Invalid(String),
+    Response(HttpResponse),
+}
+
+// Where the event takes place: at one of the group's venues, whose location
+// and coordinates it gets, and/or at a typed place
+async fn place_event(
+    mut event: NewEvent,
+    group_id: i32,
+    place: &str,
+    venues: &VenueService,
+    geo: &GeoService,
+) -> Result<NewEvent, FormError> {
+    if let Some(venue_id) = event.venue_id {
+        match venues.get(venue_id).await {
+            Ok(Some(venue)) if venue.group_id == group_id => {
+                event.location = Some(venue.label());
+                event.point = venue.point();
+            }
+            Ok(_) => return Err(FormError::Invalid("Unknown venue".to_string())),
+            Err(e) => return Err(FormError::Response(database_error(e))),
+        }
+    }
+
+    let place = place.trim();
+    if !place.is_empty() {
+        match geo.locate(place).await {
+            Ok((_, point)) => event.point = Some(point),
+            Err(e @ (GeoError::InvalidPlace(_) | GeoError::UnknownCity(_))) => {
+                return Err(FormError::Invalid(e.to_string()));
+            }
+            Err(e) => return Err(FormError::Response(database_error(e))),
+        }
+    }
+
+    Ok(event)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_event(
+    path: web::Path<i32>,
+    form: web::Form<EventForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    venues: web::Data<VenueService>,
+    geo: web::Data<GeoService>,
+) -> HttpResponse {
+    let (group, viewer) =
+        match readable_group(&session, &groups, &members, &users, path.into_inner()).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    if !viewer.can_moderate {
+        return HttpResponse::Forbidden()
+            .body("Only the group owner or an administrator can create events");
+    }
+
+    let located = match validate_event(&form) {
+        Ok(event) => {
+            let place = form.place.as_deref().unwrap_or_default();
+            match place_event(event, group.id, place, &venues, &geo).await {
+                Ok(event) => Ok(event),
+                Err(FormError::Invalid(error)) => Err(error),
+                Err(FormError::Response(response)) => return response,
+            }
+        }
+        Err(error) => Err(error),
+    };
+    let mut event = match located {
+        Ok(event) => event,
+        Err(error) => {
+            return events_response(
+                &tmpl,
+                &session,
+                &nonce,
+                &group,
+                &viewer,
+                &events,
+                &venues,
+                None,
+                Some((&form, &error)),
+            )
+            .await;
+        }
+    };
+
+    // Series keep the local time of day of whoever scheduled them
+    if let Some(user_id) = viewer.user_id {
+        if let Ok(Some(user)) = users.get_by_id(user_id).await {
+            event.time_zone = user.tz();
+        }
+    }
+
+    match events.create(group.id, event, &audit).await {
+        Ok(event) => HttpResponse::SeeOther()
+            .append_header(("Location", event_url(event.id)))
+            .finish(),
+        Err(e) => database_error(e),
+    }
+}
+
+// A comment as shown on the event page
+#[derive(Serialize)]
+struct CommentView {
+    #[serde(flatten)]
+    comment: EventComment,
+    author_name: String,
+    can_delete: bool,
+    // Answers, for questions
+    answers: Vec<CommentView>,
+    // The question, for pinned answers
+    question: Option<Box<CommentView>>,
+}
+
+// What the viewer gets to see of a comment: hidden comments are for
+// moderators only, deleted ones leave a placeholder
+fn comment_view(viewer: &GroupViewer, entry: &EventCommentEntry) -> Option<CommentView> {
+    let comment = &entry.comment;
+    if comment.hidden_at.is_some() && !viewer.can_moderate {
+        return None;
+    }
+
+    let mut comment = comment.clone();
+    if comment.deleted_at.is_some() {
+        comment.body.clear();
+    }
+    Some(CommentView {
+        can_delete: comment.deleted_at.is_none()
+            && (viewer.is_author(comment.author_id) || viewer.can_moderate),
+        comment,
+        author_name: entry
+            .author_name
+            .clone()
+            .unwrap_or_else(|| "Deleted user".to_string()),
+        answers: Vec::new(),
+        question: None,
+    })
+}
+
+// Arrange the comments of an event into pinned answers and questions with
+// their answers
+fn arrange_comments(
+    viewer: &GroupViewer,
+    entries: &[EventCommentEntry],
+) -> (Vec<CommentView>, Vec<CommentView>) {
+    let mut pinned: Vec<CommentView> = entries
+        .iter()
+        .filter(|e| e.comment.pinned_at.is_some() && e.comment.deleted_at.is_none())
+        .filter_map(|entry| {
+            let mut view = comment_view(viewer, entry)?;
+            if let Some(question_id) = entry.comment.reply_to {
+                view.question = entries
+                    .iter()
+                    .find(|e| e.comment.id == question_id && e.comment.deleted_at.is_none())
+                    .and_then(|question| comment_view(viewer, question))
+                    .map(Box::new);
+            }
+            Some(view)
+        })
+        .collect();
+    pinned.sort_by_key(|view| view.comment.pinned_at);
+
+    let threads = entries
+        .iter()
+        .filter(|e| e.comment.reply_to.is_none())
+        .filter_map(|entry| {
+            let mut view = comment_view(viewer, entry)?;
+            view.answers = entries
+                .iter()
+                .filter(|e| e.comment.reply_to == Some(entry.comment.id))
+                .filter_map(|answer| comment_view(viewer, answer))
+                .filter(|answer| answer.comment.deleted_at.is_none())
+                .collect();
+            // Deleted comments only stay to keep their answers in context
+            if view.comment.deleted_at.is_some() && view.answers.is_empty() {
+                return None;
+            }
+            Some(view)
+        })
+        .collect();
+
+    (pinned, threads)
+}
+
+// The meeting link of an online event, for members going and organizers
+fn meeting_url_for<'a>(
+    event: &'a Event,
+    viewer: &GroupViewer,
+    rsvp: Option<&RsvpStatus>,
+) -> Option<&'a str> {
+    let confirmed = viewer.can_moderate || rsvp == Some(&RsvpStatus::Going);
+    event.meeting_url.as_deref().filter(|_| confirmed)
+}
+
+pub async fn find_event(events: &EventService, id: i32) -> Result<Event, HttpResponse> {
+    match events.get(id).await {
+        Ok(Some(event)) => Ok(event),
+        Ok(None) => Err(HttpResponse::NotFound().body("Event not found")),
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+// An event with its comments and questions
+#[allow(clippy::too_many_arguments)]
+pub async fn event_page(
+    path: web::Path<i32>,
+    query: web::Query<EventsQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    comments: web::Data<EventCommentService>,
+    rsvps: web::Data<RsvpService>,
+    venues: web::Data<VenueService>,
+) -> HttpResponse {
+    let event = match find_event(&events, path.into_inner()).await {
+        Ok(event) => event,
+        Err(response) => return response,
+    };
+    let (group, viewer) =
+        match readable_group(&session, &groups, &members, &users, event.group_id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let entries = match comments.list_for_event(event.id).await {
+        Ok(entries) => entries,
+        Err(e) => return database_error(e),
+    };
+    let counts = match rsvps.counts(event.id).await {
+        Ok(counts) => counts,
+        Err(e) => return database_error(e),
+    };
+    let rsvp = match viewer.user_id {
+        Some(user_id) => match rsvps.get(event.id, user_id).await {
+            Ok(rsvp) => rsvp,
+            Err(e) => return database_error(e),
+        },
+        None => None,
+    };
+    let venue = match event.venue_id {
+        Some(venue_id) => match venues.get(venue_id).await {
+            Ok(venue) => venue,
+            Err(e) => return database_error(e),
+        },
+        None => None,
+    };
+    let rsvp = rsvp.map(|rsvp| rsvp.status);
+    let (pinned, threads) = arrange_comments(&viewer, &entries);
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert("event", &event);
+    context.insert(
+        "map_url",
+        &Point::from_columns(event.latitude, event.longitude).map(|point| point.map_url()),
+    );
+    context.insert("pinned", &pinned);
+    context.insert("comments", &threads);
+    context.insert("can_comment", &viewer.can_post());
+    context.insert("can_moderate", &viewer.can_moderate);
+    context.insert("rsvp_counts", &counts);
+    context.insert("rsvp", &rsvp);
+    context.insert("venue", &venue);
+    context.insert("recurrence", &event.recurrence.map(|r| r.label()));
+    context.insert("is_online", &event.is_online());
+    context.insert(
+        "meeting_url",
+        &meeting_url_for(&event, &viewer, rsvp.as_ref()),
+    );
+    context.insert("notice", &query.notice);
+
+    render(&tmpl, "event.html", &context)
+}
+
+// Answer whether the member is going, and let the organizer know
+#[allow(clippy::too_many_arguments)]
+pub async fn rsvp(
+    path: web::Path<i32>,
+    form: web::Form<RsvpForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    rsvps: web::Data<RsvpService>,
+    notifications: web::Data<NotificationService>,
+    jobs: web::Data<JobService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let event = match find_event(&events, path.into_inner()).await {
+        Ok(event) => event,
+        Err(response) => return response,
+    };
+    let (_, viewer) =
+        match readable_group(&session, &groups, &members, &users, event.group_id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let Some(user_id) = viewer.user_id.filter(|_| viewer.can_post()) else {
+        return HttpResponse::Forbidden().body("Only members can RSVP to this event");
+    };
+
+    let changed = match rsvps.set(event.id, user_id, form.status, &audit).await {
+        Ok((_, changed)) => changed,
+        Err(e) => return database_error(e),
+    };
+
+    if let Some(organizer) = event.created_by.filter(|_| changed) {
+        let name = display_name(&users, user_id).await;
+        let answer = match form.status {
+            RsvpStatus::Going => "is going to",
+            RsvpStatus::Maybe => "might go to",
+            RsvpStatus::NotGoing => "is not going to",
+        };
+        let notification = NewNotification {
+            kind: NotificationKind::Rsvp,
+            actor_id: Some(user_id),
+            message: format!("{} {} {}", name, answer, event.title),
+            url: event_url(event.id),
+        };
+        notify_with_email(
+            &notifications,
+            &jobs,
+            &tmpl,
+            &server,
+            &[organizer],
+            &notification,
+        )
+        .await;
+    }
+
+    redirect_with_notice(&event_url(event.id), "Your RSVP was saved")
+}
+
+async fn display_name(users: &UserService, user_id: i32) -> String {
+    users
+        .get_by_id(user_id)
+        .await
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+        .unwrap_or_default()
+}
+
+// Email a member mentioned in a comment
+fn mention_email(
+    tmpl: &Tera,
+    to: &str,
+    author: &str,
+    event: &Event,
+    comment: &EventComment,
+    url: &str,
+) -> Result<Email, tera::Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("author", author);
+    ctx.insert("event_title", &event.title);
+    ctx.insert("body", &comment.body);
+    ctx.insert("url", url);
+
+    Ok(Email {
+        to: to.to_string(),
+        subject: format!("{} mentioned you on {}", author, event.title),
+        text: tmpl.render("emails/event_mention.txt", &ctx)?,
+        html: None,
+        unsubscribe_url: None,
+    })
+}
+
+// Comment on an event, or answer a question, and notify mentioned members
+// and the author of the question
+#[allow(clippy::too_many_arguments)]
+pub async fn create_comment(
+    path: web::Path<i32>,
+    form: web::Form<CommentForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    comments: web::Data<EventCommentService>,
+    notifications: web::Data<NotificationService>,
+    jobs: web::Data<JobService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let event = match find_event(&events, path.into_inner()).await {
+        Ok(event) => event,
+        Err(response) => return response,
+    };
+    let (group, viewer) =
+        match readable_group(&session, &groups, &members, &users, event.group_id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let Some(user_id) = viewer.user_id.filter(|_| viewer.can_post()) else {
+        return HttpResponse::Forbidden().body("Only members can comment on this event");
+    };
+
+    let body = form.body.trim();
+    if body.is_empty() {
+        return redirect_with_notice(&event_url(event.id), "Comment cannot be empty");
+    }
+    if body.chars().count() > MAX_COMMENT_LENGTH {
+        return redirect_with_notice(
+            &event_url(event.id),
+            &format!("Comments are limited to {} characters", MAX_COMMENT_LENGTH),
+        );
+    }
+
+    let comment = match comments
+        .create(event.id, user_id, form.reply_to, body, &audit)
+        .await
+    {
+        Ok(comment) => comment,
+        Err(e @ (EventCommentError::NotFound | EventCommentError::Deleted)) => {
+            return redirect_with_notice(&event_url(event.id), &e.to_string());
+        }
+        Err(e) => return database_error(e),
+    };
+
+    // The comment stands even if notifications cannot be delivered
+    let author = display_name(&users, user_id).await;
+    let mentioned: Vec<i32> = comments
+        .mentioned_members(group.id, &mentioned_handles(body))
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|user| user.id)
+        .collect();
+    let link = format!("{}#comment-{}", event_url(event.id), comment.id);
+    let url = server.url(&link);
+    let mention = NewNotification {
+        kind: NotificationKind::Mention,
+        actor_id: Some(user_id),
+        message: format!("{} mentioned you on {}", author, event.title),
+        url: link.clone(),
+    };
+    notify(&notifications, &jobs, &mentioned, &mention, |user| {
+        mention_email(&tmpl, &user.email, &author, &event, &comment, &url)
+    })
+    .await;
+
+    // Mentioned authors already heard about the answer
+    let question_author = match comment.reply_to {
+        Some(question_id) => comments
+            .get(event.id, question_id)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|question| question.author_id)
+            .filter(|author_id| !mentioned.contains(author_id)),
+        None => None,
+    };
+    if let Some(question_author) = question_author {
+        let reply = NewNotification {
+            kind: NotificationKind::Reply,
+            actor_id: Some(user_id),
+            message: format!("{} answered your question on {}", author, event.title),
+            url: link,
+        };
+        notify_with_email(
+            &notifications,
+            &jobs,
+            &tmpl,
+            &server,
+            &[question_author],
+            &reply,
+        )
+        .await;
+    }
+
+    HttpResponse::SeeOther()
+        .append_header((
+            "Location",
+            format!("{}#comment-{}", event_url(event.id), comment.id),
+        ))
+        .finish()
+}
+
+// Pin, hide or delete a comment. Authors can delete their own comments,
+// everything else is for the group's owner and administrators.
+#[allow(clippy::too_many_arguments)]
+pub async fn moderate_comment(
+    path: web::Path<(i32, i32, String)>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    comments: web::Data<EventCommentService>,
+) -> HttpResponse {
+    let (event_id, comment_id, action) = path.into_inner();
+    let Some(action) = Moderation::parse(&action) else {
+        return HttpResponse::NotFound().body("Unknown action");
+    };
+    let event = match find_event(&events, event_id).await {
+        Ok(event) => event,
+        Err(response) => return response,
+    };
+    let (_, viewer) =
+        match readable_group(&session, &groups, &members, &users, event.group_id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let comment = match comments.get(event.id, comment_id).await {
+        Ok(Some(comment)) => comment,
+        Ok(None) => return HttpResponse::NotFound().body("Comment not found"),
+        Err(e) => return database_error(e),
+    };
+
+    let allowed = viewer.can_moderate
+        || (action == Moderation::Delete && viewer.is_author(comment.author_id));
+    if !allowed {
+        return HttpResponse::Forbidden().body("You cannot moderate this comment");
+    }
+
+    let notice = match action {
+        Moderation::Pin => "Comment pinned",
+        Moderation::Unpin => "Comment unpinned",
+        Moderation::Hide => "Comment hidden",
+        Moderation::Unhide => "Comment visible again",
+        Moderation::Delete => "Comment deleted",
+    };
+    match comments
+        .moderate(event.id, comment.id, action, &audit)
+        .await
+    {
+        Ok(_) => redirect_with_notice(&event_url(event.id), notice),
+        Err(e @ (EventCommentError::NotFound | EventCommentError::Deleted)) => {
+            redirect_with_notice(&event_url(event.id), &e.to_string())
+        }
+        Err(e) => database_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(starts_at: &str, ends_at: Option<&str>) -> EventForm {
+        EventForm {
+            title: " Meetup ".to_string(),
+            description: String::new(),
+            starts_at: starts_at.to_string(),
+            ends_at: ends_at.map(String::from),
+            location: Some("  ".to_string()),
+            place: None,
+            venue_id: Some(String::new()),
+            meeting_url: None,
+            recurrence: None,
+            recurs_until: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_event() {
+        let event = validate_event(&form("2026-03-01T18:30", Some(""))).unwrap();
+        assert_eq!(event.title, "Meetup");
+        assert_eq!(event.starts_at.to_rfc3339(), "2026-03-01T18:30:00+00:00");
+        assert_eq!(event.ends_at, None);
+        assert_eq!(event.location, None);
+
+        assert_eq!(event.venue_id, None);
+        assert_eq!(event.meeting_url, None);
+
+        let online = validate_event(&EventForm {
+            venue_id: Some("7".to_string()),
+            meeting_url: Some(" https://meet.example.com/abc ".to_string()),
+            ..form("2026-03-01T18:30", None)
+        })
+        .unwrap();
+        assert_eq!(online.venue_id, Some(7));
+        assert_eq!(
+            online.meeting_url.as_deref(),
+            Some("https://meet.example.com/abc")
+        );
+
+        let weekly = validate_event(&EventForm {
+            recurrence: Some("weekly".to_string()),
+            recurs_until: Some("2026-06-30".to_string()),
+            ..form("2026-03-01T18:30", None)
+        })
+        .unwrap();
+        assert_eq!(weekly.recurrence, Some(Recurrence::Weekly));
+        assert_eq!(
+            weekly
+                .recurs_until
+                .map(|until| until.to_rfc3339())
+                .as_deref(),
+            Some("2026-06-30T23:59:59+00:00")
+        );
+        for (recurrence, recurs_until) in
+            [("daily", ""), ("", "2026-06-30"), ("monthly", "2026-02-01")]
+        {
+            assert!(
+                validate_event(&EventForm {
+                    recurrence: Some(recurrence.to_string()),
+                    recurs_until: Some(recurs_until.to_string()),
+                    ..form("2026-03-01T18:30", None)
+                })
+                .is_err()
+            );
+        }
+
+        assert!(validate_event(&form("tomorrow", None)).is_err());
+        assert!(validate_event(&form("2026-03-01T18:30", Some("2026-03-01T17:00"))).is_err());
+        assert!(
+            validate_event(&EventForm {
+                meeting_url: Some("javascript:alert(1)".to_string()),
+                ..form("2026-03-01T18:30", None)
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_meeting_url_for() {
+        let now = Utc::now();
+        let event = Event {
+            id: 1,
+            group_id: 1,
+            title: "Online meetup".to_string(),
+            starts_at: now,
+            created_at: now,
+            meeting_url: Some("https://meet.example.com/abc".to_string()),
+            ..Default::default()
+        };
+        let viewer = |is_member, can_moderate| GroupViewer {
+            user_id: Some(2),
+            is_member,
+            can_moderate,
+        };
+
+        let member = viewer(true, false);
+        assert_eq!(meeting_url_for(&event, &member, None), None);
+        assert_eq!(
+            meeting_url_for(&event, &member, Some(&RsvpStatus::Maybe)),
+            None
+        );
+        assert_eq!(
+            meeting_url_for(&event, &member, Some(&RsvpStatus::Going)),
+            Some("https://meet.example.com/abc")
+        );
+        assert!(meeting_url_for(&event, &viewer(false, true), None).is_some());
+        // Never part of what is serialized
+        assert!(
+            !serde_json::to_string(&event)
+                .unwrap()
+                .contains("meet.example.com")
+        );
+    }
+
+    #[test]
+    fn test_valid_meeting_url() {
+        assert!(valid_meeting_url("https://meet.example.com/abc-def"));
+        assert!(valid_meeting_url("http://example.com"));
+        assert!(!valid_meeting_url("https://"));
+        assert!(!valid_meeting_url("ftp://example.com"));
+        assert!(!valid_meeting_url("https://example.com/a b"));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::api::context::redirect_with_notice;
+use crate::api::group_settings::settings_url;
+use crate::api::groups_api::managed_group;
+use crate::db::{
+    audit::AuditContext,
+    geo::{GeoError, GeoService},
+    group::GroupService,
+    user::UserService,
+};
+use crate::geo::Point;
+use actix_session::Session;
+use actix_web::{HttpResponse, Responder, get, web};
+use chrono::Utc;
+use serde::Deserialize;
+
+pub const DEFAULT_RADIUS_KM: f64 = 25.0;
+pub const MAX_RADIUS_KM: f64 = 500.0;
+const DEFAULT_SUGGESTIONS: i64 = 10;
+const MAX_SUGGESTIONS: i64 = 50;
+const DEFAULT_NEARBY_EVENTS: i64 = 50;
+const MAX_NEARBY_EVENTS: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct CityQuery {
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct NearbyQuery {
+    // "lat,lng" or a city
+    pub near: String,
+    pub radius_km: Option<f64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct LocationForm {
+    // City or coordinates, empty to clear
+    pub location: String,
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+// The search radius asked for, or the default
+pub fn radius_km(radius_km: Option<f64>) -> Result<f64, String> {
+    match radius_km {
+        None => Ok(DEFAULT_RADIUS_KM),
+        Some(radius) if radius > 0.0 && radius <= MAX_RADIUS_KM => Ok(radius),
+        Some(_) => Err(format!(
+            "radius_km must be more than 0 and at most {}",
+            MAX_RADIUS_KM
+        )),
+    }
+}
+
+// Coordinates of a typed place, or the response explaining why there are none
+pub async fn locate_or_reject(
+    geo: &GeoService,
+    place: &str,
+) -> Result<(String, Point), HttpResponse> {
+    match geo.locate(place).await {
+        Ok(located) => Ok(located),
+        Err(e @ (GeoError::InvalidPlace(_) | GeoError::UnknownCity(_))) => {
+            Err(HttpResponse::BadRequest().body(e.to_string()))
+        }
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+// Cities starting with `q`, the most populous first, for autocompletion
+#[get("/cities")]
+pub async fn search_cities(
+    query: web::Query<CityQuery>,
+    geo: web::Data<GeoService>,
+) -> impl Responder {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SUGGESTIONS)
+        .clamp(1, MAX_SUGGESTIONS);
+    match geo
+        .search_cities(query.q.as_deref().unwrap_or_default(), limit)
+        .await
+    {
+        Ok(cities) => HttpResponse::Ok().json(cities),
+        Err(e) => database_error(e),
+    }
+}
+
+// Upcoming events of open groups near a place, the closest first
+#[get("/events")]
+pub async fn nearby_events(
+    query: web::Query<NearbyQuery>,
+    geo: web::Data<GeoService>,
+) -> impl Responder {
+    let radius = match radius_km(query.radius_km) {
+        Ok(radius) => radius,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let (_, point) = match locate_or_reject(&geo, &query.near).await {
+        Ok(located) => located,
+        Err(response) => return response,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_NEARBY_EVENTS)
+        .clamp(1, MAX_NEARBY_EVENTS);
+
+    match geo.events_near(point, radius, Utc::now(), limit).await {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(e) => database_error(e),
+    }
+}
+
+pub fn configure_geo_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(search_cities).service(nearby_events);
+}
+
+// Set where a group meets, for its owner and admins
+pub async fn update_group_location(
+    path: web::Path<i32>,
+    form: web::Form<LocationForm>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    geo: web::Data<GeoService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let back = settings_url(group.id);
+
+    let input = form.location.trim();
+    let located = if input.is_empty() {
+        None
+    } else {
+        match geo.locate(input).await {
+            Ok(located) => Some(located),
+            Err(e @ (GeoError::InvalidPlace(_) | GeoError::UnknownCity(_))) => {
+                return redirect_with_notice(&back, &e.to_string());
+            }
+            Err(e) => return database_error(e),
+        }
+    };
+
+    let location = located
+        .as_ref()
+        .map(|(name, point)| (name.as_str(), *point));
+    match groups.set_location(group.id, location, &audit).await {
+        Ok(group) => match group.location {
+            Some(location) => redirect_with_notice(&back, &format!("Location set to {}", location)),
+            None => redirect_with_notice(&back, "Location removed"),
+        },
+        Err(e) => database_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_radius_km() {
+        assert_eq!(radius_km(None), Ok(DEFAULT_RADIUS_KM));
+        assert_eq!(radius_km(Some(10.5)), Ok(10.5));
+        assert!(radius_km(Some(0.0)).is_err());
+        assert!(radius_km(Some(-5.0)).is_err());
+        assert!(radius_km(Some(MAX_RADIUS_KM + 1.0)).is_err());
+        assert!(radius_km(Some(f64::NAN)).is_err());
+    }
+}
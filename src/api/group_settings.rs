@@ -0,0 +1,74 @@
+use crate::api::context::create_template_context;
+use crate::api::groups_api::managed_group;
+use crate::api::uploads::media_url;
+use crate::db::{
+    group::GroupService,
+    tag::{MAX_TAGS_PER_GROUP, TagService},
+    user::UserService,
+};
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use serde::Deserialize;
+use tera::Tera;
+
+#[derive(Deserialize)]
+pub struct SettingsQuery {
+    pub notice: Option<String>,
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+// Where group owners and admins change how the group is presented
+pub fn settings_url(group_id: i32) -> String {
+    format!("/groups/{}/settings", group_id)
+}
+
+// The group's tags, location and cover image
+#[allow(clippy::too_many_arguments)]
+pub async fn group_settings_page(
+    path: web::Path<i32>,
+    query: web::Query<SettingsQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    tags: web::Data<TagService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let group_tags = match tags.for_group(group.id).await {
+        Ok(group_tags) => group_tags,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert(
+        "cover_url",
+        &group.cover_key.as_ref().map(|key| media_url(key, "small")),
+    );
+    context.insert(
+        "tags",
+        &group_tags
+            .iter()
+            .map(|tag| tag.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    context.insert("max_tags", &MAX_TAGS_PER_GROUP);
+    context.insert("notice", &query.notice);
+
+    render(&tmpl, "group_settings.html", &context)
+}
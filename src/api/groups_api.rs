@@ -1,4 +1,15 @@
-use crate::db::group::GroupService;
+use crate::api::geo::{locate_or_reject, radius_km};
+use crate::db::{
+    audit::AuditContext,
+    geo::GeoService,
+    group::{GroupError, GroupService},
+    member::MemberService,
+    models::{Group, JoinPolicy},
+    tag::{TagService, slugify},
+    user::UserService,
+};
+use crate::middleware::{admin::is_site_admin, auth::current_user_id};
+use actix_session::Session;
 use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -16,6 +27,24 @@ pub struct SearchQuery {
     pub name: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct ListQuery {
+    // Comma separated tag slugs, groups must have all of them
+    pub tags: Option<String>,
+    // "lat,lng" or a city, to list the groups around it by distance
+    pub near: Option<String>,
+    pub radius_km: Option<f64>,
+}
+
+// A group found around a place
+#[derive(Serialize)]
+pub struct NearbyGroupResponse {
+    #[serde(flatten)]
+    pub group: GroupResponse,
+    pub location: Option<String>,
+    pub distance_km: f64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CreateGroupRequest {
     pub name: String,
@@ -54,6 +83,8 @@ pub async fn get_group(path: web::Path<i32>, service: web::Data<GroupService>) -
 #[post("/groups")]
 pub async fn create_group(
     group_data: web::Json<CreateGroupRequest>,
+    session: Session,
+    audit: AuditContext,
     service: web::Data<GroupService>,
 ) -> impl Responder {
     // Validate group name is not empty
@@ -61,7 +92,11 @@ pub async fn create_group(
         return HttpResponse::BadRequest().body("Group name cannot be empty");
     }
 
-    match service.create(group_data.name.clone()).await {
+    // The logged in user, if any, becomes the owner
+    match service
+        .create_with_owner(group_data.name.clone(), current_user_id(&session), &audit)
+        .await
+    {
         Ok(group) => {
             let response = GroupResponse {
                 id: group.id,
@@ -80,47 +115,41 @@ pub async fn create_group(
 pub async fn update_group(
     path: web::Path<i32>,
     group_data: web::Json<UpdateGroupRequest>,
+    session: Session,
+    audit: AuditContext,
     service: web::Data<GroupService>,
+    users: web::Data<UserService>,
 ) -> impl Responder {
+    let group_id = path.into_inner();
+
+    let mut group = match managed_group(&session, &service, &users, group_id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
     // Validate group name is not empty
     if group_data.name.trim().is_empty() {
         return HttpResponse::BadRequest().body("Group name cannot be empty");
     }
 
-    let group_id = path.into_inner();
-
-    // First check if the group exists and is not deleted
-    match service.get_by_id(group_id).await {
-        Ok(Some(mut group)) => {
-            if group.deleted_at.is_some() {
-                return HttpResponse::NotFound().body("Group not found");
-            }
+    // Update the group
+    group.name = group_data.name.clone();
 
-            // Update the group
-            group.name = group_data.name.clone();
-
-            match service.update(group).await {
-                Ok(_) => {
-                    // Get the updated group to return
-                    match service.get_by_id(group_id).await {
-                        Ok(Some(updated_group)) => {
-                            let response = GroupResponse {
-                                id: updated_group.id,
-                                name: updated_group.name,
-                                created_at: updated_group.created_at,
-                            };
-                            HttpResponse::Ok().json(response)
-                        }
-                        _ => HttpResponse::InternalServerError()
-                            .body("Failed to retrieve updated group"),
-                    }
-                }
-                Err(e) => {
-                    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+    match service.update(group, &audit).await {
+        Ok(_) => {
+            // Get the updated group to return
+            match service.get_by_id(group_id).await {
+                Ok(Some(updated_group)) => {
+                    let response = GroupResponse {
+                        id: updated_group.id,
+                        name: updated_group.name,
+                        created_at: updated_group.created_at,
+                    };
+                    HttpResponse::Ok().json(response)
                 }
+                _ => HttpResponse::InternalServerError().body("Failed to retrieve updated group"),
             }
         }
-        Ok(None) => HttpResponse::NotFound().body("Group not found"),
         Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
     }
 }
@@ -129,20 +158,162 @@ pub async fn update_group(
 #[delete("/groups/{id}")]
 pub async fn delete_group(
     path: web::Path<i32>,
+    session: Session,
+    audit: AuditContext,
+    service: web::Data<GroupService>,
+    users: web::Data<UserService>,
+) -> impl Responder {
+    let group_id = path.into_inner();
+
+    // Deleted groups are not found, so a group cannot be deleted twice
+    if let Err(response) = managed_group(&session, &service, &users, group_id).await {
+        return response;
+    }
+
+    match service.delete(group_id, &audit).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        // Deleted concurrently by someone else
+        Err(GroupError::GroupNotFound) => HttpResponse::NotFound().body("Group not found"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+// Owners and site administrators may manage a group
+pub async fn can_manage_group(users: &UserService, user_id: i32, group: &Group) -> bool {
+    group.owner_id == Some(user_id) || is_site_admin(users, user_id).await
+}
+
+// What the logged in user, if any, may do with a group's discussions, events
+// and other content
+pub struct GroupViewer {
+    pub user_id: Option<i32>,
+    pub is_member: bool,
+    // Owners and site admins moderate the group's content
+    pub can_moderate: bool,
+}
+
+impl GroupViewer {
+    pub async fn load(
+        session: &Session,
+        group: &Group,
+        members: &MemberService,
+        users: &UserService,
+    ) -> Self {
+        let user_id = current_user_id(session);
+        let (is_member, can_moderate) = match user_id {
+            Some(user_id) => (
+                members.is_member(group.id, user_id).await.unwrap_or(false),
+                can_manage_group(users, user_id, group).await,
+            ),
+            None => (false, false),
+        };
+
+        Self {
+            user_id,
+            is_member,
+            can_moderate,
+        }
+    }
+
+    // Content of open groups is public, that of other groups is for members
+    pub fn can_read(&self, group: &Group) -> bool {
+        group.join_policy == JoinPolicy::Open || self.can_post()
+    }
+
+    pub fn can_post(&self) -> bool {
+        self.is_member || self.can_moderate
+    }
+
+    // Whether the logged in user wrote something by `author_id`
+    pub fn is_author(&self, author_id: Option<i32>) -> bool {
+        self.user_id.is_some() && author_id == self.user_id
+    }
+}
+
+// The group and what the visitor may do, if they may read its content
+pub async fn readable_group(
+    session: &Session,
+    groups: &GroupService,
+    members: &MemberService,
+    users: &UserService,
+    group_id: i32,
+) -> Result<(Group, GroupViewer), HttpResponse> {
+    let group = match groups.get_by_id(group_id).await {
+        Ok(Some(group)) if group.deleted_at.is_none() => group,
+        Ok(_) => return Err(HttpResponse::NotFound().body("Group not found")),
+        Err(e) => {
+            return Err(HttpResponse::InternalServerError().body(format!("Database error: {}", e)));
+        }
+    };
+
+    let viewer = GroupViewer::load(session, &group, members, users).await;
+    if !viewer.can_read(&group) {
+        return Err(HttpResponse::Forbidden().body("Only members can see this group's content"));
+    }
+
+    Ok((group, viewer))
+}
+
+// The group, if it exists and the logged in user may manage it
+pub async fn managed_group(
+    session: &Session,
+    groups: &GroupService,
+    users: &UserService,
+    group_id: i32,
+) -> Result<Group, HttpResponse> {
+    let Some(user_id) = current_user_id(session) else {
+        return Err(HttpResponse::Unauthorized().body("Login required"));
+    };
+
+    match groups.get_by_id(group_id).await {
+        Ok(Some(group)) if group.deleted_at.is_none() => {
+            if can_manage_group(users, user_id, &group).await {
+                Ok(group)
+            } else {
+                Err(HttpResponse::Forbidden()
+                    .body("Only the group owner or an administrator can manage this group"))
+            }
+        }
+        Ok(_) => Err(HttpResponse::NotFound().body("Group not found")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("Database error: {}", e))),
+    }
+}
+
+// Restore a soft-deleted group from the trash
+#[post("/groups/{id}/restore")]
+pub async fn restore_group(
+    path: web::Path<i32>,
+    session: Session,
+    audit: AuditContext,
     service: web::Data<GroupService>,
+    users: web::Data<UserService>,
 ) -> impl Responder {
     let group_id = path.into_inner();
 
-    // First check if the group exists and is not already deleted
+    let Some(user_id) = current_user_id(&session) else {
+        return HttpResponse::Unauthorized().body("Login required");
+    };
+
     match service.get_by_id(group_id).await {
         Ok(Some(group)) => {
-            if group.deleted_at.is_some() {
-                return HttpResponse::NotFound().body("Group not found or already deleted");
+            if !can_manage_group(&users, user_id, &group).await {
+                return HttpResponse::Forbidden()
+                    .body("Only the group owner or an administrator can restore it");
+            }
+            if group.deleted_at.is_none() {
+                return HttpResponse::Conflict().body("Group is not deleted");
             }
 
-            // Now delete the group
-            match service.delete(group_id).await {
-                Ok(_) => HttpResponse::NoContent().finish(),
+            match service.restore(group_id, &audit).await {
+                Ok(_) => HttpResponse::Ok().json(GroupResponse {
+                    id: group.id,
+                    name: group.name,
+                    created_at: group.created_at,
+                }),
+                // Restored concurrently by someone else
+                Err(GroupError::GroupNotFound) => {
+                    HttpResponse::Conflict().body("Group is not deleted")
+                }
                 Err(e) => {
                     HttpResponse::InternalServerError().body(format!("Database error: {}", e))
                 }
@@ -153,10 +324,64 @@ pub async fn delete_group(
     }
 }
 
-// List all groups
+// List all groups, or those with every tag in `tags`. With `near`, only the
+// groups within `radius_km` of it, the closest first.
 #[get("/groups")]
-pub async fn list_groups(service: web::Data<GroupService>) -> impl Responder {
-    match service.list_active().await {
+pub async fn list_groups(
+    query: web::Query<ListQuery>,
+    service: web::Data<GroupService>,
+    tags: web::Data<TagService>,
+    geo: web::Data<GeoService>,
+) -> impl Responder {
+    let slugs: Vec<String> = query
+        .tags
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(slugify)
+        .filter(|slug| !slug.is_empty())
+        .collect();
+
+    if let Some(near) = &query.near {
+        let radius = match radius_km(query.radius_km) {
+            Ok(radius) => radius,
+            Err(message) => return HttpResponse::BadRequest().body(message),
+        };
+        let (_, point) = match locate_or_reject(&geo, near).await {
+            Ok(located) => located,
+            Err(response) => return response,
+        };
+
+        return match geo.groups_near(point, radius, &slugs).await {
+            Ok(found) => {
+                let responses: Vec<NearbyGroupResponse> = found
+                    .into_iter()
+                    .map(|nearby| NearbyGroupResponse {
+                        location: nearby.group.location,
+                        group: GroupResponse {
+                            id: nearby.group.id,
+                            name: nearby.group.name,
+                            created_at: nearby.group.created_at,
+                        },
+                        distance_km: nearby.distance_km,
+                    })
+                    .collect();
+
+                HttpResponse::Ok().json(responses)
+            }
+            Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+        };
+    }
+
+    let found = if slugs.is_empty() {
+        service.list_active().await.map_err(|e| e.to_string())
+    } else {
+        tags.groups_with_tags(&slugs)
+            .await
+            .map_err(|e| e.to_string())
+    };
+
+    match found {
         Ok(groups) => {
             let responses: Vec<GroupResponse> = groups
                 .into_iter()
@@ -228,12 +453,101 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
         .service(list_groups)
         .service(create_group)
         .service(delete_group)
+        .service(restore_group)
         .service(update_group);
 }
 
-// Tests disabled for now - need to be rewritten to use test database containers with PostgreSQL
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     TODO: Implement PostgreSQL integration tests using testcontainers
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use actix_session::{SessionMiddleware, storage::CookieSessionStore};
+    use actix_web::{App, cookie::Key, http::StatusCode, test};
+
+    #[actix_web::test]
+    async fn test_only_managers_can_change_a_group() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let [owner, other] = db.create_users(["Ada", "Bob"]).await;
+        let group = groups
+            .create_with_owner("Readers".to_string(), Some(owner.id), &ctx)
+            .await
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    SessionMiddleware::builder(CookieSessionStore::default(), Key::generate())
+                        .cookie_secure(false)
+                        .build(),
+                )
+                .app_data(web::Data::new(GroupService::new(db.pool.clone())))
+                .app_data(web::Data::new(UserService::new(db.pool.clone())))
+                .route(
+                    "/fake-login/{id}",
+                    web::post().to(|path: web::Path<i32>, session: Session| async move {
+                        session.insert("user_id", path.into_inner()).unwrap();
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .configure(configure_routes),
+        )
+        .await;
+        let uri = format!("/groups/{}", group.id);
+        let rename = UpdateGroupRequest {
+            name: "Renamed".to_string(),
+        };
+
+        // Anonymous visitors are asked to log in
+        let req = test::TestRequest::delete().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        // Other users are refused
+        let req = test::TestRequest::post()
+            .uri(&format!("/fake-login/{}", other.id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+
+        let req = test::TestRequest::put()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .set_json(&rename)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let unchanged = groups.get_by_id(group.id).await.unwrap().unwrap();
+        assert_eq!(unchanged.name, "Readers");
+        assert!(unchanged.deleted_at.is_none());
+
+        // The owner may rename and delete it
+        let req = test::TestRequest::post()
+            .uri(&format!("/fake-login/{}", owner.id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+
+        let req = test::TestRequest::put()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .set_json(&rename)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -1,6 +1,14 @@
-use crate::db::group::GroupService;
+use crate::api::context::create_template_context;
+use crate::config::RetentionConfig;
+use crate::db::{audit::AuditContext, group::GroupService, user::UserService};
+use crate::middleware::security_headers::CspNonce;
+use crate::middleware::{admin::is_site_admin, auth::current_user_id};
+use crate::retention::{format_remaining, purge_at};
+use actix_session::Session;
 use actix_web::{HttpResponse, Responder, get, post, web};
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
 
 // Data structure for form submissions
 #[derive(Deserialize)]
@@ -49,6 +57,8 @@ pub async fn get_groups_html(service: web::Data<GroupService>) -> impl Responder
 #[post("/api/groups")]
 pub async fn create_group_html(
     form: web::Form<GroupForm>,
+    session: Session,
+    audit: AuditContext,
     service: web::Data<GroupService>,
 ) -> impl Responder {
     // Validate group name is not empty
@@ -58,7 +68,10 @@ pub async fn create_group_html(
             .body("<div class=\"error\">Group name cannot be empty</div>");
     }
 
-    match service.create(form.name.clone()).await {
+    match service
+        .create_with_owner(form.name.clone(), current_user_id(&session), &audit)
+        .await
+    {
         Ok(group) => {
             // Return just the HTML for the new group
             let html = format!(
@@ -82,6 +95,74 @@ pub async fn create_group_html(
     }
 }
 
+// A deleted group as shown in the trash
+#[derive(Serialize)]
+struct TrashEntry {
+    id: i32,
+    name: String,
+    deleted_at: Option<DateTime<Utc>>,
+    purge_at: Option<DateTime<Utc>>,
+    remaining: String,
+}
+
+// Trash page: owners see their own deleted groups, site admins see all of them
+pub async fn trash_page(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    service: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    retention: web::Data<RetentionConfig>,
+) -> HttpResponse {
+    let mut context = create_template_context(&session, &nonce);
+
+    let Some(user_id) = current_user_id(&session) else {
+        return HttpResponse::Found()
+            .append_header(("Location", "/login"))
+            .finish();
+    };
+    let owner_filter = if is_site_admin(&users, user_id).await {
+        None
+    } else {
+        Some(user_id)
+    };
+
+    let groups = match service.list_deleted(owner_filter).await {
+        Ok(groups) => groups,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let now = Utc::now();
+    let entries: Vec<TrashEntry> = groups
+        .into_iter()
+        .map(|group| {
+            let purge_at = purge_at(&group, retention.deleted_groups());
+            TrashEntry {
+                remaining: purge_at
+                    .map(|at| format_remaining(at - now))
+                    .unwrap_or_default(),
+                id: group.id,
+                name: group.name,
+                deleted_at: group.deleted_at,
+                purge_at,
+            }
+        })
+        .collect();
+
+    context.insert("groups", &entries);
+    context.insert("retention_days", &retention.deleted_groups_days);
+
+    let rendered = tmpl
+        .render("groups_trash.html", &context)
+        .unwrap_or_else(|e| {
+            eprintln!("Template error: {}", e);
+            "Template error".to_string()
+        });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
 // Configure routes for HTML API endpoints
 pub fn configure_html_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(get_groups_html).service(create_group_html);
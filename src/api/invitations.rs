@@ -0,0 +1,419 @@
+use crate::api::context::create_template_context;
+use crate::api::groups_api::managed_group;
+use crate::api::notifications::notify_member_joined;
+use crate::config::ServerConfig;
+use crate::db::{
+    audit::AuditContext,
+    group::GroupService,
+    invitation::{InvitationError, InvitationService, NewInvitation},
+    job::JobService,
+    member::MemberService,
+    models::{Group, Invitation, InvitationStatus, User},
+    notification::NotificationService,
+    user::UserService,
+};
+use crate::mail::Email;
+use crate::middleware::auth::current_user_id;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, Responder, get, post, web};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+// Email invitations expire after this many days unless told otherwise
+const EMAIL_INVITATION_DAYS: i64 = 14;
+const MAX_INVITATION_DAYS: i64 = 365;
+
+#[derive(Deserialize)]
+pub struct CreateInvitationRequest {
+    // Invite one address instead of creating a shareable link
+    pub email: Option<String>,
+    // Link invitations only, unlimited when absent
+    pub max_uses: Option<i32>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct InvitationResponse {
+    pub id: i32,
+    pub url: String,
+    pub email: Option<String>,
+    pub status: InvitationStatus,
+    pub max_uses: Option<i32>,
+    pub use_count: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    // Pending, not expired and not used up
+    pub usable: bool,
+}
+
+impl InvitationResponse {
+    fn new(invitation: Invitation, server: &ServerConfig) -> Self {
+        Self {
+            url: server.url(&format!("/invitations/{}", invitation.code)),
+            usable: invitation.is_usable(Utc::now()),
+            id: invitation.id,
+            email: invitation.email,
+            status: invitation.status,
+            max_uses: invitation.max_uses,
+            use_count: invitation.use_count,
+            expires_at: invitation.expires_at,
+            created_at: invitation.created_at,
+        }
+    }
+}
+
+// Invitation email in text and HTML
+fn invitation_email(
+    tmpl: &Tera,
+    to: &str,
+    group: &Group,
+    inviter: Option<&User>,
+    url: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<Email, tera::Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("group_name", &group.name);
+    ctx.insert("inviter", &inviter.map(|u| u.name.as_str()).unwrap_or(""));
+    ctx.insert("url", url);
+    ctx.insert("expires_at", &expires_at);
+
+    Ok(Email {
+        to: to.to_string(),
+        subject: format!("You're invited to join {}", group.name),
+        text: tmpl.render("emails/invitation.txt", &ctx)?,
+        html: Some(tmpl.render("emails/invitation.html", &ctx)?),
+        unsubscribe_url: None,
+    })
+}
+
+// Create an invitation link, or invite an email address
+#[post("/groups/{id}/invitations")]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_invitation(
+    path: web::Path<i32>,
+    body: web::Json<CreateInvitationRequest>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    members: web::Data<MemberService>,
+    invitations: web::Data<InvitationService>,
+    jobs: web::Data<JobService>,
+    tmpl: web::Data<Tera>,
+    server: web::Data<ServerConfig>,
+) -> impl Responder {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    let email = body
+        .email
+        .as_deref()
+        .map(str::trim)
+        .filter(|e| !e.is_empty());
+    if let Some(email) = email {
+        if email.parse::<lettre::Address>().is_err() {
+            return HttpResponse::BadRequest().body("Invalid email address");
+        }
+        if let Ok(Some(user)) = users.get_by_email(email.to_string()).await {
+            if matches!(members.is_member(group.id, user.id).await, Ok(true)) {
+                return HttpResponse::Conflict().body("Already a member of this group");
+            }
+        }
+    }
+    if body.max_uses.is_some_and(|max| max < 1) {
+        return HttpResponse::BadRequest().body("max_uses must be at least 1");
+    }
+    let days = match (body.expires_in_days, email) {
+        (Some(days), _) if !(1..=MAX_INVITATION_DAYS).contains(&days) => {
+            return HttpResponse::BadRequest().body(format!(
+                "expires_in_days must be between 1 and {}",
+                MAX_INVITATION_DAYS
+            ));
+        }
+        (Some(days), _) => Some(days),
+        (None, Some(_)) => Some(EMAIL_INVITATION_DAYS),
+        (None, None) => None,
+    };
+
+    let new_invitation = NewInvitation {
+        email: email.map(str::to_string),
+        max_uses: body.max_uses,
+        expires_at: days.map(|days| Utc::now() + Duration::days(days)),
+    };
+    let invitation = match invitations.create(group.id, new_invitation, &audit).await {
+        Ok(invitation) => invitation,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+    let response = InvitationResponse::new(invitation.clone(), &server);
+
+    if let Some(to) = &invitation.email {
+        let inviter = match audit.actor_id {
+            Some(id) => users.get_by_id(id).await.ok().flatten(),
+            None => None,
+        };
+        let queued = match invitation_email(
+            &tmpl,
+            to,
+            &group,
+            inviter.as_ref(),
+            &response.url,
+            invitation.expires_at,
+        ) {
+            Ok(email) => match email.job() {
+                Ok(job) => jobs
+                    .enqueue(job)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = queued {
+            return HttpResponse::InternalServerError().body(format!(
+                "Invitation created but the email could not be queued: {}",
+                e
+            ));
+        }
+    }
+
+    HttpResponse::Created().json(response)
+}
+
+// Every invitation of a group with its status
+#[get("/groups/{id}/invitations")]
+pub async fn list_invitations(
+    path: web::Path<i32>,
+    session: Session,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    invitations: web::Data<InvitationService>,
+    server: web::Data<ServerConfig>,
+) -> impl Responder {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    match invitations.list_for_group(group.id).await {
+        Ok(list) => HttpResponse::Ok().json(
+            list.into_iter()
+                .map(|invitation| InvitationResponse::new(invitation, &server))
+                .collect::<Vec<_>>(),
+        ),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+#[post("/groups/{id}/invitations/{invitation_id}/revoke")]
+pub async fn revoke_invitation(
+    path: web::Path<(i32, i32)>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    invitations: web::Data<InvitationService>,
+    server: web::Data<ServerConfig>,
+) -> impl Responder {
+    let (group_id, invitation_id) = path.into_inner();
+    let group = match managed_group(&session, &groups, &users, group_id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    match invitations.revoke(group.id, invitation_id, &audit).await {
+        Ok(invitation) => HttpResponse::Ok().json(InvitationResponse::new(invitation, &server)),
+        Err(InvitationError::NotFound) => HttpResponse::NotFound().body("Invitation not found"),
+        Err(InvitationError::NotPending) => {
+            HttpResponse::Conflict().body("Only pending invitations can be revoked")
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+// Invitation management page for group owners and admins
+#[allow(clippy::too_many_arguments)]
+pub async fn invitations_page(
+    path: web::Path<i32>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    invitations: web::Data<InvitationService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    let list = match invitations.list_for_group(group.id).await {
+        Ok(list) => list,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+    let list: Vec<InvitationResponse> = list
+        .into_iter()
+        .map(|invitation| InvitationResponse::new(invitation, &server))
+        .collect();
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert("invitations", &list);
+    context.insert("email_invitation_days", &EMAIL_INVITATION_DAYS);
+
+    render(&tmpl, "group_invitations.html", &context)
+}
+
+// What the invitation page shows to the visitor
+async fn invitation_context(
+    context: &mut tera::Context,
+    session: &Session,
+    invitation: &Invitation,
+    group: &Group,
+    members: &MemberService,
+) {
+    let user_id = current_user_id(session);
+    let is_member = match user_id {
+        Some(user_id) => members.is_member(group.id, user_id).await.unwrap_or(false),
+        None => false,
+    };
+
+    let now = Utc::now();
+    let problem = match invitation.status {
+        _ if is_member => None,
+        InvitationStatus::Revoked => Some("This invitation has been revoked."),
+        _ if invitation.is_expired(now) => Some("This invitation has expired."),
+        _ if !invitation.is_usable(now) => Some("This invitation has already been used."),
+        _ => None,
+    };
+
+    context.insert("group", group);
+    context.insert("code", &invitation.code);
+    context.insert("is_member", &is_member);
+    context.insert("problem", &problem);
+    context.insert("expires_at", &invitation.expires_at);
+}
+
+// Landing page of an invitation link
+pub async fn invitation_page(
+    path: web::Path<String>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    invitations: web::Data<InvitationService>,
+) -> HttpResponse {
+    let code = path.into_inner();
+    let (invitation, group) = match find_invitation(&invitations, &groups, &code).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    invitation_context(&mut context, &session, &invitation, &group, &members).await;
+
+    render(&tmpl, "invitation.html", &context)
+}
+
+async fn find_invitation(
+    invitations: &InvitationService,
+    groups: &GroupService,
+    code: &str,
+) -> Result<(Invitation, Group), HttpResponse> {
+    let invitation = match invitations.get_by_code(code).await {
+        Ok(Some(invitation)) => invitation,
+        Ok(None) => return Err(HttpResponse::NotFound().body("Invitation not found")),
+        Err(e) => {
+            return Err(HttpResponse::InternalServerError().body(format!("Database error: {}", e)));
+        }
+    };
+
+    match groups.get_by_id(invitation.group_id).await {
+        Ok(Some(group)) if group.deleted_at.is_none() => Ok((invitation, group)),
+        Ok(_) => Err(HttpResponse::NotFound().body("Invitation not found")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("Database error: {}", e))),
+    }
+}
+
+// Join the group of an invitation as the logged in user
+#[allow(clippy::too_many_arguments)]
+pub async fn accept_invitation(
+    path: web::Path<String>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    members: web::Data<MemberService>,
+    invitations: web::Data<InvitationService>,
+    notifications: web::Data<NotificationService>,
+    jobs: web::Data<JobService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let code = path.into_inner();
+    let user = match current_user_id(&session) {
+        Some(user_id) => users.get_by_id(user_id).await.ok().flatten(),
+        None => None,
+    };
+    let Some(user) = user else {
+        return HttpResponse::Found()
+            .append_header(("Location", "/login"))
+            .finish();
+    };
+
+    let (invitation, group) = match find_invitation(&invitations, &groups, &code).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let error = match invitations.accept(&code, &user, &audit).await {
+        Ok(_) => {
+            notify_member_joined(&notifications, &jobs, &tmpl, &server, &group, &user).await;
+            None
+        }
+        // The page now shows the membership
+        Err(InvitationError::AlreadyMember) => None,
+        Err(
+            e @ (InvitationError::Revoked
+            | InvitationError::Expired
+            | InvitationError::UsedUp
+            | InvitationError::WrongRecipient
+            | InvitationError::NotFound),
+        ) => Some(e.to_string()),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    invitation_context(&mut context, &session, &invitation, &group, &members).await;
+    context.insert("error", &error);
+
+    render(&tmpl, "invitation.html", &context)
+}
+
+// JSON endpoints, mounted under /api
+pub fn configure_invitation_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_invitation)
+        .service(list_invitations)
+        .service(revoke_invitation);
+}
@@ -0,0 +1,547 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::groups_api::managed_group;
+use crate::api::notifications::{notify, notify_member_joined, notify_with_email};
+use crate::api::uploads::media_url;
+use crate::config::ServerConfig;
+use crate::db::{
+    audit::AuditContext,
+    group::GroupService,
+    job::JobService,
+    join_request::{
+        JoinRequestError, JoinRequestService, MAX_ANSWER_LENGTH, MAX_QUESTION_LENGTH, MAX_QUESTIONS,
+    },
+    member::MemberService,
+    models::{
+        Group, JoinAnswer, JoinPolicy, JoinQuestion, JoinRequest, JoinRequestStatus,
+        NotificationKind,
+    },
+    notification::{NewNotification, NotificationService},
+    user::UserService,
+};
+use crate::mail::Email;
+use crate::middleware::auth::current_user_id;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+#[derive(Deserialize)]
+pub struct JoinRequestsQuery {
+    pub status: Option<String>,
+    pub notice: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DecisionForm {
+    // Optional message included in the email to the applicant
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct JoinSettingsForm {
+    pub join_policy: JoinPolicy,
+    // One question per line
+    pub questions: String,
+}
+
+// A join request as shown in the approval queue
+#[derive(Serialize)]
+struct QueueEntry {
+    id: i32,
+    user_email: String,
+    user_name: String,
+    status: JoinRequestStatus,
+    answers: Vec<JoinAnswer>,
+    decision_note: Option<String>,
+    decided_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+// Pair the `answer` fields of the join form with the questions, in order.
+// Missing answers are left empty.
+fn answers_for(questions: Vec<JoinQuestion>, form: &[(String, String)]) -> Vec<JoinAnswer> {
+    let mut given = form
+        .iter()
+        .filter(|(name, _)| name == "answer")
+        .map(|(_, value)| value.trim().to_string());
+
+    questions
+        .into_iter()
+        .map(|q| JoinAnswer {
+            question: q.question,
+            answer: given.next().unwrap_or_default(),
+        })
+        .collect()
+}
+
+// Questions from the settings textarea, one per non-empty line
+fn parse_questions(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+async fn active_group(groups: &GroupService, group_id: i32) -> Result<Group, HttpResponse> {
+    match groups.get_by_id(group_id).await {
+        Ok(Some(group)) if group.deleted_at.is_none() => Ok(group),
+        Ok(_) => Err(HttpResponse::NotFound().body("Group not found")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("Database error: {}", e))),
+    }
+}
+
+// Render the join page for the current visitor, with an optional outcome of
+// what they just did
+#[allow(clippy::too_many_arguments)]
+async fn join_page_response(
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    group: &Group,
+    members: &MemberService,
+    join_requests: &JoinRequestService,
+    notice: Option<&str>,
+    error: Option<&str>,
+) -> HttpResponse {
+    let questions = match join_requests.questions(group.id).await {
+        Ok(questions) => questions,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    let (is_member, pending) = match current_user_id(session) {
+        Some(user_id) => (
+            members.is_member(group.id, user_id).await.unwrap_or(false),
+            join_requests
+                .pending_for(group.id, user_id)
+                .await
+                .ok()
+                .flatten(),
+        ),
+        None => (false, None),
+    };
+
+    let mut context = create_template_context(session, nonce);
+    context.insert("group", group);
+    context.insert("questions", &questions);
+    context.insert(
+        "cover_url",
+        &group.cover_key.as_ref().map(|key| media_url(key, "large")),
+    );
+    context.insert("is_member", &is_member);
+    context.insert("pending", &pending.as_ref().map(|r| r.created_at));
+    context.insert("notice", &notice);
+    context.insert("error", &error);
+    context.insert("max_answer_length", &MAX_ANSWER_LENGTH);
+
+    render(tmpl, "group_join.html", &context)
+}
+
+// Join page: join an open group, or request to join one that needs approval
+pub async fn join_page(
+    path: web::Path<i32>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    join_requests: web::Data<JoinRequestService>,
+) -> HttpResponse {
+    let group = match active_group(&groups, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    join_page_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &group,
+        &members,
+        &join_requests,
+        None,
+        None,
+    )
+    .await
+}
+
+// Join the group or submit a join request, depending on its join policy. The
+// form has one `answer` field per question, in order.
+#[allow(clippy::too_many_arguments)]
+pub async fn join_group(
+    path: web::Path<i32>,
+    form: web::Form<Vec<(String, String)>>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    join_requests: web::Data<JoinRequestService>,
+    notifications: web::Data<NotificationService>,
+    jobs: web::Data<JobService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let group = match active_group(&groups, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let user = match current_user_id(&session) {
+        Some(user_id) => users.get_by_id(user_id).await.ok().flatten(),
+        None => None,
+    };
+    let Some(user) = user else {
+        return HttpResponse::Found()
+            .append_header(("Location", "/login"))
+            .finish();
+    };
+    let user_id = user.id;
+
+    let outcome: Result<&str, String> = match group.join_policy {
+        JoinPolicy::Open => match members.join(group.id, user_id, &audit).await {
+            Ok(_) => {
+                notify_member_joined(&notifications, &jobs, &tmpl, &server, &group, &user).await;
+                Ok("Welcome to the group!")
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+            }
+        },
+        JoinPolicy::Invite => Err("This group can only be joined by invitation.".to_string()),
+        JoinPolicy::Approval => {
+            let questions = match join_requests.questions(group.id).await {
+                Ok(questions) => questions,
+                Err(e) => {
+                    return HttpResponse::InternalServerError()
+                        .body(format!("Database error: {}", e));
+                }
+            };
+            let answers = answers_for(questions, &form);
+
+            if answers
+                .iter()
+                .any(|a| a.answer.chars().count() > MAX_ANSWER_LENGTH)
+            {
+                Err(format!(
+                    "Answers are limited to {} characters.",
+                    MAX_ANSWER_LENGTH
+                ))
+            } else {
+                match join_requests
+                    .submit(group.id, user_id, &answers, &audit)
+                    .await
+                {
+                    Ok(_) => {
+                        if let Some(owner_id) = group.owner_id {
+                            let notification = NewNotification {
+                                kind: NotificationKind::JoinRequest,
+                                actor_id: Some(user_id),
+                                message: format!("{} asked to join {}", user.name, group.name),
+                                url: format!("/groups/{}/join-requests", group.id),
+                            };
+                            notify_with_email(
+                                &notifications,
+                                &jobs,
+                                &tmpl,
+                                &server,
+                                &[owner_id],
+                                &notification,
+                            )
+                            .await;
+                        }
+                        Ok("Your request to join was sent to the group's organizers.")
+                    }
+                    Err(
+                        e @ (JoinRequestError::AlreadyMember | JoinRequestError::AlreadyPending),
+                    ) => Err(e.to_string()),
+                    Err(e) => {
+                        return HttpResponse::InternalServerError()
+                            .body(format!("Database error: {}", e));
+                    }
+                }
+            }
+        }
+    };
+
+    let (notice, error) = match &outcome {
+        Ok(notice) => (Some(*notice), None),
+        Err(error) => (None, Some(error.as_str())),
+    };
+    join_page_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &group,
+        &members,
+        &join_requests,
+        notice,
+        error,
+    )
+    .await
+}
+
+// Approval queue and join settings, for group owners and admins
+#[allow(clippy::too_many_arguments)]
+pub async fn join_requests_page(
+    path: web::Path<i32>,
+    query: web::Query<JoinRequestsQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    join_requests: web::Data<JoinRequestService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    // Pending requests unless another status is asked for
+    let status = match query.status.as_deref() {
+        Some("all") => None,
+        other => Some(
+            JoinRequestStatus::ALL
+                .into_iter()
+                .find(|s| Some(s.as_str()) == other)
+                .unwrap_or(JoinRequestStatus::Pending),
+        ),
+    };
+
+    let (entries, questions) = match (
+        join_requests.list_for_group(group.id, status).await,
+        join_requests.questions(group.id).await,
+    ) {
+        (Ok(entries), Ok(questions)) => (entries, questions),
+        (Err(e), _) | (_, Err(e)) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+    let entries: Vec<QueueEntry> = entries
+        .into_iter()
+        .map(|entry| QueueEntry {
+            answers: entry.request.answers(),
+            id: entry.request.id,
+            user_email: entry.user_email,
+            user_name: entry.user_name,
+            status: entry.request.status,
+            decision_note: entry.request.decision_note,
+            decided_at: entry.request.decided_at,
+            created_at: entry.request.created_at,
+        })
+        .collect();
+    let questions: Vec<String> = questions.into_iter().map(|q| q.question).collect();
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("group", &group);
+    context.insert("requests", &entries);
+    context.insert("status", &status.map(|s| s.as_str()).unwrap_or("all"));
+    context.insert("questions", &questions.join("\n"));
+    context.insert("policies", &JoinPolicy::ALL);
+    context.insert("notice", &query.notice);
+    context.insert("max_questions", &MAX_QUESTIONS);
+
+    render(&tmpl, "group_join_requests.html", &context)
+}
+
+// Email telling the applicant about the decision
+fn decision_email(
+    tmpl: &Tera,
+    to: &str,
+    group: &Group,
+    request: &JoinRequest,
+    url: &str,
+) -> Result<Email, tera::Error> {
+    let approved = request.status == JoinRequestStatus::Approved;
+    let mut ctx = tera::Context::new();
+    ctx.insert("group_name", &group.name);
+    ctx.insert("approved", &approved);
+    ctx.insert("note", &request.decision_note);
+    ctx.insert("url", url);
+
+    Ok(Email {
+        to: to.to_string(),
+        subject: if approved {
+            format!("Welcome to {}", group.name)
+        } else {
+            format!("Your request to join {}", group.name)
+        },
+        text: tmpl.render("emails/join_request_decision.txt", &ctx)?,
+        html: None,
+        unsubscribe_url: None,
+    })
+}
+
+// Approve or reject a join request and notify the applicant
+#[allow(clippy::too_many_arguments)]
+pub async fn decide_join_request(
+    path: web::Path<(i32, i32, String)>,
+    form: web::Form<DecisionForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    join_requests: web::Data<JoinRequestService>,
+    notifications: web::Data<NotificationService>,
+    jobs: web::Data<JobService>,
+    server: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let (group_id, request_id, action) = path.into_inner();
+    let group = match managed_group(&session, &groups, &users, group_id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let queue = format!("/groups/{}/join-requests", group.id);
+
+    let note = form.note.as_deref();
+    let result = match action.as_str() {
+        "approve" => {
+            join_requests
+                .approve(group.id, request_id, note, &audit)
+                .await
+        }
+        "reject" => {
+            join_requests
+                .reject(group.id, request_id, note, &audit)
+                .await
+        }
+        _ => return HttpResponse::NotFound().body("Unknown action"),
+    };
+
+    let request = match result {
+        Ok(request) => request,
+        Err(e @ (JoinRequestError::NotFound | JoinRequestError::NotPending)) => {
+            return redirect_with_notice(&queue, &e.to_string());
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+        }
+    };
+
+    // The decision stands even if the applicant cannot be notified
+    let url = server.url(&format!("/groups/{}/join", group.id));
+    let notification = NewNotification {
+        kind: NotificationKind::JoinDecision,
+        actor_id: audit.actor_id,
+        message: if request.status == JoinRequestStatus::Approved {
+            format!("Your request to join {} was approved", group.name)
+        } else {
+            format!("Your request to join {} was not approved", group.name)
+        },
+        url: format!("/groups/{}/join", group.id),
+    };
+    notify(
+        &notifications,
+        &jobs,
+        &[request.user_id],
+        &notification,
+        |applicant| decision_email(&tmpl, &applicant.email, &group, &request, &url),
+    )
+    .await;
+
+    redirect_with_notice(
+        &queue,
+        &format!("Request {} {}", request.id, request.status),
+    )
+}
+
+// Change how users join the group and what they are asked
+pub async fn update_join_settings(
+    path: web::Path<i32>,
+    form: web::Form<JoinSettingsForm>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    join_requests: web::Data<JoinRequestService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let queue = format!("/groups/{}/join-requests", group.id);
+
+    let questions = parse_questions(&form.questions);
+    if questions.len() > MAX_QUESTIONS {
+        return redirect_with_notice(
+            &queue,
+            &format!("At most {} questions can be asked", MAX_QUESTIONS),
+        );
+    }
+    if questions
+        .iter()
+        .any(|q| q.chars().count() > MAX_QUESTION_LENGTH)
+    {
+        return redirect_with_notice(
+            &queue,
+            &format!(
+                "Questions are limited to {} characters",
+                MAX_QUESTION_LENGTH
+            ),
+        );
+    }
+
+    match join_requests
+        .update_settings(group.id, form.join_policy, &questions, &audit)
+        .await
+    {
+        Ok(_) => redirect_with_notice(&queue, "Join settings saved"),
+        Err(JoinRequestError::GroupNotFound) => HttpResponse::NotFound().body("Group not found"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(position: i32, text: &str) -> JoinQuestion {
+        JoinQuestion {
+            id: position,
+            group_id: 1,
+            position,
+            question: text.to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_answers_follow_question_order() {
+        let form = vec![
+            ("answer".to_string(), " Berlin ".to_string()),
+            ("csrf".to_string(), "ignored".to_string()),
+        ];
+        let answers = answers_for(
+            vec![question(0, "Where do you live?"), question(1, "Why join?")],
+            &form,
+        );
+
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[0].question, "Where do you live?");
+        assert_eq!(answers[0].answer, "Berlin");
+        assert_eq!(answers[1].answer, "");
+    }
+
+    #[test]
+    fn test_parse_questions_skips_blank_lines() {
+        assert_eq!(
+            parse_questions("  First?\r\n\n Second? \n"),
+            vec!["First?".to_string(), "Second?".to_string()]
+        );
+        assert!(parse_questions("\n  \n").is_empty());
+    }
+}
@@ -1,12 +1,34 @@
+pub mod account;
+pub mod admin;
+pub mod attendance;
 pub mod auth;
+pub mod calendar;
+pub mod context;
+pub mod digests;
+pub mod discussions;
+pub mod events;
+pub mod geo;
+pub mod group_settings;
 pub mod groups_api;
 pub mod groups_html;
 pub mod hello;
+pub mod invitations;
+pub mod join_requests;
+pub mod notifications;
+pub mod profiles;
+pub mod tags;
+pub mod updates;
+pub mod uploads;
+pub mod venues;
 
 // Re-export API modules for easier imports
+pub use admin::configure_admin_routes;
+pub use geo::configure_geo_routes;
 pub use groups_api::configure_routes as configure_groups_routes;
 pub use groups_html::configure_html_routes;
 pub use hello::hello_service;
+pub use invitations::configure_invitation_routes;
+pub use tags::configure_tag_routes;
 
 // Re-export password functions for API layer
 pub use crate::password::{hash_password, verify_password};
@@ -0,0 +1,450 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::config::ServerConfig;
+use crate::db::{
+    audit::AuditContext,
+    digest::DigestService,
+    job::JobService,
+    models::{DigestFrequency, Group, NotificationKind, NotificationPreference, User},
+    notification::{NewNotification, NotificationService},
+    user::UserService,
+};
+use crate::mail::Email;
+use crate::middleware::auth::current_user_id;
+use crate::middleware::notifications::UNREAD_NOTIFICATIONS_KEY;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono_tz::{TZ_VARIANTS, Tz};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+const NOTIFICATIONS_PER_PAGE: i64 = 30;
+
+#[derive(Deserialize)]
+pub struct NotificationsQuery {
+    pub page: Option<i64>,
+    pub notice: Option<String>,
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+fn login_redirect() -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("Location", "/login"))
+        .finish()
+}
+
+// The generic email for a notification: its message and a link
+pub fn notification_email(
+    tmpl: &Tera,
+    to: &str,
+    message: &str,
+    url: &str,
+) -> Result<Email, tera::Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("message", message);
+    ctx.insert("url", url);
+
+    Ok(Email {
+        to: to.to_string(),
+        subject: message.to_string(),
+        text: tmpl.render("emails/notification.txt", &ctx)?,
+        html: None,
+        unsubscribe_url: None,
+    })
+}
+
+// Notify the recipients in the app and queue emails for those who want them,
+// built by `email`. Notifications are best effort: failures are logged and
+// never fail the action that caused them.
+pub async fn notify<F>(
+    notifications: &NotificationService,
+    jobs: &JobService,
+    recipients: &[i32],
+    notification: &NewNotification,
+    email: F,
+) where
+    F: Fn(&User) -> Result<Email, tera::Error>,
+{
+    let by_email = match notifications.deliver(recipients, notification).await {
+        Ok(users) => users,
+        Err(e) => {
+            eprintln!(
+                "Failed to deliver {} notification: {}",
+                notification.kind, e
+            );
+            return;
+        }
+    };
+
+    for user in by_email {
+        let queued = email(&user)
+            .map_err(|e| e.to_string())
+            .and_then(|email| email.job().map_err(|e| e.to_string()));
+        let queued = match queued {
+            Ok(job) => jobs.enqueue(job).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = queued {
+            eprintln!(
+                "Failed to queue {} notification email to {}: {}",
+                notification.kind, user.email, e
+            );
+        }
+    }
+}
+
+// notify with the generic email
+pub async fn notify_with_email(
+    notifications: &NotificationService,
+    jobs: &JobService,
+    tmpl: &Tera,
+    server: &ServerConfig,
+    recipients: &[i32],
+    notification: &NewNotification,
+) {
+    let url = server.url(&notification.url);
+    notify(notifications, jobs, recipients, notification, |user| {
+        notification_email(tmpl, &user.email, &notification.message, &url)
+    })
+    .await
+}
+
+// Tell a group's owner that someone joined it
+pub async fn notify_member_joined(
+    notifications: &NotificationService,
+    jobs: &JobService,
+    tmpl: &Tera,
+    server: &ServerConfig,
+    group: &Group,
+    member: &User,
+) {
+    let Some(owner_id) = group.owner_id else {
+        return;
+    };
+    let notification = NewNotification {
+        kind: NotificationKind::MemberJoined,
+        actor_id: Some(member.id),
+        message: format!("{} joined {}", member.name, group.name),
+        url: format!("/groups/{}/join", group.id),
+    };
+    notify_with_email(
+        notifications,
+        jobs,
+        tmpl,
+        server,
+        &[owner_id],
+        &notification,
+    )
+    .await
+}
+
+// Keep the badge in the layout in sync after marking notifications read
+async fn refresh_unread(session: &Session, notifications: &NotificationService, user_id: i32) {
+    if let Ok(count) = notifications.unread_count(user_id).await {
+        let _ = session.insert(UNREAD_NOTIFICATIONS_KEY, count);
+    }
+}
+
+// The user's notifications, newest first
+pub async fn notifications_page(
+    query: web::Query<NotificationsQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    notifications: web::Data<NotificationService>,
+) -> HttpResponse {
+    let Some(user_id) = current_user_id(&session) else {
+        return login_redirect();
+    };
+
+    let page = query.page.unwrap_or(1).max(1);
+    let (list, total) = match (
+        notifications
+            .list(
+                user_id,
+                NOTIFICATIONS_PER_PAGE,
+                (page - 1) * NOTIFICATIONS_PER_PAGE,
+            )
+            .await,
+        notifications.count(user_id).await,
+    ) {
+        (Ok(list), Ok(total)) => (list, total),
+        (Err(e), _) | (_, Err(e)) => return database_error(e),
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("notifications", &list);
+    context.insert("page", &page);
+    context.insert("has_next", &(page * NOTIFICATIONS_PER_PAGE < total));
+    context.insert("notice", &query.notice);
+
+    render(&tmpl, "notifications.html", &context)
+}
+
+// Follow a notification, marking it read
+pub async fn open_notification(
+    path: web::Path<i32>,
+    session: Session,
+    notifications: web::Data<NotificationService>,
+) -> HttpResponse {
+    let Some(user_id) = current_user_id(&session) else {
+        return login_redirect();
+    };
+
+    match notifications.mark_read(user_id, path.into_inner()).await {
+        Ok(Some(notification)) => {
+            refresh_unread(&session, &notifications, user_id).await;
+            HttpResponse::SeeOther()
+                .append_header(("Location", notification.url))
+                .finish()
+        }
+        Ok(None) => HttpResponse::NotFound().body("Notification not found"),
+        Err(e) => database_error(e),
+    }
+}
+
+pub async fn mark_read(
+    path: web::Path<i32>,
+    session: Session,
+    notifications: web::Data<NotificationService>,
+) -> HttpResponse {
+    let Some(user_id) = current_user_id(&session) else {
+        return login_redirect();
+    };
+
+    match notifications.mark_read(user_id, path.into_inner()).await {
+        Ok(Some(_)) => {
+            refresh_unread(&session, &notifications, user_id).await;
+            redirect_with_notice("/notifications", "Notification marked as read")
+        }
+        Ok(None) => HttpResponse::NotFound().body("Notification not found"),
+        Err(e) => database_error(e),
+    }
+}
+
+pub async fn mark_all_read(
+    session: Session,
+    notifications: web::Data<NotificationService>,
+) -> HttpResponse {
+    let Some(user_id) = current_user_id(&session) else {
+        return login_redirect();
+    };
+
+    match notifications.mark_all_read(user_id).await {
+        Ok(_) => {
+            let _ = session.insert(UNREAD_NOTIFICATIONS_KEY, 0i64);
+            redirect_with_notice("/notifications", "All notifications marked as read")
+        }
+        Err(e) => database_error(e),
+    }
+}
+
+// A row of the settings page
+#[derive(Serialize)]
+struct PreferenceView {
+    kind: NotificationKind,
+    label: &'static str,
+    in_app: bool,
+    email: bool,
+}
+
+#[derive(Serialize)]
+struct FrequencyView {
+    value: DigestFrequency,
+    label: &'static str,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn settings_page(
+    query: web::Query<NotificationsQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    notifications: web::Data<NotificationService>,
+    users: web::Data<UserService>,
+    digests: web::Data<DigestService>,
+) -> HttpResponse {
+    let user = match current_user_id(&session) {
+        Some(user_id) => users.get_by_id(user_id).await.ok().flatten(),
+        None => None,
+    };
+    let Some(user) = user else {
+        return login_redirect();
+    };
+    let user_id = user.id;
+
+    let preferences = match notifications.preferences(user_id).await {
+        Ok(preferences) => preferences,
+        Err(e) => return database_error(e),
+    };
+    let digest = match digests.settings(user_id).await {
+        Ok(settings) => settings,
+        Err(e) => return database_error(e),
+    };
+    let preferences: Vec<PreferenceView> = preferences
+        .into_iter()
+        .map(|p| PreferenceView {
+            kind: p.kind,
+            label: p.kind.label(),
+            in_app: p.in_app,
+            email: p.email,
+        })
+        .collect();
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("preferences", &preferences);
+    context.insert("digest_frequency", &digest.frequency);
+    context.insert(
+        "digest_frequencies",
+        &DigestFrequency::ALL
+            .iter()
+            .map(|f| FrequencyView {
+                value: *f,
+                label: f.label(),
+            })
+            .collect::<Vec<_>>(),
+    );
+    context.insert("time_zone", user.tz().name());
+    context.insert(
+        "time_zones",
+        &TZ_VARIANTS.iter().map(|tz| tz.name()).collect::<Vec<_>>(),
+    );
+    context.insert("notice", &query.notice);
+
+    render(&tmpl, "notification_settings.html", &context)
+}
+
+// The settings form has a `{kind}_in_app` and a `{kind}_email` checkbox per
+// kind, unchecked boxes are not submitted, the `digest` frequency and the
+// user's `time_zone`
+fn parse_preferences(form: &[(String, String)]) -> Vec<NotificationPreference> {
+    let checked = |name: String| form.iter().any(|(field, _)| *field == name);
+
+    NotificationKind::ALL
+        .iter()
+        .map(|kind| NotificationPreference {
+            kind: *kind,
+            in_app: checked(format!("{}_in_app", kind)),
+            email: checked(format!("{}_email", kind)),
+        })
+        .collect()
+}
+
+fn parse_time_zone(form: &[(String, String)]) -> Option<Result<Tz, String>> {
+    let (_, value) = form.iter().find(|(field, _)| field == "time_zone")?;
+    Some(
+        value
+            .parse::<Tz>()
+            .map_err(|_| format!("Unknown time zone {}", value)),
+    )
+}
+
+fn parse_digest_frequency(form: &[(String, String)]) -> Option<DigestFrequency> {
+    let (_, value) = form.iter().find(|(field, _)| field == "digest")?;
+    DigestFrequency::ALL
+        .into_iter()
+        .find(|frequency| frequency.as_str() == value)
+}
+
+pub async fn update_settings(
+    form: web::Form<Vec<(String, String)>>,
+    session: Session,
+    audit: AuditContext,
+    notifications: web::Data<NotificationService>,
+    users: web::Data<UserService>,
+    digests: web::Data<DigestService>,
+) -> HttpResponse {
+    let Some(user_id) = current_user_id(&session) else {
+        return login_redirect();
+    };
+
+    match parse_time_zone(&form) {
+        Some(Ok(time_zone)) => {
+            if let Err(e) = users.set_time_zone(user_id, time_zone, &audit).await {
+                return database_error(e);
+            }
+        }
+        Some(Err(error)) => return redirect_with_notice("/notifications/settings", &error),
+        None => {}
+    }
+    if let Some(frequency) = parse_digest_frequency(&form) {
+        if let Err(e) = digests.set_frequency(user_id, frequency).await {
+            return database_error(e);
+        }
+    }
+
+    match notifications
+        .set_preferences(user_id, &parse_preferences(&form))
+        .await
+    {
+        Ok(()) => redirect_with_notice("/notifications/settings", "Preferences saved"),
+        Err(e) => database_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_preferences() {
+        let form = vec![
+            ("mention_in_app".to_string(), "on".to_string()),
+            ("mention_email".to_string(), "on".to_string()),
+            ("rsvp_email".to_string(), "on".to_string()),
+            ("unknown_email".to_string(), "on".to_string()),
+        ];
+        let preferences = parse_preferences(&form);
+
+        assert_eq!(preferences.len(), NotificationKind::ALL.len());
+        let find = |kind| *preferences.iter().find(|p| p.kind == kind).unwrap();
+        assert!(find(NotificationKind::Mention).in_app);
+        assert!(find(NotificationKind::Mention).email);
+        assert!(!find(NotificationKind::Rsvp).in_app);
+        assert!(find(NotificationKind::Rsvp).email);
+        assert!(!find(NotificationKind::Reply).in_app);
+        assert!(!find(NotificationKind::Reply).email);
+    }
+
+    #[test]
+    fn test_parse_time_zone() {
+        let field = |value: &str| vec![("time_zone".to_string(), value.to_string())];
+
+        assert_eq!(
+            parse_time_zone(&field("Europe/Paris")),
+            Some(Ok(Tz::Europe__Paris))
+        );
+        assert!(matches!(
+            parse_time_zone(&field("Mars/Olympus")),
+            Some(Err(_))
+        ));
+        assert_eq!(parse_time_zone(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_digest_frequency() {
+        let field = |value: &str| vec![("digest".to_string(), value.to_string())];
+
+        assert_eq!(
+            parse_digest_frequency(&field("daily")),
+            Some(DigestFrequency::Daily)
+        );
+        assert_eq!(
+            parse_digest_frequency(&field("never")),
+            Some(DigestFrequency::Never)
+        );
+        assert_eq!(parse_digest_frequency(&field("hourly")), None);
+        assert_eq!(parse_digest_frequency(&[]), None);
+    }
+}
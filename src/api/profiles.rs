@@ -0,0 +1,476 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::uploads::media_url;
+use crate::db::{
+    audit::AuditContext,
+    group::GroupService,
+    models::{Group, JoinPolicy, ProfileVisibility, User},
+    user::UserService,
+};
+use crate::middleware::admin::is_site_admin;
+use crate::middleware::auth::current_user_id;
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_BIO_LENGTH: usize = 2000;
+const MAX_LOCATION_LENGTH: usize = 100;
+const MAX_INTERESTS: usize = 20;
+const MAX_INTEREST_LENGTH: usize = 40;
+
+#[derive(Deserialize)]
+pub struct ProfileQuery {
+    pub notice: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProfileForm {
+    pub name: String,
+    pub bio: String,
+    pub location: String,
+    // Comma separated
+    pub interests: String,
+    pub profile_visibility: ProfileVisibility,
+    // Checkboxes, absent when unchecked
+    pub show_location: Option<String>,
+    pub show_groups: Option<String>,
+}
+
+impl ProfileForm {
+    fn from_user(user: &User) -> Self {
+        let checked = |value: bool| value.then(|| "on".to_string());
+        Self {
+            name: user.name.clone(),
+            bio: user.bio.clone(),
+            location: user.location.clone(),
+            interests: user.interests.join(", "),
+            profile_visibility: user.profile_visibility,
+            show_location: checked(user.show_location),
+            show_groups: checked(user.show_groups),
+        }
+    }
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+fn login_redirect() -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("Location", "/login"))
+        .finish()
+}
+
+// Interests typed as "rust, Hiking,rust" become ["rust", "Hiking"]
+fn parse_interests(text: &str) -> Vec<String> {
+    let mut interests: Vec<String> = Vec::new();
+    for interest in text.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        if !interests.iter().any(|i| i.eq_ignore_ascii_case(interest)) {
+            interests.push(interest.to_string());
+        }
+    }
+    interests
+}
+
+// Apply the form to the user, if it is valid
+fn apply_profile(user: &mut User, form: &ProfileForm) -> Result<(), String> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err("Name cannot be empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "Names are limited to {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+    let bio = form.bio.trim();
+    if bio.chars().count() > MAX_BIO_LENGTH {
+        return Err(format!("Bios are limited to {} characters", MAX_BIO_LENGTH));
+    }
+    let location = form.location.trim();
+    if location.chars().count() > MAX_LOCATION_LENGTH {
+        return Err(format!(
+            "Locations are limited to {} characters",
+            MAX_LOCATION_LENGTH
+        ));
+    }
+    let interests = parse_interests(&form.interests);
+    if interests.len() > MAX_INTERESTS {
+        return Err(format!("At most {} interests", MAX_INTERESTS));
+    }
+    if interests
+        .iter()
+        .any(|i| i.chars().count() > MAX_INTEREST_LENGTH)
+    {
+        return Err(format!(
+            "Interests are limited to {} characters each",
+            MAX_INTEREST_LENGTH
+        ));
+    }
+
+    user.name = name.to_string();
+    user.bio = bio.to_string();
+    user.location = location.to_string();
+    user.interests = interests;
+    user.profile_visibility = form.profile_visibility;
+    user.show_location = form.show_location.is_some();
+    user.show_groups = form.show_groups.is_some();
+    Ok(())
+}
+
+// Who is looking at a profile
+struct ProfileViewer {
+    user_id: Option<i32>,
+    is_admin: bool,
+    // Groups the viewer belongs to
+    group_ids: Vec<i32>,
+}
+
+impl ProfileViewer {
+    // Users see their own profile in full, and administrators every profile
+    fn sees_everything(&self, profile: &User) -> bool {
+        self.user_id == Some(profile.id) || self.is_admin
+    }
+
+    fn can_see(&self, profile: &User, profile_groups: &[Group]) -> bool {
+        if self.sees_everything(profile) {
+            return true;
+        }
+        match profile.profile_visibility {
+            ProfileVisibility::Everyone => true,
+            ProfileVisibility::Members => profile_groups
+                .iter()
+                .any(|group| self.group_ids.contains(&group.id)),
+            ProfileVisibility::Private => false,
+        }
+    }
+
+    // Groups whose membership list the viewer could see anyway
+    fn can_see_group(&self, profile: &User, group: &Group) -> bool {
+        self.sees_everything(profile)
+            || group.join_policy == JoinPolicy::Open
+            || self.group_ids.contains(&group.id)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct GroupLink {
+    id: i32,
+    name: String,
+}
+
+// A profile as shown to one viewer
+#[derive(Debug, Serialize)]
+struct ProfileView {
+    id: i32,
+    name: String,
+    member_since: DateTime<Utc>,
+    is_own: bool,
+    // False when only the name may be shown
+    visible: bool,
+    bio: String,
+    location: Option<String>,
+    interests: Vec<String>,
+    groups: Vec<GroupLink>,
+    // Shown with the name, whatever the privacy settings
+    avatar_url: Option<String>,
+}
+
+fn profile_view(profile: &User, groups: &[Group], viewer: &ProfileViewer) -> ProfileView {
+    let mut view = ProfileView {
+        id: profile.id,
+        name: profile.name.clone(),
+        member_since: profile.created_at,
+        is_own: viewer.user_id == Some(profile.id),
+        visible: viewer.can_see(profile, groups),
+        bio: String::new(),
+        location: None,
+        interests: Vec::new(),
+        groups: Vec::new(),
+        avatar_url: profile
+            .avatar_key
+            .as_ref()
+            .map(|key| media_url(key, "large")),
+    };
+    if !view.visible {
+        return view;
+    }
+
+    let everything = viewer.sees_everything(profile);
+    view.bio = profile.bio.clone();
+    view.interests = profile.interests.clone();
+    if profile.show_location || everything {
+        view.location = Some(profile.location.clone()).filter(|l| !l.is_empty());
+    }
+    if profile.show_groups || everything {
+        view.groups = groups
+            .iter()
+            .filter(|group| viewer.can_see_group(profile, group))
+            .map(|group| GroupLink {
+                id: group.id,
+                name: group.name.clone(),
+            })
+            .collect();
+    }
+    view
+}
+
+// A user's profile, as much of it as their privacy settings let the visitor see
+pub async fn profile_page(
+    path: web::Path<i32>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    users: web::Data<UserService>,
+    groups: web::Data<GroupService>,
+) -> HttpResponse {
+    let profile = match users.get_by_id(path.into_inner()).await {
+        Ok(Some(user)) if user.deleted_at.is_none() => user,
+        Ok(_) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => return database_error(e),
+    };
+    let profile_groups = match groups.list_for_member(profile.id).await {
+        Ok(groups) => groups,
+        Err(e) => return database_error(e),
+    };
+
+    let user_id = current_user_id(&session);
+    let viewer = match user_id {
+        Some(user_id) => ProfileViewer {
+            user_id: Some(user_id),
+            is_admin: is_site_admin(&users, user_id).await,
+            group_ids: match groups.list_for_member(user_id).await {
+                Ok(groups) => groups.iter().map(|group| group.id).collect(),
+                Err(e) => return database_error(e),
+            },
+        },
+        None => ProfileViewer {
+            user_id: None,
+            is_admin: false,
+            group_ids: Vec::new(),
+        },
+    };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("profile", &profile_view(&profile, &profile_groups, &viewer));
+    render(&tmpl, "user_profile.html", &context)
+}
+
+#[derive(Serialize)]
+struct VisibilityView {
+    value: ProfileVisibility,
+    label: &'static str,
+}
+
+fn edit_response(
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    user: &User,
+    form: &ProfileForm,
+    notice: Option<&str>,
+    error: Option<&str>,
+) -> HttpResponse {
+    let visibilities: Vec<VisibilityView> = ProfileVisibility::ALL
+        .iter()
+        .map(|visibility| VisibilityView {
+            value: *visibility,
+            label: visibility.label(),
+        })
+        .collect();
+
+    let mut context = create_template_context(session, nonce);
+    context.insert("user_id", &user.id);
+    context.insert(
+        "avatar_url",
+        &user.avatar_key.as_ref().map(|key| media_url(key, "medium")),
+    );
+    context.insert("form", form);
+    context.insert("visibilities", &visibilities);
+    context.insert("notice", &notice);
+    context.insert("error", &error);
+    render(tmpl, "profile_edit.html", &context)
+}
+
+async fn logged_in_user(session: &Session, users: &UserService) -> Result<User, HttpResponse> {
+    let Some(user_id) = current_user_id(session) else {
+        return Err(login_redirect());
+    };
+    match users.get_by_id(user_id).await {
+        Ok(Some(user)) if user.deleted_at.is_none() => Ok(user),
+        Ok(_) => Err(login_redirect()),
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+pub async fn edit_profile_page(
+    query: web::Query<ProfileQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    let user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let form = ProfileForm::from_user(&user);
+    edit_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &user,
+        &form,
+        query.notice.as_deref(),
+        None,
+    )
+}
+
+pub async fn update_profile(
+    form: web::Form<ProfileForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    let mut user = match logged_in_user(&session, &users).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    if let Err(error) = apply_profile(&mut user, &form) {
+        return edit_response(&tmpl, &session, &nonce, &user, &form, None, Some(&error));
+    }
+    let name = user.name.clone();
+    if let Err(e) = users.update(user, &audit).await {
+        return database_error(e);
+    }
+
+    // Shown in the navigation bar
+    let _ = session.insert("user_name", &name);
+    redirect_with_notice("/profile", "Profile saved")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32, visibility: ProfileVisibility) -> User {
+        let now = Utc::now();
+        User {
+            id,
+            email: format!("user{}@example.com", id),
+            name: format!("User {}", id),
+            password_hash: None,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            is_admin: false,
+            time_zone: "UTC".to_string(),
+            bio: "Hello".to_string(),
+            location: "Lyon".to_string(),
+            interests: vec!["rust".to_string()],
+            profile_visibility: visibility,
+            show_location: true,
+            show_groups: true,
+            avatar_key: None,
+            session_version: 0,
+        }
+    }
+
+    fn group(id: i32, join_policy: JoinPolicy) -> Group {
+        Group {
+            id,
+            name: format!("Group {}", id),
+            created_at: Utc::now(),
+            deleted_at: None,
+            owner_id: None,
+            join_policy,
+            cover_key: None,
+            location: None,
+            latitude: None,
+            longitude: None,
+        }
+    }
+
+    fn viewer(user_id: Option<i32>, group_ids: Vec<i32>) -> ProfileViewer {
+        ProfileViewer {
+            user_id,
+            is_admin: false,
+            group_ids,
+        }
+    }
+
+    #[test]
+    fn test_parse_interests() {
+        assert_eq!(
+            parse_interests("rust, Hiking,, RUST ,board games"),
+            vec!["rust", "Hiking", "board games"]
+        );
+        assert!(parse_interests(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_profile_visibility() {
+        let groups = [group(1, JoinPolicy::Open), group(2, JoinPolicy::Invite)];
+        let anonymous = viewer(None, vec![]);
+        let stranger = viewer(Some(7), vec![3]);
+        let member = viewer(Some(8), vec![2]);
+
+        let public = user(1, ProfileVisibility::Everyone);
+        assert!(profile_view(&public, &groups, &anonymous).visible);
+
+        let members = user(1, ProfileVisibility::Members);
+        assert!(!profile_view(&members, &groups, &anonymous).visible);
+        assert!(!profile_view(&members, &groups, &stranger).visible);
+        assert!(profile_view(&members, &groups, &member).visible);
+
+        let private = user(1, ProfileVisibility::Private);
+        let hidden = profile_view(&private, &groups, &member);
+        assert!(!hidden.visible);
+        assert!(hidden.bio.is_empty());
+        assert!(profile_view(&private, &groups, &viewer(Some(1), vec![])).visible);
+        let admin = ProfileViewer {
+            is_admin: true,
+            ..viewer(Some(9), vec![])
+        };
+        assert!(profile_view(&private, &groups, &admin).visible);
+    }
+
+    #[test]
+    fn test_profile_hides_groups_and_location() {
+        let groups = [group(1, JoinPolicy::Open), group(2, JoinPolicy::Invite)];
+        let mut profile = user(1, ProfileVisibility::Everyone);
+
+        // Closed groups only show to their members
+        let view = profile_view(&profile, &groups, &viewer(None, vec![]));
+        assert_eq!(view.groups.len(), 1);
+        assert_eq!(view.location.as_deref(), Some("Lyon"));
+        let view = profile_view(&profile, &groups, &viewer(Some(8), vec![2]));
+        assert_eq!(view.groups.len(), 2);
+
+        profile.show_groups = false;
+        profile.show_location = false;
+        let view = profile_view(&profile, &groups, &viewer(Some(8), vec![2]));
+        assert!(view.groups.is_empty());
+        assert_eq!(view.location, None);
+        // Still shown to the user themselves
+        let own = profile_view(&profile, &groups, &viewer(Some(1), vec![]));
+        assert_eq!(own.groups.len(), 2);
+        assert_eq!(own.location.as_deref(), Some("Lyon"));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::group_settings::settings_url;
+use crate::api::groups_api::managed_group;
+use crate::db::{
+    audit::AuditContext,
+    group::GroupService,
+    models::{Category, Group, Tag},
+    tag::{TagService, TagSummary, parse_tags},
+    user::UserService,
+};
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, Responder, get, web};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tera::Tera;
+
+const DEFAULT_SUGGESTIONS: i64 = 10;
+const MAX_SUGGESTIONS: i64 = 50;
+// Upcoming events listed on a tag's page
+const TAG_PAGE_EVENTS: i64 = 20;
+
+#[derive(Deserialize)]
+pub struct AutocompleteQuery {
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct GroupTagsForm {
+    // Comma separated
+    pub tags: String,
+}
+
+// A group with its tags, for lists of groups
+#[derive(Serialize)]
+pub struct TaggedGroup {
+    #[serde(flatten)]
+    pub group: Group,
+    pub tags: Vec<Tag>,
+    // When listing groups around a place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+}
+
+// A category with the tags filed under it that are in use
+#[derive(Serialize)]
+pub struct CategoryTags {
+    pub slug: String,
+    pub name: String,
+    pub tags: Vec<TagSummary>,
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+// Pair each group with its tags
+pub async fn with_tags(
+    tags: &TagService,
+    groups: Vec<Group>,
+) -> Result<Vec<TaggedGroup>, HttpResponse> {
+    let ids: Vec<i32> = groups.iter().map(|g| g.id).collect();
+    let mut by_group = tags.for_groups(&ids).await.map_err(database_error)?;
+
+    Ok(groups
+        .into_iter()
+        .map(|group| TaggedGroup {
+            tags: by_group.remove(&group.id).unwrap_or_default(),
+            group,
+            distance_km: None,
+        })
+        .collect())
+}
+
+// The categories in order with their tags in use, then the tags in use
+// without a category under "Other"
+fn by_category(categories: Vec<Category>, tags: Vec<TagSummary>) -> Vec<CategoryTags> {
+    let mut filed: HashMap<Option<i32>, Vec<TagSummary>> = HashMap::new();
+    for tag in tags.into_iter().filter(|t| t.group_count > 0) {
+        let category_id = tag
+            .category_id
+            .filter(|id| categories.iter().any(|c| c.id == *id));
+        filed.entry(category_id).or_default().push(tag);
+    }
+
+    let mut sections: Vec<CategoryTags> = categories
+        .into_iter()
+        .map(|category| CategoryTags {
+            tags: filed.remove(&Some(category.id)).unwrap_or_default(),
+            slug: category.slug,
+            name: category.name,
+        })
+        .collect();
+    if let Some(tags) = filed.remove(&None) {
+        sections.push(CategoryTags {
+            slug: "other".to_string(),
+            name: "Other".to_string(),
+            tags,
+        });
+    }
+    sections
+}
+
+// Tags starting with `q`, the most used first, for autocompletion
+#[get("/tags")]
+pub async fn autocomplete_tags(
+    query: web::Query<AutocompleteQuery>,
+    tags: web::Data<TagService>,
+) -> impl Responder {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SUGGESTIONS)
+        .clamp(1, MAX_SUGGESTIONS);
+    match tags
+        .autocomplete(query.q.as_deref().unwrap_or_default(), limit)
+        .await
+    {
+        Ok(found) => HttpResponse::Ok().json(found),
+        Err(e) => database_error(e),
+    }
+}
+
+// The categories with the tags in use under each
+#[get("/categories")]
+pub async fn list_categories(tags: web::Data<TagService>) -> impl Responder {
+    match (tags.list_categories().await, tags.list_with_counts().await) {
+        (Ok(categories), Ok(summaries)) => {
+            HttpResponse::Ok().json(by_category(categories, summaries))
+        }
+        (Err(e), _) | (_, Err(e)) => database_error(e),
+    }
+}
+
+pub fn configure_tag_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(autocomplete_tags).service(list_categories);
+}
+
+// Every topic in use, by category
+pub async fn topics_page(
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    tags: web::Data<TagService>,
+) -> HttpResponse {
+    let (categories, summaries) =
+        match (tags.list_categories().await, tags.list_with_counts().await) {
+            (Ok(categories), Ok(summaries)) => (categories, summaries),
+            (Err(e), _) | (_, Err(e)) => return database_error(e),
+        };
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("sections", &by_category(categories, summaries));
+    render(&tmpl, "topics.html", &context)
+}
+
+// The groups with a tag and their upcoming events
+pub async fn tag_page(
+    path: web::Path<String>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    tags: web::Data<TagService>,
+) -> HttpResponse {
+    let tag = match tags.get_by_slug(&path.into_inner()).await {
+        Ok(Some(tag)) => tag,
+        Ok(None) => return HttpResponse::NotFound().body("Tag not found"),
+        Err(e) => return database_error(e),
+    };
+
+    let (groups, events, categories) = match (
+        tags.groups_with_tags(std::slice::from_ref(&tag.slug)).await,
+        tags.upcoming_events(tag.id, Utc::now(), TAG_PAGE_EVENTS)
+            .await,
+        tags.list_categories().await,
+    ) {
+        (Ok(groups), Ok(events), Ok(categories)) => (groups, events, categories),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return database_error(e),
+    };
+    let groups = match with_tags(&tags, groups).await {
+        Ok(groups) => groups,
+        Err(response) => return response,
+    };
+    let category = categories
+        .into_iter()
+        .find(|c| Some(c.id) == tag.category_id);
+
+    let mut context = create_template_context(&session, &nonce);
+    context.insert("tag", &tag);
+    context.insert("category", &category);
+    context.insert("groups", &groups);
+    context.insert("events", &events);
+    render(&tmpl, "tag.html", &context)
+}
+
+// Replace a group's tags, for its owner and admins
+pub async fn update_group_tags(
+    path: web::Path<i32>,
+    form: web::Form<GroupTagsForm>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    tags: web::Data<TagService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let back = settings_url(group.id);
+
+    let parsed = match parse_tags(&form.tags) {
+        Ok(parsed) => parsed,
+        Err(message) => return redirect_with_notice(&back, &message),
+    };
+    match tags.set_group_tags(group.id, &parsed, &audit).await {
+        Ok(_) => redirect_with_notice(&back, "Tags updated"),
+        Err(e) => database_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(id: i32, slug: &str, category_id: Option<i32>, group_count: i64) -> TagSummary {
+        TagSummary {
+            id,
+            slug: slug.to_string(),
+            name: slug.to_string(),
+            category_id,
+            group_count,
+        }
+    }
+
+    #[test]
+    fn test_by_category() {
+        let category = |id: i32, slug: &str| Category {
+            id,
+            slug: slug.to_string(),
+            name: slug.to_string(),
+            position: id,
+            created_at: Utc::now(),
+        };
+        let sections = by_category(
+            vec![category(1, "tech"), category(2, "outdoors")],
+            vec![
+                summary(1, "rust", Some(1), 3),
+                summary(2, "hiking", Some(2), 1),
+                summary(3, "unused", Some(2), 0),
+                summary(4, "chess", None, 2),
+                // Filed under a category that is not listed
+                summary(5, "knitting", Some(9), 1),
+            ],
+        );
+
+        let slugs: Vec<(&str, Vec<&str>)> = sections
+            .iter()
+            .map(|s| {
+                (
+                    s.slug.as_str(),
+                    s.tags.iter().map(|t| t.slug.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            slugs,
+            vec![
+                ("tech", vec!["rust"]),
+                ("outdoors", vec!["hiking"]),
+                ("other", vec!["chess", "knitting"]),
+            ]
+        );
+    }
+}
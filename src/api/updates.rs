@@ -0,0 +1,155 @@
+use crate::api::groups_api::readable_group;
+use crate::db::{
+    event::EventService, group::GroupService, member::MemberService,
+    notification::NotificationService, realtime::Update, user::UserService,
+};
+use crate::middleware::auth::current_user_id;
+use crate::realtime::Broadcaster;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use futures_util::{StreamExt, stream};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+// Comment lines sent when nothing happens, so proxies keep the stream open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+pub struct UpdatesQuery {
+    // Event whose page is open, for its RSVP counts
+    pub event: Option<i32>,
+}
+
+// What one connection receives
+#[derive(Debug, Clone, Copy)]
+struct Subscription {
+    user_id: Option<i32>,
+    event_id: Option<i32>,
+}
+
+impl Subscription {
+    fn wants(&self, update: &Update) -> bool {
+        match update {
+            Update::GroupCreated { .. } | Update::GroupDeleted { .. } => true,
+            Update::RsvpCounts { event_id, .. } => self.event_id == Some(*event_id),
+            Update::Notifications { user_id, .. } => self.user_id == Some(*user_id),
+        }
+    }
+}
+
+fn server_sent_event(update: &Update) -> web::Bytes {
+    let data = serde_json::to_string(update).unwrap_or_default();
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", update.kind(), data))
+}
+
+// Server-sent events: groups being created and deleted, the RSVP counts of
+// the event given as `?event=`, and the user's unread notification count,
+// starting with its current value
+#[allow(clippy::too_many_arguments)]
+pub async fn updates(
+    query: web::Query<UpdatesQuery>,
+    session: Session,
+    groups: web::Data<GroupService>,
+    members: web::Data<MemberService>,
+    users: web::Data<UserService>,
+    events: web::Data<EventService>,
+    notifications: web::Data<NotificationService>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let event_id = match query.event {
+        Some(id) => {
+            let event = match events.get(id).await {
+                Ok(Some(event)) => event,
+                Ok(None) => return HttpResponse::NotFound().body("Event not found"),
+                Err(e) => {
+                    return HttpResponse::InternalServerError()
+                        .body(format!("Database error: {}", e));
+                }
+            };
+            if let Err(response) =
+                readable_group(&session, &groups, &members, &users, event.group_id).await
+            {
+                return response;
+            }
+            Some(event.id)
+        }
+        None => None,
+    };
+    let subscription = Subscription {
+        user_id: current_user_id(&session),
+        event_id,
+    };
+
+    // Subscribe before reading the count so no change falls in between
+    let receiver = broadcaster.subscribe();
+    let mut initial: Vec<Result<web::Bytes, Infallible>> = Vec::new();
+    if let Some(user_id) = subscription.user_id {
+        if let Ok(unread) = notifications.unread_count(user_id).await {
+            initial.push(Ok(server_sent_event(&Update::Notifications {
+                user_id,
+                unread,
+            })));
+        }
+    }
+
+    let keep_alive = tokio::time::interval(KEEP_ALIVE);
+    let live = stream::unfold(
+        (receiver, keep_alive),
+        move |(mut receiver, mut keep_alive)| async move {
+            loop {
+                tokio::select! {
+                    received = receiver.recv() => match received {
+                        Ok(update) if subscription.wants(&update) => {
+                            return Some((Ok(server_sent_event(&update)), (receiver, keep_alive)));
+                        }
+                        // Missed updates are skipped, the next ones are current
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    },
+                    _ = keep_alive.tick() => {
+                        let comment = web::Bytes::from_static(b": keep-alive\n\n");
+                        return Some((Ok(comment), (receiver, keep_alive)));
+                    }
+                }
+            }
+        },
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream::iter(initial).chain(live))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_filters_updates() {
+        let subscription = Subscription {
+            user_id: Some(4),
+            event_id: Some(9),
+        };
+        let counts = |event_id| Update::RsvpCounts {
+            event_id,
+            counts: Default::default(),
+        };
+        let unread = |user_id| Update::Notifications { user_id, unread: 1 };
+
+        assert!(subscription.wants(&Update::GroupDeleted { id: 1 }));
+        assert!(subscription.wants(&counts(9)));
+        assert!(!subscription.wants(&counts(10)));
+        assert!(subscription.wants(&unread(4)));
+        assert!(!subscription.wants(&unread(5)));
+
+        let anonymous = Subscription {
+            user_id: None,
+            event_id: None,
+        };
+        assert!(!anonymous.wants(&counts(9)));
+        assert!(!anonymous.wants(&unread(4)));
+    }
+}
@@ -0,0 +1,285 @@
+use crate::api::context::redirect_with_notice;
+use crate::api::group_settings::settings_url;
+use crate::api::groups_api::managed_group;
+use crate::config::UploadsConfig;
+use crate::db::{audit::AuditContext, group::GroupService, user::UserService};
+use crate::images::{self, ImageError, ImageKind};
+use crate::middleware::auth::current_user_id;
+use crate::storage::{self, Blob, BlobStore};
+use actix_multipart::Multipart;
+use actix_session::Session;
+use actix_web::{HttpRequest, HttpResponse, http::header, web};
+use futures_util::StreamExt;
+
+// Name of the file input in upload forms
+const IMAGE_FIELD: &str = "image";
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+fn storage_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Storage error: {}", e))
+}
+
+fn login_redirect() -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("Location", "/login"))
+        .finish()
+}
+
+// URL of one variant of an uploaded image
+pub fn media_url(base: &str, variant: &str) -> String {
+    format!("/media/{}", images::variant_key(base, variant))
+}
+
+// The bytes of the image field, or the message to show. Reading stops as soon
+// as the upload goes over the limit.
+async fn read_image(mut payload: Multipart, max_bytes: usize) -> Result<Vec<u8>, String> {
+    let too_large = || {
+        format!(
+            "Images are limited to {:.1} MB",
+            max_bytes as f64 / (1024.0 * 1024.0)
+        )
+    };
+    let mut image = Vec::new();
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|e| format!("The upload failed: {}", e))?;
+        let is_image = field.name() == Some(IMAGE_FIELD);
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| format!("The upload failed: {}", e))?;
+            if is_image {
+                if image.len() + chunk.len() > max_bytes {
+                    return Err(too_large());
+                }
+                image.extend_from_slice(&chunk);
+            }
+        }
+    }
+
+    if image.is_empty() {
+        return Err("Choose an image to upload".to_string());
+    }
+    Ok(image)
+}
+
+// Make the variants of an upload and store them. Returns their base key, or
+// the response to send.
+async fn store_image(
+    store: &dyn BlobStore,
+    kind: ImageKind,
+    bytes: Vec<u8>,
+    back: &str,
+) -> Result<String, HttpResponse> {
+    let base = images::base_key(kind, &bytes);
+    let thumbnails = match web::block(move || images::thumbnails(kind, &bytes)).await {
+        Ok(Ok(thumbnails)) => thumbnails,
+        Ok(Err(ImageError::Decode(_))) => {
+            return Err(redirect_with_notice(back, "The image could not be read"));
+        }
+        Ok(Err(e)) => return Err(redirect_with_notice(back, &e.to_string())),
+        Err(e) => return Err(HttpResponse::InternalServerError().body(e.to_string())),
+    };
+
+    for (variant, jpeg) in thumbnails {
+        let blob = Blob {
+            bytes: jpeg.into(),
+            content_type: "image/jpeg".to_string(),
+        };
+        store
+            .put(&images::variant_key(&base, &variant), blob)
+            .await
+            .map_err(storage_error)?;
+    }
+
+    Ok(base)
+}
+
+// Best effort: a failure leaves unused files behind but loses nothing
+async fn delete_variants(store: &dyn BlobStore, kind: ImageKind, base: &str) {
+    for key in images::variant_keys(kind, base) {
+        if let Err(e) = store.delete(&key).await {
+            eprintln!("Failed to delete {}: {}", key, e);
+        }
+    }
+}
+
+// Delete the files of an avatar nobody uses any more
+pub async fn release_avatar(store: &dyn BlobStore, users: &UserService, avatar_key: &str) {
+    match users.avatar_in_use(avatar_key).await {
+        Ok(false) => delete_variants(store, ImageKind::Avatar, avatar_key).await,
+        Ok(true) => {}
+        Err(e) => eprintln!("Failed to check avatar {}: {}", avatar_key, e),
+    }
+}
+
+async fn replace_avatar(
+    session: &Session,
+    audit: &AuditContext,
+    store: &dyn BlobStore,
+    users: &UserService,
+    avatar_key: Option<&str>,
+) -> Result<(), HttpResponse> {
+    let Some(user_id) = current_user_id(session) else {
+        return Err(login_redirect());
+    };
+
+    let previous = users
+        .set_avatar(user_id, avatar_key, audit)
+        .await
+        .map_err(database_error)?;
+    if let Some(previous) = previous.filter(|p| Some(p.as_str()) != avatar_key) {
+        release_avatar(store, users, &previous).await;
+    }
+
+    Ok(())
+}
+
+pub async fn upload_avatar(
+    payload: Multipart,
+    session: Session,
+    audit: AuditContext,
+    config: web::Data<UploadsConfig>,
+    store: web::Data<dyn BlobStore>,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    let back = "/profile";
+    if current_user_id(&session).is_none() {
+        return login_redirect();
+    }
+
+    let bytes = match read_image(payload, config.max_bytes).await {
+        Ok(bytes) => bytes,
+        Err(message) => return redirect_with_notice(back, &message),
+    };
+    let base = match store_image(&**store, ImageKind::Avatar, bytes, back).await {
+        Ok(base) => base,
+        Err(response) => return response,
+    };
+
+    match replace_avatar(&session, &audit, &**store, &users, Some(&base)).await {
+        Ok(()) => redirect_with_notice(back, "Avatar updated"),
+        Err(response) => response,
+    }
+}
+
+pub async fn remove_avatar(
+    session: Session,
+    audit: AuditContext,
+    store: web::Data<dyn BlobStore>,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    match replace_avatar(&session, &audit, &**store, &users, None).await {
+        Ok(()) => redirect_with_notice("/profile", "Avatar removed"),
+        Err(response) => response,
+    }
+}
+
+async fn replace_cover(
+    group_id: i32,
+    audit: &AuditContext,
+    store: &dyn BlobStore,
+    groups: &GroupService,
+    cover_key: Option<&str>,
+) -> Result<(), HttpResponse> {
+    let previous = groups
+        .set_cover(group_id, cover_key, audit)
+        .await
+        .map_err(database_error)?;
+    if let Some(previous) = previous.filter(|p| Some(p.as_str()) != cover_key) {
+        match groups.cover_in_use(&previous).await {
+            Ok(false) => delete_variants(store, ImageKind::Cover, &previous).await,
+            Ok(true) => {}
+            Err(e) => eprintln!("Failed to check cover {}: {}", previous, e),
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_cover(
+    path: web::Path<i32>,
+    payload: Multipart,
+    session: Session,
+    audit: AuditContext,
+    config: web::Data<UploadsConfig>,
+    store: web::Data<dyn BlobStore>,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let back = settings_url(group.id);
+
+    let bytes = match read_image(payload, config.max_bytes).await {
+        Ok(bytes) => bytes,
+        Err(message) => return redirect_with_notice(&back, &message),
+    };
+    let base = match store_image(&**store, ImageKind::Cover, bytes, &back).await {
+        Ok(base) => base,
+        Err(response) => return response,
+    };
+
+    match replace_cover(group.id, &audit, &**store, &groups, Some(&base)).await {
+        Ok(()) => redirect_with_notice(&back, "Cover image updated"),
+        Err(response) => response,
+    }
+}
+
+pub async fn remove_cover(
+    path: web::Path<i32>,
+    session: Session,
+    audit: AuditContext,
+    store: web::Data<dyn BlobStore>,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    match replace_cover(group.id, &audit, &**store, &groups, None).await {
+        Ok(()) => redirect_with_notice(&settings_url(group.id), "Cover image removed"),
+        Err(response) => response,
+    }
+}
+
+// Serve an uploaded image. Keys change whenever the content does, so browsers
+// and proxies may keep them forever.
+pub async fn media(
+    path: web::Path<String>,
+    request: HttpRequest,
+    store: web::Data<dyn BlobStore>,
+) -> HttpResponse {
+    let key = path.into_inner();
+    if !storage::valid_key(&key) || !images::is_image_key(&key) {
+        return HttpResponse::NotFound().body("Not found");
+    }
+
+    let etag = format!("\"{}\"", key.replace('/', "-"));
+    let cache_control = (header::CACHE_CONTROL, "public, max-age=31536000, immutable");
+    let matches = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag));
+
+    match store.get(&key).await {
+        Ok(Some(_)) if matches => HttpResponse::NotModified()
+            .insert_header(cache_control)
+            .insert_header((header::ETAG, etag))
+            .finish(),
+        Ok(Some(blob)) => HttpResponse::Ok()
+            .content_type(storage::content_type(&key))
+            .insert_header(cache_control)
+            .insert_header((header::ETAG, etag))
+            .body(blob.bytes),
+        Ok(None) => HttpResponse::NotFound().body("Not found"),
+        Err(e) => storage_error(e),
+    }
+}
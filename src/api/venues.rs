@@ -0,0 +1,327 @@
+use crate::api::context::{create_template_context, redirect_with_notice};
+use crate::api::groups_api::managed_group;
+use crate::db::{
+    audit::AuditContext,
+    geo::{GeoError, GeoService},
+    group::GroupService,
+    models::{Group, Venue},
+    user::UserService,
+    venue::{
+        MAX_ADDRESS_LENGTH, MAX_CAPACITY, MAX_NAME_LENGTH, MAX_NOTES_LENGTH, NewVenue, VenueError,
+        VenueService,
+    },
+};
+use crate::middleware::security_headers::CspNonce;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+#[derive(Deserialize)]
+pub struct VenuesQuery {
+    pub notice: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VenueForm {
+    pub name: String,
+    pub address: String,
+    // City or coordinates, optional
+    pub place: Option<String>,
+    pub accessibility_notes: String,
+    // Empty when not known
+    pub capacity: Option<String>,
+    pub directions: String,
+}
+
+fn render(tmpl: &Tera, name: &str, context: &tera::Context) -> HttpResponse {
+    let rendered = tmpl.render(name, context).unwrap_or_else(|e| {
+        eprintln!("Template error: {}", e);
+        "Template error".to_string()
+    });
+    HttpResponse::Ok().content_type("text/html").body(rendered)
+}
+
+fn database_error(e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::InternalServerError().body(format!("Database error: {}", e))
+}
+
+fn venues_url(group_id: i32) -> String {
+    format!("/groups/{}/venues", group_id)
+}
+
+fn check_length(value: &str, label: &str, max: usize) -> Result<(), String> {
+    if value.chars().count() > max {
+        return Err(format!("{} is limited to {} characters", label, max));
+    }
+    Ok(())
+}
+
+// The venue described by the form, without coordinates
+fn validate_venue(form: &VenueForm) -> Result<NewVenue, String> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err("Name cannot be empty".to_string());
+    }
+    check_length(name, "Name", MAX_NAME_LENGTH)?;
+    let address = form.address.trim();
+    check_length(address, "Address", MAX_ADDRESS_LENGTH)?;
+    let accessibility_notes = form.accessibility_notes.trim();
+    check_length(accessibility_notes, "Accessibility notes", MAX_NOTES_LENGTH)?;
+    let directions = form.directions.trim();
+    check_length(directions, "Directions", MAX_NOTES_LENGTH)?;
+
+    let capacity = match form.capacity.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(capacity) => match capacity.parse::<i32>() {
+            Ok(capacity) if (1..=MAX_CAPACITY).contains(&capacity) => Some(capacity),
+            _ => {
+                return Err(format!(
+                    "Capacity must be a number from 1 to {}",
+                    MAX_CAPACITY
+                ));
+            }
+        },
+    };
+
+    Ok(NewVenue {
+        name: name.to_string(),
+        address: address.to_string(),
+        point: None,
+        accessibility_notes: accessibility_notes.to_string(),
+        capacity,
+        directions: directions.to_string(),
+    })
+}
+
+// The venue with the coordinates of its typed place, or the message to show
+async fn venue_from_form(
+    form: &VenueForm,
+    geo: &GeoService,
+) -> Result<Result<NewVenue, String>, HttpResponse> {
+    let mut venue = match validate_venue(form) {
+        Ok(venue) => venue,
+        Err(error) => return Ok(Err(error)),
+    };
+    let place = form.place.as_deref().unwrap_or_default().trim();
+    if !place.is_empty() {
+        match geo.locate(place).await {
+            Ok((_, point)) => venue.point = Some(point),
+            Err(e @ (GeoError::InvalidPlace(_) | GeoError::UnknownCity(_))) => {
+                return Ok(Err(e.to_string()));
+            }
+            Err(e) => return Err(database_error(e)),
+        }
+    }
+
+    Ok(Ok(venue))
+}
+
+// One of the group's venues
+async fn group_venue(
+    venues: &VenueService,
+    group: &Group,
+    venue_id: i32,
+) -> Result<Venue, HttpResponse> {
+    match venues.get(venue_id).await {
+        Ok(Some(venue)) if venue.group_id == group.id => Ok(venue),
+        Ok(_) => Err(HttpResponse::NotFound().body("Venue not found")),
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+async fn venues_response(
+    tmpl: &Tera,
+    session: &Session,
+    nonce: &CspNonce,
+    group: &Group,
+    venues: &VenueService,
+    notice: Option<&str>,
+    form: Option<(&VenueForm, &str)>,
+) -> HttpResponse {
+    let group_venues = match venues.list_for_group(group.id).await {
+        Ok(group_venues) => group_venues,
+        Err(e) => return database_error(e),
+    };
+
+    let mut context = create_template_context(session, nonce);
+    context.insert("group", group);
+    context.insert("venues", &group_venues);
+    context.insert("notice", &notice);
+    context.insert("form", &form.map(|(f, _)| f));
+    context.insert("error", &form.map(|(_, error)| error));
+    context.insert("max_capacity", &MAX_CAPACITY);
+
+    render(tmpl, "group_venues.html", &context)
+}
+
+// The group's venues, for its owner and admins
+#[allow(clippy::too_many_arguments)]
+pub async fn venues_page(
+    path: web::Path<i32>,
+    query: web::Query<VenuesQuery>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    venues: web::Data<VenueService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    venues_response(
+        &tmpl,
+        &session,
+        &nonce,
+        &group,
+        &venues,
+        query.notice.as_deref(),
+        None,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_venue(
+    path: web::Path<i32>,
+    form: web::Form<VenueForm>,
+    tmpl: web::Data<Tera>,
+    session: Session,
+    nonce: CspNonce,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    venues: web::Data<VenueService>,
+    geo: web::Data<GeoService>,
+) -> HttpResponse {
+    let group = match managed_group(&session, &groups, &users, path.into_inner()).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    let venue = match venue_from_form(&form, &geo).await {
+        Ok(Ok(venue)) => venue,
+        Ok(Err(error)) => {
+            return venues_response(
+                &tmpl,
+                &session,
+                &nonce,
+                &group,
+                &venues,
+                None,
+                Some((&form, &error)),
+            )
+            .await;
+        }
+        Err(response) => return response,
+    };
+
+    match venues.create(group.id, &venue, &audit).await {
+        Ok(venue) => redirect_with_notice(
+            &venues_url(group.id),
+            &format!("Venue {} added", venue.name),
+        ),
+        Err(e) => database_error(e),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn update_venue(
+    path: web::Path<(i32, i32)>,
+    form: web::Form<VenueForm>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    venues: web::Data<VenueService>,
+    geo: web::Data<GeoService>,
+) -> HttpResponse {
+    let (group_id, venue_id) = path.into_inner();
+    let group = match managed_group(&session, &groups, &users, group_id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let back = venues_url(group.id);
+    let current = match group_venue(&venues, &group, venue_id).await {
+        Ok(venue) => venue,
+        Err(response) => return response,
+    };
+
+    let venue = match venue_from_form(&form, &geo).await {
+        Ok(Ok(venue)) => venue,
+        Ok(Err(error)) => {
+            return redirect_with_notice(&back, &format!("{}: {}", current.name, error));
+        }
+        Err(response) => return response,
+    };
+
+    match venues.update(current.id, &venue, Utc::now(), &audit).await {
+        Ok(venue) => redirect_with_notice(&back, &format!("Venue {} updated", venue.name)),
+        Err(VenueError::NotFound) => HttpResponse::NotFound().body("Venue not found"),
+        Err(e) => database_error(e),
+    }
+}
+
+// Events at the venue keep their location
+pub async fn delete_venue(
+    path: web::Path<(i32, i32)>,
+    session: Session,
+    audit: AuditContext,
+    groups: web::Data<GroupService>,
+    users: web::Data<UserService>,
+    venues: web::Data<VenueService>,
+) -> HttpResponse {
+    let (group_id, venue_id) = path.into_inner();
+    let group = match managed_group(&session, &groups, &users, group_id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let current = match group_venue(&venues, &group, venue_id).await {
+        Ok(venue) => venue,
+        Err(response) => return response,
+    };
+
+    match venues.delete(current.id, &audit).await {
+        Ok(venue) => redirect_with_notice(
+            &venues_url(group.id),
+            &format!("Venue {} removed", venue.name),
+        ),
+        Err(VenueError::NotFound) => HttpResponse::NotFound().body("Venue not found"),
+        Err(e) => database_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(name: &str, capacity: &str) -> VenueForm {
+        VenueForm {
+            name: name.to_string(),
+            address: " 1 High Street ".to_string(),
+            place: None,
+            accessibility_notes: "Step-free entrance".to_string(),
+            capacity: Some(capacity.to_string()),
+            directions: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate_venue() {
+        let venue = validate_venue(&form(" The Crown ", "40")).unwrap();
+        assert_eq!(venue.name, "The Crown");
+        assert_eq!(venue.address, "1 High Street");
+        assert_eq!(venue.capacity, Some(40));
+        assert_eq!(venue.point, None);
+        assert_eq!(validate_venue(&form("Hall", " ")).unwrap().capacity, None);
+
+        assert!(validate_venue(&form("  ", "40")).is_err());
+        assert!(validate_venue(&form("Hall", "0")).is_err());
+        assert!(validate_venue(&form("Hall", "lots")).is_err());
+        assert!(validate_venue(&form(&"x".repeat(MAX_NAME_LENGTH + 1), "")).is_err());
+    }
+}
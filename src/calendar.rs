@@ -0,0 +1,476 @@
+use crate::db::models::{Event, Recurrence};
+use chrono::{
+    DateTime, Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+// Days listed by the agenda view
+pub const AGENDA_DAYS: u64 = 30;
+// Occurrences of one event listed at most, whatever the range
+const MAX_OCCURRENCES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarView {
+    Month,
+    Week,
+    Agenda,
+}
+
+impl CalendarView {
+    // The view named in a query, the month by default
+    pub fn parse(value: Option<&str>) -> Option<Self> {
+        match value.map(str::trim) {
+            None | Some("") | Some("month") => Some(CalendarView::Month),
+            Some("week") => Some(CalendarView::Week),
+            Some("agenda") => Some(CalendarView::Agenda),
+            Some(_) => None,
+        }
+    }
+
+    // The days shown around `date`: the first one and the one after the
+    // last. Month views are whole weeks, Monday first.
+    pub fn range(&self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            CalendarView::Month => {
+                let first = date.with_day(1).unwrap_or(date);
+                let next = first + Months::new(1);
+                (week_start(first), week_start(next + Days::new(6)))
+            }
+            CalendarView::Week => {
+                let start = week_start(date);
+                (start, start + Days::new(7))
+            }
+            CalendarView::Agenda => (date, date + Days::new(AGENDA_DAYS)),
+        }
+    }
+
+    // The date to show when going back or forward
+    pub fn step(&self, date: NaiveDate, forward: bool) -> NaiveDate {
+        match (self, forward) {
+            (CalendarView::Month, true) => date.with_day(1).unwrap_or(date) + Months::new(1),
+            (CalendarView::Month, false) => date.with_day(1).unwrap_or(date) - Months::new(1),
+            (CalendarView::Week, true) => date + Days::new(7),
+            (CalendarView::Week, false) => date - Days::new(7),
+            (CalendarView::Agenda, true) => date + Days::new(AGENDA_DAYS),
+            (CalendarView::Agenda, false) => date - Days::new(AGENDA_DAYS),
+        }
+    }
+
+    fn title(&self, date: NaiveDate) -> String {
+        match self {
+            CalendarView::Month => date.format("%B %Y").to_string(),
+            CalendarView::Week => format!("Week of {}", week_start(date).format("%-d %B %Y")),
+            CalendarView::Agenda => format!("From {}", date.format("%-d %B %Y")),
+        }
+    }
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday().into())
+}
+
+// The time a local time in the zone falls on. Ambiguous times are the
+// earlier one, times skipped by a daylight saving change the hour after.
+fn local_to_utc(tz: Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|at| at.with_timezone(&Utc))
+}
+
+// Midnight in the time zone at the start of both days
+pub fn day_bounds(first: NaiveDate, end: NaiveDate, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+    let midnight = |day: NaiveDate| {
+        let local = day.and_time(NaiveTime::MIN);
+        local_to_utc(tz, local).unwrap_or_else(|| local.and_utc())
+    };
+    (midnight(first), midnight(end))
+}
+
+impl Recurrence {
+    // Start of the occurrence after `n` repetitions of the first one, at the
+    // same local time in `tz` whatever daylight saving changes come between.
+    // Monthly events on the 29th to the 31st fall on the last day of shorter
+    // months.
+    pub fn nth_start(&self, first: DateTime<Utc>, n: u32, tz: Tz) -> Option<DateTime<Utc>> {
+        let first = first.with_timezone(&tz).naive_local();
+        let local = match self {
+            Recurrence::Weekly => first.checked_add_signed(Duration::weeks(n.into())),
+            Recurrence::Biweekly => first.checked_add_signed(Duration::weeks(2 * i64::from(n))),
+            Recurrence::Monthly => first.checked_add_months(Months::new(n)),
+        }?;
+        local_to_utc(tz, local)
+    }
+
+    // Repetitions that all start before `at`, to skip without computing them.
+    // Occurrences move by an hour at most with daylight saving, which the
+    // count rounding down leaves room for.
+    fn repetitions_before(&self, first: DateTime<Utc>, at: DateTime<Utc>, tz: Tz) -> u32 {
+        if at <= first {
+            return 0;
+        }
+        let (first, at) = (first.with_timezone(&tz), at.with_timezone(&tz));
+        let repetitions = match self {
+            Recurrence::Weekly => (at - first).num_weeks(),
+            Recurrence::Biweekly => (at - first).num_weeks() / 2,
+            Recurrence::Monthly => {
+                i64::from(at.year() - first.year()) * 12 + i64::from(at.month())
+                    - i64::from(first.month())
+                    - 1
+            }
+        };
+        u32::try_from(repetitions.max(0)).unwrap_or(u32::MAX)
+    }
+}
+
+// One time an event takes place
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Occurrence {
+    pub event_id: i32,
+    pub group_id: i32,
+    pub group_name: String,
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub location: Option<String>,
+    pub is_online: bool,
+    pub recurrence: Option<Recurrence>,
+}
+
+// The times the event takes place in [from, to): those starting before `to`
+// and not over by `from`
+pub fn occurrences(
+    event: &Event,
+    group_name: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<Occurrence> {
+    let duration = event
+        .ends_at
+        .map(|ends_at| ends_at - event.starts_at)
+        .unwrap_or_default();
+    let occurrence = |starts_at: DateTime<Utc>| Occurrence {
+        event_id: event.id,
+        group_id: event.group_id,
+        group_name: group_name.to_string(),
+        title: event.title.clone(),
+        starts_at,
+        ends_at: event.ends_at.map(|_| starts_at + duration),
+        location: event.location.clone(),
+        is_online: event.is_online(),
+        recurrence: event.recurrence,
+    };
+
+    let Some(recurrence) = event.recurrence else {
+        if event.starts_at < to && event.starts_at + duration >= from {
+            return vec![occurrence(event.starts_at)];
+        }
+        return Vec::new();
+    };
+
+    let tz = event.tz();
+    let mut found = Vec::new();
+    let mut n = recurrence.repetitions_before(event.starts_at, from - duration, tz);
+    while found.len() < MAX_OCCURRENCES {
+        let Some(starts_at) = recurrence.nth_start(event.starts_at, n, tz) else {
+            break;
+        };
+        if starts_at >= to || event.recurs_until.is_some_and(|until| starts_at > until) {
+            break;
+        }
+        if starts_at + duration >= from {
+            found.push(occurrence(starts_at));
+        }
+        n += 1;
+    }
+    found
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Day {
+    pub date: NaiveDate,
+    // False for the days of other months completing the weeks of a month
+    pub in_period: bool,
+    pub is_today: bool,
+    pub occurrences: Vec<Occurrence>,
+}
+
+// What a calendar view shows, ready for the templates
+#[derive(Debug, Clone, Serialize)]
+pub struct Calendar {
+    pub view: CalendarView,
+    pub date: NaiveDate,
+    pub title: String,
+    pub previous: NaiveDate,
+    pub next: NaiveDate,
+    pub today: NaiveDate,
+    // Month and week views: rows of seven days, Monday first
+    pub weeks: Vec<Vec<Day>>,
+    // Agenda view: the days with occurrences
+    pub days: Vec<Day>,
+    // The viewer's, days and times are shown in it
+    pub time_zone: String,
+}
+
+// Lay out the occurrences of the range of `view` around `date`. Each is
+// shown on the day it starts in `tz`, or the first day if it started before.
+pub fn build(
+    view: CalendarView,
+    date: NaiveDate,
+    today: NaiveDate,
+    mut occurrences: Vec<Occurrence>,
+    tz: Tz,
+) -> Calendar {
+    let (first, end) = view.range(date);
+    occurrences.sort_by(|a, b| {
+        a.starts_at
+            .cmp(&b.starts_at)
+            .then(a.event_id.cmp(&b.event_id))
+    });
+
+    let mut days: Vec<Day> = first
+        .iter_days()
+        .take_while(|day| *day < end)
+        .map(|day| Day {
+            date: day,
+            in_period: view != CalendarView::Month || day.month() == date.month(),
+            is_today: day == today,
+            occurrences: Vec::new(),
+        })
+        .collect();
+    for occurrence in occurrences {
+        let start = occurrence
+            .starts_at
+            .with_timezone(&tz)
+            .date_naive()
+            .max(first);
+        if let Some(day) = days.get_mut((start - first).num_days() as usize) {
+            day.occurrences.push(occurrence);
+        }
+    }
+
+    let (weeks, days) = match view {
+        CalendarView::Agenda => (
+            Vec::new(),
+            days.into_iter()
+                .filter(|day| !day.occurrences.is_empty())
+                .collect(),
+        ),
+        _ => (days.chunks(7).map(<[Day]>::to_vec).collect(), Vec::new()),
+    };
+
+    Calendar {
+        view,
+        date,
+        title: view.title(date),
+        previous: view.step(date, false),
+        next: view.step(date, true),
+        today,
+        weeks,
+        days,
+        time_zone: tz.name().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn event(starts_at: &str, recurrence: Option<Recurrence>) -> Event {
+        let starts_at = at(starts_at);
+        Event {
+            id: 1,
+            group_id: 1,
+            title: "Meetup".to_string(),
+            starts_at,
+            ends_at: Some(starts_at + Duration::hours(2)),
+            created_at: starts_at,
+            recurrence,
+            time_zone: "UTC".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn starts(event: &Event, from: &str, to: &str) -> Vec<DateTime<Utc>> {
+        occurrences(event, "Group", at(from), at(to))
+            .into_iter()
+            .map(|occurrence| occurrence.starts_at)
+            .collect()
+    }
+
+    #[test]
+    fn test_view_range() {
+        // March 2026 starts on a Sunday and ends on a Tuesday
+        assert_eq!(
+            CalendarView::Month.range(date("2026-03-18")),
+            (date("2026-02-23"), date("2026-04-06"))
+        );
+        // February 2021 is exactly four weeks
+        assert_eq!(
+            CalendarView::Month.range(date("2021-02-10")),
+            (date("2021-02-01"), date("2021-03-01"))
+        );
+        assert_eq!(
+            CalendarView::Week.range(date("2026-03-18")),
+            (date("2026-03-16"), date("2026-03-23"))
+        );
+        assert_eq!(
+            CalendarView::Agenda.range(date("2026-03-18")),
+            (date("2026-03-18"), date("2026-04-17"))
+        );
+        assert_eq!(
+            CalendarView::Month.step(date("2026-01-31"), true),
+            date("2026-02-01")
+        );
+        assert_eq!(CalendarView::parse(Some("day")), None);
+        assert_eq!(CalendarView::parse(None), Some(CalendarView::Month));
+    }
+
+    #[test]
+    fn test_single_occurrence() {
+        let once = event("2026-03-10T18:00:00Z", None);
+        assert_eq!(
+            starts(&once, "2026-03-01T00:00:00Z", "2026-04-01T00:00:00Z").len(),
+            1
+        );
+        // Still going at the start of the range
+        assert_eq!(
+            starts(&once, "2026-03-10T19:00:00Z", "2026-04-01T00:00:00Z").len(),
+            1
+        );
+        assert!(starts(&once, "2026-03-10T21:00:00Z", "2026-04-01T00:00:00Z").is_empty());
+        assert!(starts(&once, "2026-02-01T00:00:00Z", "2026-03-10T18:00:00Z").is_empty());
+    }
+
+    #[test]
+    fn test_recurring_occurrences() {
+        let weekly = event("2025-01-07T18:00:00Z", Some(Recurrence::Weekly));
+        assert_eq!(
+            starts(&weekly, "2026-03-01T00:00:00Z", "2026-03-15T00:00:00Z"),
+            vec![at("2026-03-03T18:00:00Z"), at("2026-03-10T18:00:00Z")]
+        );
+
+        let biweekly = event("2026-03-03T18:00:00Z", Some(Recurrence::Biweekly));
+        assert_eq!(
+            starts(&biweekly, "2026-03-04T00:00:00Z", "2026-04-01T00:00:00Z"),
+            vec![at("2026-03-17T18:00:00Z"), at("2026-03-31T18:00:00Z")]
+        );
+
+        let mut monthly = event("2026-01-31T18:00:00Z", Some(Recurrence::Monthly));
+        assert_eq!(
+            starts(&monthly, "2026-02-01T00:00:00Z", "2026-05-01T00:00:00Z"),
+            vec![
+                at("2026-02-28T18:00:00Z"),
+                at("2026-03-31T18:00:00Z"),
+                at("2026-04-30T18:00:00Z")
+            ]
+        );
+        monthly.recurs_until = Some(at("2026-03-31T18:00:00Z"));
+        assert_eq!(
+            starts(&monthly, "2026-02-01T00:00:00Z", "2026-05-01T00:00:00Z").len(),
+            2
+        );
+
+        // An occurrence that started the evening before the range
+        let mut overnight = event("2026-03-02T22:00:00Z", Some(Recurrence::Weekly));
+        overnight.ends_at = Some(at("2026-03-03T02:00:00Z"));
+        let found = occurrences(
+            &overnight,
+            "Group",
+            at("2026-03-10T00:00:00Z"),
+            at("2026-03-11T00:00:00Z"),
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].starts_at, at("2026-03-09T22:00:00Z"));
+        assert_eq!(found[0].ends_at, Some(at("2026-03-10T02:00:00Z")));
+    }
+
+    #[test]
+    fn test_build() {
+        let weekly = event("2026-03-03T18:00:00Z", Some(Recurrence::Weekly));
+        let (first, end) = CalendarView::Month.range(date("2026-03-01"));
+        let (from, to) = day_bounds(first, end, Tz::UTC);
+        let found = occurrences(&weekly, "Group", from, to);
+
+        let month = build(
+            CalendarView::Month,
+            date("2026-03-01"),
+            date("2026-03-10"),
+            found.clone(),
+            Tz::UTC,
+        );
+        assert_eq!(month.title, "March 2026");
+        assert_eq!(month.weeks.len(), 6);
+        assert!(!month.weeks[0][0].in_period);
+        assert_eq!(month.weeks[1][1].date, date("2026-03-03"));
+        assert_eq!(month.weeks[1][1].occurrences.len(), 1);
+        assert!(month.weeks[2][1].is_today);
+        assert_eq!(month.previous, date("2026-02-01"));
+
+        let agenda = build(
+            CalendarView::Agenda,
+            date("2026-03-01"),
+            date("2026-03-10"),
+            found,
+            Tz::UTC,
+        );
+        assert!(agenda.weeks.is_empty());
+        // The 31st is the day after the last of the agenda
+        assert_eq!(agenda.days.len(), 4);
+        assert_eq!(agenda.days[0].date, date("2026-03-03"));
+    }
+
+    #[test]
+    fn test_daylight_saving() {
+        // 19:00 in Berlin every week, an hour earlier in UTC once summer time
+        // starts on 29 March
+        let mut weekly = event("2026-03-17T18:00:00Z", Some(Recurrence::Weekly));
+        weekly.time_zone = "Europe/Berlin".to_string();
+        assert_eq!(
+            starts(&weekly, "2026-03-16T00:00:00Z", "2026-04-08T00:00:00Z"),
+            vec![
+                at("2026-03-17T18:00:00Z"),
+                at("2026-03-24T18:00:00Z"),
+                at("2026-03-31T17:00:00Z"),
+                at("2026-04-07T17:00:00Z")
+            ]
+        );
+        // Found the same way when the range starts past the change
+        assert_eq!(
+            starts(&weekly, "2026-03-31T17:30:00Z", "2026-04-08T00:00:00Z"),
+            vec![at("2026-03-31T17:00:00Z"), at("2026-04-07T17:00:00Z")]
+        );
+
+        // Days are laid out in the viewer's time zone: the evening of the 4th
+        // in New York is already the 5th in UTC
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        let (first, end) = CalendarView::Week.range(date("2026-03-04"));
+        let (from, to) = day_bounds(first, end, new_york);
+        assert_eq!(from, at("2026-03-02T05:00:00Z"));
+        assert_eq!(to, at("2026-03-09T04:00:00Z"));
+        let late = event("2026-03-05T02:00:00Z", None);
+        let week = build(
+            CalendarView::Week,
+            date("2026-03-04"),
+            date("2026-03-04"),
+            occurrences(&late, "Group", from, to),
+            new_york,
+        );
+        assert_eq!(week.weeks[0][2].date, date("2026-03-04"));
+        assert_eq!(week.weeks[0][2].occurrences.len(), 1);
+        assert_eq!(week.time_zone, "America/New_York");
+    }
+}
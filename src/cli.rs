@@ -0,0 +1,356 @@
+use crate::db::{
+    self,
+    audit::AuditContext,
+    connection::{DatabaseError, DbPool},
+    geo::{GeoError, GeoService},
+    group::{GroupError, GroupService},
+    user::{UserError, UserService},
+};
+use crate::geo::parse_geonames_line;
+use crate::password::hash_password;
+use chrono::{Duration, Utc};
+use clap::{Parser, Subcommand};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+};
+use thiserror::Error;
+
+// Cities written to the database per transaction when importing
+const CITY_BATCH: usize = 1000;
+
+// Same minimum as the registration form
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "groups",
+    version,
+    about = "Groups web application and admin tools"
+)]
+pub struct Cli {
+    /// TOML config file (defaults to GROUPS_CONFIG or ./config.toml)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration with secrets redacted and exit
+    #[arg(long, global = true)]
+    pub print_config: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Run migrations and start the web server (default)
+    Serve,
+    /// Process background jobs without serving HTTP
+    Worker,
+    /// Apply database migrations and exit
+    Migrate,
+    /// Create an administrator account
+    CreateAdmin {
+        #[arg(long)]
+        email: String,
+        /// Display name, defaults to the email local part
+        #[arg(long)]
+        name: Option<String>,
+        /// Read from stdin when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Set a new password for an existing user
+    ResetPassword {
+        #[arg(long)]
+        email: String,
+        /// Read from stdin when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Soft-delete a user account
+    SoftDeleteUser {
+        #[arg(long)]
+        email: String,
+    },
+    /// Permanently remove users and groups that were soft-deleted long enough ago
+    PurgeDeleted {
+        #[arg(long, default_value_t = 30)]
+        older_than_days: i64,
+        /// Only list what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Insert development seed data
+    Seed,
+    /// Load cities for geocoding from a GeoNames dump such as cities15000.txt
+    ImportCities {
+        #[arg(long)]
+        file: PathBuf,
+        /// Skip smaller cities
+        #[arg(long, default_value_t = 0)]
+        min_population: i64,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("No user with email {0}")]
+    UserNotFound(String),
+    #[error("A user with email {0} already exists")]
+    UserExists(String),
+    #[error("Password must be at least {MIN_PASSWORD_LENGTH} characters")]
+    PasswordTooShort,
+    #[error("Failed to hash password: {0}")]
+    PasswordHash(String),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Cannot read input: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    User(#[from] UserError),
+    #[error("{0}")]
+    Group(#[from] GroupError),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
+    #[error("{0}")]
+    Geo(#[from] GeoError),
+}
+
+// Run an admin command against the database; `Serve` and `Worker` are handled
+// by the binary
+pub async fn run(command: Command, pool: &DbPool) -> Result<(), CliError> {
+    let users = UserService::new(pool.clone());
+    let groups = GroupService::new(pool.clone());
+    // Changes made from the command line are audited without an actor
+    let audit = AuditContext::system();
+
+    match command {
+        Command::Serve | Command::Worker => Err(CliError::Invalid(
+            "serve and worker are not admin commands".to_string(),
+        )),
+        Command::Migrate => {
+            db::run_migrations(pool).await?;
+            println!("Migrations applied");
+            Ok(())
+        }
+        Command::Seed => {
+            db::run_seed(pool).await?;
+            println!("Seed data inserted");
+            Ok(())
+        }
+        Command::CreateAdmin {
+            email,
+            name,
+            password,
+        } => {
+            if users.get_by_email(email.clone()).await?.is_some() {
+                return Err(CliError::UserExists(email));
+            }
+
+            let password = resolve_password(password)?;
+            let name = name.unwrap_or_else(|| default_name(&email));
+            let password_hash = hash(&password)?;
+            let user = users
+                .create_with_password(email, name, password_hash, &audit)
+                .await?;
+            users.set_admin(user.id, true, &audit).await?;
+
+            println!("Created admin {} (id {})", user.email, user.id);
+            Ok(())
+        }
+        Command::ResetPassword { email, password } => {
+            let user = users
+                .get_by_email(email.clone())
+                .await?
+                .filter(|u| u.deleted_at.is_none())
+                .ok_or(CliError::UserNotFound(email))?;
+
+            let password = resolve_password(password)?;
+            // Also ends every session of the user
+            users
+                .change_password(user.id, &hash(&password)?, &audit)
+                .await?;
+
+            println!("Password reset for {}", user.email);
+            Ok(())
+        }
+        Command::SoftDeleteUser { email } => {
+            let user = users
+                .get_by_email(email.clone())
+                .await?
+                .filter(|u| u.deleted_at.is_none())
+                .ok_or(CliError::UserNotFound(email))?;
+
+            users.delete(user.id, &audit).await?;
+
+            println!("Soft-deleted {} (id {})", user.email, user.id);
+            Ok(())
+        }
+        Command::PurgeDeleted {
+            older_than_days,
+            dry_run,
+        } => {
+            if older_than_days < 0 {
+                return Err(CliError::Invalid(
+                    "--older-than-days must not be negative".to_string(),
+                ));
+            }
+            let cutoff = Utc::now() - Duration::days(older_than_days);
+            let prefix = if dry_run { "Would purge" } else { "Purged" };
+
+            for group in groups.list_deleted_before(cutoff).await? {
+                if !dry_run {
+                    groups.hard_delete(group.id, &audit).await?;
+                }
+                println!("{} group {} ({})", prefix, group.id, group.name);
+            }
+            for user in users.list_deleted_before(cutoff).await? {
+                if !dry_run {
+                    users.hard_delete(user.id, &audit).await?;
+                }
+                println!("{} user {} ({})", prefix, user.id, user.email);
+            }
+
+            Ok(())
+        }
+        Command::ImportCities {
+            file,
+            min_population,
+        } => {
+            let geo = GeoService::new(pool.clone());
+            let reader = BufReader::new(File::open(&file)?);
+            let (mut imported, mut skipped) = (0, 0);
+            let mut batch = Vec::with_capacity(CITY_BATCH);
+
+            for line in reader.lines() {
+                match parse_geonames_line(&line?) {
+                    Some(city) if city.population >= min_population => batch.push(city),
+                    _ => skipped += 1,
+                }
+                if batch.len() == CITY_BATCH {
+                    imported += geo.import_cities(&batch).await?;
+                    batch.clear();
+                }
+            }
+            imported += geo.import_cities(&batch).await?;
+
+            println!("Imported {} cities, skipped {} lines", imported, skipped);
+            Ok(())
+        }
+    }
+}
+
+// Name derived from the email local part, as registration does
+fn default_name(email: &str) -> String {
+    email.split('@').next().unwrap_or("User").to_string()
+}
+
+fn hash(password: &str) -> Result<String, CliError> {
+    hash_password(password.as_bytes()).map_err(|e| CliError::PasswordHash(e.to_string()))
+}
+
+// Use the --password value or read a single line from stdin, so passwords can
+// be piped in instead of ending up in the shell history
+fn resolve_password(password: Option<String>) -> Result<String, CliError> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            eprint!("Password: ");
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    validate_password(&password)?;
+    Ok(password)
+}
+
+fn validate_password(password: &str) -> Result<(), CliError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(CliError::PasswordTooShort);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+
+    #[test]
+    fn test_defaults_to_no_subcommand() {
+        let cli = Cli::try_parse_from(["groups"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(!cli.print_config);
+    }
+
+    #[test]
+    fn test_parses_admin_subcommands() {
+        let cli = Cli::try_parse_from([
+            "groups",
+            "--config",
+            "prod.toml",
+            "purge-deleted",
+            "--older-than-days",
+            "7",
+            "--dry-run",
+        ])
+        .unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("prod.toml")));
+        assert!(matches!(
+            cli.command,
+            Some(Command::PurgeDeleted {
+                older_than_days: 7,
+                dry_run: true
+            })
+        ));
+
+        let cli =
+            Cli::try_parse_from(["groups", "create-admin", "--email", "ops@example.com"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::CreateAdmin { ref email, name: None, password: None }) if email == "ops@example.com"
+        ));
+
+        assert!(Cli::try_parse_from(["groups", "soft-delete-user"]).is_err());
+
+        let cli =
+            Cli::try_parse_from(["groups", "import-cities", "--file", "cities15000.txt"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::ImportCities { ref file, min_population: 0 }) if file == &PathBuf::from("cities15000.txt")
+        ));
+    }
+
+    #[test]
+    fn test_password_rules() {
+        assert!(validate_password("short").is_err());
+        assert!(validate_password("long enough").is_ok());
+        assert_eq!(default_name("ops@example.com"), "ops");
+    }
+
+    #[tokio::test]
+    async fn test_reset_password_ends_sessions() {
+        let db = create_test_pool().await.unwrap();
+        let users = UserService::new(db.pool.clone());
+        let [ada] = db.create_users(["Ada"]).await;
+        let version = users.session_version(ada.id).await.unwrap().unwrap();
+
+        let reset = Command::ResetPassword {
+            email: ada.email.clone(),
+            password: Some("long enough".to_string()),
+        };
+        run(reset, &db.pool).await.unwrap();
+
+        let ada = users.get_by_id(ada.id).await.unwrap().unwrap();
+        assert!(ada.password_hash.is_some());
+        assert_eq!(
+            users.session_version(ada.id).await.unwrap(),
+            Some(version + 1)
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::AdminAction;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AdminActionError {
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+pub struct AdminActionService {
+    pool: DbPool,
+}
+
+impl AdminActionService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Append an entry to the audit trail
+    pub async fn record(
+        &self,
+        admin_id: i32,
+        action: &str,
+        target_type: &str,
+        target_id: i32,
+        details: &str,
+    ) -> Result<AdminAction, AdminActionError> {
+        let entry = sqlx::query_as::<_, AdminAction>(
+            "INSERT INTO admin_actions (admin_id, action, target_type, target_id, details) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        )
+        .bind(admin_id)
+        .bind(action)
+        .bind(target_type)
+        .bind(target_id)
+        .bind(details)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(entry)
+    }
+
+    // Most recent entries first
+    pub async fn list_recent(&self, limit: i64) -> Result<Vec<AdminAction>, AdminActionError> {
+        let actions = sqlx::query_as::<_, AdminAction>(
+            "SELECT * FROM admin_actions ORDER BY created_at DESC, id DESC LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+
+    #[tokio::test]
+    async fn test_list_recent_comes_newest_first() {
+        let db = create_test_pool().await.unwrap();
+        let actions = AdminActionService::new(db.pool.clone());
+
+        let [admin] = db.create_users(["Admin"]).await;
+        actions
+            .record(admin.id, "suspend", "user", 7, "spam")
+            .await
+            .unwrap();
+        actions
+            .record(admin.id, "delete", "group", 3, "")
+            .await
+            .unwrap();
+
+        let recent = actions.list_recent(10).await.unwrap();
+        let recorded: Vec<(&str, &str, i32)> = recent
+            .iter()
+            .map(|a| (a.action.as_str(), a.target_type.as_str(), a.target_id))
+            .collect();
+        assert_eq!(recorded, [("delete", "group", 3), ("suspend", "user", 7)]);
+        assert_eq!(recent[1].admin_id, Some(admin.id));
+        assert_eq!(actions.list_recent(1).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,503 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::event::not_over;
+use crate::db::models::{Attendance, CheckInMethod, RsvpStatus};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "event_attendance";
+
+// Members listed at most when searching the check-in list
+pub const MAX_SEARCH_RESULTS: i64 = 100;
+
+#[derive(Debug, Error)]
+pub enum AttendanceError {
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// Someone hosts may check in at an event: a member of its group, someone who
+// answered, or someone already checked in
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Attendee {
+    pub user_id: i32,
+    pub name: String,
+    pub status: Option<RsvpStatus>,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    pub method: Option<CheckInMethod>,
+}
+
+// Who said they were going to an event and who showed up. Walk-ins are those
+// checked in without having said they were going.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, FromRow)]
+pub struct AttendanceSummary {
+    pub going: i64,
+    pub checked_in: i64,
+    pub no_shows: i64,
+    pub walk_ins: i64,
+}
+
+// The attendance of one of a group's past events
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct EventAttendance {
+    pub event_id: i32,
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub summary: AttendanceSummary,
+}
+
+// How often a member showed up at a group's events they said they were
+// going to, counting only the events where attendance was taken
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct MemberAttendance {
+    pub user_id: i32,
+    pub name: String,
+    pub going: i64,
+    pub attended: i64,
+    pub no_shows: i64,
+}
+
+// Counts per event for the events in the `counted` CTE (id, title,
+// starts_at): everyone who said they were going or was checked in
+fn summary_sql(counted: &str) -> String {
+    format!(
+        "WITH counted AS ({counted}), \
+         people AS ( \
+             SELECT r.event_id, r.user_id FROM event_rsvps r JOIN counted c ON c.id = r.event_id \
+             WHERE r.status = 'going' \
+             UNION \
+             SELECT a.event_id, a.user_id FROM event_attendance a JOIN counted c ON c.id = a.event_id \
+         ) \
+         SELECT c.id AS event_id, c.title, c.starts_at, \
+                COUNT(*) FILTER (WHERE r.status = 'going') AS going, \
+                COUNT(a.id) AS checked_in, \
+                COUNT(*) FILTER (WHERE r.status = 'going' AND a.id IS NULL) AS no_shows, \
+                COUNT(a.id) FILTER (WHERE r.status IS DISTINCT FROM 'going') AS walk_ins \
+         FROM counted c \
+         LEFT JOIN people p ON p.event_id = c.id \
+         LEFT JOIN event_rsvps r ON r.event_id = p.event_id AND r.user_id = p.user_id \
+         LEFT JOIN event_attendance a ON a.event_id = p.event_id AND a.user_id = p.user_id \
+         GROUP BY c.id, c.title, c.starts_at ORDER BY c.starts_at DESC, c.id DESC"
+    )
+}
+
+pub struct AttendanceService {
+    pool: DbPool,
+}
+
+impl AttendanceService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Those who answered going or maybe and those already checked in, by
+    // name. With `search`, the members of the group whose name contains it.
+    pub async fn attendees(
+        &self,
+        event_id: i32,
+        group_id: i32,
+        search: Option<&str>,
+    ) -> Result<Vec<Attendee>, AttendanceError> {
+        let pattern = search
+            .map(str::trim)
+            .filter(|search| !search.is_empty())
+            .map(|search| format!("%{}%", search));
+        let attendees = sqlx::query_as::<_, Attendee>(
+            "SELECT u.id AS user_id, u.name, r.status, a.checked_in_at, a.method FROM users u \
+             LEFT JOIN event_rsvps r ON r.event_id = $1 AND r.user_id = u.id \
+             LEFT JOIN event_attendance a ON a.event_id = $1 AND a.user_id = u.id \
+             WHERE u.deleted_at IS NULL AND u.id IN ( \
+                 SELECT user_id FROM group_members WHERE group_id = $2 \
+                 UNION SELECT user_id FROM event_rsvps WHERE event_id = $1 \
+                 UNION SELECT user_id FROM event_attendance WHERE event_id = $1 \
+             ) \
+             AND CASE WHEN $3::text IS NULL THEN r.status IN ('going', 'maybe') OR a.id IS NOT NULL \
+                      ELSE u.name ILIKE $3 END \
+             ORDER BY lower(u.name), u.id LIMIT $4",
+        )
+        .bind(event_id)
+        .bind(group_id)
+        .bind(pattern)
+        .bind(MAX_SEARCH_RESULTS)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(attendees)
+    }
+
+    // One person hosts may check in, None if they may not be
+    pub async fn attendee(
+        &self,
+        event_id: i32,
+        group_id: i32,
+        user_id: i32,
+    ) -> Result<Option<Attendee>, AttendanceError> {
+        let attendee = sqlx::query_as::<_, Attendee>(
+            "SELECT u.id AS user_id, u.name, r.status, a.checked_in_at, a.method FROM users u \
+             LEFT JOIN event_rsvps r ON r.event_id = $1 AND r.user_id = u.id \
+             LEFT JOIN event_attendance a ON a.event_id = $1 AND a.user_id = u.id \
+             WHERE u.id = $3 AND u.deleted_at IS NULL \
+             AND (r.id IS NOT NULL OR a.id IS NOT NULL OR EXISTS ( \
+                 SELECT 1 FROM group_members m WHERE m.group_id = $2 AND m.user_id = u.id \
+             ))",
+        )
+        .bind(event_id)
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(attendee)
+    }
+
+    // The member whose RSVP to the event has this check-in token
+    pub async fn find_token(
+        &self,
+        event_id: i32,
+        token: &str,
+    ) -> Result<Option<i32>, AttendanceError> {
+        let user_id = sqlx::query_scalar::<_, i32>(
+            "SELECT user_id FROM event_rsvps WHERE event_id = $1 AND check_in_token = $2",
+        )
+        .bind(event_id)
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    // Record that the member showed up. Returns the attendance and whether
+    // they were checked in just now.
+    pub async fn check_in(
+        &self,
+        event_id: i32,
+        user_id: i32,
+        method: CheckInMethod,
+        ctx: &AuditContext,
+    ) -> Result<(Attendance, bool), AttendanceError> {
+        let mut tx = self.pool.begin().await?;
+        let created = sqlx::query_as::<_, Attendance>(
+            "INSERT INTO event_attendance (event_id, user_id, checked_in_by, method) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (event_id, user_id) DO NOTHING RETURNING *",
+        )
+        .bind(event_id)
+        .bind(user_id)
+        .bind(ctx.actor_id)
+        .bind(method)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(created) = created else {
+            let existing = sqlx::query_as::<_, Attendance>(
+                "SELECT * FROM event_attendance WHERE event_id = $1 AND user_id = $2",
+            )
+            .bind(event_id)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            return Ok((existing, false));
+        };
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "create",
+            ENTITY,
+            created.id,
+            None,
+            Some(&created),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok((created, true))
+    }
+
+    // Take back a check-in made by mistake
+    pub async fn undo_check_in(
+        &self,
+        event_id: i32,
+        user_id: i32,
+        ctx: &AuditContext,
+    ) -> Result<Option<Attendance>, AttendanceError> {
+        let mut tx = self.pool.begin().await?;
+        let removed = sqlx::query_as::<_, Attendance>(
+            "DELETE FROM event_attendance WHERE event_id = $1 AND user_id = $2 RETURNING *",
+        )
+        .bind(event_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(removed) = &removed {
+            audit::record(
+                &mut tx,
+                ctx,
+                "delete",
+                ENTITY,
+                removed.id,
+                Some(removed),
+                None,
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(removed)
+    }
+
+    pub async fn summary(&self, event_id: i32) -> Result<AttendanceSummary, AttendanceError> {
+        let query = summary_sql("SELECT id, title, starts_at FROM events WHERE id = $1");
+        let summary = sqlx::query_as::<_, EventAttendance>(&query)
+            .bind(event_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|event| event.summary)
+            .unwrap_or_default();
+
+        Ok(summary)
+    }
+
+    // The group's most recent events over by `now`, latest first. Recurring
+    // events count once their series is over.
+    pub async fn events(
+        &self,
+        group_id: i32,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<EventAttendance>, AttendanceError> {
+        let query = summary_sql(&format!(
+            "SELECT id, title, starts_at FROM events WHERE group_id = $1 AND deleted_at IS NULL \
+             AND NOT {} ORDER BY starts_at DESC LIMIT $3",
+            not_over("events")
+        ));
+        let events = sqlx::query_as::<_, EventAttendance>(&query)
+            .bind(group_id)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(events)
+    }
+
+    // Attendance of each member over the group's events that are over and
+    // where someone was checked in, the most no-shows first
+    pub async fn members(
+        &self,
+        group_id: i32,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<MemberAttendance>, AttendanceError> {
+        let query = format!(
+            "WITH taken AS ( \
+                 SELECT e.id FROM events e WHERE e.group_id = $1 AND e.deleted_at IS NULL \
+                 AND NOT {} \
+                 AND EXISTS (SELECT 1 FROM event_attendance a WHERE a.event_id = e.id) \
+             ), \
+             people AS ( \
+                 SELECT r.event_id, r.user_id FROM event_rsvps r JOIN taken t ON t.id = r.event_id \
+                 WHERE r.status = 'going' \
+                 UNION \
+                 SELECT a.event_id, a.user_id FROM event_attendance a JOIN taken t ON t.id = a.event_id \
+             ) \
+             SELECT u.id AS user_id, u.name, \
+                    COUNT(*) FILTER (WHERE r.status = 'going') AS going, \
+                    COUNT(a.id) AS attended, \
+                    COUNT(*) FILTER (WHERE r.status = 'going' AND a.id IS NULL) AS no_shows \
+             FROM people p JOIN users u ON u.id = p.user_id \
+             LEFT JOIN event_rsvps r ON r.event_id = p.event_id AND r.user_id = p.user_id \
+             LEFT JOIN event_attendance a ON a.event_id = p.event_id AND a.user_id = p.user_id \
+             WHERE u.deleted_at IS NULL \
+             GROUP BY u.id, u.name ORDER BY no_shows DESC, lower(u.name), u.id",
+            not_over("e")
+        );
+        let members = sqlx::query_as::<_, MemberAttendance>(&query)
+            .bind(group_id)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(members)
+    }
+}
+
+impl MemberAttendance {
+    // Share of the events they said they were going to that they missed,
+    // in percent
+    pub fn no_show_rate(&self) -> Option<i64> {
+        (self.going > 0).then(|| (self.no_shows * 100 + self.going / 2) / self.going)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::event::{EventService, NewEvent};
+    use crate::db::group::GroupService;
+    use crate::db::member::MemberService;
+    use crate::db::rsvp::RsvpService;
+    use chrono::Duration;
+
+    #[test]
+    fn test_no_show_rate() {
+        let member = |going, no_shows| MemberAttendance {
+            user_id: 1,
+            name: "Ada".to_string(),
+            going,
+            attended: going - no_shows,
+            no_shows,
+        };
+        assert_eq!(member(0, 0).no_show_rate(), None);
+        assert_eq!(member(4, 1).no_show_rate(), Some(25));
+        assert_eq!(member(3, 2).no_show_rate(), Some(67));
+        assert_eq!(member(5, 0).no_show_rate(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_check_in_and_statistics() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let members = MemberService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+        let rsvps = RsvpService::new(db.pool.clone());
+        let attendance = AttendanceService::new(db.pool.clone());
+
+        let [ada, bob, cy, dan, eve] = db
+            .create_users(["ada", "bob", "cy", "dan", "eve"])
+            .await
+            .map(|user| user.id);
+        let group = groups
+            .create_with_owner("Readers".to_string(), Some(ada), &ctx)
+            .await
+            .unwrap();
+        members.join(group.id, bob, &ctx).await.unwrap();
+
+        let now = Utc::now();
+        let mut event_ids = Vec::new();
+        for starts_at in [now - Duration::days(7), now + Duration::days(7)] {
+            let event = NewEvent {
+                title: "Meetup".to_string(),
+                starts_at,
+                ..Default::default()
+            };
+            event_ids.push(events.create(group.id, event, &ctx).await.unwrap().id);
+        }
+        let (past, upcoming) = (event_ids[0], event_ids[1]);
+        rsvps.set(past, ada, RsvpStatus::Going, &ctx).await.unwrap();
+        let (bobs, _) = rsvps.set(past, bob, RsvpStatus::Going, &ctx).await.unwrap();
+        rsvps.set(past, cy, RsvpStatus::Maybe, &ctx).await.unwrap();
+        rsvps
+            .set(upcoming, bob, RsvpStatus::Going, &ctx)
+            .await
+            .unwrap();
+
+        // Checking in twice keeps the first check-in
+        let (first, created) = attendance
+            .check_in(past, ada, CheckInMethod::List, &ctx)
+            .await
+            .unwrap();
+        assert!(created);
+        let (again, created) = attendance
+            .check_in(past, ada, CheckInMethod::QrCode, &ctx)
+            .await
+            .unwrap();
+        assert!(!created);
+        assert_eq!(again.id, first.id);
+        assert_eq!(again.method, CheckInMethod::List);
+
+        // A scanned token finds its member; a check-in can be taken back
+        let scanned = attendance
+            .find_token(past, &bobs.check_in_token)
+            .await
+            .unwrap();
+        assert_eq!(scanned, Some(bob));
+        assert_eq!(attendance.find_token(past, "nope").await.unwrap(), None);
+        assert_eq!(
+            attendance
+                .find_token(upcoming, &bobs.check_in_token)
+                .await
+                .unwrap(),
+            None
+        );
+        attendance
+            .check_in(past, bob, CheckInMethod::QrCode, &ctx)
+            .await
+            .unwrap();
+        assert!(
+            attendance
+                .undo_check_in(past, bob, &ctx)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            attendance
+                .undo_check_in(past, bob, &ctx)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Hosts may check in anyone, someone from outside the group walks in
+        assert!(
+            attendance
+                .attendee(past, group.id, eve)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        attendance
+            .check_in(past, dan, CheckInMethod::List, &ctx)
+            .await
+            .unwrap();
+
+        let listed: Vec<i32> = attendance
+            .attendees(past, group.id, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|a| a.user_id)
+            .collect();
+        assert_eq!(listed, [ada, bob, cy, dan]);
+        let found: Vec<i32> = attendance
+            .attendees(past, group.id, Some(" D "))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|a| a.user_id)
+            .collect();
+        assert_eq!(found, [ada, dan]);
+
+        let summary = AttendanceSummary {
+            going: 2,
+            checked_in: 2,
+            no_shows: 1,
+            walk_ins: 1,
+        };
+        assert_eq!(attendance.summary(past).await.unwrap(), summary);
+
+        // Only events that are over count towards the statistics
+        let over = attendance.events(group.id, now, 10).await.unwrap();
+        assert_eq!(over.len(), 1);
+        assert_eq!(over[0].event_id, past);
+        assert_eq!(over[0].summary, summary);
+        let counted: Vec<(i32, i64, i64, i64)> = attendance
+            .members(group.id, now)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.user_id, m.going, m.attended, m.no_shows))
+            .collect();
+        assert_eq!(counted, [(bob, 1, 0, 1), (ada, 1, 1, 0), (dan, 0, 1, 0)]);
+    }
+}
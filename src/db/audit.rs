@@ -0,0 +1,303 @@
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::AuditEvent;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use thiserror::Error;
+
+// Fields whose values never end up in the audit log, only the fact they changed
+const REDACTED_FIELDS: &[&str] = &["password_hash", "token"];
+// Personal data is kept out the same way, per entity type: the log is
+// append-only, so deleting an account could not erase it afterwards
+const PERSONAL_FIELDS: &[(&str, &[&str])] = &[
+    (
+        "user",
+        &[
+            "email",
+            "name",
+            "bio",
+            "location",
+            "interests",
+            "avatar_key",
+        ],
+    ),
+    ("invitation", &["email"]),
+];
+const REDACTED: &str = "[redacted]";
+
+fn is_redacted(entity_type: &str, field: &str) -> bool {
+    REDACTED_FIELDS.contains(&field)
+        || PERSONAL_FIELDS
+            .iter()
+            .any(|(entity, fields)| *entity == entity_type && fields.contains(&field))
+}
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// Who made a change and from which request. Extracted from the request in
+// handlers; background jobs and the CLI use `AuditContext::system()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditContext {
+    pub actor_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl AuditContext {
+    pub fn system() -> Self {
+        Self::default()
+    }
+}
+
+// Criteria for the audit log viewer and export
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<i32>,
+    pub actor_id: Option<i32>,
+    pub action: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+// Changed fields between two serialized versions of an entity, as
+// {"field": {"before": old, "after": new}}. A missing side (create, hard
+// delete) records every field.
+pub fn diff(entity_type: &str, before: Option<&Value>, after: Option<&Value>) -> Value {
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.and_then(Value::as_object).unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let old = before.get(key).unwrap_or(&Value::Null);
+        let new = after.get(key).unwrap_or(&Value::Null);
+        if old == new || changes.contains_key(key) {
+            continue;
+        }
+
+        let (old, new) = if is_redacted(entity_type, key) {
+            let redact = |v: &Value| {
+                if v.is_null() {
+                    Value::Null
+                } else {
+                    json!(REDACTED)
+                }
+            };
+            (redact(old), redact(new))
+        } else {
+            (old.clone(), new.clone())
+        };
+        changes.insert(key.clone(), json!({ "before": old, "after": new }));
+    }
+
+    Value::Object(changes)
+}
+
+// Append an event for a change to an entity. Takes the connection of the
+// transaction making the change so both are committed or rolled back together.
+// Updates that change nothing are not recorded.
+pub async fn record<T: Serialize>(
+    conn: &mut PgConnection,
+    ctx: &AuditContext,
+    action: &str,
+    entity_type: &str,
+    entity_id: i32,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<(), sqlx::Error> {
+    let to_value = |entity: Option<&T>| entity.and_then(|e| serde_json::to_value(e).ok());
+    let changes = diff(
+        entity_type,
+        to_value(before).as_ref(),
+        to_value(after).as_ref(),
+    );
+    if before.is_some() && after.is_some() && changes.as_object().is_some_and(Map::is_empty) {
+        return Ok(());
+    }
+
+    sqlx::query(
+        "INSERT INTO audit_events (actor_id, action, entity_type, entity_id, changes, ip_address, request_id) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(ctx.actor_id)
+    .bind(action)
+    .bind(entity_type)
+    .bind(entity_id)
+    .bind(changes.to_string())
+    .bind(&ctx.ip_address)
+    .bind(&ctx.request_id)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub struct AuditService {
+    pool: DbPool,
+}
+
+impl AuditService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Matching events, most recent first
+    pub async fn search(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>, AuditError> {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM audit_events WHERE TRUE");
+
+        if let Some(entity_type) = filter.entity_type.as_deref().filter(|t| !t.is_empty()) {
+            query
+                .push(" AND entity_type = ")
+                .push_bind(entity_type.to_string());
+        }
+        if let Some(entity_id) = filter.entity_id {
+            query.push(" AND entity_id = ").push_bind(entity_id);
+        }
+        if let Some(actor_id) = filter.actor_id {
+            query.push(" AND actor_id = ").push_bind(actor_id);
+        }
+        if let Some(action) = filter.action.as_deref().filter(|a| !a.is_empty()) {
+            query.push(" AND action = ").push_bind(action.to_string());
+        }
+        if let Some(since) = filter.since {
+            query.push(" AND created_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND created_at < ").push_bind(until);
+        }
+        query
+            .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+            .push_bind(filter.limit);
+
+        let events = query
+            .build_query_as::<AuditEvent>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::group::GroupService;
+
+    #[test]
+    fn test_diff_only_keeps_changed_fields() {
+        let before = json!({ "id": 1, "name": "Rustaceans", "deleted_at": null });
+        let after = json!({ "id": 1, "name": "Rust Berlin", "deleted_at": null });
+
+        assert_eq!(
+            diff("group", Some(&before), Some(&after)),
+            json!({ "name": { "before": "Rustaceans", "after": "Rust Berlin" } })
+        );
+        assert_eq!(diff("group", Some(&before), Some(&before)), json!({}));
+    }
+
+    #[test]
+    fn test_diff_of_created_entity_and_redaction() {
+        let after = json!({ "id": 7, "password_hash": "$argon2id$secret", "token": "abc" });
+
+        assert_eq!(
+            diff("group", None, Some(&after)),
+            json!({
+                "id": { "before": null, "after": 7 },
+                "password_hash": { "before": null, "after": "[redacted]" },
+                "token": { "before": null, "after": "[redacted]" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_diff_redacts_personal_fields_of_users() {
+        let before =
+            json!({ "id": 3, "email": "ada@example.com", "name": "Ada", "is_admin": false });
+        let after =
+            json!({ "id": 3, "email": "ada@example.org", "name": "Ada L", "is_admin": true });
+
+        assert_eq!(
+            diff("user", Some(&before), Some(&after)),
+            json!({
+                "email": { "before": "[redacted]", "after": "[redacted]" },
+                "name": { "before": "[redacted]", "after": "[redacted]" },
+                "is_admin": { "before": false, "after": true }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_recorded_and_searchable() {
+        let db = create_test_pool().await.unwrap();
+        let groups = GroupService::new(db.pool.clone());
+        let audit = AuditService::new(db.pool.clone());
+
+        let [ada] = db.create_users(["Ada"]).await;
+        let ctx = AuditContext {
+            actor_id: Some(ada.id),
+            ip_address: Some("192.0.2.1".to_string()),
+            request_id: Some("req-1".to_string()),
+        };
+        let mut group = groups.create("Rustaceans".to_string(), &ctx).await.unwrap();
+        groups.update(group.clone(), &ctx).await.unwrap();
+        group.name = "Rust Berlin".to_string();
+        groups.update(group.clone(), &ctx).await.unwrap();
+
+        // The update that changed nothing left no event
+        let filter = AuditFilter {
+            entity_type: Some("group".to_string()),
+            entity_id: Some(group.id),
+            limit: 10,
+            ..AuditFilter::default()
+        };
+        let events = audit.search(&filter).await.unwrap();
+        let actions: Vec<&str> = events.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["update", "create"]);
+        let changes: Value = serde_json::from_str(&events[0].changes).unwrap();
+        assert_eq!(
+            changes,
+            json!({ "name": { "before": "Rustaceans", "after": "Rust Berlin" } })
+        );
+        assert_eq!(events[0].actor_id, Some(ada.id));
+        assert_eq!(events[0].ip_address.as_deref(), Some("192.0.2.1"));
+        assert_eq!(events[0].request_id.as_deref(), Some("req-1"));
+
+        let by_actor = AuditFilter {
+            actor_id: Some(ada.id),
+            action: Some("create".to_string()),
+            limit: 10,
+            ..AuditFilter::default()
+        };
+        let created = audit.search(&by_actor).await.unwrap();
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].entity_type, "group");
+
+        // Personal fields are redacted in what is stored
+        let of_user = AuditFilter {
+            entity_type: Some("user".to_string()),
+            entity_id: Some(ada.id),
+            limit: 10,
+            ..AuditFilter::default()
+        };
+        let events = audit.search(&of_user).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(!events[0].changes.contains("ada@example.com"));
+
+        // The log is append-only
+        sqlx::query("DELETE FROM audit_events")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(audit.search(&of_user).await.unwrap().len(), 1);
+    }
+}
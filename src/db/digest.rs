@@ -0,0 +1,391 @@
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{DigestFrequency, DigestSettings, User};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use thiserror::Error;
+
+// Longest list per section of a digest
+const ITEMS_PER_SECTION: i64 = 20;
+
+#[derive(Debug, Error)]
+pub enum DigestError {
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A user who receives digests
+#[derive(Debug, Clone, FromRow)]
+pub struct Subscriber {
+    #[sqlx(flatten)]
+    pub user: User,
+    pub frequency: DigestFrequency,
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DigestEvent {
+    pub id: i32,
+    pub group_id: i32,
+    pub group_name: String,
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DigestThread {
+    pub id: i32,
+    pub group_id: i32,
+    pub group_name: String,
+    pub title: String,
+    // None when the author's account was removed
+    pub author_name: Option<String>,
+    pub reply_count: i32,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DigestMember {
+    pub group_id: i32,
+    pub group_name: String,
+    pub name: String,
+}
+
+// What happened in a user's groups
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Digest {
+    pub events: Vec<DigestEvent>,
+    pub threads: Vec<DigestThread>,
+    pub members: Vec<DigestMember>,
+}
+
+impl Digest {
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.threads.is_empty() && self.members.is_empty()
+    }
+}
+
+// Unguessable token used in unsubscribe links
+fn generate_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+pub struct DigestService {
+    pool: DbPool,
+}
+
+impl DigestService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // The user's settings, created with the defaults the first time
+    pub async fn settings(&self, user_id: i32) -> Result<DigestSettings, DigestError> {
+        sqlx::query(
+            "INSERT INTO digest_settings (user_id, frequency, unsubscribe_token) \
+             VALUES ($1, $2, $3) ON CONFLICT (user_id) DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(DigestFrequency::default())
+        .bind(generate_token())
+        .execute(&self.pool)
+        .await?;
+
+        let settings =
+            sqlx::query_as::<_, DigestSettings>("SELECT * FROM digest_settings WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(settings)
+    }
+
+    pub async fn set_frequency(
+        &self,
+        user_id: i32,
+        frequency: DigestFrequency,
+    ) -> Result<(), DigestError> {
+        sqlx::query(
+            "INSERT INTO digest_settings (user_id, frequency, unsubscribe_token) \
+             VALUES ($1, $2, $3) ON CONFLICT (user_id) DO UPDATE SET frequency = $2",
+        )
+        .bind(user_id)
+        .bind(frequency)
+        .bind(generate_token())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Stop the digests of whoever the token belongs to
+    pub async fn unsubscribe(&self, token: &str) -> Result<Option<DigestSettings>, DigestError> {
+        let settings = sqlx::query_as::<_, DigestSettings>(
+            "UPDATE digest_settings SET frequency = $1 \
+             WHERE unsubscribe_token = $2 RETURNING *",
+        )
+        .bind(DigestFrequency::Never)
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(settings)
+    }
+
+    pub async fn get_by_token(&self, token: &str) -> Result<Option<DigestSettings>, DigestError> {
+        let settings = sqlx::query_as::<_, DigestSettings>(
+            "SELECT * FROM digest_settings WHERE unsubscribe_token = $1",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(settings)
+    }
+
+    // Users who receive digests and belong to at least one group
+    pub async fn subscribers(&self) -> Result<Vec<Subscriber>, DigestError> {
+        let subscribers = sqlx::query_as::<_, Subscriber>(
+            "SELECT u.*, COALESCE(s.frequency, $1) AS frequency, s.last_sent_at \
+             FROM users u LEFT JOIN digest_settings s ON s.user_id = u.id \
+             WHERE u.deleted_at IS NULL AND COALESCE(s.frequency, $1) <> $2 \
+             AND EXISTS (SELECT 1 FROM group_members m JOIN groups g ON g.id = m.group_id \
+                         WHERE m.user_id = u.id AND g.deleted_at IS NULL) \
+             ORDER BY u.id",
+        )
+        .bind(DigestFrequency::default())
+        .bind(DigestFrequency::Never)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(subscribers)
+    }
+
+    pub async fn subscriber(&self, user_id: i32) -> Result<Option<Subscriber>, DigestError> {
+        let subscriber = sqlx::query_as::<_, Subscriber>(
+            "SELECT u.*, COALESCE(s.frequency, $1) AS frequency, s.last_sent_at \
+             FROM users u LEFT JOIN digest_settings s ON s.user_id = u.id \
+             WHERE u.id = $2 AND u.deleted_at IS NULL",
+        )
+        .bind(DigestFrequency::default())
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(subscriber)
+    }
+
+    pub async fn mark_sent(&self, user_id: i32, at: DateTime<Utc>) -> Result<(), DigestError> {
+        sqlx::query(
+            "INSERT INTO digest_settings (user_id, frequency, unsubscribe_token, last_sent_at) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (user_id) DO UPDATE SET last_sent_at = $4",
+        )
+        .bind(user_id)
+        .bind(DigestFrequency::default())
+        .bind(generate_token())
+        .bind(at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // What happened in the user's groups in (since, until], other than by the
+    // user, and the events starting in (until, upcoming_until]
+    pub async fn collect(
+        &self,
+        user_id: i32,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        upcoming_until: DateTime<Utc>,
+    ) -> Result<Digest, DigestError> {
+        let events = sqlx::query_as::<_, DigestEvent>(
+            "SELECT e.id, e.group_id, g.name AS group_name, e.title, e.starts_at, e.location \
+             FROM events e JOIN groups g ON g.id = e.group_id \
+             JOIN group_members m ON m.group_id = e.group_id AND m.user_id = $1 \
+             WHERE e.deleted_at IS NULL AND g.deleted_at IS NULL \
+             AND e.starts_at > $2 AND e.starts_at <= $3 \
+             ORDER BY e.starts_at, e.id LIMIT $4",
+        )
+        .bind(user_id)
+        .bind(until)
+        .bind(upcoming_until)
+        .bind(ITEMS_PER_SECTION)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let threads = sqlx::query_as::<_, DigestThread>(
+            "SELECT p.id, p.group_id, g.name AS group_name, COALESCE(p.title, '') AS title, \
+             a.name AS author_name, p.reply_count \
+             FROM posts p JOIN groups g ON g.id = p.group_id \
+             JOIN group_members m ON m.group_id = p.group_id AND m.user_id = $1 \
+             LEFT JOIN users a ON a.id = p.author_id AND a.deleted_at IS NULL \
+             WHERE p.thread_id IS NULL AND p.deleted_at IS NULL AND g.deleted_at IS NULL \
+             AND p.author_id IS DISTINCT FROM $1 \
+             AND p.created_at > $2 AND p.created_at <= $3 \
+             ORDER BY p.created_at DESC, p.id DESC LIMIT $4",
+        )
+        .bind(user_id)
+        .bind(since)
+        .bind(until)
+        .bind(ITEMS_PER_SECTION)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let members = sqlx::query_as::<_, DigestMember>(
+            "SELECT m.group_id, g.name AS group_name, u.name \
+             FROM group_members m JOIN groups g ON g.id = m.group_id \
+             JOIN users u ON u.id = m.user_id AND u.deleted_at IS NULL \
+             JOIN group_members mine ON mine.group_id = m.group_id AND mine.user_id = $1 \
+             WHERE m.user_id <> $1 AND g.deleted_at IS NULL \
+             AND m.created_at > $2 AND m.created_at <= $3 \
+             ORDER BY g.name, m.group_id, m.created_at LIMIT $4",
+        )
+        .bind(user_id)
+        .bind(since)
+        .bind(until)
+        .bind(ITEMS_PER_SECTION)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Digest {
+            events,
+            threads,
+            members,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::audit::AuditContext;
+    use crate::db::connection::create_test_pool;
+    use crate::db::event::{EventService, NewEvent};
+    use crate::db::group::GroupService;
+    use crate::db::member::MemberService;
+    use crate::db::post::PostService;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_subscribers_and_unsubscribe() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let members = MemberService::new(db.pool.clone());
+        let digests = DigestService::new(db.pool.clone());
+
+        let [ada, bob, cy] = db
+            .create_users(["ada", "bob", "cy"])
+            .await
+            .map(|user| user.id);
+        let group = groups
+            .create_with_owner("Readers".to_string(), Some(ada), &ctx)
+            .await
+            .unwrap();
+        members.join(group.id, cy, &ctx).await.unwrap();
+        digests
+            .set_frequency(cy, DigestFrequency::Daily)
+            .await
+            .unwrap();
+
+        // Users without groups get no digest
+        let subscribers = digests.subscribers().await.unwrap();
+        let found: Vec<(i32, DigestFrequency)> = subscribers
+            .iter()
+            .map(|s| (s.user.id, s.frequency))
+            .collect();
+        assert_eq!(
+            found,
+            [(ada, DigestFrequency::Weekly), (cy, DigestFrequency::Daily)]
+        );
+        assert!(digests.subscriber(bob).await.unwrap().is_some());
+
+        let settings = digests.settings(cy).await.unwrap();
+        assert_eq!(digests.settings(cy).await.unwrap(), settings);
+        let unsubscribed = digests
+            .unsubscribe(&settings.unsubscribe_token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(unsubscribed.frequency, DigestFrequency::Never);
+        assert!(digests.unsubscribe("unknown").await.unwrap().is_none());
+
+        let sent_at = Utc::now();
+        digests.mark_sent(ada, sent_at).await.unwrap();
+        let subscribers = digests.subscribers().await.unwrap();
+        assert_eq!(subscribers.len(), 1);
+        assert_eq!(subscribers[0].user.id, ada);
+        assert!(subscribers[0].last_sent_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_collect_leaves_out_own_activity() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let members = MemberService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+        let posts = PostService::new(db.pool.clone());
+        let digests = DigestService::new(db.pool.clone());
+
+        let since = Utc::now() - Duration::hours(1);
+        let [ada, bob] = db.create_users(["Ada", "Bob"]).await;
+        let group = groups
+            .create_with_owner("Readers".to_string(), Some(ada.id), &ctx)
+            .await
+            .unwrap();
+        let other = groups.create("Hikers".to_string(), &ctx).await.unwrap();
+        members.join(group.id, bob.id, &ctx).await.unwrap();
+        members.join(other.id, bob.id, &ctx).await.unwrap();
+        posts
+            .create_thread(group.id, bob.id, "Next book", "Ideas?", &ctx)
+            .await
+            .unwrap();
+        posts
+            .create_thread(group.id, ada.id, "Welcome", "Hello", &ctx)
+            .await
+            .unwrap();
+        posts
+            .create_thread(other.id, bob.id, "Trail", "Map", &ctx)
+            .await
+            .unwrap();
+        let until = Utc::now() + Duration::minutes(1);
+        for (title, starts_at) in [
+            ("Soon", until + Duration::days(2)),
+            ("Much later", until + Duration::days(30)),
+        ] {
+            let event = NewEvent {
+                title: title.to_string(),
+                starts_at,
+                ..Default::default()
+            };
+            events.create(group.id, event, &ctx).await.unwrap();
+        }
+
+        let digest = digests
+            .collect(ada.id, since, until, until + Duration::days(7))
+            .await
+            .unwrap();
+        let titles: Vec<&str> = digest.events.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["Soon"]);
+        assert_eq!(digest.threads.len(), 1);
+        assert_eq!(digest.threads[0].title, "Next book");
+        assert_eq!(digest.threads[0].author_name.as_deref(), Some("Bob"));
+        assert_eq!(digest.members.len(), 1);
+        assert_eq!(digest.members[0].name, "Bob");
+
+        let later = digests
+            .collect(
+                ada.id,
+                until,
+                until + Duration::days(1),
+                until + Duration::days(1),
+            )
+            .await
+            .unwrap();
+        assert!(later.is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{Event, Recurrence};
+use crate::geo::Point;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use sqlx::FromRow;
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "event";
+
+pub const MAX_TITLE_LENGTH: usize = 200;
+pub const MAX_DESCRIPTION_LENGTH: usize = 20_000;
+pub const MAX_LOCATION_LENGTH: usize = 255;
+pub const MAX_MEETING_URL_LENGTH: usize = 2000;
+
+#[derive(Debug, Error)]
+pub enum EventError {
+    #[error("Event not found")]
+    NotFound,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// An event to be created
+#[derive(Debug, Clone, Default)]
+pub struct NewEvent {
+    pub title: String,
+    pub description: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub location: Option<String>,
+    // Coordinates of the location, when known
+    pub point: Option<Point>,
+    pub venue_id: Option<i32>,
+    pub meeting_url: Option<String>,
+    pub recurrence: Option<Recurrence>,
+    pub recurs_until: Option<DateTime<Utc>>,
+    pub time_zone: Tz,
+}
+
+// An event with the name of its group, for calendars spanning groups
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct CalendarEntry {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub event: Event,
+    pub group_name: String,
+}
+
+// SQL condition for the events of `table` not over by $2, recurring events
+// counting as over once their last occurrence is
+pub(crate) fn not_over(table: &str) -> String {
+    format!(
+        "(COALESCE({t}.ends_at, {t}.starts_at) >= $2 OR ({t}.recurrence IS NOT NULL \
+         AND ({t}.recurs_until IS NULL \
+              OR {t}.recurs_until + (COALESCE({t}.ends_at, {t}.starts_at) - {t}.starts_at) >= $2)))",
+        t = table
+    )
+}
+
+pub struct EventService {
+    pool: DbPool,
+}
+
+impl EventService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        group_id: i32,
+        event: NewEvent,
+        ctx: &AuditContext,
+    ) -> Result<Event, EventError> {
+        let mut tx = self.pool.begin().await?;
+        let created = sqlx::query_as::<_, Event>(
+            "INSERT INTO events (group_id, title, description, starts_at, ends_at, location, created_by, \
+                                 latitude, longitude, venue_id, meeting_url, recurrence, recurs_until, \
+                                 time_zone) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) RETURNING *",
+        )
+        .bind(group_id)
+        .bind(&event.title)
+        .bind(&event.description)
+        .bind(event.starts_at)
+        .bind(event.ends_at)
+        .bind(&event.location)
+        .bind(ctx.actor_id)
+        .bind(event.point.map(|point| point.latitude))
+        .bind(event.point.map(|point| point.longitude))
+        .bind(event.venue_id)
+        .bind(&event.meeting_url)
+        .bind(event.recurrence)
+        .bind(event.recurs_until)
+        .bind(event.time_zone.name())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "create",
+            ENTITY,
+            created.id,
+            None,
+            Some(&created),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    // An event that is not deleted, in a group that is not deleted either
+    pub async fn get(&self, id: i32) -> Result<Option<Event>, EventError> {
+        let event = sqlx::query_as::<_, Event>(
+            "SELECT e.* FROM events e JOIN groups g ON g.id = e.group_id \
+             WHERE e.id = $1 AND e.deleted_at IS NULL AND g.deleted_at IS NULL",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(event)
+    }
+
+    // Events not over yet, soonest first
+    pub async fn list_upcoming(
+        &self,
+        group_id: i32,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Event>, EventError> {
+        let query = format!(
+            "SELECT * FROM events WHERE group_id = $1 AND deleted_at IS NULL \
+             AND {} ORDER BY starts_at, id",
+            not_over("events")
+        );
+        let events = sqlx::query_as::<_, Event>(&query)
+            .bind(group_id)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(events)
+    }
+
+    // Events of every group starting in (from, to], and the recurring ones
+    // that may have an occurrence starting then, for reminders
+    pub async fn list_starting_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Event>, EventError> {
+        let events = sqlx::query_as::<_, Event>(
+            "SELECT e.* FROM events e JOIN groups g ON g.id = e.group_id \
+             WHERE e.deleted_at IS NULL AND g.deleted_at IS NULL AND e.starts_at <= $2 \
+             AND (e.starts_at > $1 OR (e.recurrence IS NOT NULL \
+                  AND (e.recurs_until IS NULL OR e.recurs_until > $1))) \
+             ORDER BY e.starts_at, e.id",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+
+    // Events of the groups taking place in [from, to), recurring events
+    // whose series overlaps it included, with the name of their group.
+    // Occurrences are worked out by calendar::occurrences.
+    pub async fn list_for_calendar(
+        &self,
+        group_ids: &[i32],
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEntry>, EventError> {
+        let query = format!(
+            "SELECT e.*, g.name AS group_name FROM events e JOIN groups g ON g.id = e.group_id \
+             WHERE e.group_id = ANY($1) AND e.deleted_at IS NULL AND g.deleted_at IS NULL \
+             AND e.starts_at < $3 AND {} ORDER BY e.starts_at, e.id",
+            not_over("e")
+        );
+        let entries = sqlx::query_as::<_, CalendarEntry>(&query)
+            .bind(group_ids)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(entries)
+    }
+
+    // The most recent past events, latest first
+    pub async fn list_past(
+        &self,
+        group_id: i32,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Event>, EventError> {
+        let query = format!(
+            "SELECT * FROM events WHERE group_id = $1 AND deleted_at IS NULL \
+             AND NOT {} ORDER BY starts_at DESC, id DESC LIMIT $3",
+            not_over("events")
+        );
+        let events = sqlx::query_as::<_, Event>(&query)
+            .bind(group_id)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar;
+    use crate::db::connection::create_test_pool;
+    use crate::db::group::GroupService;
+    use chrono::Duration;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn event(
+        title: &str,
+        starts_at: DateTime<Utc>,
+        recurrence: Option<Recurrence>,
+        recurs_until: Option<DateTime<Utc>>,
+    ) -> NewEvent {
+        NewEvent {
+            title: title.to_string(),
+            starts_at,
+            ends_at: Some(starts_at + Duration::hours(2)),
+            recurrence,
+            recurs_until,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_for_calendar_includes_recurring_series() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+
+        let readers = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let hikers = groups.create("Hikers".to_string(), &ctx).await.unwrap();
+        let weekly = Some(Recurrence::Weekly);
+        let (from, to) = (at("2026-03-02T00:00:00Z"), at("2026-03-09T00:00:00Z"));
+        let new_events = [
+            (
+                readers.id,
+                event("Inside", at("2026-03-04T18:00:00Z"), None, None),
+            ),
+            (
+                readers.id,
+                event("Before", at("2026-02-25T18:00:00Z"), None, None),
+            ),
+            (
+                readers.id,
+                event("After", at("2026-03-09T18:00:00Z"), None, None),
+            ),
+            (
+                readers.id,
+                event("Series", at("2026-02-05T18:00:00Z"), weekly, None),
+            ),
+            (
+                readers.id,
+                event(
+                    "Ended",
+                    at("2026-02-03T18:00:00Z"),
+                    weekly,
+                    Some(at("2026-02-24T18:00:00Z")),
+                ),
+            ),
+            (
+                hikers.id,
+                event("Elsewhere", at("2026-03-04T18:00:00Z"), None, None),
+            ),
+        ];
+        for (group_id, new_event) in new_events {
+            events.create(group_id, new_event, &ctx).await.unwrap();
+        }
+
+        let entries = events
+            .list_for_calendar(&[readers.id], from, to)
+            .await
+            .unwrap();
+        let titles: Vec<&str> = entries.iter().map(|e| e.event.title.as_str()).collect();
+        assert_eq!(titles, ["Series", "Inside"]);
+        assert_eq!(entries[0].group_name, "Readers");
+
+        let series = calendar::occurrences(&entries[0].event, &entries[0].group_name, from, to);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].starts_at, at("2026-03-05T18:00:00Z"));
+    }
+
+    #[tokio::test]
+    async fn test_list_starting_between_includes_recurring_series() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let weekly = Some(Recurrence::Weekly);
+        let (from, to) = (at("2026-03-05T17:00:00Z"), at("2026-03-05T18:00:00Z"));
+        let new_events = [
+            event("Soon", at("2026-03-05T17:30:00Z"), None, None),
+            event("Later", at("2026-03-05T19:00:00Z"), None, None),
+            event("Series", at("2026-02-19T18:00:00Z"), weekly, None),
+            event(
+                "Ended",
+                at("2026-02-05T18:00:00Z"),
+                weekly,
+                Some(at("2026-02-26T18:00:00Z")),
+            ),
+        ];
+        for new_event in new_events {
+            events.create(group.id, new_event, &ctx).await.unwrap();
+        }
+
+        let found = events.list_starting_between(from, to).await.unwrap();
+        let titles: Vec<&str> = found.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["Series", "Soon"]);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{EventComment, User};
+use serde::Serialize;
+use sqlx::{FromRow, PgConnection};
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "event_comment";
+
+pub const MAX_COMMENT_LENGTH: usize = 5000;
+
+#[derive(Debug, Error)]
+pub enum EventCommentError {
+    #[error("Comment not found")]
+    NotFound,
+    #[error("Comment has been deleted")]
+    Deleted,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A comment with its author's name for display
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct EventCommentEntry {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub comment: EventComment,
+    pub author_name: Option<String>,
+}
+
+// Moderation and pinning actions on a comment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Moderation {
+    Pin,
+    Unpin,
+    Hide,
+    Unhide,
+    Delete,
+}
+
+impl Moderation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Moderation::Pin => "pin",
+            Moderation::Unpin => "unpin",
+            Moderation::Hide => "hide",
+            Moderation::Unhide => "unhide",
+            Moderation::Delete => "delete",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<Self> {
+        [
+            Moderation::Pin,
+            Moderation::Unpin,
+            Moderation::Hide,
+            Moderation::Unhide,
+            Moderation::Delete,
+        ]
+        .into_iter()
+        .find(|m| m.as_str() == action)
+    }
+
+    fn update(&self) -> &'static str {
+        match self {
+            Moderation::Pin => {
+                "UPDATE event_comments SET pinned_at = NOW() WHERE id = $1 RETURNING *"
+            }
+            Moderation::Unpin => {
+                "UPDATE event_comments SET pinned_at = NULL WHERE id = $1 RETURNING *"
+            }
+            Moderation::Hide => {
+                "UPDATE event_comments SET hidden_at = NOW() WHERE id = $1 RETURNING *"
+            }
+            Moderation::Unhide => {
+                "UPDATE event_comments SET hidden_at = NULL WHERE id = $1 RETURNING *"
+            }
+            Moderation::Delete => {
+                "UPDATE event_comments SET deleted_at = NOW(), pinned_at = NULL WHERE id = $1 RETURNING *"
+            }
+        }
+    }
+}
+
+// Handles mentioned in a text, lowercased: `@name` where the name is made of
+// letters, digits, `_`, `-` and `.`. An `@` inside a word, as in an email
+// address, is not a mention.
+pub fn mentioned_handles(text: &str) -> Vec<String> {
+    let mut handles: Vec<String> = Vec::new();
+    let mut previous = ' ';
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '@' && !previous.is_alphanumeric() {
+            let mut handle = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_alphanumeric() || matches!(next, '_' | '-' | '.') {
+                    handle.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            // A trailing dot ends the sentence, not the handle
+            let handle = handle.trim_end_matches('.').to_lowercase();
+            if !handle.is_empty() && !handles.contains(&handle) {
+                handles.push(handle);
+            }
+            previous = 'a';
+        } else {
+            previous = c;
+        }
+    }
+
+    handles
+}
+
+pub struct EventCommentService {
+    pool: DbPool,
+}
+
+impl EventCommentService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Comment on an event. Answers to an answer are attached to the question,
+    // so Q&A stays one level deep.
+    pub async fn create(
+        &self,
+        event_id: i32,
+        author_id: i32,
+        reply_to: Option<i32>,
+        body: &str,
+        ctx: &AuditContext,
+    ) -> Result<EventComment, EventCommentError> {
+        let mut tx = self.pool.begin().await?;
+        let reply_to = match reply_to {
+            Some(id) => {
+                let parent = Self::lock(&mut tx, event_id, id).await?;
+                if parent.deleted_at.is_some() {
+                    return Err(EventCommentError::Deleted);
+                }
+                Some(parent.reply_to.unwrap_or(parent.id))
+            }
+            None => None,
+        };
+
+        let comment = sqlx::query_as::<_, EventComment>(
+            "INSERT INTO event_comments (event_id, author_id, reply_to, body) \
+             VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(event_id)
+        .bind(author_id)
+        .bind(reply_to)
+        .bind(body)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "create",
+            ENTITY,
+            comment.id,
+            None,
+            Some(&comment),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(comment)
+    }
+
+    // Comments of an event, oldest first, including hidden and deleted ones
+    pub async fn list_for_event(
+        &self,
+        event_id: i32,
+    ) -> Result<Vec<EventCommentEntry>, EventCommentError> {
+        let comments = sqlx::query_as::<_, EventCommentEntry>(
+            "SELECT c.*, u.name AS author_name FROM event_comments c \
+             LEFT JOIN users u ON u.id = c.author_id WHERE c.event_id = $1 ORDER BY c.id",
+        )
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(comments)
+    }
+
+    pub async fn get(
+        &self,
+        event_id: i32,
+        id: i32,
+    ) -> Result<Option<EventComment>, EventCommentError> {
+        let comment = sqlx::query_as::<_, EventComment>(
+            "SELECT * FROM event_comments WHERE id = $1 AND event_id = $2",
+        )
+        .bind(id)
+        .bind(event_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(comment)
+    }
+
+    // Pin, hide or delete a comment, or undo pinning and hiding
+    pub async fn moderate(
+        &self,
+        event_id: i32,
+        id: i32,
+        action: Moderation,
+        ctx: &AuditContext,
+    ) -> Result<EventComment, EventCommentError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, event_id, id).await?;
+        if before.deleted_at.is_some() {
+            return Err(EventCommentError::Deleted);
+        }
+
+        let after = sqlx::query_as::<_, EventComment>(action.update())
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            action.as_str(),
+            ENTITY,
+            id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after)
+    }
+
+    // Members of the group mentioned by handle, see mentioned_handles. A
+    // member's handle is their name without spaces.
+    pub async fn mentioned_members(
+        &self,
+        group_id: i32,
+        handles: &[String],
+    ) -> Result<Vec<User>, EventCommentError> {
+        if handles.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let users = sqlx::query_as::<_, User>(
+            "SELECT u.* FROM users u JOIN group_members m ON m.user_id = u.id \
+             WHERE m.group_id = $1 AND u.deleted_at IS NULL \
+             AND LOWER(REPLACE(u.name, ' ', '')) = ANY($2) ORDER BY u.id",
+        )
+        .bind(group_id)
+        .bind(handles)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn lock(
+        conn: &mut PgConnection,
+        event_id: i32,
+        id: i32,
+    ) -> Result<EventComment, EventCommentError> {
+        sqlx::query_as::<_, EventComment>(
+            "SELECT * FROM event_comments WHERE id = $1 AND event_id = $2 FOR UPDATE",
+        )
+        .bind(id)
+        .bind(event_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(EventCommentError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::event::{EventService, NewEvent};
+    use crate::db::group::GroupService;
+    use crate::db::member::MemberService;
+    use crate::db::user::UserService;
+    use chrono::Utc;
+
+    #[test]
+    fn test_mentioned_handles() {
+        assert_eq!(
+            mentioned_handles("@Ada is the parking free? cc @bob_s and @ada."),
+            vec!["ada".to_string(), "bob_s".to_string()]
+        );
+        assert!(mentioned_handles("mail ada@example.com or @ alone").is_empty());
+        assert_eq!(mentioned_handles("(@Grace)"), vec!["grace".to_string()]);
+    }
+
+    #[test]
+    fn test_moderation_parse() {
+        assert_eq!(Moderation::parse("pin"), Some(Moderation::Pin));
+        assert_eq!(Moderation::parse("unhide"), Some(Moderation::Unhide));
+        assert_eq!(Moderation::parse("erase"), None);
+    }
+
+    #[tokio::test]
+    async fn test_questions_answers_and_moderation() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+        let groups = GroupService::new(db.pool.clone());
+        let members = MemberService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+        let comments = EventCommentService::new(db.pool.clone());
+
+        let ada = users
+            .create(
+                "ada@example.com".to_string(),
+                "Ada Lovelace".to_string(),
+                &ctx,
+            )
+            .await
+            .unwrap();
+        let [bob] = db.create_users(["Bob"]).await;
+        let group = groups
+            .create_with_owner("Readers".to_string(), Some(ada.id), &ctx)
+            .await
+            .unwrap();
+        let event = NewEvent {
+            title: "Meetup".to_string(),
+            starts_at: Utc::now(),
+            ..Default::default()
+        };
+        let event = events.create(group.id, event, &ctx).await.unwrap();
+
+        // Answers to an answer are attached to the question
+        let question = comments
+            .create(event.id, bob.id, None, "Is parking free?", &ctx)
+            .await
+            .unwrap();
+        let answer = comments
+            .create(event.id, ada.id, Some(question.id), "Yes", &ctx)
+            .await
+            .unwrap();
+        let thanks = comments
+            .create(event.id, bob.id, Some(answer.id), "Thanks", &ctx)
+            .await
+            .unwrap();
+        assert_eq!(answer.reply_to, Some(question.id));
+        assert_eq!(thanks.reply_to, Some(question.id));
+
+        let pinned = comments
+            .moderate(event.id, answer.id, Moderation::Pin, &ctx)
+            .await
+            .unwrap();
+        assert!(pinned.pinned_at.is_some());
+        let deleted = comments
+            .moderate(event.id, answer.id, Moderation::Delete, &ctx)
+            .await
+            .unwrap();
+        assert!(deleted.pinned_at.is_none());
+        assert!(matches!(
+            comments
+                .moderate(event.id, answer.id, Moderation::Pin, &ctx)
+                .await,
+            Err(EventCommentError::Deleted)
+        ));
+        assert!(matches!(
+            comments
+                .create(event.id, bob.id, Some(answer.id), "Sure?", &ctx)
+                .await,
+            Err(EventCommentError::Deleted)
+        ));
+
+        let listed = comments.list_for_event(event.id).await.unwrap();
+        let authors: Vec<Option<&str>> = listed.iter().map(|c| c.author_name.as_deref()).collect();
+        assert_eq!(authors, [Some("Bob"), Some("Ada Lovelace"), Some("Bob")]);
+
+        // Only members can be mentioned
+        let handles = mentioned_handles("@AdaLovelace and @bob, see above");
+        let mentioned = comments
+            .mentioned_members(group.id, &handles)
+            .await
+            .unwrap();
+        assert_eq!(mentioned.len(), 1);
+        assert_eq!(mentioned[0].id, ada.id);
+        members.join(group.id, bob.id, &ctx).await.unwrap();
+        let mentioned = comments
+            .mentioned_members(group.id, &handles)
+            .await
+            .unwrap();
+        assert_eq!(mentioned.len(), 2);
+    }
+}
@@ -0,0 +1,414 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{DataExport, DataExportStatus};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "data_export";
+
+// Each file of an export with the query producing it, as JSON text. `$1` is
+// the user. Everything stored about the user, except the password hash and
+// the tokens of links sent to them.
+const FILES: &[(&str, &str)] = &[
+    (
+        "profile.json",
+        "SELECT row_to_json(t)::text FROM ( \
+             SELECT id, email, name, time_zone, bio, location, interests, profile_visibility, \
+                    show_location, show_groups, avatar_key, is_admin, created_at, updated_at \
+             FROM users WHERE id = $1 \
+         ) t",
+    ),
+    (
+        "memberships.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.joined_at), '[]')::text FROM ( \
+             SELECT m.group_id, g.name AS group_name, m.role, m.created_at AS joined_at \
+             FROM group_members m JOIN groups g ON g.id = m.group_id \
+             WHERE m.user_id = $1 \
+         ) t",
+    ),
+    (
+        "join_requests.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.created_at), '[]')::text FROM ( \
+             SELECT r.group_id, g.name AS group_name, r.status, r.answers::json AS answers, \
+                    r.decision_note, r.decided_at, r.created_at \
+             FROM group_join_requests r JOIN groups g ON g.id = r.group_id \
+             WHERE r.user_id = $1 \
+         ) t",
+    ),
+    (
+        "rsvps.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.created_at), '[]')::text FROM ( \
+             SELECT r.event_id, e.title AS event_title, e.starts_at AS event_starts_at, \
+                    r.status, r.created_at, r.updated_at \
+             FROM event_rsvps r JOIN events e ON e.id = r.event_id \
+             WHERE r.user_id = $1 \
+         ) t",
+    ),
+    (
+        "attendance.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.checked_in_at), '[]')::text FROM ( \
+             SELECT a.event_id, e.title AS event_title, e.starts_at AS event_starts_at, \
+                    a.method, a.checked_in_at \
+             FROM event_attendance a JOIN events e ON e.id = a.event_id \
+             WHERE a.user_id = $1 \
+         ) t",
+    ),
+    (
+        "posts.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.created_at), '[]')::text FROM ( \
+             SELECT p.id, p.group_id, g.name AS group_name, p.thread_id, p.title, p.body, \
+                    p.created_at, p.edited_at, p.deleted_at \
+             FROM posts p JOIN groups g ON g.id = p.group_id \
+             WHERE p.author_id = $1 \
+         ) t",
+    ),
+    (
+        "event_comments.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.created_at), '[]')::text FROM ( \
+             SELECT c.id, c.event_id, e.title AS event_title, c.reply_to, c.body, \
+                    c.created_at, c.deleted_at \
+             FROM event_comments c JOIN events e ON e.id = c.event_id \
+             WHERE c.author_id = $1 \
+         ) t",
+    ),
+    (
+        "notifications.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.created_at), '[]')::text FROM ( \
+             SELECT kind, message, url, created_at, read_at \
+             FROM notifications WHERE user_id = $1 \
+         ) t",
+    ),
+    (
+        "settings.json",
+        "SELECT json_build_object( \
+             'notifications', (SELECT COALESCE(json_agg(p ORDER BY p.kind), '[]') FROM ( \
+                 SELECT kind, in_app, email FROM notification_preferences WHERE user_id = $1 \
+             ) p), \
+             'digest_frequency', (SELECT frequency FROM digest_settings WHERE user_id = $1) \
+         )::text",
+    ),
+    (
+        "audit_log.json",
+        "SELECT COALESCE(json_agg(t ORDER BY t.id), '[]')::text FROM ( \
+             SELECT id, actor_id, action, entity_type, entity_id, changes::json AS changes, \
+                    CASE WHEN actor_id = $1 THEN ip_address END AS ip_address, created_at \
+             FROM audit_events \
+             WHERE actor_id = $1 OR (entity_type = 'user' AND entity_id = $1) \
+         ) t",
+    ),
+];
+
+fn generate_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+#[derive(Debug, Error)]
+pub enum DataExportError {
+    #[error("Export not found")]
+    NotFound,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+pub struct DataExportService {
+    pool: DbPool,
+}
+
+impl DataExportService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // A pending export, built later by a job
+    pub async fn create(
+        &self,
+        user_id: i32,
+        ctx: &AuditContext,
+    ) -> Result<DataExport, DataExportError> {
+        let mut tx = self.pool.begin().await?;
+        let export = sqlx::query_as::<_, DataExport>(
+            "INSERT INTO data_exports (user_id, status, token) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(user_id)
+        .bind(DataExportStatus::Pending)
+        .bind(generate_token())
+        .fetch_one(&mut *tx)
+        .await?;
+        audit::record(
+            &mut tx,
+            ctx,
+            "create",
+            ENTITY,
+            export.id,
+            None,
+            Some(&export),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(export)
+    }
+
+    pub async fn get(&self, id: i32) -> Result<DataExport, DataExportError> {
+        sqlx::query_as::<_, DataExport>("SELECT * FROM data_exports WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(DataExportError::NotFound)
+    }
+
+    pub async fn get_by_token(&self, token: &str) -> Result<DataExport, DataExportError> {
+        sqlx::query_as::<_, DataExport>("SELECT * FROM data_exports WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(DataExportError::NotFound)
+    }
+
+    // The user's most recent export, if any
+    pub async fn latest(&self, user_id: i32) -> Result<Option<DataExport>, DataExportError> {
+        let export = sqlx::query_as::<_, DataExport>(
+            "SELECT * FROM data_exports WHERE user_id = $1 ORDER BY created_at DESC, id DESC LIMIT 1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(export)
+    }
+
+    pub async fn mark_ready(
+        &self,
+        id: i32,
+        blob_key: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<DataExport, DataExportError> {
+        sqlx::query_as::<_, DataExport>(
+            "UPDATE data_exports SET status = $2, blob_key = $3, completed_at = $4, expires_at = $5 \
+             WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .bind(DataExportStatus::Ready)
+        .bind(blob_key)
+        .bind(now)
+        .bind(expires_at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(DataExportError::NotFound)
+    }
+
+    // Failed exports expire too, so they are cleaned up like the others
+    pub async fn mark_failed(
+        &self,
+        id: i32,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), DataExportError> {
+        sqlx::query(
+            "UPDATE data_exports SET status = $2, completed_at = $3, expires_at = $4 WHERE id = $1",
+        )
+        .bind(id)
+        .bind(DataExportStatus::Failed)
+        .bind(now)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_expired(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DataExport>, DataExportError> {
+        let exports = sqlx::query_as::<_, DataExport>(
+            "SELECT * FROM data_exports WHERE expires_at <= $1 ORDER BY id",
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(exports)
+    }
+
+    pub async fn delete(&self, id: i32) -> Result<(), DataExportError> {
+        sqlx::query("DELETE FROM data_exports WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Returns the deleted exports, so their archives can be deleted too
+    pub async fn delete_for_user(&self, user_id: i32) -> Result<Vec<DataExport>, DataExportError> {
+        let exports = sqlx::query_as::<_, DataExport>(
+            "DELETE FROM data_exports WHERE user_id = $1 RETURNING *",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(exports)
+    }
+
+    // The files of the user's export, each a JSON document
+    pub async fn collect(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<(&'static str, String)>, DataExportError> {
+        let mut files = Vec::new();
+        for (name, query) in FILES {
+            let json: Option<String> = sqlx::query_scalar(query)
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+            files.push((*name, json.unwrap_or_else(|| "null".to_string())));
+        }
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::digest::DigestService;
+    use crate::db::group::GroupService;
+    use crate::db::models::{DigestFrequency, User};
+    use crate::db::post::PostService;
+    use crate::db::user::UserService;
+    use chrono::Duration;
+    use serde_json::{Value, json};
+
+    #[tokio::test]
+    async fn test_collect_every_file() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+        let groups = GroupService::new(db.pool.clone());
+        let posts = PostService::new(db.pool.clone());
+        let digests = DigestService::new(db.pool.clone());
+        let exports = DataExportService::new(db.pool.clone());
+
+        let ada = users
+            .create_with_password(
+                "ada@example.com".to_string(),
+                "Ada".to_string(),
+                "$argon2id$secret".to_string(),
+                &ctx,
+            )
+            .await
+            .unwrap();
+        let [bob] = db.create_users(["Bob"]).await;
+        let group = groups
+            .create_with_owner("Readers".to_string(), Some(ada.id), &ctx)
+            .await
+            .unwrap();
+        let as_ada = AuditContext {
+            actor_id: Some(ada.id),
+            ip_address: Some("192.0.2.1".to_string()),
+            ..AuditContext::system()
+        };
+        posts
+            .create_thread(group.id, ada.id, "Hello", "First post", &as_ada)
+            .await
+            .unwrap();
+        // An administrator renaming Ada, their address is not hers to export
+        let as_admin = AuditContext {
+            actor_id: Some(bob.id),
+            ip_address: Some("192.0.2.2".to_string()),
+            ..AuditContext::system()
+        };
+        let renamed = User {
+            name: "Ada L.".to_string(),
+            ..ada.clone()
+        };
+        users.update(renamed, &as_admin).await.unwrap();
+        posts
+            .create_thread(group.id, bob.id, "Hi", "Not Ada's", &ctx)
+            .await
+            .unwrap();
+        digests
+            .set_frequency(ada.id, DigestFrequency::Daily)
+            .await
+            .unwrap();
+
+        let files = exports.collect(ada.id).await.unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            FILES.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        );
+        let file = |name: &str| -> Value {
+            let (_, json) = files.iter().find(|(n, _)| *n == name).unwrap();
+            serde_json::from_str(json).unwrap()
+        };
+
+        let profile = file("profile.json");
+        assert_eq!(profile["email"], "ada@example.com");
+        assert!(profile.get("password_hash").is_none());
+        assert_eq!(file("memberships.json")[0]["group_name"], "Readers");
+        let written = file("posts.json");
+        assert_eq!(written.as_array().unwrap().len(), 1);
+        assert_eq!(written[0]["body"], "First post");
+        assert_eq!(file("rsvps.json"), json!([]));
+        assert_eq!(file("settings.json")["digest_frequency"], "daily");
+        let audit_log = file("audit_log.json");
+        let events = audit_log.as_array().unwrap();
+        let address_of = |actor_id: i32| {
+            let event = events.iter().find(|e| e["actor_id"] == actor_id).unwrap();
+            event["ip_address"].clone()
+        };
+        assert_eq!(address_of(ada.id), "192.0.2.1");
+        assert_eq!(address_of(bob.id), Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_export_lifecycle() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let exports = DataExportService::new(db.pool.clone());
+
+        let [ada] = db.create_users(["Ada"]).await;
+        assert!(exports.latest(ada.id).await.unwrap().is_none());
+        let failed = exports.create(ada.id, &ctx).await.unwrap();
+        let ready = exports.create(ada.id, &ctx).await.unwrap();
+        assert_eq!(ready.status, DataExportStatus::Pending);
+        assert_eq!(exports.latest(ada.id).await.unwrap().unwrap().id, ready.id);
+
+        let now = Utc::now();
+        exports
+            .mark_failed(failed.id, now, now + Duration::hours(1))
+            .await
+            .unwrap();
+        exports
+            .mark_ready(ready.id, "exports/ada.zip", now, now + Duration::days(7))
+            .await
+            .unwrap();
+        let ready = exports.get_by_token(&ready.token).await.unwrap();
+        assert!(ready.is_downloadable(now));
+        assert_eq!(ready.blob_key.as_deref(), Some("exports/ada.zip"));
+        assert!(matches!(
+            exports.get_by_token("unknown").await,
+            Err(DataExportError::NotFound)
+        ));
+
+        let expired = exports.list_expired(now + Duration::days(1)).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, failed.id);
+        exports.delete(failed.id).await.unwrap();
+        assert!(matches!(
+            exports.get(failed.id).await,
+            Err(DataExportError::NotFound)
+        ));
+        let deleted = exports.delete_for_user(ada.id).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert!(exports.latest(ada.id).await.unwrap().is_none());
+    }
+}
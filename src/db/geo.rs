@@ -0,0 +1,371 @@
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{City, Group};
+use crate::geo::{EARTH_RADIUS_KM, NewCity, Place, Point, parse_place};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GeoError {
+    #[error("{0}")]
+    InvalidPlace(String),
+    #[error("Unknown city: {0}")]
+    UnknownCity(String),
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A group and how far away it is
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct NearbyGroup {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub group: Group,
+    pub distance_km: f64,
+}
+
+// An upcoming event of an open group and how far away it is
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct NearbyEvent {
+    pub id: i32,
+    pub group_id: i32,
+    pub group_name: String,
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    pub location: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub distance_km: f64,
+}
+
+// Haversine distance in SQL from ($1, $2) to the given columns, the same
+// formula as Point::distance_km
+fn distance_sql(latitude: &str, longitude: &str) -> String {
+    format!(
+        "(2 * {radius} * asin(least(1, sqrt( \
+             power(sin(radians({latitude} - $1) / 2), 2) + \
+             cos(radians($1)) * cos(radians({latitude})) * \
+             power(sin(radians({longitude} - $2) / 2), 2) \
+         ))))",
+        radius = EARTH_RADIUS_KM,
+    )
+}
+
+// A LIKE pattern matching values starting with `prefix`
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::new();
+    for c in prefix.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+pub struct GeoService {
+    pool: DbPool,
+}
+
+impl GeoService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // The most populous city with this name, in the country if one is given
+    pub async fn find_city(
+        &self,
+        name: &str,
+        country_code: Option<&str>,
+    ) -> Result<Option<City>, GeoError> {
+        let city = sqlx::query_as::<_, City>(
+            "SELECT * FROM cities WHERE lower(name) = lower($1) \
+             AND ($2::text IS NULL OR country_code = $2) \
+             ORDER BY population DESC, id LIMIT 1",
+        )
+        .bind(name.trim())
+        .bind(country_code)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(city)
+    }
+
+    // The coordinates of a typed place with how to show it: coordinates as
+    // typed, or a city found in the cities table
+    pub async fn locate(&self, input: &str) -> Result<(String, Point), GeoError> {
+        match parse_place(input).map_err(GeoError::InvalidPlace)? {
+            Place::Coordinates(point) => Ok((
+                format!("{:.5}, {:.5}", point.latitude, point.longitude),
+                point,
+            )),
+            Place::City { name, country_code } => {
+                let city = self
+                    .find_city(&name, country_code.as_deref())
+                    .await?
+                    .ok_or_else(|| GeoError::UnknownCity(input.trim().to_string()))?;
+                Ok((
+                    format!("{}, {}", city.name, city.country_code),
+                    Point {
+                        latitude: city.latitude,
+                        longitude: city.longitude,
+                    },
+                ))
+            }
+        }
+    }
+
+    // Cities whose name starts with `prefix`, the most populous first
+    pub async fn search_cities(&self, prefix: &str, limit: i64) -> Result<Vec<City>, GeoError> {
+        let prefix = prefix.trim();
+        if prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+        let cities = sqlx::query_as::<_, City>(
+            "SELECT * FROM cities WHERE lower(name) LIKE lower($1) \
+             ORDER BY population DESC, name LIMIT $2",
+        )
+        .bind(like_prefix(prefix))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(cities)
+    }
+
+    // Add cities or update those already listed. Returns how many were given.
+    pub async fn import_cities(&self, cities: &[NewCity]) -> Result<usize, GeoError> {
+        let mut tx = self.pool.begin().await?;
+        for city in cities {
+            sqlx::query(
+                "INSERT INTO cities (name, country_code, latitude, longitude, population) \
+                 VALUES ($1, $2, $3, $4, $5) \
+                 ON CONFLICT (name, country_code) DO UPDATE SET latitude = EXCLUDED.latitude, \
+                 longitude = EXCLUDED.longitude, population = EXCLUDED.population",
+            )
+            .bind(&city.name)
+            .bind(&city.country_code)
+            .bind(city.point.latitude)
+            .bind(city.point.longitude)
+            .bind(city.population)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(cities.len())
+    }
+
+    // Groups within `radius_km`, the closest first, having every tag in
+    // `slugs` when there are any
+    pub async fn groups_near(
+        &self,
+        point: Point,
+        radius_km: f64,
+        slugs: &[String],
+    ) -> Result<Vec<NearbyGroup>, GeoError> {
+        let (south, north) = point.latitude_band(radius_km);
+        let query = format!(
+            "SELECT * FROM ( \
+                 SELECT g.*, {distance} AS distance_km FROM groups g \
+                 WHERE g.deleted_at IS NULL AND g.latitude BETWEEN $3 AND $4 \
+                 AND g.longitude IS NOT NULL \
+                 AND (cardinality($6::text[]) = 0 OR ( \
+                     SELECT COUNT(DISTINCT t.slug) FROM group_tags gt JOIN tags t ON t.id = gt.tag_id \
+                     WHERE gt.group_id = g.id AND t.slug = ANY($6) \
+                 ) = cardinality($6::text[])) \
+             ) nearby WHERE distance_km <= $5 ORDER BY distance_km, id",
+            distance = distance_sql("g.latitude", "g.longitude"),
+        );
+        let groups = sqlx::query_as::<_, NearbyGroup>(&query)
+            .bind(point.latitude)
+            .bind(point.longitude)
+            .bind(south)
+            .bind(north)
+            .bind(radius_km)
+            .bind(slugs)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(groups)
+    }
+
+    // Upcoming events of open groups within `radius_km`, the closest first.
+    // Events without coordinates of their own are placed where their group is.
+    pub async fn events_near(
+        &self,
+        point: Point,
+        radius_km: f64,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<NearbyEvent>, GeoError> {
+        let (south, north) = point.latitude_band(radius_km);
+        let query = format!(
+            "SELECT * FROM ( \
+                 SELECT id, group_id, group_name, title, starts_at, location, latitude, longitude, \
+                        {distance} AS distance_km \
+                 FROM ( \
+                     SELECT e.id, e.group_id, g.name AS group_name, e.title, e.starts_at, e.location, \
+                            COALESCE(e.latitude, g.latitude) AS latitude, \
+                            CASE WHEN e.latitude IS NULL THEN g.longitude ELSE e.longitude END AS longitude \
+                     FROM events e JOIN groups g ON g.id = e.group_id \
+                     WHERE e.deleted_at IS NULL AND g.deleted_at IS NULL AND g.join_policy = 'open' \
+                     AND COALESCE(e.ends_at, e.starts_at) >= $6 \
+                 ) located \
+                 WHERE latitude BETWEEN $3 AND $4 AND longitude IS NOT NULL \
+             ) nearby WHERE distance_km <= $5 ORDER BY distance_km, starts_at, id LIMIT $7",
+            distance = distance_sql("latitude", "longitude"),
+        );
+        let events = sqlx::query_as::<_, NearbyEvent>(&query)
+            .bind(point.latitude)
+            .bind(point.longitude)
+            .bind(south)
+            .bind(north)
+            .bind(radius_km)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::audit::AuditContext;
+    use crate::db::connection::create_test_pool;
+    use crate::db::event::{EventService, NewEvent};
+    use crate::db::group::GroupService;
+    use crate::db::tag::{TagService, parse_tags};
+    use chrono::Duration;
+
+    fn point(latitude: f64, longitude: f64) -> Point {
+        Point::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_like_prefix() {
+        assert_eq!(like_prefix("San"), "San%");
+        assert_eq!(like_prefix("50%_off\\"), "50\\%\\_off\\\\%");
+    }
+
+    #[tokio::test]
+    async fn test_locate_and_import_cities() {
+        let db = create_test_pool().await.unwrap();
+        let geo = GeoService::new(db.pool.clone());
+
+        let (name, paris) = geo.locate(" paris ").await.unwrap();
+        assert_eq!(name, "Paris, FR");
+        assert!(paris.distance_km(&point(48.8566, 2.3522)) < 5.0);
+        let (name, _) = geo.locate("48.85, 2.35").await.unwrap();
+        assert_eq!(name, "48.85000, 2.35000");
+        assert!(matches!(
+            geo.locate("Nowhere, ZZ").await,
+            Err(GeoError::UnknownCity(_))
+        ));
+
+        // Importing again updates the cities already listed
+        let city = |population| NewCity {
+            name: "Testville".to_string(),
+            country_code: "ZZ".to_string(),
+            point: point(10.0, 20.0),
+            population,
+        };
+        geo.import_cities(&[city(1000)]).await.unwrap();
+        geo.import_cities(&[city(2000)]).await.unwrap();
+        let found = geo
+            .find_city("TESTVILLE", Some("ZZ"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.population, 2000);
+        assert!(
+            geo.find_city("Testville", Some("FR"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        assert_eq!(geo.search_cities("Par", 5).await.unwrap()[0].name, "Paris");
+        assert!(geo.search_cities("%", 5).await.unwrap().is_empty());
+        assert!(geo.search_cities(" ", 5).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_groups_and_events_near() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+        let tags = TagService::new(db.pool.clone());
+        let geo = GeoService::new(db.pool.clone());
+
+        let paris = point(48.85341, 2.3488);
+        let places = [
+            ("Paris", Some(paris)),
+            ("London", Some(point(51.50853, -0.12574))),
+            ("Lyon", Some(point(45.74846, 4.84671))),
+            ("Nowhere", None),
+            ("Gone", Some(paris)),
+        ];
+        let mut ids = Vec::new();
+        for (name, place) in places {
+            let group = groups.create(name.to_string(), &ctx).await.unwrap();
+            let location = place.map(|point| (name, point));
+            groups.set_location(group.id, location, &ctx).await.unwrap();
+            ids.push(group.id);
+        }
+        groups.delete(ids[4], &ctx).await.unwrap();
+        tags.set_group_tags(ids[2], &parse_tags("food").unwrap(), &ctx)
+            .await
+            .unwrap();
+
+        let near =
+            |groups: Vec<NearbyGroup>| groups.into_iter().map(|g| g.group.name).collect::<Vec<_>>();
+        assert_eq!(
+            near(geo.groups_near(paris, 50.0, &[]).await.unwrap()),
+            ["Paris"]
+        );
+        let within_500 = geo.groups_near(paris, 500.0, &[]).await.unwrap();
+        assert_eq!(within_500[0].distance_km, 0.0);
+        assert_eq!(near(within_500), ["Paris", "London", "Lyon"]);
+        let food = ["food".to_string()];
+        assert_eq!(
+            near(geo.groups_near(paris, 500.0, &food).await.unwrap()),
+            ["Lyon"]
+        );
+
+        // Events are placed where their group is, unless they have coordinates
+        let now = Utc::now();
+        let new_event = |title: &str, point: Option<Point>| NewEvent {
+            title: title.to_string(),
+            starts_at: now + Duration::days(1),
+            point,
+            ..Default::default()
+        };
+        let day_trip = new_event("Day trip", Some(point(48.80, 2.13)));
+        events.create(ids[1], day_trip, &ctx).await.unwrap();
+        events
+            .create(ids[0], new_event("Picnic", None), &ctx)
+            .await
+            .unwrap();
+        events
+            .create(ids[2], new_event("Dinner", None), &ctx)
+            .await
+            .unwrap();
+        let found = geo.events_near(paris, 50.0, now, 10).await.unwrap();
+        let titles: Vec<&str> = found.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["Picnic", "Day trip"]);
+        assert_eq!(found[1].group_name, "London");
+        assert_eq!(
+            geo.events_near(paris, 500.0, now, 2).await.unwrap().len(),
+            2
+        );
+    }
+}
@@ -1,7 +1,17 @@
+use crate::db::audit::{self, AuditContext};
 use crate::db::connection::{DatabaseError, DbPool};
-use crate::db::models::Group;
+use crate::db::member;
+use crate::db::models::{Group, MemberRole};
+use crate::db::realtime::{self, Update};
+use crate::db::user::DeletedFilter;
+use crate::geo::Point;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
 use thiserror::Error;
 
+// Entity name used in the audit log
+const ENTITY: &str = "group";
+
 #[derive(Debug, Error)]
 #[allow(dead_code)]
 pub enum GroupError {
@@ -15,6 +25,14 @@ pub enum GroupError {
     SqlError(#[from] sqlx::Error),
 }
 
+// Criteria for the admin group listing
+#[derive(Debug, Clone, Default)]
+pub struct GroupFilter {
+    // Case-insensitive match on the name
+    pub query: Option<String>,
+    pub deleted: DeletedFilter,
+}
+
 pub struct GroupService {
     pool: DbPool,
 }
@@ -26,10 +44,41 @@ impl GroupService {
     }
 
     // Create a new group
-    pub async fn create(&self, name: String) -> Result<Group, GroupError> {
-        let group = sqlx::query_as::<_, Group>("INSERT INTO groups (name) VALUES ($1) RETURNING *")
-            .bind(&name)
-            .fetch_one(&self.pool)
+    pub async fn create(&self, name: String, ctx: &AuditContext) -> Result<Group, GroupError> {
+        self.create_with_owner(name, None, ctx).await
+    }
+
+    // Create a new group owned by the given user, who becomes its first member
+    pub async fn create_with_owner(
+        &self,
+        name: String,
+        owner_id: Option<i32>,
+        ctx: &AuditContext,
+    ) -> Result<Group, GroupError> {
+        let mut tx = self.pool.begin().await?;
+        let group = sqlx::query_as::<_, Group>(
+            "INSERT INTO groups (name, owner_id) VALUES ($1, $2) RETURNING *",
+        )
+        .bind(&name)
+        .bind(owner_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(&mut tx, ctx, "create", ENTITY, group.id, None, Some(&group)).await?;
+        if let Some(owner_id) = owner_id {
+            member::add(&mut tx, ctx, group.id, owner_id, MemberRole::Owner, None).await?;
+        }
+        realtime::publish(&mut tx, &Update::group_created(&group)).await?;
+        tx.commit().await?;
+
+        Ok(group)
+    }
+
+    // Lock a group row for the rest of the transaction, returning its current state
+    async fn lock(conn: &mut PgConnection, id: i32) -> Result<Option<Group>, GroupError> {
+        let group = sqlx::query_as::<_, Group>("SELECT * FROM groups WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(conn)
             .await?;
 
         Ok(group)
@@ -58,39 +107,152 @@ impl GroupService {
     }
 
     // Update group
-    pub async fn update(&self, group: Group) -> Result<(), GroupError> {
-        let affected_rows =
-            sqlx::query("UPDATE groups SET name = $1 WHERE id = $2 AND deleted_at IS NULL")
-                .bind(&group.name)
-                .bind(group.id)
-                .execute(&self.pool)
-                .await?
-                .rows_affected();
-
-        if affected_rows == 0 {
-            return Err(GroupError::GroupNotFound);
-        }
+    pub async fn update(&self, group: Group, ctx: &AuditContext) -> Result<(), GroupError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, group.id).await?;
+
+        let after = sqlx::query_as::<_, Group>(
+            "UPDATE groups SET name = $1 WHERE id = $2 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(&group.name)
+        .bind(group.id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(GroupError::GroupNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            group.id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
-    // Delete group by ID (soft delete)
-    pub async fn delete(&self, id: i32) -> Result<(), GroupError> {
-        sqlx::query("UPDATE groups SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL")
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
+    // Replace the cover image. Returns the previous key.
+    pub async fn set_cover(
+        &self,
+        id: i32,
+        cover_key: Option<&str>,
+        ctx: &AuditContext,
+    ) -> Result<Option<String>, GroupError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, Group>(
+            "UPDATE groups SET cover_key = $1 WHERE id = $2 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(cover_key)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(GroupError::GroupNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(before.and_then(|group| group.cover_key))
+    }
+
+    // Set or clear where the group meets
+    pub async fn set_location(
+        &self,
+        id: i32,
+        location: Option<(&str, Point)>,
+        ctx: &AuditContext,
+    ) -> Result<Group, GroupError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, Group>(
+            "UPDATE groups SET location = $1, latitude = $2, longitude = $3 \
+             WHERE id = $4 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(location.map(|(name, _)| name))
+        .bind(location.map(|(_, point)| point.latitude))
+        .bind(location.map(|(_, point)| point.longitude))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(GroupError::GroupNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after)
+    }
+
+    // Whether any group still has this cover, see UserService::avatar_in_use
+    pub async fn cover_in_use(&self, cover_key: &str) -> Result<bool, GroupError> {
+        let in_use: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM groups WHERE cover_key = $1)")
+                .bind(cover_key)
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(in_use)
+    }
+
+    // Delete group by ID (soft delete), GroupNotFound if no active group has it
+    pub async fn delete(&self, id: i32, ctx: &AuditContext) -> Result<(), GroupError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, Group>(
+            "UPDATE groups SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(GroupError::GroupNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "delete",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        realtime::publish(&mut tx, &Update::GroupDeleted { id }).await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
     // Hard delete group by ID (permanent removal)
     // Only allowed for groups that have already been soft-deleted
-    pub async fn hard_delete(&self, id: i32) -> Result<(), GroupError> {
-        // First check if group exists and is soft-deleted
-        let group = self.get_by_id(id).await?;
+    pub async fn hard_delete(&self, id: i32, ctx: &AuditContext) -> Result<(), GroupError> {
+        let mut tx = self.pool.begin().await?;
 
-        match group {
+        // First check if group exists and is soft-deleted
+        match Self::lock(&mut tx, id).await? {
             Some(group) => {
                 // Check if the group is soft-deleted
                 if group.deleted_at.is_none() {
@@ -100,9 +262,12 @@ impl GroupService {
                 // Group is soft-deleted, proceed with hard deletion
                 sqlx::query("DELETE FROM groups WHERE id = $1")
                     .bind(id)
-                    .execute(&self.pool)
+                    .execute(&mut *tx)
                     .await?;
 
+                audit::record(&mut tx, ctx, "hard_delete", ENTITY, id, Some(&group), None).await?;
+                tx.commit().await?;
+
                 Ok(())
             }
             None => Ok(()), // Group doesn't exist, nothing to delete
@@ -120,6 +285,115 @@ impl GroupService {
         Ok(groups)
     }
 
+    // Groups the user belongs to, by name
+    pub async fn list_for_member(&self, user_id: i32) -> Result<Vec<Group>, GroupError> {
+        let groups = sqlx::query_as::<_, Group>(
+            "SELECT g.* FROM groups g JOIN group_members m ON m.group_id = g.id \
+             WHERE m.user_id = $1 AND g.deleted_at IS NULL ORDER BY g.name, g.id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(groups)
+    }
+
+    // Restore a soft-deleted group
+    pub async fn restore(&self, id: i32, ctx: &AuditContext) -> Result<(), GroupError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, Group>(
+            "UPDATE groups SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(GroupError::GroupNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "restore",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        realtime::publish(&mut tx, &Update::group_created(&after)).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Search groups for the admin area, newest first
+    pub async fn search(&self, filter: &GroupFilter) -> Result<Vec<Group>, GroupError> {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM groups WHERE TRUE");
+
+        match filter.deleted {
+            DeletedFilter::Active => query.push(" AND deleted_at IS NULL"),
+            DeletedFilter::Deleted => query.push(" AND deleted_at IS NOT NULL"),
+            DeletedFilter::All => &mut query,
+        };
+        if let Some(text) = filter.query.as_deref().filter(|q| !q.trim().is_empty()) {
+            query
+                .push(" AND name ILIKE ")
+                .push_bind(format!("%{}%", text.trim()));
+        }
+        query.push(" ORDER BY created_at DESC");
+
+        let groups = query
+            .build_query_as::<Group>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(groups)
+    }
+
+    // List groups soft-deleted before the given time, oldest first
+    pub async fn list_deleted_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<Vec<Group>, GroupError> {
+        let groups = sqlx::query_as::<_, Group>(
+            "SELECT * FROM groups WHERE deleted_at IS NOT NULL AND deleted_at < $1 ORDER BY deleted_at",
+        )
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(groups)
+    }
+
+    // List soft-deleted groups, most recently deleted first. With an owner
+    // only that user's groups are returned.
+    pub async fn list_deleted(&self, owner_id: Option<i32>) -> Result<Vec<Group>, GroupError> {
+        let mut query =
+            QueryBuilder::<Postgres>::new("SELECT * FROM groups WHERE deleted_at IS NOT NULL");
+        if let Some(owner_id) = owner_id {
+            query.push(" AND owner_id = ").push_bind(owner_id);
+        }
+        query.push(" ORDER BY deleted_at DESC");
+
+        let groups = query
+            .build_query_as::<Group>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(groups)
+    }
+
+    // Count soft-deleted groups
+    pub async fn count_deleted(&self) -> Result<i64, GroupError> {
+        let count: (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM groups WHERE deleted_at IS NOT NULL")
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(count.0)
+    }
+
     // Count total number of groups
     pub async fn count(&self) -> Result<i64, GroupError> {
         let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM groups")
@@ -130,4 +404,82 @@ impl GroupService {
     }
 }
 
-// Tests will be rewritten for PostgreSQL in a separate module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_trash_restore_and_purge() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+
+        let [owner] = db.create_users(["Ada"]).await;
+        let mine = groups
+            .create_with_owner("Readers".to_string(), Some(owner.id), &ctx)
+            .await
+            .unwrap();
+        let other = groups.create("Hikers".to_string(), &ctx).await.unwrap();
+        assert!(matches!(
+            groups.hard_delete(mine.id, &ctx).await,
+            Err(GroupError::NotSoftDeleted)
+        ));
+        groups.delete(mine.id, &ctx).await.unwrap();
+        groups.delete(other.id, &ctx).await.unwrap();
+
+        // The trash of an owner only shows their groups
+        let trash = groups.list_deleted(Some(owner.id)).await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].id, mine.id);
+        assert_eq!(groups.list_deleted(None).await.unwrap().len(), 2);
+        assert!(groups.list_active().await.unwrap().is_empty());
+
+        groups.restore(mine.id, &ctx).await.unwrap();
+        assert!(matches!(
+            groups.restore(mine.id, &ctx).await,
+            Err(GroupError::GroupNotFound)
+        ));
+        assert_eq!(groups.list_active().await.unwrap()[0].id, mine.id);
+
+        // Only groups deleted before the cutoff are purged
+        let cutoff = Utc::now() + Duration::seconds(1);
+        let expired = groups.list_deleted_before(cutoff).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, other.id);
+        assert!(
+            groups
+                .list_deleted_before(cutoff - Duration::hours(1))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        groups.hard_delete(other.id, &ctx).await.unwrap();
+        assert!(groups.get_by_id(other.id).await.unwrap().is_none());
+        assert_eq!(groups.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_covers_of_active_groups() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        assert_eq!(
+            groups.set_cover(group.id, Some("c1"), &ctx).await.unwrap(),
+            None
+        );
+        let previous = groups.set_cover(group.id, Some("c2"), &ctx).await.unwrap();
+        assert_eq!(previous.as_deref(), Some("c1"));
+        assert!(!groups.cover_in_use("c1").await.unwrap());
+        assert!(groups.cover_in_use("c2").await.unwrap());
+
+        groups.delete(group.id, &ctx).await.unwrap();
+        assert!(matches!(
+            groups.set_cover(group.id, None, &ctx).await,
+            Err(GroupError::GroupNotFound)
+        ));
+    }
+}
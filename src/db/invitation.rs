@@ -0,0 +1,368 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::member;
+use crate::db::models::{GroupMember, Invitation, InvitationStatus, MemberRole, User};
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "invitation";
+
+#[derive(Debug, Error)]
+pub enum InvitationError {
+    #[error("Invitation not found")]
+    NotFound,
+    #[error("Invitation has been revoked")]
+    Revoked,
+    #[error("Invitation has expired")]
+    Expired,
+    #[error("Invitation has already been used")]
+    UsedUp,
+    #[error("Invitation was sent to another email address")]
+    WrongRecipient,
+    #[error("Already a member of this group")]
+    AlreadyMember,
+    #[error("Only pending invitations can be revoked")]
+    NotPending,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// An invitation to be created
+#[derive(Debug, Clone, Default)]
+pub struct NewInvitation {
+    // Restrict the invitation to one address; such invitations can be used once
+    pub email: Option<String>,
+    pub max_uses: Option<i32>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// Unguessable code used in invitation links
+fn generate_code() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+fn same_email(a: &str, b: &str) -> bool {
+    a.trim().eq_ignore_ascii_case(b.trim())
+}
+
+pub struct InvitationService {
+    pool: DbPool,
+}
+
+impl InvitationService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        group_id: i32,
+        invitation: NewInvitation,
+        ctx: &AuditContext,
+    ) -> Result<Invitation, InvitationError> {
+        let email = invitation
+            .email
+            .map(|email| email.trim().to_string())
+            .filter(|email| !email.is_empty());
+        let max_uses = if email.is_some() {
+            Some(1)
+        } else {
+            invitation.max_uses
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let created = sqlx::query_as::<_, Invitation>(
+            "INSERT INTO group_invitations (group_id, code, email, invited_by, max_uses, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        )
+        .bind(group_id)
+        .bind(generate_code())
+        .bind(&email)
+        .bind(ctx.actor_id)
+        .bind(max_uses)
+        .bind(invitation.expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "create",
+            ENTITY,
+            created.id,
+            None,
+            Some(&created),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    pub async fn get_by_code(&self, code: &str) -> Result<Option<Invitation>, InvitationError> {
+        let invitation =
+            sqlx::query_as::<_, Invitation>("SELECT * FROM group_invitations WHERE code = $1")
+                .bind(code)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(invitation)
+    }
+
+    // Every invitation of a group, newest first
+    pub async fn list_for_group(&self, group_id: i32) -> Result<Vec<Invitation>, InvitationError> {
+        let invitations = sqlx::query_as::<_, Invitation>(
+            "SELECT * FROM group_invitations WHERE group_id = $1 ORDER BY created_at DESC, id DESC",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(invitations)
+    }
+
+    pub async fn revoke(
+        &self,
+        group_id: i32,
+        id: i32,
+        ctx: &AuditContext,
+    ) -> Result<Invitation, InvitationError> {
+        let mut tx = self.pool.begin().await?;
+        let before = sqlx::query_as::<_, Invitation>(
+            "SELECT * FROM group_invitations WHERE id = $1 AND group_id = $2 FOR UPDATE",
+        )
+        .bind(id)
+        .bind(group_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(InvitationError::NotFound)?;
+
+        if before.status != InvitationStatus::Pending {
+            return Err(InvitationError::NotPending);
+        }
+
+        let after = sqlx::query_as::<_, Invitation>(
+            "UPDATE group_invitations SET status = 'revoked', revoked_at = NOW() \
+             WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "revoke",
+            ENTITY,
+            id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after)
+    }
+
+    // Join the invitation's group. The invitation row stays locked until the
+    // membership exists, so concurrent uses cannot exceed `max_uses`.
+    pub async fn accept(
+        &self,
+        code: &str,
+        user: &User,
+        ctx: &AuditContext,
+    ) -> Result<GroupMember, InvitationError> {
+        let mut tx = self.pool.begin().await?;
+        let invitation = sqlx::query_as::<_, Invitation>(
+            "SELECT i.* FROM group_invitations i JOIN groups g ON g.id = i.group_id \
+             WHERE i.code = $1 AND g.deleted_at IS NULL FOR UPDATE OF i",
+        )
+        .bind(code)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(InvitationError::NotFound)?;
+
+        let now = Utc::now();
+        if !invitation.is_usable(now) {
+            return Err(match invitation.status {
+                InvitationStatus::Revoked => InvitationError::Revoked,
+                _ if invitation.is_expired(now) => InvitationError::Expired,
+                _ => InvitationError::UsedUp,
+            });
+        }
+        if let Some(email) = &invitation.email {
+            if !same_email(email, &user.email) {
+                return Err(InvitationError::WrongRecipient);
+            }
+        }
+
+        let member = Self::use_invitation(&mut tx, ctx, &invitation, user.id)
+            .await?
+            .ok_or(InvitationError::AlreadyMember)?;
+        tx.commit().await?;
+
+        Ok(member)
+    }
+
+    // Add the member and count the use, marking the invitation accepted once it
+    // cannot be used again. Nothing changes when the user already is a member.
+    async fn use_invitation(
+        conn: &mut PgConnection,
+        ctx: &AuditContext,
+        invitation: &Invitation,
+        user_id: i32,
+    ) -> Result<Option<GroupMember>, sqlx::Error> {
+        let Some(member) = member::add(
+            conn,
+            ctx,
+            invitation.group_id,
+            user_id,
+            MemberRole::Member,
+            Some(invitation.id),
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let after = sqlx::query_as::<_, Invitation>(
+            "UPDATE group_invitations SET use_count = use_count + 1, \
+             status = CASE WHEN max_uses IS NOT NULL AND use_count + 1 >= max_uses \
+                 THEN 'accepted' ELSE status END, \
+             accepted_at = NOW() \
+             WHERE id = $1 RETURNING *",
+        )
+        .bind(invitation.id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        audit::record(
+            conn,
+            ctx,
+            "accept",
+            ENTITY,
+            invitation.id,
+            Some(invitation),
+            Some(&after),
+        )
+        .await?;
+
+        Ok(Some(member))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::group::GroupService;
+    use chrono::Duration;
+
+    fn invitation() -> Invitation {
+        let now = Utc::now();
+        Invitation {
+            id: 1,
+            group_id: 1,
+            code: generate_code(),
+            email: None,
+            invited_by: None,
+            status: InvitationStatus::Pending,
+            max_uses: Some(2),
+            use_count: 0,
+            expires_at: Some(now + Duration::days(1)),
+            created_at: now,
+            accepted_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn test_invitation_usable() {
+        let now = Utc::now();
+        let mut link = invitation();
+        assert_eq!(link.code.len(), 32);
+        assert!(link.is_usable(now));
+
+        link.use_count = 2;
+        assert!(!link.is_usable(now));
+
+        link.max_uses = None;
+        assert!(link.is_usable(now));
+        assert!(!link.is_usable(now + Duration::days(2)));
+
+        link.status = InvitationStatus::Revoked;
+        assert!(!link.is_usable(now));
+    }
+
+    #[test]
+    fn test_same_email_ignores_case() {
+        assert!(same_email("Ada@Example.com ", "ada@example.com"));
+        assert!(!same_email("ada@example.com", "bob@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_accept_counts_uses() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let invitations = InvitationService::new(db.pool.clone());
+
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let [ada, bob, cy] = db.create_users(["ada", "bob", "cy"]).await;
+        let link = NewInvitation {
+            max_uses: Some(2),
+            ..NewInvitation::default()
+        };
+        let link = invitations.create(group.id, link, &ctx).await.unwrap();
+
+        let member = invitations.accept(&link.code, &ada, &ctx).await.unwrap();
+        assert_eq!(member.group_id, group.id);
+        assert!(matches!(
+            invitations.accept(&link.code, &ada, &ctx).await,
+            Err(InvitationError::AlreadyMember)
+        ));
+        invitations.accept(&link.code, &bob, &ctx).await.unwrap();
+        assert!(matches!(
+            invitations.accept(&link.code, &cy, &ctx).await,
+            Err(InvitationError::UsedUp)
+        ));
+
+        let link = invitations.get_by_code(&link.code).await.unwrap().unwrap();
+        assert_eq!(link.use_count, 2);
+        assert_eq!(link.status, InvitationStatus::Accepted);
+    }
+
+    #[tokio::test]
+    async fn test_accept_personal_invitation() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let invitations = InvitationService::new(db.pool.clone());
+
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let personal = NewInvitation {
+            email: Some("Ada@Example.com".to_string()),
+            ..NewInvitation::default()
+        };
+        let personal = invitations.create(group.id, personal, &ctx).await.unwrap();
+
+        let [ada, bob] = db.create_users(["Ada", "Bob"]).await;
+        assert!(matches!(
+            invitations.accept(&personal.code, &bob, &ctx).await,
+            Err(InvitationError::WrongRecipient)
+        ));
+        let member = invitations
+            .accept(&personal.code, &ada, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(member.user_id, ada.id);
+        assert!(matches!(
+            invitations.accept(&personal.code, &ada, &ctx).await,
+            Err(InvitationError::UsedUp)
+        ));
+    }
+}
@@ -0,0 +1,501 @@
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{Job, JobSchedule, JobStatus};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::{Postgres, QueryBuilder};
+use thiserror::Error;
+
+pub const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("Job not found")]
+    JobNotFound,
+    #[error("Invalid job payload: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A job to be enqueued
+#[derive(Debug, Clone)]
+pub struct NewJob {
+    pub kind: String,
+    pub payload: String,
+    pub run_at: Option<DateTime<Utc>>,
+    pub max_attempts: i32,
+    pub unique_key: Option<String>,
+}
+
+impl NewJob {
+    pub fn new(kind: &str, payload: &impl Serialize) -> Result<Self, JobError> {
+        Ok(Self {
+            kind: kind.to_string(),
+            payload: serde_json::to_string(payload)?,
+            run_at: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            unique_key: None,
+        })
+    }
+
+    // Run no earlier than the given time
+    pub fn run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = Some(run_at);
+        self
+    }
+
+    // Skip enqueueing when a job with this key was enqueued before
+    pub fn unique_key(mut self, key: impl Into<String>) -> Self {
+        self.unique_key = Some(key.into());
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+}
+
+// Number of jobs in each status
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobStats {
+    pub pending: i64,
+    pub running: i64,
+    pub completed: i64,
+    pub failed: i64,
+}
+
+pub struct JobService {
+    pool: DbPool,
+}
+
+impl JobService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Add a job to the queue. Returns None when the unique key was already used.
+    pub async fn enqueue(&self, job: NewJob) -> Result<Option<Job>, JobError> {
+        let job = sqlx::query_as::<_, Job>(
+            "INSERT INTO jobs (kind, payload, run_at, max_attempts, unique_key) \
+             VALUES ($1, $2, COALESCE($3, NOW()), $4, $5) \
+             ON CONFLICT (unique_key) DO NOTHING RETURNING *",
+        )
+        .bind(&job.kind)
+        .bind(&job.payload)
+        .bind(job.run_at)
+        .bind(job.max_attempts)
+        .bind(&job.unique_key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(job)
+    }
+
+    // Claim the next due job for a worker. SKIP LOCKED lets concurrent workers,
+    // in this or another process, each take a different job without blocking.
+    pub async fn claim_next(&self, worker_id: &str) -> Result<Option<Job>, JobError> {
+        let job = sqlx::query_as::<_, Job>(
+            "UPDATE jobs SET status = 'running', attempts = attempts + 1, \
+             locked_at = NOW(), locked_by = $1, heartbeat_at = NOW() \
+             WHERE id = ( \
+                 SELECT id FROM jobs WHERE status = 'pending' AND run_at <= NOW() \
+                 ORDER BY run_at, id FOR UPDATE SKIP LOCKED LIMIT 1 \
+             ) RETURNING *",
+        )
+        .bind(worker_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(job)
+    }
+
+    // Tell other processes the worker is still running the job
+    pub async fn heartbeat(&self, id: i32, worker_id: &str) -> Result<(), JobError> {
+        sqlx::query(
+            "UPDATE jobs SET heartbeat_at = NOW() \
+             WHERE id = $1 AND status = 'running' AND locked_by = $2",
+        )
+        .bind(id)
+        .bind(worker_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Mark a job the worker still holds as done. False when the lease was lost,
+    // the job having been requeued and possibly claimed by another worker.
+    pub async fn complete(&self, id: i32, worker_id: &str) -> Result<bool, JobError> {
+        let updated = sqlx::query(
+            "UPDATE jobs SET status = 'completed', finished_at = NOW(), \
+             locked_at = NULL, locked_by = NULL, heartbeat_at = NULL, last_error = NULL \
+             WHERE id = $1 AND status = 'running' AND locked_by = $2",
+        )
+        .bind(id)
+        .bind(worker_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(updated > 0)
+    }
+
+    // Record a failed attempt, scheduling another one at `retry_at` or giving
+    // up when there is none. False when the worker no longer held the job.
+    pub async fn fail(
+        &self,
+        id: i32,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, JobError> {
+        let updated = match retry_at {
+            Some(retry_at) => sqlx::query(
+                "UPDATE jobs SET status = 'pending', run_at = $3, last_error = $4, \
+                 locked_at = NULL, locked_by = NULL, heartbeat_at = NULL \
+                 WHERE id = $1 AND status = 'running' AND locked_by = $2",
+            )
+            .bind(id)
+            .bind(worker_id)
+            .bind(retry_at)
+            .bind(error)
+            .execute(&self.pool)
+            .await?
+            .rows_affected(),
+            None => sqlx::query(
+                "UPDATE jobs SET status = 'failed', finished_at = NOW(), last_error = $3, \
+                 locked_at = NULL, locked_by = NULL, heartbeat_at = NULL \
+                 WHERE id = $1 AND status = 'running' AND locked_by = $2",
+            )
+            .bind(id)
+            .bind(worker_id)
+            .bind(error)
+            .execute(&self.pool)
+            .await?
+            .rows_affected(),
+        };
+
+        Ok(updated > 0)
+    }
+
+    // Put a failed job back in the queue with a fresh set of attempts
+    pub async fn retry(&self, id: i32) -> Result<(), JobError> {
+        let affected_rows = sqlx::query(
+            "UPDATE jobs SET status = 'pending', attempts = 0, run_at = NOW(), finished_at = NULL \
+             WHERE id = $1 AND status = 'failed'",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if affected_rows == 0 {
+            return Err(JobError::JobNotFound);
+        }
+
+        Ok(())
+    }
+
+    // Release jobs whose worker disappeared mid-run (crash, deploy) so they are
+    // picked up again. Running workers send heartbeats, so a job is only
+    // released once its worker has been silent for `silent_for`.
+    pub async fn requeue_stale(&self, silent_for: Duration) -> Result<u64, JobError> {
+        let affected_rows = sqlx::query(
+            "UPDATE jobs SET status = 'pending', locked_at = NULL, locked_by = NULL, \
+             heartbeat_at = NULL, last_error = 'worker stopped responding' \
+             WHERE status = 'running' AND COALESCE(heartbeat_at, locked_at) < $1",
+        )
+        .bind(Utc::now() - silent_for)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(affected_rows)
+    }
+
+    // Delete completed and failed jobs that finished before the cutoff
+    pub async fn delete_finished_before(&self, cutoff: DateTime<Utc>) -> Result<u64, JobError> {
+        let affected_rows = sqlx::query(
+            "DELETE FROM jobs WHERE status IN ('completed', 'failed') AND finished_at < $1",
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(affected_rows)
+    }
+
+    pub async fn get_by_id(&self, id: i32) -> Result<Option<Job>, JobError> {
+        let job = sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(job)
+    }
+
+    // Most recently created jobs first, optionally only those with a status
+    pub async fn list_recent(
+        &self,
+        status: Option<JobStatus>,
+        limit: i64,
+    ) -> Result<Vec<Job>, JobError> {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM jobs");
+        if let Some(status) = status {
+            query.push(" WHERE status = ").push_bind(status.as_str());
+        }
+        query
+            .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+            .push_bind(limit);
+
+        let jobs = query.build_query_as::<Job>().fetch_all(&self.pool).await?;
+
+        Ok(jobs)
+    }
+
+    pub async fn stats(&self) -> Result<JobStats, JobError> {
+        let rows: Vec<(JobStatus, i64)> =
+            sqlx::query_as("SELECT status, COUNT(*) FROM jobs GROUP BY status")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut stats = JobStats::default();
+        for (status, count) in rows {
+            match status {
+                JobStatus::Pending => stats.pending = count,
+                JobStatus::Running => stats.running = count,
+                JobStatus::Completed => stats.completed = count,
+                JobStatus::Failed => stats.failed = count,
+            }
+        }
+
+        Ok(stats)
+    }
+
+    // Make the stored schedules match the configured ones. A schedule whose
+    // cron expression changed starts over from `next_run_at`.
+    pub async fn sync_schedules(&self, schedules: &[JobSchedule]) -> Result<(), JobError> {
+        let mut tx = self.pool.begin().await?;
+
+        let kinds: Vec<String> = schedules.iter().map(|s| s.kind.clone()).collect();
+        sqlx::query("DELETE FROM job_schedules WHERE NOT (kind = ANY($1))")
+            .bind(&kinds)
+            .execute(&mut *tx)
+            .await?;
+
+        for schedule in schedules {
+            sqlx::query(
+                "INSERT INTO job_schedules (kind, cron, next_run_at) VALUES ($1, $2, $3) \
+                 ON CONFLICT (kind) DO UPDATE SET cron = EXCLUDED.cron, next_run_at = EXCLUDED.next_run_at \
+                 WHERE job_schedules.cron <> EXCLUDED.cron",
+            )
+            .bind(&schedule.kind)
+            .bind(&schedule.cron)
+            .bind(schedule.next_run_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn list_schedules(&self) -> Result<Vec<JobSchedule>, JobError> {
+        let schedules =
+            sqlx::query_as::<_, JobSchedule>("SELECT * FROM job_schedules ORDER BY kind")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(schedules)
+    }
+
+    // Enqueue a job for every schedule that is due and move it to its next run,
+    // as computed by `next_after`. Locked rows are skipped so only one process
+    // fires a given schedule.
+    pub async fn enqueue_due_schedules(
+        &self,
+        next_after: impl Fn(&JobSchedule, DateTime<Utc>) -> Option<DateTime<Utc>>,
+    ) -> Result<Vec<String>, JobError> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let due = sqlx::query_as::<_, JobSchedule>(
+            "SELECT * FROM job_schedules WHERE next_run_at <= $1 FOR UPDATE SKIP LOCKED",
+        )
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut fired = Vec::new();
+        for schedule in due {
+            // The key makes a run idempotent should the same slot fire twice
+            let unique_key = format!(
+                "schedule:{}:{}",
+                schedule.kind,
+                schedule.next_run_at.timestamp()
+            );
+            sqlx::query(
+                "INSERT INTO jobs (kind, unique_key) VALUES ($1, $2) ON CONFLICT (unique_key) DO NOTHING",
+            )
+            .bind(&schedule.kind)
+            .bind(unique_key)
+            .execute(&mut *tx)
+            .await?;
+
+            // An expression without future runs is parked far in the future
+            let next_run_at = next_after(&schedule, now).unwrap_or(now + Duration::days(365 * 100));
+            sqlx::query(
+                "UPDATE job_schedules SET next_run_at = $2, last_run_at = $3 WHERE kind = $1",
+            )
+            .bind(&schedule.kind)
+            .bind(next_run_at)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+            fired.push(schedule.kind);
+        }
+
+        tx.commit().await?;
+        Ok(fired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+
+    #[tokio::test]
+    async fn test_claim_fail_and_complete() {
+        let db = create_test_pool().await.unwrap();
+        let jobs = JobService::new(db.pool.clone());
+
+        let greeting = NewJob::new("send_email", &"hello")
+            .unwrap()
+            .unique_key("greeting");
+        let job = jobs.enqueue(greeting.clone()).await.unwrap().unwrap();
+        assert!(jobs.enqueue(greeting).await.unwrap().is_none());
+        let later = NewJob::new("send_email", &"later")
+            .unwrap()
+            .run_at(Utc::now() + Duration::hours(1));
+        jobs.enqueue(later).await.unwrap().unwrap();
+
+        let claimed = jobs.claim_next("worker-1").await.unwrap().unwrap();
+        assert_eq!(claimed.id, job.id);
+        assert_eq!(claimed.status, JobStatus::Running);
+        assert_eq!(claimed.attempts, 1);
+        assert_eq!(claimed.locked_by.as_deref(), Some("worker-1"));
+        assert!(claimed.heartbeat_at.is_some());
+        assert!(jobs.claim_next("worker-2").await.unwrap().is_none());
+
+        assert!(
+            jobs.fail(job.id, "worker-1", "timeout", Some(Utc::now()))
+                .await
+                .unwrap()
+        );
+        let claimed = jobs.claim_next("worker-2").await.unwrap().unwrap();
+        assert_eq!(claimed.attempts, 2);
+        // The first worker lost the job, its late result is ignored
+        assert!(!jobs.complete(job.id, "worker-1").await.unwrap());
+        assert!(
+            !jobs
+                .fail(job.id, "worker-1", "late", Some(Utc::now()))
+                .await
+                .unwrap()
+        );
+        let running = jobs.get_by_id(job.id).await.unwrap().unwrap();
+        assert_eq!(running.status, JobStatus::Running);
+        assert_eq!(running.locked_by.as_deref(), Some("worker-2"));
+        assert!(
+            jobs.fail(job.id, "worker-2", "timeout", None)
+                .await
+                .unwrap()
+        );
+        let failed = jobs.get_by_id(job.id).await.unwrap().unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.last_error.as_deref(), Some("timeout"));
+
+        jobs.retry(job.id).await.unwrap();
+        let claimed = jobs.claim_next("worker-1").await.unwrap().unwrap();
+        assert_eq!(claimed.attempts, 1);
+        assert!(jobs.complete(job.id, "worker-1").await.unwrap());
+        assert!(!jobs.complete(job.id, "worker-1").await.unwrap());
+
+        let stats = jobs.stats().await.unwrap();
+        assert_eq!(
+            (stats.pending, stats.running, stats.completed, stats.failed),
+            (1, 0, 1, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requeue_stale_spares_jobs_with_heartbeats() {
+        let db = create_test_pool().await.unwrap();
+        let jobs = JobService::new(db.pool.clone());
+
+        for payload in ["alive", "silent"] {
+            jobs.enqueue(NewJob::new("send_email", &payload).unwrap())
+                .await
+                .unwrap();
+        }
+        let alive = jobs.claim_next("worker-1").await.unwrap().unwrap();
+        let silent = jobs.claim_next("worker-2").await.unwrap().unwrap();
+
+        // Both jobs were claimed long ago, only the first worker still reports in
+        sqlx::query("UPDATE jobs SET locked_at = $1, heartbeat_at = $1")
+            .bind(Utc::now() - Duration::minutes(30))
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        jobs.heartbeat(alive.id, "worker-1").await.unwrap();
+        jobs.heartbeat(silent.id, "worker-1").await.unwrap();
+
+        assert_eq!(jobs.requeue_stale(Duration::minutes(10)).await.unwrap(), 1);
+        let alive = jobs.get_by_id(alive.id).await.unwrap().unwrap();
+        assert_eq!(alive.status, JobStatus::Running);
+        let silent = jobs.get_by_id(silent.id).await.unwrap().unwrap();
+        assert_eq!(silent.status, JobStatus::Pending);
+        assert!(silent.locked_by.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_due_schedules() {
+        let db = create_test_pool().await.unwrap();
+        let jobs = JobService::new(db.pool.clone());
+
+        let now = Utc::now();
+        let schedule = |kind: &str, next_run_at| JobSchedule {
+            kind: kind.to_string(),
+            cron: "0 * * * *".to_string(),
+            next_run_at,
+            last_run_at: None,
+        };
+        let schedules = [
+            schedule("digest", now - Duration::minutes(1)),
+            schedule("cleanup", now + Duration::hours(1)),
+        ];
+        jobs.sync_schedules(&schedules).await.unwrap();
+
+        let next = now + Duration::hours(1);
+        let fired = jobs.enqueue_due_schedules(|_, _| Some(next)).await.unwrap();
+        assert_eq!(fired, vec!["digest".to_string()]);
+        assert!(
+            jobs.enqueue_due_schedules(|_, _| Some(next))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let queued = jobs.list_recent(None, 10).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].kind, "digest");
+        let digest = &jobs.list_schedules().await.unwrap()[1];
+        assert_eq!(digest.kind, "digest");
+        assert!(digest.last_run_at.is_some());
+    }
+}
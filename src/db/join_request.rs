@@ -0,0 +1,426 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::member;
+use crate::db::models::{
+    Group, JoinAnswer, JoinPolicy, JoinQuestion, JoinRequest, JoinRequestStatus, MemberRole,
+};
+use serde::Serialize;
+use sqlx::{FromRow, PgConnection, Postgres, QueryBuilder};
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "join_request";
+
+pub const MAX_QUESTIONS: usize = 10;
+pub const MAX_QUESTION_LENGTH: usize = 500;
+pub const MAX_ANSWER_LENGTH: usize = 2000;
+
+#[derive(Debug, Error)]
+pub enum JoinRequestError {
+    #[error("Join request not found")]
+    NotFound,
+    #[error("Group not found")]
+    GroupNotFound,
+    #[error("Join request has already been decided")]
+    NotPending,
+    #[error("Already a member of this group")]
+    AlreadyMember,
+    #[error("A request to join this group is already waiting for approval")]
+    AlreadyPending,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A join request with the applicant's account details, for the approval queue
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct JoinRequestEntry {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub request: JoinRequest,
+    pub user_email: String,
+    pub user_name: String,
+}
+
+// Join settings of a group as recorded in the audit log
+#[derive(Serialize)]
+struct JoinSettings<'a> {
+    join_policy: JoinPolicy,
+    questions: Vec<&'a str>,
+}
+
+pub struct JoinRequestService {
+    pool: DbPool,
+}
+
+impl JoinRequestService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Questions of a group in the order they are asked
+    pub async fn questions(&self, group_id: i32) -> Result<Vec<JoinQuestion>, JoinRequestError> {
+        let questions = sqlx::query_as::<_, JoinQuestion>(
+            "SELECT * FROM group_join_questions WHERE group_id = $1 ORDER BY position, id",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(questions)
+    }
+
+    // Change the join policy and replace the questions of a group
+    pub async fn update_settings(
+        &self,
+        group_id: i32,
+        join_policy: JoinPolicy,
+        questions: &[String],
+        ctx: &AuditContext,
+    ) -> Result<Group, JoinRequestError> {
+        let mut tx = self.pool.begin().await?;
+        let before = sqlx::query_as::<_, Group>(
+            "SELECT * FROM groups WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        )
+        .bind(group_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(JoinRequestError::GroupNotFound)?;
+        let previous: Vec<String> = sqlx::query_scalar(
+            "DELETE FROM group_join_questions WHERE group_id = $1 RETURNING question",
+        )
+        .bind(group_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let group = sqlx::query_as::<_, Group>(
+            "UPDATE groups SET join_policy = $2 WHERE id = $1 RETURNING *",
+        )
+        .bind(group_id)
+        .bind(join_policy)
+        .fetch_one(&mut *tx)
+        .await?;
+        for (position, question) in questions.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO group_join_questions (group_id, position, question) VALUES ($1, $2, $3)",
+            )
+            .bind(group_id)
+            .bind(position as i32)
+            .bind(question)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let before = JoinSettings {
+            join_policy: before.join_policy,
+            questions: previous.iter().map(String::as_str).collect(),
+        };
+        let after = JoinSettings {
+            join_policy,
+            questions: questions.iter().map(String::as_str).collect(),
+        };
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            "group",
+            group_id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(group)
+    }
+
+    // Ask to join a group. Fails when the user already is a member or already
+    // has a request waiting.
+    pub async fn submit(
+        &self,
+        group_id: i32,
+        user_id: i32,
+        answers: &[JoinAnswer],
+        ctx: &AuditContext,
+    ) -> Result<JoinRequest, JoinRequestError> {
+        let mut tx = self.pool.begin().await?;
+        let is_member: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM group_members WHERE group_id = $1 AND user_id = $2)",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if is_member {
+            return Err(JoinRequestError::AlreadyMember);
+        }
+
+        let answers = serde_json::to_string(answers).unwrap_or_else(|_| "[]".to_string());
+        let request = sqlx::query_as::<_, JoinRequest>(
+            "INSERT INTO group_join_requests (group_id, user_id, answers) VALUES ($1, $2, $3) \
+             RETURNING *",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .bind(answers)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                JoinRequestError::AlreadyPending
+            }
+            _ => JoinRequestError::SqlError(e),
+        })?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "create",
+            ENTITY,
+            request.id,
+            None,
+            Some(&request),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(request)
+    }
+
+    // The user's request waiting for approval, if any
+    pub async fn pending_for(
+        &self,
+        group_id: i32,
+        user_id: i32,
+    ) -> Result<Option<JoinRequest>, JoinRequestError> {
+        let request = sqlx::query_as::<_, JoinRequest>(
+            "SELECT * FROM group_join_requests \
+             WHERE group_id = $1 AND user_id = $2 AND status = 'pending'",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    // Requests of a group, oldest first so the queue is worked in order
+    pub async fn list_for_group(
+        &self,
+        group_id: i32,
+        status: Option<JoinRequestStatus>,
+    ) -> Result<Vec<JoinRequestEntry>, JoinRequestError> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "SELECT r.*, u.email AS user_email, u.name AS user_name \
+             FROM group_join_requests r JOIN users u ON u.id = r.user_id WHERE r.group_id = ",
+        );
+        query.push_bind(group_id);
+        if let Some(status) = status {
+            query.push(" AND r.status = ").push_bind(status.as_str());
+        }
+        query.push(" ORDER BY r.created_at, r.id");
+
+        let requests = query
+            .build_query_as::<JoinRequestEntry>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(requests)
+    }
+
+    pub async fn count_pending(&self, group_id: i32) -> Result<i64, JoinRequestError> {
+        let count = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM group_join_requests WHERE group_id = $1 AND status = 'pending'",
+        )
+        .bind(group_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    // Approve a request, adding the applicant as a member in the same
+    // transaction
+    pub async fn approve(
+        &self,
+        group_id: i32,
+        id: i32,
+        note: Option<&str>,
+        ctx: &AuditContext,
+    ) -> Result<JoinRequest, JoinRequestError> {
+        let mut tx = self.pool.begin().await?;
+        let request = Self::decide(
+            &mut tx,
+            group_id,
+            id,
+            JoinRequestStatus::Approved,
+            note,
+            ctx,
+        )
+        .await?;
+        // Nothing to add when the applicant joined meanwhile, e.g. by invitation
+        member::add(
+            &mut tx,
+            ctx,
+            group_id,
+            request.user_id,
+            MemberRole::Member,
+            None,
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(request)
+    }
+
+    pub async fn reject(
+        &self,
+        group_id: i32,
+        id: i32,
+        note: Option<&str>,
+        ctx: &AuditContext,
+    ) -> Result<JoinRequest, JoinRequestError> {
+        let mut tx = self.pool.begin().await?;
+        let request = Self::decide(
+            &mut tx,
+            group_id,
+            id,
+            JoinRequestStatus::Rejected,
+            note,
+            ctx,
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(request)
+    }
+
+    // Record the decision on a pending request, locking it so two managers
+    // cannot decide the same request
+    async fn decide(
+        conn: &mut PgConnection,
+        group_id: i32,
+        id: i32,
+        status: JoinRequestStatus,
+        note: Option<&str>,
+        ctx: &AuditContext,
+    ) -> Result<JoinRequest, JoinRequestError> {
+        let before = sqlx::query_as::<_, JoinRequest>(
+            "SELECT * FROM group_join_requests WHERE id = $1 AND group_id = $2 FOR UPDATE",
+        )
+        .bind(id)
+        .bind(group_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(JoinRequestError::NotFound)?;
+        if before.status != JoinRequestStatus::Pending {
+            return Err(JoinRequestError::NotPending);
+        }
+
+        let after = sqlx::query_as::<_, JoinRequest>(
+            "UPDATE group_join_requests SET status = $2, decided_by = $3, decided_at = NOW(), \
+             decision_note = $4 WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .bind(status)
+        .bind(ctx.actor_id)
+        .bind(note.map(str::trim).filter(|n| !n.is_empty()))
+        .fetch_one(&mut *conn)
+        .await?;
+
+        audit::record(
+            conn,
+            ctx,
+            status.as_str(),
+            ENTITY,
+            id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+
+        Ok(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::group::GroupService;
+    use crate::db::member::MemberService;
+
+    #[tokio::test]
+    async fn test_approval_queue() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let members = MemberService::new(db.pool.clone());
+        let requests = JoinRequestService::new(db.pool.clone());
+
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let questions = ["Why join?".to_string(), "Favourite book?".to_string()];
+        let group = requests
+            .update_settings(group.id, JoinPolicy::Approval, &questions, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(group.join_policy, JoinPolicy::Approval);
+        let asked: Vec<String> = requests
+            .questions(group.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|q| q.question)
+            .collect();
+        assert_eq!(asked, questions);
+
+        let [ada, bob] = db.create_users(["Ada", "Bob"]).await;
+        let answers = [JoinAnswer {
+            question: questions[0].clone(),
+            answer: "To read more".to_string(),
+        }];
+        let first = requests
+            .submit(group.id, ada.id, &answers, &ctx)
+            .await
+            .unwrap();
+        assert!(matches!(
+            requests.submit(group.id, ada.id, &[], &ctx).await,
+            Err(JoinRequestError::AlreadyPending)
+        ));
+        let second = requests.submit(group.id, bob.id, &[], &ctx).await.unwrap();
+
+        let queue = requests
+            .list_for_group(group.id, Some(JoinRequestStatus::Pending))
+            .await
+            .unwrap();
+        let applicants: Vec<&str> = queue.iter().map(|e| e.user_name.as_str()).collect();
+        assert_eq!(applicants, ["Ada", "Bob"]);
+        assert_eq!(queue[0].request.answers(), answers);
+
+        requests
+            .approve(group.id, first.id, Some(" Welcome "), &ctx)
+            .await
+            .unwrap();
+        assert!(members.is_member(group.id, ada.id).await.unwrap());
+        assert!(matches!(
+            requests.reject(group.id, first.id, None, &ctx).await,
+            Err(JoinRequestError::NotPending)
+        ));
+        assert!(matches!(
+            requests.submit(group.id, ada.id, &[], &ctx).await,
+            Err(JoinRequestError::AlreadyMember)
+        ));
+
+        let rejected = requests
+            .reject(group.id, second.id, None, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(rejected.status, JoinRequestStatus::Rejected);
+        assert!(!members.is_member(group.id, bob.id).await.unwrap());
+        assert_eq!(requests.count_pending(group.id).await.unwrap(), 0);
+        let pending = requests.pending_for(group.id, bob.id).await.unwrap();
+        assert!(pending.is_none());
+    }
+}
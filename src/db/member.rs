@@ -0,0 +1,101 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{GroupMember, MemberRole};
+use sqlx::PgConnection;
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "group_member";
+
+#[derive(Debug, Error)]
+pub enum MemberError {
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// Add a user to a group as part of a larger transaction (group creation,
+// accepting an invitation). Returns None when the user already is a member.
+pub async fn add(
+    conn: &mut PgConnection,
+    ctx: &AuditContext,
+    group_id: i32,
+    user_id: i32,
+    role: MemberRole,
+    invitation_id: Option<i32>,
+) -> Result<Option<GroupMember>, sqlx::Error> {
+    let member = sqlx::query_as::<_, GroupMember>(
+        "INSERT INTO group_members (group_id, user_id, role, invitation_id) \
+         VALUES ($1, $2, $3, $4) ON CONFLICT (group_id, user_id) DO NOTHING RETURNING *",
+    )
+    .bind(group_id)
+    .bind(user_id)
+    .bind(role)
+    .bind(invitation_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some(member) = &member {
+        audit::record(conn, ctx, "create", ENTITY, member.id, None, Some(member)).await?;
+    }
+
+    Ok(member)
+}
+
+pub struct MemberService {
+    pool: DbPool,
+}
+
+impl MemberService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Join a group directly, for groups with the open join policy. Returns None
+    // when the user already is a member.
+    pub async fn join(
+        &self,
+        group_id: i32,
+        user_id: i32,
+        ctx: &AuditContext,
+    ) -> Result<Option<GroupMember>, MemberError> {
+        let mut tx = self.pool.begin().await?;
+        let member = add(&mut tx, ctx, group_id, user_id, MemberRole::Member, None).await?;
+        tx.commit().await?;
+
+        Ok(member)
+    }
+
+    pub async fn get(
+        &self,
+        group_id: i32,
+        user_id: i32,
+    ) -> Result<Option<GroupMember>, MemberError> {
+        let member = sqlx::query_as::<_, GroupMember>(
+            "SELECT * FROM group_members WHERE group_id = $1 AND user_id = $2",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    pub async fn is_member(&self, group_id: i32, user_id: i32) -> Result<bool, MemberError> {
+        Ok(self.get(group_id, user_id).await?.is_some())
+    }
+
+    // Members of a group, oldest first
+    pub async fn list_for_group(&self, group_id: i32) -> Result<Vec<GroupMember>, MemberError> {
+        let members = sqlx::query_as::<_, GroupMember>(
+            "SELECT * FROM group_members WHERE group_id = $1 ORDER BY created_at, id",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+}
@@ -1,6 +1,24 @@
+pub mod admin_action;
+pub mod attendance;
+pub mod audit;
 pub mod connection;
+pub mod digest;
+pub mod event;
+pub mod event_comment;
+pub mod export;
+pub mod geo;
 pub mod group;
+pub mod invitation;
+pub mod job;
+pub mod join_request;
+pub mod member;
 pub mod models;
+pub mod notification;
+pub mod post;
+pub mod realtime;
+pub mod rsvp;
+pub mod tag;
 pub mod user;
+pub mod venue;
 
-pub use connection::{create_pool, health_check, run_migrations};
+pub use connection::{create_pool, health_check, run_migrations, run_seed};
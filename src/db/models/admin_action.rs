@@ -0,0 +1,15 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+// Entry in the admin audit trail
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct AdminAction {
+    pub id: i32,
+    pub admin_id: Option<i32>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: i32,
+    pub details: String,
+    pub created_at: DateTime<Utc>,
+}
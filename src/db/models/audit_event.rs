@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+// Entry in the append-only audit log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct AuditEvent {
+    pub id: i32,
+    // None for changes made by the system or an anonymous visitor
+    pub actor_id: Option<i32>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: i32,
+    // JSON object of changed fields, see db::audit::diff
+    pub changes: String,
+    pub ip_address: Option<String>,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+// A city of the offline geocoding table
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct City {
+    pub id: i32,
+    pub name: String,
+    pub country_code: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub population: i64,
+}
@@ -0,0 +1,52 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+
+// Lifecycle of a data export. Expiry is derived from `expires_at`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum DataExportStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+impl DataExportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataExportStatus::Pending => "pending",
+            DataExportStatus::Ready => "ready",
+            DataExportStatus::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for DataExportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Archive of everything stored about a user, requested by that user
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct DataExport {
+    pub id: i32,
+    pub user_id: i32,
+    pub status: DataExportStatus,
+    pub token: String,
+    pub blob_key: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl DataExport {
+    // Whether the archive can be downloaded
+    pub fn is_downloadable(&self, now: DateTime<Utc>) -> bool {
+        self.status == DataExportStatus::Ready
+            && self.blob_key.is_some()
+            && self.expires_at.is_some_and(|expires_at| expires_at > now)
+    }
+}
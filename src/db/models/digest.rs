@@ -0,0 +1,63 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+
+// How often a user receives the activity digest
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum DigestFrequency {
+    Daily,
+    #[default]
+    Weekly,
+    Never,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 3] = [
+        DigestFrequency::Daily,
+        DigestFrequency::Weekly,
+        DigestFrequency::Never,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+            DigestFrequency::Never => "never",
+        }
+    }
+
+    // Shown on the settings page
+    pub fn label(&self) -> &'static str {
+        match self {
+            DigestFrequency::Daily => "Every day",
+            DigestFrequency::Weekly => "Every week",
+            DigestFrequency::Never => "Never",
+        }
+    }
+
+    // Time covered by one digest, None when none are sent
+    pub fn period(&self) -> Option<Duration> {
+        match self {
+            DigestFrequency::Daily => Some(Duration::days(1)),
+            DigestFrequency::Weekly => Some(Duration::weeks(1)),
+            DigestFrequency::Never => None,
+        }
+    }
+}
+
+impl fmt::Display for DigestFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct DigestSettings {
+    pub user_id: i32,
+    pub frequency: DigestFrequency,
+    pub unsubscribe_token: String,
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+
+// An event organized by a group
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, FromRow)]
+pub struct Event {
+    pub id: i32,
+    pub group_id: i32,
+    pub title: String,
+    pub description: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub location: Option<String>,
+    pub created_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    // Coordinates of the location, when known
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // Where it takes place; location and coordinates are copied from it
+    pub venue_id: Option<i32>,
+    // For online events. Never serialized, so it cannot end up in a page or
+    // API response by accident: it is for members going only.
+    #[serde(skip_serializing, default)]
+    pub meeting_url: Option<String>,
+    // How the event repeats, None when it is held once
+    pub recurrence: Option<Recurrence>,
+    // The last time an occurrence may start, None to repeat without end
+    pub recurs_until: Option<DateTime<Utc>>,
+    // IANA time zone it was created in, occurrences keep its local time of day
+    pub time_zone: String,
+}
+
+impl Event {
+    pub fn is_online(&self) -> bool {
+        self.meeting_url.is_some()
+    }
+
+    // The event's time zone, UTC when it is not a known one
+    pub fn tz(&self) -> Tz {
+        self.time_zone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+// How often a recurring event takes place, counted from its first start
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum Recurrence {
+    Weekly,
+    Biweekly,
+    Monthly,
+}
+
+impl Recurrence {
+    pub const ALL: [Recurrence; 3] = [
+        Recurrence::Weekly,
+        Recurrence::Biweekly,
+        Recurrence::Monthly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Recurrence::Weekly => "weekly",
+            Recurrence::Biweekly => "biweekly",
+            Recurrence::Monthly => "monthly",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Recurrence::Weekly => "Every week",
+            Recurrence::Biweekly => "Every two weeks",
+            Recurrence::Monthly => "Every month",
+        }
+    }
+}
+
+// A comment or question on an event, or an answer to one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct EventComment {
+    pub id: i32,
+    pub event_id: i32,
+    // None when the author's account was removed
+    pub author_id: Option<i32>,
+    // The comment this one answers
+    pub reply_to: Option<i32>,
+    pub body: String,
+    pub pinned_at: Option<DateTime<Utc>>,
+    pub hidden_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum RsvpStatus {
+    Going,
+    Maybe,
+    NotGoing,
+}
+
+impl RsvpStatus {
+    pub const ALL: [RsvpStatus; 3] = [RsvpStatus::Going, RsvpStatus::Maybe, RsvpStatus::NotGoing];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RsvpStatus::Going => "going",
+            RsvpStatus::Maybe => "maybe",
+            RsvpStatus::NotGoing => "not_going",
+        }
+    }
+}
+
+impl fmt::Display for RsvpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// A member's answer to whether they attend an event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct Rsvp {
+    pub id: i32,
+    pub event_id: i32,
+    pub user_id: i32,
+    pub status: RsvpStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    // Shown to the member as a QR code for hosts to scan. Never serialized,
+    // so it stays out of the audit log.
+    #[serde(skip_serializing, default)]
+    pub check_in_token: String,
+}
+
+// How a member was checked in at an event
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum CheckInMethod {
+    // Found in the attendee list
+    List,
+    // Their RSVP's QR code was scanned
+    QrCode,
+}
+
+// A member who showed up at an event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct Attendance {
+    pub id: i32,
+    pub event_id: i32,
+    pub user_id: i32,
+    pub checked_in_by: Option<i32>,
+    pub method: CheckInMethod,
+    pub checked_in_at: DateTime<Utc>,
+}
@@ -1,6 +1,39 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::fmt;
+
+// How users become members of a group
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum JoinPolicy {
+    // Anyone can join
+    #[default]
+    Open,
+    // Users request to join and the group's managers approve them
+    Approval,
+    // Only through invitations
+    Invite,
+}
+
+impl JoinPolicy {
+    pub const ALL: [JoinPolicy; 3] = [JoinPolicy::Open, JoinPolicy::Approval, JoinPolicy::Invite];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JoinPolicy::Open => "open",
+            JoinPolicy::Approval => "approval",
+            JoinPolicy::Invite => "invite",
+        }
+    }
+}
+
+impl fmt::Display for JoinPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 // Current Group model for PostgreSQL
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
@@ -9,6 +42,15 @@ pub struct Group {
     pub name: String,
     pub created_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
+    // Creator, None for seeded groups or when the owner's account was removed
+    pub owner_id: Option<i32>,
+    pub join_policy: JoinPolicy,
+    // Base key of the cover variants, see images::base_key
+    pub cover_key: Option<String>,
+    // Where the group meets, as typed, and its coordinates
+    pub location: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
 }
 
 // Data transfer object for creating groups
@@ -0,0 +1,64 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+
+// Lifecycle of an invitation. Expiry is not a status, it is derived from
+// `expires_at` when the invitation is used.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum InvitationStatus {
+    Pending,
+    // An email invitation was used, or a link reached its maximum uses
+    Accepted,
+    Revoked,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Revoked => "revoked",
+        }
+    }
+}
+
+impl fmt::Display for InvitationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Invitation to join a group, by shareable link or for a single email address
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct Invitation {
+    pub id: i32,
+    pub group_id: i32,
+    pub code: String,
+    // None for shareable links
+    pub email: Option<String>,
+    pub invited_by: Option<i32>,
+    pub status: InvitationStatus,
+    // None for unlimited
+    pub max_uses: Option<i32>,
+    pub use_count: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl Invitation {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    // Whether the invitation can still be used to join
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        self.status == InvitationStatus::Pending
+            && !self.is_expired(now)
+            && self.max_uses.is_none_or(|max| self.use_count < max)
+    }
+}
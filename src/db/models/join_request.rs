@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum JoinRequestStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl JoinRequestStatus {
+    pub const ALL: [JoinRequestStatus; 3] = [
+        JoinRequestStatus::Pending,
+        JoinRequestStatus::Approved,
+        JoinRequestStatus::Rejected,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JoinRequestStatus::Pending => "pending",
+            JoinRequestStatus::Approved => "approved",
+            JoinRequestStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl fmt::Display for JoinRequestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Question a group asks users requesting to join
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct JoinQuestion {
+    pub id: i32,
+    pub group_id: i32,
+    pub position: i32,
+    pub question: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// A question with the applicant's answer, as stored with the request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JoinAnswer {
+    pub question: String,
+    pub answer: String,
+}
+
+// Request to join a group with the approval join policy
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct JoinRequest {
+    pub id: i32,
+    pub group_id: i32,
+    pub user_id: i32,
+    pub status: JoinRequestStatus,
+    // JSON array of JoinAnswer, see JoinRequest::answers
+    pub answers: String,
+    pub decided_by: Option<i32>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub decision_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl JoinRequest {
+    pub fn answers(&self) -> Vec<JoinAnswer> {
+        serde_json::from_str(&self.answers).unwrap_or_default()
+    }
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum MemberRole {
+    Owner,
+    Member,
+}
+
+// Membership of a user in a group
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct GroupMember {
+    pub id: i32,
+    pub group_id: i32,
+    pub user_id: i32,
+    pub role: MemberRole,
+    // The invitation used to join, if any
+    pub invitation_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
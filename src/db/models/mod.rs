@@ -1,5 +1,33 @@
+mod admin_action;
+mod audit_event;
+mod city;
+mod data_export;
+mod digest;
+mod event;
 mod group;
+mod invitation;
+mod job;
+mod join_request;
+mod member;
+mod notification;
+mod post;
+mod tag;
 mod user;
+mod venue;
 
-pub use group::Group;
-pub use user::User;
+pub use admin_action::AdminAction;
+pub use audit_event::AuditEvent;
+pub use city::City;
+pub use data_export::{DataExport, DataExportStatus};
+pub use digest::{DigestFrequency, DigestSettings};
+pub use event::{Attendance, CheckInMethod, Event, EventComment, Recurrence, Rsvp, RsvpStatus};
+pub use group::{CreateGroup, Group, JoinPolicy, UpdateGroup};
+pub use invitation::{Invitation, InvitationStatus};
+pub use job::{Job, JobSchedule, JobStatus};
+pub use join_request::{JoinAnswer, JoinQuestion, JoinRequest, JoinRequestStatus};
+pub use member::{GroupMember, MemberRole};
+pub use notification::{Notification, NotificationKind, NotificationPreference};
+pub use post::{Post, PostRevision};
+pub use tag::{Category, Tag};
+pub use user::{CreateUser, EmailChange, ProfileVisibility, UpdateUser, User};
+pub use venue::Venue;
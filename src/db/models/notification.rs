@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+
+// What a notification is about. Users choose per kind how they are notified.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum NotificationKind {
+    // Someone joined a group you own
+    MemberJoined,
+    // Someone asked to join a group you own
+    JoinRequest,
+    // Your request to join a group was decided
+    JoinDecision,
+    // Someone answered an event you created
+    Rsvp,
+    // Someone replied to your thread or answered your question
+    Reply,
+    // Someone mentioned you
+    Mention,
+    // An event you are going to is coming up
+    Reminder,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 7] = [
+        NotificationKind::MemberJoined,
+        NotificationKind::JoinRequest,
+        NotificationKind::JoinDecision,
+        NotificationKind::Rsvp,
+        NotificationKind::Reply,
+        NotificationKind::Mention,
+        NotificationKind::Reminder,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::MemberJoined => "member_joined",
+            NotificationKind::JoinRequest => "join_request",
+            NotificationKind::JoinDecision => "join_decision",
+            NotificationKind::Rsvp => "rsvp",
+            NotificationKind::Reply => "reply",
+            NotificationKind::Mention => "mention",
+            NotificationKind::Reminder => "reminder",
+        }
+    }
+
+    // Shown on the settings page
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotificationKind::MemberJoined => "Someone joins a group you own",
+            NotificationKind::JoinRequest => "Someone asks to join a group you own",
+            NotificationKind::JoinDecision => "Your request to join a group is decided",
+            NotificationKind::Rsvp => "Someone RSVPs to your event",
+            NotificationKind::Reply => "Someone replies to your post or answers your question",
+            NotificationKind::Mention => "Someone mentions you",
+            NotificationKind::Reminder => "An event you are going to is coming up",
+        }
+    }
+
+    // Preferences of users who did not change them. Everything shows up in
+    // the app, only what needs the user's attention is also emailed.
+    pub fn default_preference(&self) -> NotificationPreference {
+        NotificationPreference {
+            kind: *self,
+            in_app: true,
+            email: matches!(
+                self,
+                NotificationKind::JoinRequest
+                    | NotificationKind::JoinDecision
+                    | NotificationKind::Mention
+                    | NotificationKind::Reminder
+            ),
+        }
+    }
+}
+
+impl fmt::Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub actor_id: Option<i32>,
+    pub message: String,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+// How a user is notified of one kind of notification
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, FromRow)]
+pub struct NotificationPreference {
+    pub kind: NotificationKind,
+    pub in_app: bool,
+    pub email: bool,
+}
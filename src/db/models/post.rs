@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+// A post in a group discussion: either a thread's opening post or a reply
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct Post {
+    pub id: i32,
+    pub group_id: i32,
+    // None for the opening post of a thread
+    pub thread_id: Option<i32>,
+    // None when the author's account was removed
+    pub author_id: Option<i32>,
+    // Only threads have a title
+    pub title: Option<String>,
+    pub body: String,
+    pub reply_count: i32,
+    pub last_activity_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Post {
+    pub fn is_thread(&self) -> bool {
+        self.thread_id.is_none()
+    }
+
+    // The thread this post belongs to
+    pub fn thread(&self) -> i32 {
+        self.thread_id.unwrap_or(self.id)
+    }
+}
+
+// A version of a post replaced by an edit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct PostRevision {
+    pub id: i32,
+    pub post_id: i32,
+    pub title: Option<String>,
+    pub body: String,
+    pub written_at: DateTime<Utc>,
+    pub edited_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
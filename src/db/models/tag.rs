@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+// A top-level category, curated by site admins
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct Category {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub position: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+// A topic groups are tagged with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct Tag {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    // None until an admin files it under a category
+    pub category_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,6 +1,53 @@
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::fmt;
+
+// Who can see a user's profile beyond their name
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum ProfileVisibility {
+    // Anyone, logged in or not
+    Everyone,
+    // Users who belong to one of the user's groups
+    #[default]
+    Members,
+    // Only the user and site administrators
+    Private,
+}
+
+impl ProfileVisibility {
+    pub const ALL: [ProfileVisibility; 3] = [
+        ProfileVisibility::Everyone,
+        ProfileVisibility::Members,
+        ProfileVisibility::Private,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfileVisibility::Everyone => "everyone",
+            ProfileVisibility::Members => "members",
+            ProfileVisibility::Private => "private",
+        }
+    }
+
+    // Shown on the profile form
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProfileVisibility::Everyone => "Everyone",
+            ProfileVisibility::Members => "Members of my groups",
+            ProfileVisibility::Private => "Only me",
+        }
+    }
+}
+
+impl fmt::Display for ProfileVisibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 // Current User model for PostgreSQL
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
@@ -12,6 +59,37 @@ pub struct User {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
+    pub is_admin: bool,
+    // IANA name, e.g. "Europe/Paris"
+    pub time_zone: String,
+    pub bio: String,
+    pub location: String,
+    pub interests: Vec<String>,
+    pub profile_visibility: ProfileVisibility,
+    pub show_location: bool,
+    pub show_groups: bool,
+    // Base key of the avatar variants, see images::base_key
+    pub avatar_key: Option<String>,
+    // Sessions remember the version they were created with and end when it
+    // changes, see middleware::session_validation
+    pub session_version: i32,
+}
+
+impl User {
+    // The user's time zone, UTC when it is not a known one
+    pub fn tz(&self) -> Tz {
+        self.time_zone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+// A new email address waiting to be confirmed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct EmailChange {
+    pub user_id: i32,
+    pub new_email: String,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
 }
 
 // Data transfer object for creating users
@@ -0,0 +1,292 @@
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{Notification, NotificationKind, NotificationPreference, User};
+use crate::db::realtime;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NotificationError {
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A notification to send to one or more users
+#[derive(Debug, Clone)]
+pub struct NewNotification {
+    pub kind: NotificationKind,
+    // Never notified of their own actions
+    pub actor_id: Option<i32>,
+    pub message: String,
+    pub url: String,
+}
+
+pub struct NotificationService {
+    pool: DbPool,
+}
+
+impl NotificationService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Fan a notification out to the recipients, according to their
+    // preferences. Returns the recipients who also want it by email.
+    pub async fn deliver(
+        &self,
+        recipients: &[i32],
+        notification: &NewNotification,
+    ) -> Result<Vec<User>, NotificationError> {
+        let recipients: Vec<i32> = recipients
+            .iter()
+            .copied()
+            .filter(|id| Some(*id) != notification.actor_id)
+            .collect();
+        if recipients.is_empty() {
+            return Ok(Vec::new());
+        }
+        let defaults = notification.kind.default_preference();
+
+        let mut tx = self.pool.begin().await?;
+        let notified: Vec<i32> = sqlx::query_scalar(
+            "INSERT INTO notifications (user_id, kind, actor_id, message, url) \
+             SELECT u.id, $2, $3, $4, $5 FROM users u \
+             LEFT JOIN notification_preferences p ON p.user_id = u.id AND p.kind = $2 \
+             WHERE u.id = ANY($1) AND u.deleted_at IS NULL AND COALESCE(p.in_app, $6) \
+             RETURNING user_id",
+        )
+        .bind(&recipients)
+        .bind(notification.kind)
+        .bind(notification.actor_id)
+        .bind(&notification.message)
+        .bind(&notification.url)
+        .bind(defaults.in_app)
+        .fetch_all(&mut *tx)
+        .await?;
+        for user_id in notified {
+            realtime::publish_unread(&mut tx, user_id).await?;
+        }
+
+        let by_email = sqlx::query_as::<_, User>(
+            "SELECT u.* FROM users u \
+             LEFT JOIN notification_preferences p ON p.user_id = u.id AND p.kind = $2 \
+             WHERE u.id = ANY($1) AND u.deleted_at IS NULL AND COALESCE(p.email, $3) \
+             ORDER BY u.id",
+        )
+        .bind(&recipients)
+        .bind(notification.kind)
+        .bind(defaults.email)
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(by_email)
+    }
+
+    // The user's notifications, newest first
+    pub async fn list(
+        &self,
+        user_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Notification>, NotificationError> {
+        let notifications = sqlx::query_as::<_, Notification>(
+            "SELECT * FROM notifications WHERE user_id = $1 \
+             ORDER BY created_at DESC, id DESC LIMIT $2 OFFSET $3",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(notifications)
+    }
+
+    pub async fn count(&self, user_id: i32) -> Result<i64, NotificationError> {
+        let count = sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    pub async fn unread_count(&self, user_id: i32) -> Result<i64, NotificationError> {
+        let count = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    // Mark one of the user's notifications read, returning it
+    pub async fn mark_read(
+        &self,
+        user_id: i32,
+        id: i32,
+    ) -> Result<Option<Notification>, NotificationError> {
+        let mut tx = self.pool.begin().await?;
+        let notification = sqlx::query_as::<_, Notification>(
+            "UPDATE notifications SET read_at = COALESCE(read_at, NOW()) \
+             WHERE id = $1 AND user_id = $2 RETURNING *",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        // Other tabs of the user update their badge
+        realtime::publish_unread(&mut tx, user_id).await?;
+        tx.commit().await?;
+
+        Ok(notification)
+    }
+
+    // Returns the number of notifications marked
+    pub async fn mark_all_read(&self, user_id: i32) -> Result<u64, NotificationError> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        realtime::publish_unread(&mut tx, user_id).await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // The user's preference for every kind, defaults included
+    pub async fn preferences(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<NotificationPreference>, NotificationError> {
+        let saved = sqlx::query_as::<_, NotificationPreference>(
+            "SELECT kind, in_app, email FROM notification_preferences WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(NotificationKind::ALL
+            .iter()
+            .map(|kind| {
+                saved
+                    .iter()
+                    .find(|p| p.kind == *kind)
+                    .copied()
+                    .unwrap_or_else(|| kind.default_preference())
+            })
+            .collect())
+    }
+
+    pub async fn set_preferences(
+        &self,
+        user_id: i32,
+        preferences: &[NotificationPreference],
+    ) -> Result<(), NotificationError> {
+        let mut tx = self.pool.begin().await?;
+        for preference in preferences {
+            sqlx::query(
+                "INSERT INTO notification_preferences (user_id, kind, in_app, email) \
+                 VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (user_id, kind) DO UPDATE SET in_app = $3, email = $4",
+            )
+            .bind(user_id)
+            .bind(preference.kind)
+            .bind(preference.in_app)
+            .bind(preference.email)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::audit::AuditContext;
+    use crate::db::connection::create_test_pool;
+    use crate::db::user::UserService;
+
+    #[tokio::test]
+    async fn test_fan_out_follows_preferences() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+        let notifications = NotificationService::new(db.pool.clone());
+
+        let ids = db
+            .create_users(["ada", "bob", "cy", "dan"])
+            .await
+            .map(|u| u.id);
+        let [ada, bob, cy, dan] = ids;
+        users.delete(dan, &ctx).await.unwrap();
+        let quiet = NotificationPreference {
+            kind: NotificationKind::Mention,
+            in_app: false,
+            email: false,
+        };
+        notifications.set_preferences(cy, &[quiet]).await.unwrap();
+
+        // Neither the actor, deleted users nor those who opted out are notified
+        let mention = NewNotification {
+            kind: NotificationKind::Mention,
+            actor_id: Some(ada),
+            message: "ada mentioned you".to_string(),
+            url: "/events/1".to_string(),
+        };
+        let by_email = notifications.deliver(&ids, &mention).await.unwrap();
+        assert_eq!(by_email.len(), 1);
+        assert_eq!(by_email[0].id, bob);
+        for user_id in [ada, cy, dan] {
+            assert_eq!(notifications.count(user_id).await.unwrap(), 0);
+        }
+
+        let rsvp = NewNotification {
+            kind: NotificationKind::Rsvp,
+            ..mention
+        };
+        assert!(
+            notifications
+                .deliver(&[bob], &rsvp)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let listed = notifications.list(bob, 10, 0).await.unwrap();
+        let kinds: Vec<NotificationKind> = listed.iter().map(|n| n.kind).collect();
+        assert_eq!(kinds, [NotificationKind::Rsvp, NotificationKind::Mention]);
+        assert_eq!(notifications.unread_count(bob).await.unwrap(), 2);
+
+        // Notifications of others cannot be marked
+        assert!(
+            notifications
+                .mark_read(cy, listed[0].id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let read = notifications
+            .mark_read(bob, listed[0].id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(read.read_at.is_some());
+        assert_eq!(notifications.unread_count(bob).await.unwrap(), 1);
+        assert_eq!(notifications.mark_all_read(bob).await.unwrap(), 1);
+        assert_eq!(notifications.unread_count(bob).await.unwrap(), 0);
+
+        let preferences = notifications.preferences(cy).await.unwrap();
+        assert_eq!(preferences.len(), NotificationKind::ALL.len());
+        assert!(preferences.contains(&quiet));
+        assert!(preferences.contains(&NotificationKind::Reply.default_preference()));
+    }
+}
@@ -0,0 +1,361 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{Post, PostRevision};
+use serde::Serialize;
+use sqlx::FromRow;
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "post";
+
+pub const MAX_TITLE_LENGTH: usize = 200;
+pub const MAX_BODY_LENGTH: usize = 20_000;
+
+#[derive(Debug, Error)]
+pub enum PostError {
+    #[error("Post not found")]
+    NotFound,
+    #[error("Post has been deleted")]
+    Deleted,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A post with its author's name for display
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PostEntry {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub post: Post,
+    pub author_name: Option<String>,
+}
+
+const SELECT_ENTRIES: &str =
+    "SELECT p.*, u.name AS author_name FROM posts p LEFT JOIN users u ON u.id = p.author_id";
+
+pub struct PostService {
+    pool: DbPool,
+}
+
+impl PostService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Start a thread in a group
+    pub async fn create_thread(
+        &self,
+        group_id: i32,
+        author_id: i32,
+        title: &str,
+        body: &str,
+        ctx: &AuditContext,
+    ) -> Result<Post, PostError> {
+        let mut tx = self.pool.begin().await?;
+        let post = sqlx::query_as::<_, Post>(
+            "INSERT INTO posts (group_id, author_id, title, body) VALUES ($1, $2, $3, $4) \
+             RETURNING *",
+        )
+        .bind(group_id)
+        .bind(author_id)
+        .bind(title)
+        .bind(body)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(&mut tx, ctx, "create", ENTITY, post.id, None, Some(&post)).await?;
+        tx.commit().await?;
+
+        Ok(post)
+    }
+
+    // Reply to a thread, bumping it to the top of the group's discussions
+    pub async fn reply(
+        &self,
+        thread_id: i32,
+        author_id: i32,
+        body: &str,
+        ctx: &AuditContext,
+    ) -> Result<Post, PostError> {
+        let mut tx = self.pool.begin().await?;
+        let thread = sqlx::query_as::<_, Post>(
+            "SELECT * FROM posts WHERE id = $1 AND thread_id IS NULL FOR UPDATE",
+        )
+        .bind(thread_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(PostError::NotFound)?;
+        if thread.deleted_at.is_some() {
+            return Err(PostError::Deleted);
+        }
+
+        let post = sqlx::query_as::<_, Post>(
+            "INSERT INTO posts (group_id, thread_id, author_id, body) VALUES ($1, $2, $3, $4) \
+             RETURNING *",
+        )
+        .bind(thread.group_id)
+        .bind(thread.id)
+        .bind(author_id)
+        .bind(body)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE posts SET reply_count = reply_count + 1, last_activity_at = $2 WHERE id = $1",
+        )
+        .bind(thread.id)
+        .bind(post.created_at)
+        .execute(&mut *tx)
+        .await?;
+
+        audit::record(&mut tx, ctx, "create", ENTITY, post.id, None, Some(&post)).await?;
+        tx.commit().await?;
+
+        Ok(post)
+    }
+
+    // Any post, including deleted ones
+    pub async fn get(&self, id: i32) -> Result<Option<PostEntry>, PostError> {
+        let post = sqlx::query_as::<_, PostEntry>(&format!("{} WHERE p.id = $1", SELECT_ENTRIES))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(post)
+    }
+
+    // Threads of a group that are not deleted, most recently active first
+    pub async fn list_threads(
+        &self,
+        group_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PostEntry>, PostError> {
+        let threads = sqlx::query_as::<_, PostEntry>(&format!(
+            "{} WHERE p.group_id = $1 AND p.thread_id IS NULL AND p.deleted_at IS NULL \
+             ORDER BY p.last_activity_at DESC, p.id DESC LIMIT $2 OFFSET $3",
+            SELECT_ENTRIES
+        ))
+        .bind(group_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(threads)
+    }
+
+    pub async fn count_threads(&self, group_id: i32) -> Result<i64, PostError> {
+        let count = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM posts \
+             WHERE group_id = $1 AND thread_id IS NULL AND deleted_at IS NULL",
+        )
+        .bind(group_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    // Replies of a thread in order, starting after the reply `after`. Deleted
+    // replies are included so the conversation keeps its shape.
+    pub async fn list_replies(
+        &self,
+        thread_id: i32,
+        after: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<PostEntry>, PostError> {
+        let replies = sqlx::query_as::<_, PostEntry>(&format!(
+            "{} WHERE p.thread_id = $1 AND p.id > $2 ORDER BY p.id LIMIT $3",
+            SELECT_ENTRIES
+        ))
+        .bind(thread_id)
+        .bind(after.unwrap_or(0))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(replies)
+    }
+
+    // Replace the text of a post, keeping the previous version. The title is
+    // only changed on threads.
+    pub async fn edit(
+        &self,
+        id: i32,
+        title: Option<&str>,
+        body: &str,
+        ctx: &AuditContext,
+    ) -> Result<Post, PostError> {
+        let mut tx = self.pool.begin().await?;
+        let before = sqlx::query_as::<_, Post>("SELECT * FROM posts WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(PostError::NotFound)?;
+        if before.deleted_at.is_some() {
+            return Err(PostError::Deleted);
+        }
+
+        sqlx::query(
+            "INSERT INTO post_revisions (post_id, title, body, written_at, edited_by) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(&before.title)
+        .bind(&before.body)
+        .bind(before.edited_at.unwrap_or(before.created_at))
+        .bind(ctx.actor_id)
+        .execute(&mut *tx)
+        .await?;
+
+        let title = if before.is_thread() {
+            title.or(before.title.as_deref())
+        } else {
+            None
+        };
+        let after = sqlx::query_as::<_, Post>(
+            "UPDATE posts SET title = $2, body = $3, edited_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .bind(title)
+        .bind(body)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after)
+    }
+
+    // Earlier versions of a post, oldest first
+    pub async fn revisions(&self, post_id: i32) -> Result<Vec<PostRevision>, PostError> {
+        let revisions = sqlx::query_as::<_, PostRevision>(
+            "SELECT * FROM post_revisions WHERE post_id = $1 ORDER BY id",
+        )
+        .bind(post_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(revisions)
+    }
+
+    // Soft delete a post. Deleting a thread hides it with all its replies.
+    pub async fn delete(&self, id: i32, ctx: &AuditContext) -> Result<Post, PostError> {
+        let mut tx = self.pool.begin().await?;
+        let before = sqlx::query_as::<_, Post>("SELECT * FROM posts WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(PostError::NotFound)?;
+        if before.deleted_at.is_some() {
+            return Err(PostError::Deleted);
+        }
+
+        let after = sqlx::query_as::<_, Post>(
+            "UPDATE posts SET deleted_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if let Some(thread_id) = before.thread_id {
+            sqlx::query("UPDATE posts SET reply_count = reply_count - 1 WHERE id = $1")
+                .bind(thread_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "delete",
+            ENTITY,
+            id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::group::GroupService;
+
+    #[tokio::test]
+    async fn test_threads_replies_and_edit_history() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let posts = PostService::new(db.pool.clone());
+
+        let [ada] = db.create_users(["Ada"]).await;
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let first = posts
+            .create_thread(group.id, ada.id, "First", "Hello", &ctx)
+            .await
+            .unwrap();
+        let second = posts
+            .create_thread(group.id, ada.id, "Second", "Hi", &ctx)
+            .await
+            .unwrap();
+
+        // A reply brings its thread back to the top
+        let reply = posts
+            .reply(first.id, ada.id, "Welcome", &ctx)
+            .await
+            .unwrap();
+        let threads = posts.list_threads(group.id, 10, 0).await.unwrap();
+        let ids: Vec<i32> = threads.iter().map(|t| t.post.id).collect();
+        assert_eq!(ids, [first.id, second.id]);
+        assert_eq!(threads[0].post.reply_count, 1);
+        assert_eq!(threads[0].author_name.as_deref(), Some("Ada"));
+        assert_eq!(posts.list_threads(group.id, 10, 1).await.unwrap().len(), 1);
+
+        let edited = posts
+            .edit(reply.id, Some("ignored"), "Welcome!", &ctx)
+            .await
+            .unwrap();
+        assert_eq!(edited.title, None);
+        assert!(edited.edited_at.is_some());
+        let revisions = posts.revisions(reply.id).await.unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].body, "Welcome");
+        let replies = posts.list_replies(first.id, None, 10).await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].post.body, "Welcome!");
+        assert!(
+            posts
+                .list_replies(first.id, Some(reply.id), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        posts.delete(reply.id, &ctx).await.unwrap();
+        let first = posts.get(first.id).await.unwrap().unwrap();
+        assert_eq!(first.post.reply_count, 0);
+        posts.delete(second.id, &ctx).await.unwrap();
+        assert!(matches!(
+            posts.reply(second.id, ada.id, "Too late", &ctx).await,
+            Err(PostError::Deleted)
+        ));
+        assert_eq!(posts.count_threads(group.id).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::db::models::Group;
+use crate::db::rsvp::RsvpCounts;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+// PostgreSQL channel the updates are sent on, see crate::realtime
+pub const CHANNEL: &str = "groups_updates";
+
+// A change pushed to connected browsers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Update {
+    // A group was created or restored
+    GroupCreated {
+        id: i32,
+        name: String,
+        created_at: DateTime<Utc>,
+    },
+    GroupDeleted {
+        id: i32,
+    },
+    RsvpCounts {
+        event_id: i32,
+        #[serde(flatten)]
+        counts: RsvpCounts,
+    },
+    // Only sent to the user concerned
+    Notifications {
+        user_id: i32,
+        unread: i64,
+    },
+}
+
+impl Update {
+    pub fn group_created(group: &Group) -> Self {
+        Update::GroupCreated {
+            id: group.id,
+            name: group.name.clone(),
+            created_at: group.created_at,
+        }
+    }
+
+    // Name of the server-sent event
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Update::GroupCreated { .. } => "group_created",
+            Update::GroupDeleted { .. } => "group_deleted",
+            Update::RsvpCounts { .. } => "rsvp_counts",
+            Update::Notifications { .. } => "notifications",
+        }
+    }
+}
+
+// Send an update to every server instance. Called inside the transaction
+// making the change: PostgreSQL only delivers it once that commits.
+pub async fn publish(conn: &mut PgConnection, update: &Update) -> Result<(), sqlx::Error> {
+    let payload = serde_json::to_string(update).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(CHANNEL)
+        .bind(payload)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+// Publish the user's unread notification count
+pub async fn publish_unread(conn: &mut PgConnection, user_id: i32) -> Result<(), sqlx::Error> {
+    let unread: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    publish(conn, &Update::Notifications { user_id, unread }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_json() {
+        let update = Update::RsvpCounts {
+            event_id: 3,
+            counts: RsvpCounts {
+                going: 2,
+                maybe: 1,
+                not_going: 0,
+            },
+        };
+        let json = serde_json::to_string(&update).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"rsvp_counts","event_id":3,"going":2,"maybe":1,"not_going":0}"#
+        );
+        assert_eq!(serde_json::from_str::<Update>(&json).unwrap(), update);
+    }
+}
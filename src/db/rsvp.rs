@@ -0,0 +1,137 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{Rsvp, RsvpStatus, User};
+use crate::db::realtime::{self, Update};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "event_rsvp";
+
+#[derive(Debug, Error)]
+pub enum RsvpError {
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// Number of members per answer
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct RsvpCounts {
+    pub going: i64,
+    pub maybe: i64,
+    pub not_going: i64,
+}
+
+pub struct RsvpService {
+    pool: DbPool,
+}
+
+impl RsvpService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Record or change the user's answer. Returns the RSVP and whether it
+    // changed.
+    pub async fn set(
+        &self,
+        event_id: i32,
+        user_id: i32,
+        status: RsvpStatus,
+        ctx: &AuditContext,
+    ) -> Result<(Rsvp, bool), RsvpError> {
+        let mut tx = self.pool.begin().await?;
+        let before = sqlx::query_as::<_, Rsvp>(
+            "SELECT * FROM event_rsvps WHERE event_id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(event_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(before) = before.as_ref().filter(|rsvp| rsvp.status == status) {
+            return Ok((before.clone(), false));
+        }
+
+        let after = sqlx::query_as::<_, Rsvp>(
+            "INSERT INTO event_rsvps (event_id, user_id, status) VALUES ($1, $2, $3) \
+             ON CONFLICT (event_id, user_id) DO UPDATE SET status = $3, updated_at = NOW() \
+             RETURNING *",
+        )
+        .bind(event_id)
+        .bind(user_id)
+        .bind(status)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            if before.is_some() { "update" } else { "create" },
+            ENTITY,
+            after.id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        let counts = Self::count(&mut tx, event_id).await?;
+        realtime::publish(&mut tx, &Update::RsvpCounts { event_id, counts }).await?;
+        tx.commit().await?;
+
+        Ok((after, true))
+    }
+
+    pub async fn get(&self, event_id: i32, user_id: i32) -> Result<Option<Rsvp>, RsvpError> {
+        let rsvp = sqlx::query_as::<_, Rsvp>(
+            "SELECT * FROM event_rsvps WHERE event_id = $1 AND user_id = $2",
+        )
+        .bind(event_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(rsvp)
+    }
+
+    // Members who gave the answer, still members of the event's group
+    pub async fn attendees(
+        &self,
+        event_id: i32,
+        status: RsvpStatus,
+    ) -> Result<Vec<User>, RsvpError> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT u.* FROM event_rsvps r \
+             JOIN users u ON u.id = r.user_id \
+             JOIN events e ON e.id = r.event_id \
+             JOIN group_members m ON m.group_id = e.group_id AND m.user_id = u.id \
+             WHERE r.event_id = $1 AND r.status = $2 AND u.deleted_at IS NULL ORDER BY u.id",
+        )
+        .bind(event_id)
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    pub async fn counts(&self, event_id: i32) -> Result<RsvpCounts, RsvpError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::count(&mut conn, event_id).await
+    }
+
+    async fn count(conn: &mut PgConnection, event_id: i32) -> Result<RsvpCounts, RsvpError> {
+        let counts = sqlx::query_as::<_, RsvpCounts>(
+            "SELECT COUNT(*) FILTER (WHERE status = 'going') AS going, \
+             COUNT(*) FILTER (WHERE status = 'maybe') AS maybe, \
+             COUNT(*) FILTER (WHERE status = 'not_going') AS not_going \
+             FROM event_rsvps WHERE event_id = $1",
+        )
+        .bind(event_id)
+        .fetch_one(conn)
+        .await?;
+
+        Ok(counts)
+    }
+}
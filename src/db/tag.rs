@@ -0,0 +1,523 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::models::{Category, Group, Tag};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use std::collections::HashMap;
+use thiserror::Error;
+
+// Tags are recorded in the audit log as a change of the group
+const GROUP_ENTITY: &str = "group";
+
+pub const MAX_TAG_LENGTH: usize = 40;
+pub const MAX_TAGS_PER_GROUP: usize = 10;
+pub const MAX_CATEGORY_LENGTH: usize = 50;
+
+#[derive(Debug, Error)]
+pub enum TagError {
+    #[error("Tag not found")]
+    NotFound,
+    #[error("Category not found")]
+    CategoryNotFound,
+    #[error("A category with that name already exists")]
+    CategoryExists,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A tag with the number of groups using it
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TagSummary {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub category_id: Option<i32>,
+    pub group_count: i64,
+}
+
+// An upcoming event of a group with a tag
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TagEvent {
+    pub id: i32,
+    pub group_id: i32,
+    pub group_name: String,
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    pub location: Option<String>,
+}
+
+#[derive(FromRow)]
+struct GroupTag {
+    group_id: i32,
+    #[sqlx(flatten)]
+    tag: Tag,
+}
+
+// How a group's tags appear in the audit log
+#[derive(Serialize)]
+struct GroupTags<'a> {
+    tags: Vec<&'a str>,
+}
+
+// The URL form of a tag or category name: lowercase, with every run of
+// characters other than letters and digits turned into a single dash
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+// Tags typed as a comma separated list, as (slug, name) pairs without
+// duplicates, or the message to show
+pub fn parse_tags(input: &str) -> Result<Vec<(String, String)>, String> {
+    let mut tags: Vec<(String, String)> = Vec::new();
+    for name in input.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if name.chars().count() > MAX_TAG_LENGTH {
+            return Err(format!("Tags are limited to {} characters", MAX_TAG_LENGTH));
+        }
+        let slug = slugify(name);
+        if slug.is_empty() {
+            return Err(format!("\"{}\" has no letters or digits", name));
+        }
+        if !tags.iter().any(|(s, _)| *s == slug) {
+            tags.push((slug, name.to_string()));
+        }
+    }
+
+    if tags.len() > MAX_TAGS_PER_GROUP {
+        return Err(format!(
+            "Groups can have at most {} tags",
+            MAX_TAGS_PER_GROUP
+        ));
+    }
+    Ok(tags)
+}
+
+pub struct TagService {
+    pool: DbPool,
+}
+
+impl TagService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_by_slug(&self, slug: &str) -> Result<Option<Tag>, TagError> {
+        let tag = sqlx::query_as::<_, Tag>("SELECT * FROM tags WHERE slug = $1")
+            .bind(slug)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(tag)
+    }
+
+    // Tags starting with what was typed, the most used first
+    pub async fn autocomplete(
+        &self,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<TagSummary>, TagError> {
+        // Slugs have no LIKE wildcards to escape
+        let slug = slugify(prefix);
+        let tags = sqlx::query_as::<_, TagSummary>(
+            "SELECT t.id, t.slug, t.name, t.category_id, COUNT(g.id) AS group_count \
+             FROM tags t \
+             LEFT JOIN group_tags gt ON gt.tag_id = t.id \
+             LEFT JOIN groups g ON g.id = gt.group_id AND g.deleted_at IS NULL \
+             WHERE t.slug LIKE $1 || '%' \
+             GROUP BY t.id ORDER BY group_count DESC, t.slug LIMIT $2",
+        )
+        .bind(slug)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+
+    // Every tag with how many groups use it, by name
+    pub async fn list_with_counts(&self) -> Result<Vec<TagSummary>, TagError> {
+        let tags = sqlx::query_as::<_, TagSummary>(
+            "SELECT t.id, t.slug, t.name, t.category_id, COUNT(g.id) AS group_count \
+             FROM tags t \
+             LEFT JOIN group_tags gt ON gt.tag_id = t.id \
+             LEFT JOIN groups g ON g.id = gt.group_id AND g.deleted_at IS NULL \
+             GROUP BY t.id ORDER BY t.slug",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+
+    pub async fn for_group(&self, group_id: i32) -> Result<Vec<Tag>, TagError> {
+        let tags = sqlx::query_as::<_, Tag>(
+            "SELECT t.* FROM tags t JOIN group_tags gt ON gt.tag_id = t.id \
+             WHERE gt.group_id = $1 ORDER BY t.slug",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+
+    // The tags of each of the groups, in one query
+    pub async fn for_groups(&self, group_ids: &[i32]) -> Result<HashMap<i32, Vec<Tag>>, TagError> {
+        let rows = sqlx::query_as::<_, GroupTag>(
+            "SELECT gt.group_id, t.* FROM tags t JOIN group_tags gt ON gt.tag_id = t.id \
+             WHERE gt.group_id = ANY($1) ORDER BY t.slug",
+        )
+        .bind(group_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags: HashMap<i32, Vec<Tag>> = HashMap::new();
+        for row in rows {
+            tags.entry(row.group_id).or_default().push(row.tag);
+        }
+        Ok(tags)
+    }
+
+    // Replace the tags of a group, creating the ones not used before
+    pub async fn set_group_tags(
+        &self,
+        group_id: i32,
+        tags: &[(String, String)],
+        ctx: &AuditContext,
+    ) -> Result<Vec<Tag>, TagError> {
+        let mut tx = self.pool.begin().await?;
+        let before: Vec<String> = sqlx::query_scalar(
+            "SELECT t.slug FROM tags t JOIN group_tags gt ON gt.tag_id = t.id \
+             WHERE gt.group_id = $1 ORDER BY t.slug",
+        )
+        .bind(group_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM group_tags WHERE group_id = $1")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+        let mut saved = Vec::new();
+        for (slug, name) in tags {
+            // The no-op update makes RETURNING give back existing tags too
+            let tag = sqlx::query_as::<_, Tag>(
+                "INSERT INTO tags (slug, name) VALUES ($1, $2) \
+                 ON CONFLICT (slug) DO UPDATE SET slug = EXCLUDED.slug RETURNING *",
+            )
+            .bind(slug)
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await?;
+            sqlx::query("INSERT INTO group_tags (group_id, tag_id) VALUES ($1, $2)")
+                .bind(group_id)
+                .bind(tag.id)
+                .execute(&mut *tx)
+                .await?;
+            saved.push(tag);
+        }
+        saved.sort_by(|a, b| a.slug.cmp(&b.slug));
+
+        let before = GroupTags {
+            tags: before.iter().map(String::as_str).collect(),
+        };
+        let after = GroupTags {
+            tags: saved.iter().map(|t| t.slug.as_str()).collect(),
+        };
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            GROUP_ENTITY,
+            group_id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(saved)
+    }
+
+    // Groups that are not deleted and have every one of the tags, newest first
+    pub async fn groups_with_tags(&self, slugs: &[String]) -> Result<Vec<Group>, TagError> {
+        let groups = sqlx::query_as::<_, Group>(
+            "SELECT g.* FROM groups g WHERE g.deleted_at IS NULL AND ( \
+                 SELECT COUNT(DISTINCT t.slug) FROM group_tags gt JOIN tags t ON t.id = gt.tag_id \
+                 WHERE gt.group_id = g.id AND t.slug = ANY($1) \
+             ) = cardinality($1::text[]) \
+             ORDER BY g.created_at DESC",
+        )
+        .bind(slugs)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(groups)
+    }
+
+    // Events not over yet of the open groups with the tag, soonest first.
+    // Events of other groups are for their members only.
+    pub async fn upcoming_events(
+        &self,
+        tag_id: i32,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TagEvent>, TagError> {
+        let events = sqlx::query_as::<_, TagEvent>(
+            "SELECT e.id, e.group_id, g.name AS group_name, e.title, e.starts_at, e.location \
+             FROM events e \
+             JOIN groups g ON g.id = e.group_id \
+             JOIN group_tags gt ON gt.group_id = g.id \
+             WHERE gt.tag_id = $1 AND e.deleted_at IS NULL AND g.deleted_at IS NULL \
+             AND g.join_policy = 'open' AND COALESCE(e.ends_at, e.starts_at) >= $2 \
+             ORDER BY e.starts_at, e.id LIMIT $3",
+        )
+        .bind(tag_id)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+
+    pub async fn delete_tag(&self, id: i32) -> Result<Tag, TagError> {
+        sqlx::query_as::<_, Tag>("DELETE FROM tags WHERE id = $1 RETURNING *")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(TagError::NotFound)
+    }
+
+    // File a tag under a category, or take it out of its category with None
+    pub async fn set_category(
+        &self,
+        tag_id: i32,
+        category_id: Option<i32>,
+    ) -> Result<Tag, TagError> {
+        let result =
+            sqlx::query_as::<_, Tag>("UPDATE tags SET category_id = $2 WHERE id = $1 RETURNING *")
+                .bind(tag_id)
+                .bind(category_id)
+                .fetch_optional(&self.pool)
+                .await;
+
+        match result {
+            Ok(Some(tag)) => Ok(tag),
+            Ok(None) => Err(TagError::NotFound),
+            Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+                Err(TagError::CategoryNotFound)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn list_categories(&self) -> Result<Vec<Category>, TagError> {
+        let categories =
+            sqlx::query_as::<_, Category>("SELECT * FROM categories ORDER BY position, name")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(categories)
+    }
+
+    pub async fn create_category(&self, name: &str, position: i32) -> Result<Category, TagError> {
+        let result = sqlx::query_as::<_, Category>(
+            "INSERT INTO categories (slug, name, position) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(slugify(name))
+        .bind(name)
+        .bind(position)
+        .fetch_one(&self.pool)
+        .await;
+
+        match result {
+            Ok(category) => Ok(category),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                Err(TagError::CategoryExists)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Its tags stay, without a category
+    pub async fn delete_category(&self, id: i32) -> Result<Category, TagError> {
+        sqlx::query_as::<_, Category>("DELETE FROM categories WHERE id = $1 RETURNING *")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(TagError::CategoryNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::event::{EventService, NewEvent};
+    use crate::db::group::GroupService;
+    use crate::db::join_request::JoinRequestService;
+    use crate::db::models::JoinPolicy;
+    use chrono::Duration;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Rust"), "rust");
+        assert_eq!(slugify("  Board games & RPGs "), "board-games-rpgs");
+        assert_eq!(slugify("C++"), "c");
+        assert_eq!(slugify("Café culture"), "café-culture");
+        assert_eq!(slugify("--"), "");
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags("Rust, web,, rust ,Web Dev").unwrap(),
+            vec![
+                ("rust".to_string(), "Rust".to_string()),
+                ("web".to_string(), "web".to_string()),
+                ("web-dev".to_string(), "Web Dev".to_string()),
+            ]
+        );
+        assert_eq!(parse_tags("").unwrap(), vec![]);
+        assert!(parse_tags("!!!").is_err());
+        assert!(parse_tags(&"x".repeat(MAX_TAG_LENGTH + 1)).is_err());
+        let many: Vec<String> = (0..=MAX_TAGS_PER_GROUP)
+            .map(|i| format!("t{}", i))
+            .collect();
+        assert!(parse_tags(&many.join(",")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_group_tags_and_topic_pages() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+        let requests = JoinRequestService::new(db.pool.clone());
+        let tags = TagService::new(db.pool.clone());
+
+        let readers = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let closed = groups.create("Closed".to_string(), &ctx).await.unwrap();
+        let gone = groups.create("Gone".to_string(), &ctx).await.unwrap();
+        let saved = tags
+            .set_group_tags(readers.id, &parse_tags("Web, Rust").unwrap(), &ctx)
+            .await
+            .unwrap();
+        let slugs: Vec<&str> = saved.iter().map(|t| t.slug.as_str()).collect();
+        assert_eq!(slugs, ["rust", "web"]);
+        for group in [&closed, &gone] {
+            tags.set_group_tags(group.id, &parse_tags("rust").unwrap(), &ctx)
+                .await
+                .unwrap();
+        }
+        requests
+            .update_settings(closed.id, JoinPolicy::Approval, &[], &ctx)
+            .await
+            .unwrap();
+        groups.delete(gone.id, &ctx).await.unwrap();
+
+        // Existing tags are reused, keeping the name they were created with
+        let rust = tags.get_by_slug("rust").await.unwrap().unwrap();
+        assert_eq!(rust.name, "Rust");
+        assert_eq!(tags.for_group(closed.id).await.unwrap()[0].id, rust.id);
+
+        let found = tags.autocomplete("RU", 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].slug.as_str(), found[0].group_count), ("rust", 2));
+        let counts: Vec<(String, i64)> = tags
+            .list_with_counts()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.slug, t.group_count))
+            .collect();
+        assert_eq!(counts, [("rust".to_string(), 2), ("web".to_string(), 1)]);
+
+        let ids = |groups: Vec<Group>| groups.iter().map(|g| g.id).collect::<Vec<_>>();
+        let with_rust = tags.groups_with_tags(&["rust".to_string()]).await.unwrap();
+        assert_eq!(ids(with_rust), [closed.id, readers.id]);
+        let both = ["rust".to_string(), "web".to_string()];
+        assert_eq!(
+            ids(tags.groups_with_tags(&both).await.unwrap()),
+            [readers.id]
+        );
+        let by_group = tags.for_groups(&[readers.id, closed.id]).await.unwrap();
+        assert_eq!(by_group[&readers.id].len(), 2);
+        assert_eq!(by_group[&closed.id].len(), 1);
+
+        // Topic pages only list the events of open groups
+        let now = Utc::now();
+        for group in [&readers, &closed] {
+            let event = NewEvent {
+                title: format!("{} meetup", group.name),
+                starts_at: now + Duration::days(1),
+                ..Default::default()
+            };
+            events.create(group.id, event, &ctx).await.unwrap();
+        }
+        let upcoming = tags.upcoming_events(rust.id, now, 10).await.unwrap();
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].title, "Readers meetup");
+
+        tags.set_group_tags(readers.id, &[], &ctx).await.unwrap();
+        assert!(tags.for_group(readers.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_categories() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let tags = TagService::new(db.pool.clone());
+
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let rust = tags
+            .set_group_tags(group.id, &parse_tags("Rust").unwrap(), &ctx)
+            .await
+            .unwrap()
+            .remove(0);
+        let tech = tags.create_category("Tech", 2).await.unwrap();
+        tags.create_category("Outdoors", 1).await.unwrap();
+        assert!(matches!(
+            tags.create_category("tech", 3).await,
+            Err(TagError::CategoryExists)
+        ));
+        let names: Vec<String> = tags
+            .list_categories()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, ["Outdoors", "Tech"]);
+
+        let filed = tags.set_category(rust.id, Some(tech.id)).await.unwrap();
+        assert_eq!(filed.category_id, Some(tech.id));
+        assert!(matches!(
+            tags.set_category(rust.id, Some(tech.id + 100)).await,
+            Err(TagError::CategoryNotFound)
+        ));
+
+        // Tags outlive their category
+        tags.delete_category(tech.id).await.unwrap();
+        let rust = tags.get_by_slug("rust").await.unwrap().unwrap();
+        assert_eq!(rust.category_id, None);
+        tags.delete_tag(rust.id).await.unwrap();
+        assert!(tags.for_group(group.id).await.unwrap().is_empty());
+        assert!(matches!(
+            tags.delete_tag(rust.id).await,
+            Err(TagError::NotFound)
+        ));
+    }
+}
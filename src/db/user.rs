@@ -1,7 +1,24 @@
+use crate::db::audit::{self, AuditContext};
 use crate::db::connection::{DatabaseError, DbPool};
-use crate::db::models::User;
+use crate::db::models::{EmailChange, Group, GroupMember, MemberRole, ProfileVisibility, User};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use sqlx::{PgConnection, Postgres, QueryBuilder};
 use thiserror::Error;
 
+// Entity name used in the audit log
+const ENTITY: &str = "user";
+const GROUP_ENTITY: &str = "group";
+const MEMBER_ENTITY: &str = "group_member";
+
+// Name left on content written by deleted accounts
+pub const DELETED_USER_NAME: &str = "Deleted user";
+
+// Unguessable token used in email confirmation links
+fn generate_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
 #[derive(Debug, Error)]
 #[allow(dead_code)]
 pub enum UserError {
@@ -9,12 +26,34 @@ pub enum UserError {
     NotSoftDeleted,
     #[error("User not found")]
     UserNotFound,
+    #[error("Email already registered")]
+    EmailTaken,
+    #[error("The confirmation link is invalid or has expired")]
+    EmailChangeNotFound,
     #[error("Database error: {0}")]
     DbError(#[from] DatabaseError),
     #[error("SQL error: {0}")]
     SqlError(#[from] sqlx::Error),
 }
 
+// Which users to include based on their soft-delete state
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DeletedFilter {
+    #[default]
+    Active,
+    Deleted,
+    All,
+}
+
+// Criteria for the admin user listing
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    // Case-insensitive match on email or name
+    pub query: Option<String>,
+    pub deleted: DeletedFilter,
+    pub admins_only: bool,
+}
+
 pub struct UserService {
     pool: DbPool,
 }
@@ -26,15 +65,24 @@ impl UserService {
     }
 
     // Create a new user
-    pub async fn create(&self, email: String, name: String) -> Result<User, UserError> {
+    pub async fn create(
+        &self,
+        email: String,
+        name: String,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        let mut tx = self.pool.begin().await?;
         let user = sqlx::query_as::<_, User>(
             "INSERT INTO users (email, name) VALUES ($1, $2) RETURNING *",
         )
         .bind(&email)
         .bind(&name)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        audit::record(&mut tx, ctx, "create", ENTITY, user.id, None, Some(&user)).await?;
+        tx.commit().await?;
+
         Ok(user)
     }
 
@@ -44,16 +92,31 @@ impl UserService {
         email: String,
         name: String,
         password_hash: String,
+        ctx: &AuditContext,
     ) -> Result<User, UserError> {
+        let mut tx = self.pool.begin().await?;
         let user = sqlx::query_as::<_, User>(
             "INSERT INTO users (email, name, password_hash) VALUES ($1, $2, $3) RETURNING *",
         )
         .bind(&email)
         .bind(&name)
         .bind(&password_hash)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        audit::record(&mut tx, ctx, "create", ENTITY, user.id, None, Some(&user)).await?;
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    // Lock a user row for the rest of the transaction, returning its current state
+    async fn lock(conn: &mut PgConnection, id: i32) -> Result<Option<User>, UserError> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(conn)
+            .await?;
+
         Ok(user)
     }
 
@@ -78,41 +141,78 @@ impl UserService {
     }
 
     // Update user
-    pub async fn update(&self, user: User) -> Result<(), UserError> {
-        let affected_rows = sqlx::query(
-            "UPDATE users SET name = $1, password_hash = $2, updated_at = NOW() WHERE id = $3 AND deleted_at IS NULL"
+    pub async fn update(&self, user: User, ctx: &AuditContext) -> Result<(), UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, user.id).await?;
+
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET name = $1, password_hash = $2, bio = $3, location = $4, \
+             interests = $5, profile_visibility = $6, show_location = $7, show_groups = $8, \
+             updated_at = NOW() WHERE id = $9 AND deleted_at IS NULL RETURNING *",
         )
         .bind(&user.name)
         .bind(&user.password_hash)
+        .bind(&user.bio)
+        .bind(&user.location)
+        .bind(&user.interests)
+        .bind(user.profile_visibility)
+        .bind(user.show_location)
+        .bind(user.show_groups)
         .bind(user.id)
-        .execute(&self.pool)
+        .fetch_optional(&mut *tx)
         .await?
-        .rows_affected();
+        .ok_or(UserError::UserNotFound)?;
 
-        if affected_rows == 0 {
-            return Err(UserError::UserNotFound);
-        }
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            user.id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
-    // Delete user by ID (soft delete)
-    pub async fn delete(&self, id: i32) -> Result<(), UserError> {
-        sqlx::query("UPDATE users SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL")
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
+    // Delete user by ID (soft delete), UserNotFound if no active user has it
+    pub async fn delete(&self, id: i32, ctx: &AuditContext) -> Result<(), UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "delete",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
     // Hard delete user by ID (permanent removal)
     // Only allowed for users that have already been soft-deleted
-    pub async fn hard_delete(&self, id: i32) -> Result<(), UserError> {
-        // First check if user exists and is soft-deleted
-        let user = self.get_by_id(id).await?;
+    pub async fn hard_delete(&self, id: i32, ctx: &AuditContext) -> Result<(), UserError> {
+        let mut tx = self.pool.begin().await?;
 
-        match user {
+        // First check if user exists and is soft-deleted
+        match Self::lock(&mut tx, id).await? {
             Some(user) => {
                 // Check if the user is soft-deleted
                 if user.deleted_at.is_none() {
@@ -122,9 +222,12 @@ impl UserService {
                 // User is soft-deleted, proceed with hard deletion
                 sqlx::query("DELETE FROM users WHERE id = $1")
                     .bind(id)
-                    .execute(&self.pool)
+                    .execute(&mut *tx)
                     .await?;
 
+                audit::record(&mut tx, ctx, "hard_delete", ENTITY, id, Some(&user), None).await?;
+                tx.commit().await?;
+
                 Ok(())
             }
             None => Ok(()), // User doesn't exist, nothing to delete
@@ -142,6 +245,512 @@ impl UserService {
         Ok(users)
     }
 
+    // Restore a soft-deleted user
+    pub async fn restore(&self, id: i32, ctx: &AuditContext) -> Result<(), UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET deleted_at = NULL, updated_at = NOW() WHERE id = $1 AND deleted_at IS NOT NULL RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "restore",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Grant or revoke the site-wide admin role
+    pub async fn set_admin(
+        &self,
+        id: i32,
+        is_admin: bool,
+        ctx: &AuditContext,
+    ) -> Result<(), UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET is_admin = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+        )
+        .bind(is_admin)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn set_time_zone(
+        &self,
+        id: i32,
+        time_zone: Tz,
+        ctx: &AuditContext,
+    ) -> Result<(), UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET time_zone = $1, updated_at = NOW() \
+             WHERE id = $2 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(time_zone.name())
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Replace the avatar. Returns the previous key.
+    pub async fn set_avatar(
+        &self,
+        id: i32,
+        avatar_key: Option<&str>,
+        ctx: &AuditContext,
+    ) -> Result<Option<String>, UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET avatar_key = $1, updated_at = NOW() \
+             WHERE id = $2 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(avatar_key)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(before.and_then(|user| user.avatar_key))
+    }
+
+    // Whether any user still has this avatar. Uploads are keyed by content, so
+    // two users may share one.
+    pub async fn avatar_in_use(&self, avatar_key: &str) -> Result<bool, UserError> {
+        let in_use: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE avatar_key = $1)")
+                .bind(avatar_key)
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(in_use)
+    }
+
+    // Current session version of an active user, None once the account is
+    // deleted
+    pub async fn session_version(&self, id: i32) -> Result<Option<i32>, UserError> {
+        let version = sqlx::query_scalar(
+            "SELECT session_version FROM users WHERE id = $1 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(version)
+    }
+
+    // Set a new password and end every session of the user. Returns the new
+    // session version.
+    pub async fn change_password(
+        &self,
+        id: i32,
+        password_hash: &str,
+        ctx: &AuditContext,
+    ) -> Result<i32, UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET password_hash = $1, session_version = session_version + 1, \
+             updated_at = NOW() WHERE id = $2 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(password_hash)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after.session_version)
+    }
+
+    async fn email_taken(
+        conn: &mut PgConnection,
+        email: &str,
+        except_id: i32,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM users WHERE LOWER(email) = LOWER($1) AND id <> $2)",
+        )
+        .bind(email)
+        .bind(except_id)
+        .fetch_one(conn)
+        .await
+    }
+
+    // Start changing the user's email address. Replaces any earlier request.
+    pub async fn request_email_change(
+        &self,
+        id: i32,
+        new_email: &str,
+        valid_for: Duration,
+    ) -> Result<EmailChange, UserError> {
+        let mut conn = self.pool.acquire().await?;
+        if Self::email_taken(&mut conn, new_email, id).await? {
+            return Err(UserError::EmailTaken);
+        }
+
+        let change = sqlx::query_as::<_, EmailChange>(
+            "INSERT INTO email_changes (user_id, new_email, token, expires_at) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (user_id) DO UPDATE \
+             SET new_email = $2, token = $3, created_at = NOW(), expires_at = $4 RETURNING *",
+        )
+        .bind(id)
+        .bind(new_email)
+        .bind(generate_token())
+        .bind(Utc::now() + valid_for)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(change)
+    }
+
+    // The pending change a confirmation link is for, unless it has expired
+    pub async fn get_email_change(&self, token: &str) -> Result<Option<EmailChange>, UserError> {
+        let change = sqlx::query_as::<_, EmailChange>(
+            "SELECT * FROM email_changes WHERE token = $1 AND expires_at > NOW()",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(change)
+    }
+
+    pub async fn pending_email_change(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<EmailChange>, UserError> {
+        let change = sqlx::query_as::<_, EmailChange>(
+            "SELECT * FROM email_changes WHERE user_id = $1 AND expires_at > NOW()",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(change)
+    }
+
+    // Switch to the new address of a confirmed change
+    pub async fn confirm_email_change(
+        &self,
+        token: &str,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        let mut tx = self.pool.begin().await?;
+        let change = sqlx::query_as::<_, EmailChange>(
+            "DELETE FROM email_changes WHERE token = $1 AND expires_at > NOW() RETURNING *",
+        )
+        .bind(token)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::EmailChangeNotFound)?;
+
+        // Someone may have registered the address since the request
+        if Self::email_taken(&mut tx, &change.new_email, change.user_id).await? {
+            return Err(UserError::EmailTaken);
+        }
+
+        let before = Self::lock(&mut tx, change.user_id).await?;
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET email = $1, updated_at = NOW() \
+             WHERE id = $2 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(&change.new_email)
+        .bind(change.user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            after.id,
+            before.as_ref(),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after)
+    }
+
+    // Hand each group the user owns to the longest-standing other member. A
+    // group nobody else belongs to is left without an owner, for site admins
+    // to manage.
+    async fn transfer_ownership(
+        conn: &mut PgConnection,
+        id: i32,
+        ctx: &AuditContext,
+    ) -> Result<(), sqlx::Error> {
+        let owned =
+            sqlx::query_as::<_, Group>("SELECT * FROM groups WHERE owner_id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_all(&mut *conn)
+                .await?;
+
+        for before in owned {
+            let successor: Option<i32> = sqlx::query_scalar(
+                "SELECT m.user_id FROM group_members m \
+                 JOIN users u ON u.id = m.user_id AND u.deleted_at IS NULL \
+                 WHERE m.group_id = $1 AND m.user_id <> $2 \
+                 ORDER BY m.role = $3 DESC, m.created_at, m.id LIMIT 1",
+            )
+            .bind(before.id)
+            .bind(id)
+            .bind(MemberRole::Owner)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+            let after = sqlx::query_as::<_, Group>(
+                "UPDATE groups SET owner_id = $1 WHERE id = $2 RETURNING *",
+            )
+            .bind(successor)
+            .bind(before.id)
+            .fetch_one(&mut *conn)
+            .await?;
+            audit::record(
+                conn,
+                ctx,
+                "update",
+                GROUP_ENTITY,
+                after.id,
+                Some(&before),
+                Some(&after),
+            )
+            .await?;
+
+            if let Some(successor) = successor {
+                let member_before = sqlx::query_as::<_, GroupMember>(
+                    "SELECT * FROM group_members WHERE group_id = $1 AND user_id = $2 FOR UPDATE",
+                )
+                .bind(after.id)
+                .bind(successor)
+                .fetch_one(&mut *conn)
+                .await?;
+                let member_after = sqlx::query_as::<_, GroupMember>(
+                    "UPDATE group_members SET role = $1 WHERE id = $2 RETURNING *",
+                )
+                .bind(MemberRole::Owner)
+                .bind(member_before.id)
+                .fetch_one(&mut *conn)
+                .await?;
+                audit::record(
+                    conn,
+                    ctx,
+                    "update",
+                    MEMBER_ENTITY,
+                    member_after.id,
+                    Some(&member_before),
+                    Some(&member_after),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Delete an account at its owner's request: hand over the groups they own,
+    // remove their memberships, RSVPs and other personal records, then
+    // soft-delete the user with every personal field blanked. Posts and
+    // comments stay, shown as written by a deleted user. Returns the user as
+    // they were, for cleaning up files such as the avatar.
+    pub async fn delete_account(&self, id: i32, ctx: &AuditContext) -> Result<User, UserError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id)
+            .await?
+            .filter(|user| user.deleted_at.is_none())
+            .ok_or(UserError::UserNotFound)?;
+
+        Self::transfer_ownership(&mut tx, id, ctx).await?;
+
+        let memberships = sqlx::query_as::<_, GroupMember>(
+            "DELETE FROM group_members WHERE user_id = $1 RETURNING *",
+        )
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for member in &memberships {
+            audit::record(
+                &mut tx,
+                ctx,
+                "delete",
+                MEMBER_ENTITY,
+                member.id,
+                Some(member),
+                None,
+            )
+            .await?;
+        }
+
+        for query in [
+            "DELETE FROM event_rsvps WHERE user_id = $1",
+            "DELETE FROM event_attendance WHERE user_id = $1",
+            "DELETE FROM group_join_requests WHERE user_id = $1",
+            "DELETE FROM notifications WHERE user_id = $1",
+            "DELETE FROM notification_preferences WHERE user_id = $1",
+            "DELETE FROM digest_settings WHERE user_id = $1",
+            "DELETE FROM email_changes WHERE user_id = $1",
+            // Admin actions note the email of the user they were about
+            "UPDATE admin_actions SET details = '' WHERE target_type = 'user' AND target_id = $1",
+        ] {
+            sqlx::query(query).bind(id).execute(&mut *tx).await?;
+        }
+        sqlx::query(
+            "DELETE FROM group_invitations WHERE LOWER(email) = LOWER($1) AND status = 'pending'",
+        )
+        .bind(&before.email)
+        .execute(&mut *tx)
+        .await?;
+
+        // The address must stay unique, and must not be one anybody can have
+        let after = sqlx::query_as::<_, User>(
+            "UPDATE users SET email = $1, name = $2, password_hash = NULL, bio = '', \
+             location = '', interests = '{}', profile_visibility = $3, time_zone = 'UTC', \
+             avatar_key = NULL, session_version = session_version + 1, \
+             deleted_at = NOW(), updated_at = NOW() WHERE id = $4 RETURNING *",
+        )
+        .bind(format!("deleted-{}@deleted.invalid", id))
+        .bind(DELETED_USER_NAME)
+        .bind(ProfileVisibility::Private)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // Only the anonymised state: the append-only audit log must not gain
+        // another copy of the personal data
+        audit::record(&mut tx, ctx, "delete", ENTITY, id, None, Some(&after)).await?;
+        tx.commit().await?;
+
+        Ok(before)
+    }
+
+    // Search users for the admin area, newest first
+    pub async fn search(&self, filter: &UserFilter) -> Result<Vec<User>, UserError> {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM users WHERE TRUE");
+
+        match filter.deleted {
+            DeletedFilter::Active => query.push(" AND deleted_at IS NULL"),
+            DeletedFilter::Deleted => query.push(" AND deleted_at IS NOT NULL"),
+            DeletedFilter::All => &mut query,
+        };
+        if filter.admins_only {
+            query.push(" AND is_admin");
+        }
+        if let Some(text) = filter.query.as_deref().filter(|q| !q.trim().is_empty()) {
+            let pattern = format!("%{}%", text.trim());
+            query
+                .push(" AND (email ILIKE ")
+                .push_bind(pattern.clone())
+                .push(" OR name ILIKE ")
+                .push_bind(pattern)
+                .push(")");
+        }
+        query.push(" ORDER BY created_at DESC");
+
+        let users = query.build_query_as::<User>().fetch_all(&self.pool).await?;
+
+        Ok(users)
+    }
+
+    // List users soft-deleted before the given time, oldest first
+    pub async fn list_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<User>, UserError> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT * FROM users WHERE deleted_at IS NOT NULL AND deleted_at < $1 ORDER BY deleted_at",
+        )
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    // Count soft-deleted users
+    pub async fn count_deleted(&self) -> Result<i64, UserError> {
+        let count: (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM users WHERE deleted_at IS NOT NULL")
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(count.0)
+    }
+
     // Count total number of users
     pub async fn count(&self) -> Result<i64, UserError> {
         let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
@@ -152,4 +761,251 @@ impl UserService {
     }
 }
 
-// Tests will be rewritten for PostgreSQL in a separate module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::group::GroupService;
+    use crate::db::invitation::{InvitationService, NewInvitation};
+    use crate::db::member::MemberService;
+    use crate::db::models::ProfileVisibility;
+
+    #[tokio::test]
+    async fn test_admin_search_delete_and_restore() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+
+        let [ada, bob] = db.create_users(["Ada", "Bob"]).await;
+        users.set_admin(ada.id, true, &ctx).await.unwrap();
+        users.delete(bob.id, &ctx).await.unwrap();
+
+        let admins = UserFilter {
+            admins_only: true,
+            ..UserFilter::default()
+        };
+        let found = users.search(&admins).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, ada.id);
+        let deleted = UserFilter {
+            query: Some("BOB".to_string()),
+            deleted: DeletedFilter::Deleted,
+            ..UserFilter::default()
+        };
+        assert_eq!(users.search(&deleted).await.unwrap()[0].id, bob.id);
+        assert_eq!(users.count_deleted().await.unwrap(), 1);
+
+        // Only active users can be deleted, and only deleted ones restored
+        assert!(matches!(
+            users.delete(bob.id, &ctx).await,
+            Err(UserError::UserNotFound)
+        ));
+        assert!(matches!(
+            users.delete(bob.id + 100, &ctx).await,
+            Err(UserError::UserNotFound)
+        ));
+        assert!(matches!(
+            users.set_admin(bob.id + 100, true, &ctx).await,
+            Err(UserError::UserNotFound)
+        ));
+        users.restore(bob.id, &ctx).await.unwrap();
+        assert!(matches!(
+            users.restore(bob.id, &ctx).await,
+            Err(UserError::UserNotFound)
+        ));
+        assert_eq!(users.list_active().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_update_saves_the_profile() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+        let groups = GroupService::new(db.pool.clone());
+
+        let [mut ada] = db.create_users(["Ada"]).await;
+        assert_eq!(ada.profile_visibility, ProfileVisibility::Members);
+        ada.bio = "Writes programs for engines".to_string();
+        ada.location = "London".to_string();
+        ada.interests = vec!["maths".to_string(), "poetry".to_string()];
+        ada.profile_visibility = ProfileVisibility::Everyone;
+        ada.show_location = false;
+        users.update(ada.clone(), &ctx).await.unwrap();
+
+        let saved = users.get_by_id(ada.id).await.unwrap().unwrap();
+        assert_eq!(saved.bio, ada.bio);
+        assert_eq!(saved.location, ada.location);
+        assert_eq!(saved.interests, ada.interests);
+        assert_eq!(saved.profile_visibility, ProfileVisibility::Everyone);
+        assert!(!saved.show_location);
+        assert!(saved.show_groups);
+
+        // The groups listed on the profile, by name
+        for name in ["Writers", "Engines", "Closed"] {
+            groups
+                .create_with_owner(name.to_string(), Some(ada.id), &ctx)
+                .await
+                .unwrap();
+        }
+        let closed = &groups.find_by_name("Closed".to_string()).await.unwrap()[0];
+        groups.delete(closed.id, &ctx).await.unwrap();
+        let names: Vec<String> = groups
+            .list_for_member(ada.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|group| group.name)
+            .collect();
+        assert_eq!(names, ["Engines", "Writers"]);
+
+        users.delete(ada.id, &ctx).await.unwrap();
+        assert!(matches!(
+            users.update(ada, &ctx).await,
+            Err(UserError::UserNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_avatars_shared_by_content() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+
+        let [ada, bob] = db.create_users(["Ada", "Bob"]).await;
+        assert_eq!(
+            users.set_avatar(ada.id, Some("a1"), &ctx).await.unwrap(),
+            None
+        );
+        users.set_avatar(bob.id, Some("a1"), &ctx).await.unwrap();
+
+        // The old upload can only go once no one uses it
+        let previous = users.set_avatar(ada.id, Some("a2"), &ctx).await.unwrap();
+        assert_eq!(previous.as_deref(), Some("a1"));
+        assert!(users.avatar_in_use("a1").await.unwrap());
+        users.set_avatar(bob.id, None, &ctx).await.unwrap();
+        assert!(!users.avatar_in_use("a1").await.unwrap());
+        let ada = users.get_by_id(ada.id).await.unwrap().unwrap();
+        assert_eq!(ada.avatar_key.as_deref(), Some("a2"));
+    }
+
+    #[tokio::test]
+    async fn test_email_change_and_password() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+
+        let [ada, _bob] = db.create_users(["Ada", "Bob"]).await;
+        let day = Duration::days(1);
+        assert!(matches!(
+            users
+                .request_email_change(ada.id, "BOB@example.com", day)
+                .await,
+            Err(UserError::EmailTaken)
+        ));
+
+        // A new request replaces the earlier one
+        let first = users
+            .request_email_change(ada.id, "ada@example.org", day)
+            .await
+            .unwrap();
+        let second = users
+            .request_email_change(ada.id, "ada@example.net", day)
+            .await
+            .unwrap();
+        assert!(
+            users
+                .get_email_change(&first.token)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let pending = users.pending_email_change(ada.id).await.unwrap().unwrap();
+        assert_eq!(pending.new_email, "ada@example.net");
+
+        let changed = users
+            .confirm_email_change(&second.token, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(changed.email, "ada@example.net");
+        assert!(matches!(
+            users.confirm_email_change(&second.token, &ctx).await,
+            Err(UserError::EmailChangeNotFound)
+        ));
+        let expired = users
+            .request_email_change(ada.id, "ada@example.com", -day)
+            .await
+            .unwrap();
+        assert!(
+            users
+                .get_email_change(&expired.token)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Changing the password ends the other sessions
+        let version = users.session_version(ada.id).await.unwrap().unwrap();
+        let new_version = users.change_password(ada.id, "hash", &ctx).await.unwrap();
+        assert_eq!(new_version, version + 1);
+        assert_eq!(
+            users.session_version(ada.id).await.unwrap(),
+            Some(new_version)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_account_hands_over_groups() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let users = UserService::new(db.pool.clone());
+        let groups = GroupService::new(db.pool.clone());
+        let members = MemberService::new(db.pool.clone());
+        let invitations = InvitationService::new(db.pool.clone());
+
+        let [ada, bob] = db.create_users(["Ada", "Bob"]).await;
+        let shared = groups
+            .create_with_owner("Readers".to_string(), Some(ada.id), &ctx)
+            .await
+            .unwrap();
+        let alone = groups
+            .create_with_owner("Writers".to_string(), Some(ada.id), &ctx)
+            .await
+            .unwrap();
+        members.join(shared.id, bob.id, &ctx).await.unwrap();
+        let invitation = NewInvitation {
+            email: Some("Ada@example.com".to_string()),
+            ..NewInvitation::default()
+        };
+        let invitation = invitations
+            .create(alone.id, invitation, &ctx)
+            .await
+            .unwrap();
+
+        let before = users.delete_account(ada.id, &ctx).await.unwrap();
+        assert_eq!(before.email, "ada@example.com");
+        let shared = groups.get_by_id(shared.id).await.unwrap().unwrap();
+        assert_eq!(shared.owner_id, Some(bob.id));
+        let successor = members.get(shared.id, bob.id).await.unwrap().unwrap();
+        assert_eq!(successor.role, MemberRole::Owner);
+        let alone = groups.get_by_id(alone.id).await.unwrap().unwrap();
+        assert_eq!(alone.owner_id, None);
+        assert!(groups.list_for_member(ada.id).await.unwrap().is_empty());
+        assert!(
+            invitations
+                .get_by_code(&invitation.code)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let deleted = users.get_by_id(ada.id).await.unwrap().unwrap();
+        assert_eq!(deleted.name, DELETED_USER_NAME);
+        assert_eq!(deleted.email, format!("deleted-{}@deleted.invalid", ada.id));
+        assert!(deleted.password_hash.is_none());
+        assert!(users.session_version(ada.id).await.unwrap().is_none());
+        assert!(matches!(
+            users.delete_account(ada.id, &ctx).await,
+            Err(UserError::UserNotFound)
+        ));
+    }
+}
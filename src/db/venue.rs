@@ -0,0 +1,308 @@
+use crate::db::audit::{self, AuditContext};
+use crate::db::connection::{DatabaseError, DbPool};
+use crate::db::event::MAX_LOCATION_LENGTH;
+use crate::db::models::Venue;
+use crate::geo::Point;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use thiserror::Error;
+
+// Entity name used in the audit log
+const ENTITY: &str = "venue";
+
+pub const MAX_NAME_LENGTH: usize = 200;
+pub const MAX_ADDRESS_LENGTH: usize = 500;
+pub const MAX_NOTES_LENGTH: usize = 2000;
+pub const MAX_CAPACITY: i32 = 100_000;
+
+#[derive(Debug, Error)]
+pub enum VenueError {
+    #[error("Venue not found")]
+    NotFound,
+    #[error("Database error: {0}")]
+    DbError(#[from] DatabaseError),
+    #[error("SQL error: {0}")]
+    SqlError(#[from] sqlx::Error),
+}
+
+// A venue to be created or the new details of one
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewVenue {
+    pub name: String,
+    pub address: String,
+    pub point: Option<Point>,
+    pub accessibility_notes: String,
+    pub capacity: Option<i32>,
+    pub directions: String,
+}
+
+// How events at a venue give their location: its name and address, cut to
+// the length of an event's location
+pub fn venue_label(name: &str, address: &str) -> String {
+    let label = if address.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}, {}", name, address)
+    };
+    label.chars().take(MAX_LOCATION_LENGTH).collect()
+}
+
+impl Venue {
+    pub fn label(&self) -> String {
+        venue_label(&self.name, &self.address)
+    }
+
+    pub fn point(&self) -> Option<Point> {
+        Point::from_columns(self.latitude, self.longitude)
+    }
+}
+
+pub struct VenueService {
+    pool: DbPool,
+}
+
+impl VenueService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    async fn lock(conn: &mut PgConnection, id: i32) -> Result<Venue, VenueError> {
+        sqlx::query_as::<_, Venue>("SELECT * FROM venues WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(conn)
+            .await?
+            .ok_or(VenueError::NotFound)
+    }
+
+    pub async fn get(&self, id: i32) -> Result<Option<Venue>, VenueError> {
+        let venue = sqlx::query_as::<_, Venue>("SELECT * FROM venues WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(venue)
+    }
+
+    pub async fn list_for_group(&self, group_id: i32) -> Result<Vec<Venue>, VenueError> {
+        let venues = sqlx::query_as::<_, Venue>(
+            "SELECT * FROM venues WHERE group_id = $1 ORDER BY lower(name), id",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(venues)
+    }
+
+    pub async fn create(
+        &self,
+        group_id: i32,
+        venue: &NewVenue,
+        ctx: &AuditContext,
+    ) -> Result<Venue, VenueError> {
+        let mut tx = self.pool.begin().await?;
+        let created = sqlx::query_as::<_, Venue>(
+            "INSERT INTO venues (group_id, name, address, latitude, longitude, accessibility_notes, \
+                                 capacity, directions) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        )
+        .bind(group_id)
+        .bind(&venue.name)
+        .bind(&venue.address)
+        .bind(venue.point.map(|point| point.latitude))
+        .bind(venue.point.map(|point| point.longitude))
+        .bind(&venue.accessibility_notes)
+        .bind(venue.capacity)
+        .bind(&venue.directions)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "create",
+            ENTITY,
+            created.id,
+            None,
+            Some(&created),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    // Change the details, and the location of the events at the venue that
+    // are not over yet
+    pub async fn update(
+        &self,
+        id: i32,
+        venue: &NewVenue,
+        now: DateTime<Utc>,
+        ctx: &AuditContext,
+    ) -> Result<Venue, VenueError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        let after = sqlx::query_as::<_, Venue>(
+            "UPDATE venues SET name = $1, address = $2, latitude = $3, longitude = $4, \
+             accessibility_notes = $5, capacity = $6, directions = $7, updated_at = $8 \
+             WHERE id = $9 RETURNING *",
+        )
+        .bind(&venue.name)
+        .bind(&venue.address)
+        .bind(venue.point.map(|point| point.latitude))
+        .bind(venue.point.map(|point| point.longitude))
+        .bind(&venue.accessibility_notes)
+        .bind(venue.capacity)
+        .bind(&venue.directions)
+        .bind(now)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE events SET location = $1, latitude = $2, longitude = $3 \
+             WHERE venue_id = $4 AND deleted_at IS NULL AND COALESCE(ends_at, starts_at) >= $5",
+        )
+        .bind(after.label())
+        .bind(after.latitude)
+        .bind(after.longitude)
+        .bind(id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            ctx,
+            "update",
+            ENTITY,
+            id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(after)
+    }
+
+    // Events at the venue keep their copy of its location
+    pub async fn delete(&self, id: i32, ctx: &AuditContext) -> Result<Venue, VenueError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::lock(&mut tx, id).await?;
+
+        sqlx::query("DELETE FROM venues WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        audit::record(&mut tx, ctx, "delete", ENTITY, id, Some(&before), None).await?;
+        tx.commit().await?;
+
+        Ok(before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::create_test_pool;
+    use crate::db::event::{EventService, NewEvent};
+    use crate::db::group::GroupService;
+    use chrono::Duration;
+
+    fn venue(name: &str, address: &str) -> NewVenue {
+        NewVenue {
+            name: name.to_string(),
+            address: address.to_string(),
+            point: Point::new(51.5, -0.12),
+            accessibility_notes: "Step-free entrance".to_string(),
+            capacity: Some(40),
+            directions: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_venue_label() {
+        assert_eq!(venue_label("The Crown", ""), "The Crown");
+        assert_eq!(
+            venue_label("The Crown", "1 High Street"),
+            "The Crown, 1 High Street"
+        );
+        let long = "x".repeat(MAX_ADDRESS_LENGTH);
+        assert_eq!(
+            venue_label("The Crown", &long).chars().count(),
+            MAX_LOCATION_LENGTH
+        );
+    }
+
+    #[tokio::test]
+    async fn test_updates_reach_upcoming_events_only() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+        let venues = VenueService::new(db.pool.clone());
+
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let crown = venues
+            .create(group.id, &venue("the Crown", "1 High Street"), &ctx)
+            .await
+            .unwrap();
+        venues
+            .create(group.id, &venue("Library", ""), &ctx)
+            .await
+            .unwrap();
+        let names: Vec<String> = venues
+            .list_for_group(group.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| v.name)
+            .collect();
+        assert_eq!(names, ["Library", "the Crown"]);
+
+        let now = Utc::now();
+        let mut ids = Vec::new();
+        for starts_at in [now - Duration::days(7), now + Duration::days(7)] {
+            let event = NewEvent {
+                title: "Meetup".to_string(),
+                starts_at,
+                location: Some(crown.label()),
+                point: crown.point(),
+                venue_id: Some(crown.id),
+                ..Default::default()
+            };
+            ids.push(events.create(group.id, event, &ctx).await.unwrap().id);
+        }
+
+        let moved = venues
+            .update(crown.id, &venue("The Crown", "2 Low Street"), now, &ctx)
+            .await
+            .unwrap();
+        let past = events.get(ids[0]).await.unwrap().unwrap();
+        assert_eq!(past.location.as_deref(), Some("the Crown, 1 High Street"));
+        let upcoming = events.get(ids[1]).await.unwrap().unwrap();
+        assert_eq!(
+            upcoming.location.as_deref(),
+            Some("The Crown, 2 Low Street")
+        );
+        assert_eq!(moved.capacity, Some(40));
+
+        // Events keep the location of a deleted venue
+        venues.delete(crown.id, &ctx).await.unwrap();
+        let upcoming = events.get(ids[1]).await.unwrap().unwrap();
+        assert_eq!(upcoming.venue_id, None);
+        assert_eq!(
+            upcoming.location.as_deref(),
+            Some("The Crown, 2 Low Street")
+        );
+        assert!(venues.get(crown.id).await.unwrap().is_none());
+        assert!(matches!(
+            venues.update(crown.id, &venue("Gone", ""), now, &ctx).await,
+            Err(VenueError::NotFound)
+        ));
+    }
+}
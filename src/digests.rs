@@ -0,0 +1,333 @@
+use crate::config::ServerConfig;
+use crate::db::{
+    digest::{Digest, DigestError, DigestMember, DigestService, Subscriber},
+    job::{JobError, JobService, NewJob},
+    models::{DigestFrequency, User},
+};
+use crate::mail::Email;
+use crate::reminders::local_time;
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+use thiserror::Error;
+
+// Scheduled job queueing the digests that are due
+pub const SCHEDULE_DIGESTS_JOB: &str = "schedule_digests";
+// Sends one user's digest
+pub const SEND_DIGEST_JOB: &str = "send_digest";
+
+#[derive(Debug, Error)]
+pub enum DigestJobError {
+    #[error("Digest error: {0}")]
+    Digest(#[from] DigestError),
+    #[error("Job error: {0}")]
+    Job(#[from] JobError),
+    #[error("Template error: {0}")]
+    Template(#[from] tera::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DigestPayload {
+    pub user_id: i32,
+    // The send time the digest is for, see latest_slot
+    pub slot: DateTime<Utc>,
+}
+
+impl DigestPayload {
+    // One digest per user and slot, however often the schedule runs
+    fn unique_key(&self) -> String {
+        format!("digest:{}:{}", self.user_id, self.slot.timestamp())
+    }
+}
+
+// `hour` o'clock on `date` in the time zone. Times skipped by a daylight
+// saving change are taken as UTC.
+fn local_hour(tz: Tz, date: NaiveDate, hour: u32) -> Option<DateTime<Utc>> {
+    let naive = date.and_hms_opt(hour, 0, 0)?;
+    let at = tz
+        .from_local_datetime(&naive)
+        .earliest()
+        .unwrap_or_else(|| tz.from_utc_datetime(&naive));
+    Some(at.with_timezone(&Utc))
+}
+
+// The most recent time a digest of this frequency was due: `hour` o'clock in
+// the user's time zone, every day or every Monday
+fn latest_slot(
+    frequency: DigestFrequency,
+    tz: Tz,
+    hour: u32,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let step = Days::new(frequency.period()?.num_days() as u64);
+    let mut date = now.with_timezone(&tz).date_naive();
+    if frequency == DigestFrequency::Weekly {
+        date = date - Days::new(date.weekday().num_days_from_monday() as u64);
+    }
+
+    let slot = local_hour(tz, date, hour)?;
+    if slot <= now {
+        Some(slot)
+    } else {
+        local_hour(tz, date - step, hour)
+    }
+}
+
+// The slot the subscriber's digest is due for, if it was not sent yet
+fn due_slot(subscriber: &Subscriber, hour: u32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let slot = latest_slot(subscriber.frequency, subscriber.user.tz(), hour, now)?;
+    match subscriber.last_sent_at {
+        Some(sent) if sent >= slot => None,
+        _ => Some(slot),
+    }
+}
+
+// Queue the digests that are due. Returns the number queued.
+pub async fn schedule_digests(
+    digests: &DigestService,
+    jobs: &JobService,
+    hour: u32,
+    now: DateTime<Utc>,
+) -> Result<usize, DigestJobError> {
+    let mut queued = 0;
+    for subscriber in digests.subscribers().await? {
+        let Some(slot) = due_slot(&subscriber, hour, now) else {
+            continue;
+        };
+        let payload = DigestPayload {
+            user_id: subscriber.user.id,
+            slot,
+        };
+        let job = NewJob::new(SEND_DIGEST_JOB, &payload)?.unique_key(payload.unique_key());
+        if jobs.enqueue(job).await?.is_some() {
+            queued += 1;
+        }
+    }
+
+    Ok(queued)
+}
+
+#[derive(Debug, Serialize)]
+struct EventView {
+    title: String,
+    group_name: String,
+    starts_at: String,
+    location: Option<String>,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ThreadView {
+    title: String,
+    group_name: String,
+    author_name: Option<String>,
+    reply_count: i32,
+    url: String,
+}
+
+// New members of one group
+#[derive(Debug, PartialEq, Serialize)]
+struct GroupMembersView {
+    group_name: String,
+    url: String,
+    names: Vec<String>,
+}
+
+// Members come ordered by group
+fn members_by_group(members: &[DigestMember], server: &ServerConfig) -> Vec<GroupMembersView> {
+    let mut groups: Vec<(i32, GroupMembersView)> = Vec::new();
+    for member in members {
+        match groups.last_mut() {
+            Some((group_id, view)) if *group_id == member.group_id => {
+                view.names.push(member.name.clone())
+            }
+            _ => groups.push((
+                member.group_id,
+                GroupMembersView {
+                    group_name: member.group_name.clone(),
+                    url: server.url(&format!("/groups/{}/events", member.group_id)),
+                    names: vec![member.name.clone()],
+                },
+            )),
+        }
+    }
+    groups.into_iter().map(|(_, view)| view).collect()
+}
+
+fn digest_email(
+    tmpl: &Tera,
+    server: &ServerConfig,
+    user: &User,
+    frequency: DigestFrequency,
+    digest: &Digest,
+    unsubscribe_url: &str,
+) -> Result<Email, tera::Error> {
+    let events: Vec<EventView> = digest
+        .events
+        .iter()
+        .map(|event| EventView {
+            title: event.title.clone(),
+            group_name: event.group_name.clone(),
+            starts_at: local_time(event.starts_at, user),
+            location: event.location.clone(),
+            url: server.url(&format!("/events/{}", event.id)),
+        })
+        .collect();
+    let threads: Vec<ThreadView> = digest
+        .threads
+        .iter()
+        .map(|thread| ThreadView {
+            title: thread.title.clone(),
+            group_name: thread.group_name.clone(),
+            author_name: thread.author_name.clone(),
+            reply_count: thread.reply_count,
+            url: server.url(&format!(
+                "/groups/{}/discussions/{}",
+                thread.group_id, thread.id
+            )),
+        })
+        .collect();
+    let period = match frequency {
+        DigestFrequency::Daily => "daily",
+        _ => "weekly",
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("name", &user.name);
+    ctx.insert("period", period);
+    ctx.insert("events", &events);
+    ctx.insert("threads", &threads);
+    ctx.insert("members", &members_by_group(&digest.members, server));
+    ctx.insert("settings_url", &server.url("/notifications/settings"));
+    ctx.insert("unsubscribe_url", unsubscribe_url);
+
+    Ok(Email {
+        to: user.email.clone(),
+        subject: format!("Your {} digest", period),
+        text: tmpl.render("emails/digest.txt", &ctx)?,
+        html: Some(tmpl.render("emails/digest.html", &ctx)?),
+        unsubscribe_url: Some(unsubscribe_url.to_string()),
+    })
+}
+
+// Collect and email one user's digest. Nothing is sent when nothing happened
+// in their groups, or when they unsubscribed since the digest was queued.
+pub struct DigestSender {
+    pub digests: DigestService,
+    pub jobs: JobService,
+    pub tmpl: Tera,
+    pub server: ServerConfig,
+}
+
+impl DigestSender {
+    // Returns whether an email was queued
+    pub async fn send(
+        &self,
+        payload: &DigestPayload,
+        now: DateTime<Utc>,
+    ) -> Result<bool, DigestJobError> {
+        let Some(subscriber) = self.digests.subscriber(payload.user_id).await? else {
+            return Ok(false);
+        };
+        let Some(period) = subscriber.frequency.period() else {
+            return Ok(false);
+        };
+        if subscriber.last_sent_at >= Some(payload.slot) {
+            return Ok(false);
+        }
+
+        // Since the previous digest, at most one period back
+        let since = (payload.slot - period).max(subscriber.last_sent_at.unwrap_or_default());
+        let digest = self
+            .digests
+            .collect(subscriber.user.id, since, now, now + period)
+            .await?;
+
+        let queued = !digest.is_empty();
+        if queued {
+            let settings = self.digests.settings(subscriber.user.id).await?;
+            let unsubscribe_url = self.server.url(&format!(
+                "/digest/unsubscribe/{}",
+                settings.unsubscribe_token
+            ));
+            let email = digest_email(
+                &self.tmpl,
+                &self.server,
+                &subscriber.user,
+                subscriber.frequency,
+                &digest,
+                &unsubscribe_url,
+            )?;
+            // The key keeps a retried job from sending the digest twice
+            self.jobs
+                .enqueue(email.job()?.unique_key(payload.unique_key() + ":email"))
+                .await?;
+        }
+        self.digests.mark_sent(subscriber.user.id, now).await?;
+
+        Ok(queued)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_latest_slot() {
+        use DigestFrequency::*;
+        // Wednesday
+        let now = at("2026-03-04T10:00:00Z");
+
+        assert_eq!(
+            latest_slot(Daily, Tz::UTC, 8, now),
+            Some(at("2026-03-04T08:00:00Z"))
+        );
+        assert_eq!(
+            latest_slot(Daily, Tz::UTC, 11, now),
+            Some(at("2026-03-03T11:00:00Z"))
+        );
+        // 8:00 in Paris is 7:00 UTC
+        assert_eq!(
+            latest_slot(Daily, Tz::Europe__Paris, 8, now),
+            Some(at("2026-03-04T07:00:00Z"))
+        );
+        // 8:00 in Tokyo is 23:00 UTC the day before
+        assert_eq!(
+            latest_slot(Daily, Tz::Asia__Tokyo, 8, now),
+            Some(at("2026-03-03T23:00:00Z"))
+        );
+        assert_eq!(
+            latest_slot(Weekly, Tz::UTC, 8, now),
+            Some(at("2026-03-02T08:00:00Z"))
+        );
+        // Monday before the send hour: the previous Monday
+        assert_eq!(
+            latest_slot(Weekly, Tz::UTC, 8, at("2026-03-02T07:00:00Z")),
+            Some(at("2026-02-23T08:00:00Z"))
+        );
+        assert_eq!(latest_slot(Never, Tz::UTC, 8, now), None);
+    }
+
+    #[test]
+    fn test_members_by_group() {
+        let server = ServerConfig::default();
+        let member = |group_id, name: &str| DigestMember {
+            group_id,
+            group_name: format!("Group {}", group_id),
+            name: name.to_string(),
+        };
+        let members = [member(1, "Ada"), member(1, "Alan"), member(2, "Grace")];
+
+        let groups = members_by_group(&members, &server);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].names, vec!["Ada", "Alan"]);
+        assert_eq!(groups[1].group_name, "Group 2");
+        assert_eq!(groups[1].names, vec!["Grace"]);
+    }
+}
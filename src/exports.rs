@@ -0,0 +1,277 @@
+use crate::config::ServerConfig;
+use crate::db::{
+    export::{DataExportError, DataExportService},
+    job::{JobError, JobService},
+    models::{DataExport, DataExportStatus},
+    user::{UserError, UserService},
+};
+use crate::images;
+use crate::mail::Email;
+use crate::storage::{Blob, BlobError, BlobStore};
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use tera::Tera;
+use thiserror::Error;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+// Builds one requested export
+pub const EXPORT_USER_DATA_JOB: &str = "export_user_data";
+// Scheduled job deleting the exports whose link has expired
+pub const PURGE_DATA_EXPORTS_JOB: &str = "purge_data_exports";
+
+#[derive(Debug, Error)]
+pub enum ExportJobError {
+    #[error("Export error: {0}")]
+    Export(#[from] DataExportError),
+    #[error("User error: {0}")]
+    User(#[from] UserError),
+    #[error("Job error: {0}")]
+    Job(#[from] JobError),
+    #[error("Storage error: {0}")]
+    Storage(#[from] BlobError),
+    #[error("Archive error: {0}")]
+    Archive(#[from] zip::result::ZipError),
+    #[error("Template error: {0}")]
+    Template(#[from] tera::Error),
+    #[error("Archive task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportPayload {
+    pub export_id: i32,
+}
+
+impl ExportPayload {
+    pub fn unique_key(&self) -> String {
+        format!("data_export:{}", self.export_id)
+    }
+}
+
+// Where an export's archive is stored. Not an image key, so /media does not
+// serve it and it can only be downloaded through its link.
+pub fn blob_key(export: &DataExport) -> String {
+    format!("exports/{}.zip", export.token)
+}
+
+// A ZIP archive of the files, compressed and dated `modified`
+pub fn archive(
+    files: &[(String, Vec<u8>)],
+    modified: DateTime<Utc>,
+) -> Result<Vec<u8>, zip::result::ZipError> {
+    // ZIP dates start in 1980 and have no time zone
+    let modified = zip::DateTime::from_date_and_time(
+        modified.year().clamp(1980, 2107) as u16,
+        modified.month() as u8,
+        modified.day() as u8,
+        modified.hour() as u8,
+        modified.minute() as u8,
+        modified.second() as u8,
+    )
+    .unwrap_or_default();
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(modified)
+        .unix_permissions(0o644);
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, bytes) in files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(bytes)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+// JSON from the database, indented for people reading the files
+fn pretty(json: &str) -> Vec<u8> {
+    serde_json::from_str::<serde_json::Value>(json)
+        .and_then(|value| serde_json::to_vec_pretty(&value))
+        .unwrap_or_else(|_| json.as_bytes().to_vec())
+}
+
+fn ready_email(
+    tmpl: &Tera,
+    to: &str,
+    name: &str,
+    url: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<Email, tera::Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("name", name);
+    ctx.insert("url", url);
+    ctx.insert(
+        "expires_at",
+        &expires_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+    );
+
+    Ok(Email {
+        to: to.to_string(),
+        subject: "Your data export is ready".to_string(),
+        text: tmpl.render("emails/data_export_ready.txt", &ctx)?,
+        html: None,
+        unsubscribe_url: None,
+    })
+}
+
+pub struct ExportBuilder {
+    pub exports: DataExportService,
+    pub users: UserService,
+    pub jobs: JobService,
+    pub store: Arc<dyn BlobStore>,
+    pub tmpl: Tera,
+    pub server: ServerConfig,
+    // How long the download link works
+    pub keep_for: Duration,
+}
+
+impl ExportBuilder {
+    // Returns the export once it is ready, None when there was nothing to do
+    pub async fn build(
+        &self,
+        payload: &ExportPayload,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DataExport>, ExportJobError> {
+        let export = match self.exports.get(payload.export_id).await {
+            Ok(export) => export,
+            Err(DataExportError::NotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if export.status != DataExportStatus::Pending {
+            return Ok(None);
+        }
+        let Some(user) = self
+            .users
+            .get_by_id(export.user_id)
+            .await?
+            .filter(|user| user.deleted_at.is_none())
+        else {
+            return Ok(None);
+        };
+
+        let mut files: Vec<(String, Vec<u8>)> = self
+            .exports
+            .collect(user.id)
+            .await?
+            .into_iter()
+            .map(|(name, json)| (name.to_string(), pretty(&json)))
+            .collect();
+        if let Some(avatar_key) = &user.avatar_key {
+            let key = images::variant_key(avatar_key, "large");
+            if let Some(blob) = self.store.get(&key).await? {
+                files.push(("avatar.jpg".to_string(), blob.bytes.to_vec()));
+            }
+        }
+
+        // Compressing is CPU bound, keep it off the async workers
+        let zip = tokio::task::spawn_blocking(move || archive(&files, now)).await??;
+        let key = blob_key(&export);
+        let blob = Blob {
+            bytes: zip.into(),
+            content_type: "application/zip".to_string(),
+        };
+        self.store.put(&key, blob).await?;
+
+        let expires_at = now + self.keep_for;
+        let export = self
+            .exports
+            .mark_ready(export.id, &key, now, expires_at)
+            .await?;
+
+        let url = self
+            .server
+            .url(&format!("/account/export/{}", export.token));
+        let email = ready_email(&self.tmpl, &user.email, &user.name, &url, expires_at)?;
+        // The key keeps a retried job from sending the email twice
+        self.jobs
+            .enqueue(email.job()?.unique_key(payload.unique_key() + ":email"))
+            .await?;
+
+        Ok(Some(export))
+    }
+}
+
+// Delete the archives and records of exports whose link has expired
+pub async fn purge_expired_exports(
+    exports: &DataExportService,
+    store: &dyn BlobStore,
+    now: DateTime<Utc>,
+) -> Result<usize, ExportJobError> {
+    let expired = exports.list_expired(now).await?;
+    for export in &expired {
+        if let Some(key) = &export.blob_key {
+            store.delete(key).await?;
+        }
+        exports.delete(export.id).await?;
+    }
+
+    Ok(expired.len())
+}
+
+// Delete every export of a user, archives included. Best effort: an archive
+// that fails to delete is left behind, but nothing links to it any more.
+pub async fn delete_user_exports(exports: &DataExportService, store: &dyn BlobStore, user_id: i32) {
+    let deleted = match exports.delete_for_user(user_id).await {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            eprintln!("Failed to delete the data exports of {}: {}", user_id, e);
+            return;
+        }
+    };
+    for key in deleted.iter().filter_map(|export| export.blob_key.as_ref()) {
+        if let Err(e) = store.delete(key).await {
+            eprintln!("Failed to delete {}: {}", key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_archive() {
+        let files = vec![
+            (
+                "profile.json".to_string(),
+                pretty(r#"{"id":7,"name":"Ada"}"#),
+            ),
+            ("posts.json".to_string(), pretty("[]")),
+        ];
+        let bytes = archive(&files, Utc::now()).unwrap();
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.len(), 2);
+        let mut profile = String::new();
+        zip.by_name("profile.json")
+            .unwrap()
+            .read_to_string(&mut profile)
+            .unwrap();
+        assert_eq!(profile, "{\n  \"id\": 7,\n  \"name\": \"Ada\"\n}");
+        assert_eq!(
+            zip.by_name("posts.json").unwrap().compression(),
+            CompressionMethod::Deflated
+        );
+    }
+
+    #[test]
+    fn test_blob_key_is_private() {
+        let export = DataExport {
+            id: 1,
+            user_id: 2,
+            status: DataExportStatus::Pending,
+            token: "0123456789abcdef0123456789abcdef".to_string(),
+            blob_key: None,
+            created_at: Utc::now(),
+            completed_at: None,
+            expires_at: None,
+        };
+        let key = blob_key(&export);
+
+        assert!(crate::storage::valid_key(&key));
+        assert!(!images::is_image_key(&key));
+    }
+}
@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+
+// Mean radius, as used by the haversine formula
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+// Length of one degree of latitude
+pub const KM_PER_DEGREE: f64 = 111.195;
+
+// A position in WGS84 degrees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Point {
+    // None unless both coordinates are in range
+    pub fn new(latitude: f64, longitude: f64) -> Option<Self> {
+        ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)).then_some(
+            Self {
+                latitude,
+                longitude,
+            },
+        )
+    }
+
+    // A point from optional columns, when both are set
+    pub fn from_columns(latitude: Option<f64>, longitude: Option<f64>) -> Option<Self> {
+        Self::new(latitude?, longitude?)
+    }
+
+    // Great-circle distance, the same formula as the SQL in db::geo
+    pub fn distance_km(&self, other: &Point) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlng = (other.longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlng / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+
+    // The latitudes within `radius_km`, to narrow down a search before
+    // computing distances
+    pub fn latitude_band(&self, radius_km: f64) -> (f64, f64) {
+        let delta = radius_km / KM_PER_DEGREE;
+        (
+            (self.latitude - delta).max(-90.0),
+            (self.latitude + delta).min(90.0),
+        )
+    }
+
+    // A link to the point on OpenStreetMap
+    pub fn map_url(&self) -> String {
+        format!(
+            "https://www.openstreetmap.org/?mlat={lat:.5}&mlon={lng:.5}#map=15/{lat:.5}/{lng:.5}",
+            lat = self.latitude,
+            lng = self.longitude
+        )
+    }
+}
+
+// What a typed place is: coordinates as "lat,lng", or the name of a city to
+// look up, optionally followed by a country code as in "Portland, US"
+#[derive(Debug, Clone, PartialEq)]
+pub enum Place {
+    Coordinates(Point),
+    City {
+        name: String,
+        country_code: Option<String>,
+    },
+}
+
+pub fn parse_place(input: &str) -> Result<Place, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Enter a city or coordinates".to_string());
+    }
+
+    let parts: Vec<&str> = input.split(',').map(str::trim).collect();
+    if let [latitude, longitude] = parts[..]
+        && let (Ok(latitude), Ok(longitude)) = (latitude.parse::<f64>(), longitude.parse::<f64>())
+    {
+        return Point::new(latitude, longitude)
+            .map(Place::Coordinates)
+            .ok_or_else(|| "Coordinates are out of range".to_string());
+    }
+
+    match parts[..] {
+        [name] => Ok(Place::City {
+            name: name.to_string(),
+            country_code: None,
+        }),
+        [name, country]
+            if country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic()) =>
+        {
+            Ok(Place::City {
+                name: name.to_string(),
+                country_code: Some(country.to_ascii_uppercase()),
+            })
+        }
+        _ => Err(format!("Unknown place: {}", input)),
+    }
+}
+
+// A city as listed in a GeoNames dump (cities15000.txt and the like)
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewCity {
+    pub name: String,
+    pub country_code: String,
+    pub point: Point,
+    pub population: i64,
+}
+
+// One tab separated line of a GeoNames dump: the name is column 2, the
+// coordinates columns 5 and 6, the country code 9 and the population 15
+pub fn parse_geonames_line(line: &str) -> Option<NewCity> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() < 15 {
+        return None;
+    }
+    let point = Point::new(columns[4].parse().ok()?, columns[5].parse().ok()?)?;
+    let country_code = columns[8];
+    if columns[1].is_empty() || country_code.len() != 2 {
+        return None;
+    }
+
+    Some(NewCity {
+        name: columns[1].to_string(),
+        country_code: country_code.to_string(),
+        point,
+        population: columns[14].parse().unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        let berlin = Point::new(52.52437, 13.41053).unwrap();
+        let paris = Point::new(48.85341, 2.3488).unwrap();
+        let distance = berlin.distance_km(&paris);
+        assert!((distance - 877.5).abs() < 1.0, "{}", distance);
+        assert_eq!(berlin.distance_km(&berlin), 0.0);
+
+        // Across the antimeridian
+        let a = Point::new(0.0, 179.5).unwrap();
+        let b = Point::new(0.0, -179.5).unwrap();
+        assert!((a.distance_km(&b) - KM_PER_DEGREE).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_latitude_band() {
+        let point = Point::new(89.5, 0.0).unwrap();
+        let (south, north) = point.latitude_band(KM_PER_DEGREE);
+        assert!((south - 88.5).abs() < 1e-9);
+        assert_eq!(north, 90.0);
+    }
+
+    #[test]
+    fn test_parse_place() {
+        assert_eq!(
+            parse_place(" 52.52, 13.405 "),
+            Ok(Place::Coordinates(Point {
+                latitude: 52.52,
+                longitude: 13.405
+            }))
+        );
+        assert!(parse_place("95,10").is_err());
+        assert!(parse_place("").is_err());
+        assert_eq!(
+            parse_place("Portland, us"),
+            Ok(Place::City {
+                name: "Portland".to_string(),
+                country_code: Some("US".to_string())
+            })
+        );
+        assert_eq!(
+            parse_place("New York City"),
+            Ok(Place::City {
+                name: "New York City".to_string(),
+                country_code: None
+            })
+        );
+        assert!(parse_place("Springfield, Illinois, USA").is_err());
+    }
+
+    #[test]
+    fn test_parse_geonames_line() {
+        let line = "2950159\tBerlin\tBerlin\tBerlim,Berlín\t52.52437\t13.41053\tP\tPPLC\tDE\t\t16\t00\t11000\t11000000\t3426354\t\t74\tEurope/Berlin\t2022-03-09";
+        assert_eq!(
+            parse_geonames_line(line),
+            Some(NewCity {
+                name: "Berlin".to_string(),
+                country_code: "DE".to_string(),
+                point: Point {
+                    latitude: 52.52437,
+                    longitude: 13.41053
+                },
+                population: 3426354,
+            })
+        );
+        assert_eq!(parse_geonames_line("Berlin\t52.5"), None);
+    }
+}
@@ -0,0 +1,189 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use thiserror::Error;
+
+// Largest width or height accepted, which bounds the memory decoding takes
+const MAX_DIMENSION: u32 = 8000;
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("The file is not a PNG, JPEG, GIF or WebP image")]
+    UnsupportedFormat,
+    #[error("The image could not be read: {0}")]
+    Decode(#[from] image::ImageError),
+}
+
+// What an uploaded image is used for, which decides its sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Avatar,
+    Cover,
+}
+
+impl ImageKind {
+    // Variant names with their width and height
+    pub fn variants(&self) -> &'static [(&'static str, u32, u32)] {
+        match self {
+            ImageKind::Avatar => &[("small", 64, 64), ("medium", 128, 128), ("large", 256, 256)],
+            ImageKind::Cover => &[
+                ("small", 480, 160),
+                ("medium", 960, 320),
+                ("large", 1920, 640),
+            ],
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            ImageKind::Avatar => "avatars",
+            ImageKind::Cover => "covers",
+        }
+    }
+}
+
+// The format of an image, from its first bytes rather than the file name or
+// the content type the browser sent
+pub fn sniff(bytes: &[u8]) -> Option<ImageFormat> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(ImageFormat::Png)
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some(ImageFormat::Jpeg)
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some(ImageFormat::Gif)
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some(ImageFormat::WebP)
+    } else {
+        None
+    }
+}
+
+// Key shared by all variants of an upload. Derived from the content, so the
+// files never change once written and can be cached forever.
+pub fn base_key(kind: ImageKind, bytes: &[u8]) -> String {
+    format!("{}/{}", kind.prefix(), hex::encode(Sha256::digest(bytes)))
+}
+
+// Whether a key belongs to an uploaded image, as opposed to other stored files
+pub fn is_image_key(key: &str) -> bool {
+    [ImageKind::Avatar, ImageKind::Cover]
+        .iter()
+        .any(|kind| key.starts_with(&format!("{}/", kind.prefix())))
+}
+
+pub fn variant_key(base: &str, variant: &str) -> String {
+    format!("{}-{}.jpg", base, variant)
+}
+
+// Every variant key of an upload
+pub fn variant_keys(kind: ImageKind, base: &str) -> Vec<String> {
+    kind.variants()
+        .iter()
+        .map(|(variant, _, _)| variant_key(base, variant))
+        .collect()
+}
+
+// Transparent pixels become white, as JPEG has no alpha channel
+fn flatten(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+// Decode an upload and produce the JPEG of each variant, cropped to fill its
+// size. CPU heavy, so run it off the async workers.
+pub fn thumbnails(kind: ImageKind, bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, ImageError> {
+    let format = sniff(bytes).ok_or(ImageError::UnsupportedFormat)?;
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    reader.limits(limits);
+    let image = reader.decode()?;
+
+    let mut thumbnails = Vec::new();
+    for (variant, width, height) in kind.variants() {
+        let resized = image.resize_to_fill(*width, *height, FilterType::Lanczos3);
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode_image(&flatten(&resized))?;
+        thumbnails.push((variant.to_string(), jpeg));
+    }
+
+    Ok(thumbnails)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbaImage::from_pixel(width, height, image::Rgba([200, 0, 0, 0]));
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(&png(1, 1)), Some(ImageFormat::Png));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0rest"), Some(ImageFormat::Jpeg));
+        assert_eq!(sniff(b"GIF89a..."), Some(ImageFormat::Gif));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some(ImageFormat::WebP));
+        assert_eq!(sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_thumbnails() {
+        let variants = thumbnails(ImageKind::Cover, &png(300, 300)).unwrap();
+        let sizes: Vec<(String, u32, u32)> = variants
+            .iter()
+            .map(|(variant, jpeg)| {
+                let image = image::load_from_memory(jpeg).unwrap();
+                (variant.clone(), image.width(), image.height())
+            })
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                ("small".to_string(), 480, 160),
+                ("medium".to_string(), 960, 320),
+                ("large".to_string(), 1920, 640),
+            ]
+        );
+
+        // Fully transparent pixels come out white
+        let small = image::load_from_memory(&variants[0].1).unwrap().to_rgb8();
+        assert!(small.get_pixel(10, 10).0.iter().all(|c| *c > 250));
+
+        assert!(matches!(
+            thumbnails(ImageKind::Avatar, b"not an image"),
+            Err(ImageError::UnsupportedFormat)
+        ));
+        // Right magic bytes, broken content
+        assert!(matches!(
+            thumbnails(ImageKind::Avatar, b"\x89PNG\r\n\x1a\nbroken"),
+            Err(ImageError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn test_keys() {
+        let base = base_key(ImageKind::Avatar, b"image");
+        assert!(base.starts_with("avatars/"));
+        assert_eq!(base, base_key(ImageKind::Avatar, b"image"));
+        assert!(crate::storage::valid_key(&variant_key(&base, "small")));
+        assert_eq!(variant_keys(ImageKind::Avatar, &base).len(), 3);
+        assert!(is_image_key(&variant_key(&base, "small")));
+        assert!(!is_image_key("exports/abc.zip"));
+    }
+}
@@ -0,0 +1,315 @@
+pub mod worker;
+
+use crate::config::Config;
+use crate::db::{
+    connection::DbPool,
+    digest::DigestService,
+    event::EventService,
+    export::DataExportService,
+    group::GroupService,
+    job::{JobError, JobService},
+    models::{Job, JobSchedule},
+    notification::NotificationService,
+    rsvp::RsvpService,
+    user::UserService,
+};
+use crate::digests::{
+    DigestPayload, DigestSender, SCHEDULE_DIGESTS_JOB, SEND_DIGEST_JOB, schedule_digests,
+};
+use crate::exports::{
+    EXPORT_USER_DATA_JOB, ExportBuilder, ExportPayload, PURGE_DATA_EXPORTS_JOB,
+    purge_expired_exports,
+};
+use crate::mail::{Email, MailError, Mailer, SEND_EMAIL_JOB};
+use crate::markdown;
+use crate::reminders::{
+    ReminderPayload, ReminderSender, SCHEDULE_REMINDERS_JOB, SEND_REMINDER_JOB, schedule_reminders,
+};
+use crate::retention::purge_expired_groups;
+use crate::storage;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::future::BoxFuture;
+use std::{collections::HashMap, error::Error, future::Future, str::FromStr, sync::Arc};
+use thiserror::Error;
+
+pub use worker::{WorkerHandle, WorkerPool};
+
+// Error returned by a job handler; the job is retried until it runs out of attempts
+pub type HandlerError = Box<dyn Error + Send + Sync>;
+
+// Why the workers could not be started
+#[derive(Debug, Error)]
+pub enum WorkerError {
+    #[error("Invalid mail configuration: {0}")]
+    Mail(#[from] MailError),
+    #[error("Invalid templates: {0}")]
+    Templates(#[from] tera::Error),
+    #[error("{0}")]
+    Job(#[from] JobError),
+}
+
+type Handler = Arc<dyn Fn(Job) -> BoxFuture<'static, Result<(), HandlerError>> + Send + Sync>;
+
+// Maps job kinds to the async functions that run them
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    handlers: HashMap<String, Handler>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<F, Fut>(&mut self, kind: &str, handler: F)
+    where
+        F: Fn(Job) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), HandlerError>> + Send + 'static,
+    {
+        self.handlers.insert(
+            kind.to_string(),
+            Arc::new(move |job| Box::pin(handler(job)) as BoxFuture<'static, _>),
+        );
+    }
+
+    pub fn contains(&self, kind: &str) -> bool {
+        self.handlers.contains_key(kind)
+    }
+
+    fn get(&self, kind: &str) -> Option<Handler> {
+        self.handlers.get(kind).cloned()
+    }
+}
+
+// Handlers for every job kind the application knows about
+pub fn registry(pool: &DbPool, config: &Config) -> Result<JobRegistry, WorkerError> {
+    let mut registry = JobRegistry::new();
+
+    let groups = Arc::new(GroupService::new(pool.clone()));
+    let retention = config.retention.deleted_groups();
+    registry.register("purge_deleted_groups", move |_job| {
+        let groups = groups.clone();
+        async move {
+            for group in purge_expired_groups(&groups, retention).await? {
+                println!("Purged deleted group {} ({})", group.id, group.name);
+            }
+            Ok(())
+        }
+    });
+
+    let jobs = Arc::new(JobService::new(pool.clone()));
+    let keep_finished = config.retention.finished_jobs();
+    registry.register("cleanup_jobs", move |_job| {
+        let jobs = jobs.clone();
+        async move {
+            jobs.delete_finished_before(Utc::now() - keep_finished)
+                .await?;
+            Ok(())
+        }
+    });
+
+    let mailer = Arc::new(Mailer::from_config(&config.mail)?);
+    registry.register(SEND_EMAIL_JOB, move |job| {
+        let mailer = mailer.clone();
+        async move {
+            let email: Email = serde_json::from_str(&job.payload)?;
+            mailer.send(&email).await?;
+            Ok(())
+        }
+    });
+
+    let events = Arc::new(EventService::new(pool.clone()));
+    let jobs = Arc::new(JobService::new(pool.clone()));
+    let offsets = config.reminders.offsets();
+    registry.register(SCHEDULE_REMINDERS_JOB, move |_job| {
+        let (events, jobs, offsets) = (events.clone(), jobs.clone(), offsets.clone());
+        async move {
+            let queued = schedule_reminders(&events, &jobs, &offsets, Utc::now()).await?;
+            if queued > 0 {
+                println!("Queued {} event reminders", queued);
+            }
+            Ok(())
+        }
+    });
+
+    let mut tmpl = tera::Tera::new(&config.paths.templates_glob())?;
+    markdown::register_filters(&mut tmpl);
+    let reminders = Arc::new(ReminderSender {
+        events: EventService::new(pool.clone()),
+        rsvps: RsvpService::new(pool.clone()),
+        notifications: NotificationService::new(pool.clone()),
+        jobs: JobService::new(pool.clone()),
+        tmpl: tmpl.clone(),
+        server: config.server.clone(),
+    });
+    registry.register(SEND_REMINDER_JOB, move |job| {
+        let reminders = reminders.clone();
+        async move {
+            let payload: ReminderPayload = serde_json::from_str(&job.payload)?;
+            reminders.send(&payload, Utc::now()).await?;
+            Ok(())
+        }
+    });
+
+    let digests = Arc::new(DigestService::new(pool.clone()));
+    let jobs = Arc::new(JobService::new(pool.clone()));
+    let send_hour = config.digests.send_hour;
+    registry.register(SCHEDULE_DIGESTS_JOB, move |_job| {
+        let (digests, jobs) = (digests.clone(), jobs.clone());
+        async move {
+            let queued = schedule_digests(&digests, &jobs, send_hour, Utc::now()).await?;
+            if queued > 0 {
+                println!("Queued {} digests", queued);
+            }
+            Ok(())
+        }
+    });
+
+    let store = storage::from_config(&config.uploads);
+    let builder = Arc::new(ExportBuilder {
+        exports: DataExportService::new(pool.clone()),
+        users: UserService::new(pool.clone()),
+        jobs: JobService::new(pool.clone()),
+        store: store.clone(),
+        tmpl: tmpl.clone(),
+        server: config.server.clone(),
+        keep_for: config.retention.data_exports(),
+    });
+    registry.register(EXPORT_USER_DATA_JOB, move |job| {
+        let builder = builder.clone();
+        async move {
+            let payload: ExportPayload = serde_json::from_str(&job.payload)?;
+            let now = Utc::now();
+            match builder.build(&payload, now).await {
+                Ok(Some(export)) => println!("Data export {} is ready", export.id),
+                Ok(None) => {}
+                // The user is told on the account page instead of waiting forever
+                Err(e) if job.attempts >= job.max_attempts => {
+                    builder
+                        .exports
+                        .mark_failed(payload.export_id, now, now + builder.keep_for)
+                        .await?;
+                    return Err(e.into());
+                }
+                Err(e) => return Err(e.into()),
+            }
+            Ok(())
+        }
+    });
+
+    let exports = Arc::new(DataExportService::new(pool.clone()));
+    registry.register(PURGE_DATA_EXPORTS_JOB, move |_job| {
+        let (exports, store) = (exports.clone(), store.clone());
+        async move {
+            let purged = purge_expired_exports(&exports, &*store, Utc::now()).await?;
+            if purged > 0 {
+                println!("Purged {} expired data exports", purged);
+            }
+            Ok(())
+        }
+    });
+
+    let sender = Arc::new(DigestSender {
+        digests: DigestService::new(pool.clone()),
+        jobs: JobService::new(pool.clone()),
+        tmpl,
+        server: config.server.clone(),
+    });
+    registry.register(SEND_DIGEST_JOB, move |job| {
+        let sender = sender.clone();
+        async move {
+            let payload: DigestPayload = serde_json::from_str(&job.payload)?;
+            sender.send(&payload, Utc::now()).await?;
+            Ok(())
+        }
+    });
+
+    Ok(registry)
+}
+
+// Store the configured schedules and start this process's workers
+pub async fn start_workers(pool: &DbPool, config: &Config) -> Result<WorkerHandle, WorkerError> {
+    let registry = registry(pool, config)?;
+    for kind in config.jobs.schedules.keys() {
+        if !registry.contains(kind) {
+            eprintln!("Warning: schedule for unknown job kind '{}'", kind);
+        }
+    }
+
+    JobService::new(pool.clone())
+        .sync_schedules(&configured_schedules(config))
+        .await?;
+
+    Ok(WorkerPool::new(pool.clone(), registry, config.jobs.clone()).start())
+}
+
+// Delay before the next attempt: base * 2^(attempts - 1), capped at max
+pub fn backoff(attempts: i32, base_seconds: u64, max_seconds: u64) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 32) as u32;
+    let seconds = base_seconds
+        .saturating_mul(2u64.saturating_pow(exponent))
+        .min(max_seconds);
+    Duration::seconds(seconds as i64)
+}
+
+// Next time a cron expression fires after `after`
+pub fn next_run(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let schedule = cron::Schedule::from_str(expression).ok()?;
+    schedule.after(&after).next()
+}
+
+// Schedules from the configuration, ready to be stored
+pub fn configured_schedules(config: &Config) -> Vec<JobSchedule> {
+    let now = Utc::now();
+    config
+        .jobs
+        .schedules
+        .iter()
+        .filter_map(|(kind, expression)| {
+            Some(JobSchedule {
+                kind: kind.clone(),
+                cron: expression.clone(),
+                next_run_at: next_run(expression, now)?,
+                last_run_at: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(1, 10, 3600), Duration::seconds(10));
+        assert_eq!(backoff(2, 10, 3600), Duration::seconds(20));
+        assert_eq!(backoff(4, 10, 3600), Duration::seconds(80));
+        assert_eq!(backoff(20, 10, 3600), Duration::seconds(3600));
+        assert_eq!(backoff(i32::MAX, 10, 3600), Duration::seconds(3600));
+    }
+
+    #[test]
+    fn test_next_run() {
+        let after = Utc.with_ymd_and_hms(2025, 1, 1, 10, 15, 0).unwrap();
+        assert_eq!(
+            next_run("0 0 * * * *", after),
+            Some(Utc.with_ymd_and_hms(2025, 1, 1, 11, 0, 0).unwrap())
+        );
+        assert_eq!(next_run("not cron", after), None);
+    }
+
+    #[tokio::test]
+    async fn test_default_schedules_have_handlers() {
+        let config = Config::default();
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgresql://localhost/unused")
+            .unwrap();
+        let registry = registry(&pool, &config).unwrap();
+
+        for kind in config.jobs.schedules.keys() {
+            assert!(registry.contains(kind), "no handler for {}", kind);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::JobsConfig;
+use crate::db::{connection::DbPool, job::JobService, models::Job};
+use crate::jobs::{JobRegistry, backoff, next_run};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tokio::{sync::watch, task::JoinHandle, time};
+
+// A set of workers polling the job queue, plus the scheduler that turns due
+// cron schedules into jobs. Any number of processes can run one.
+pub struct WorkerPool {
+    jobs: Arc<JobService>,
+    registry: Arc<JobRegistry>,
+    config: JobsConfig,
+}
+
+// Returned by `WorkerPool::start`, used to stop the workers
+pub struct WorkerHandle {
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn new(pool: DbPool, registry: JobRegistry, config: JobsConfig) -> Self {
+        Self {
+            jobs: Arc::new(JobService::new(pool)),
+            registry: Arc::new(registry),
+            config,
+        }
+    }
+
+    pub fn start(self) -> WorkerHandle {
+        let (shutdown, stop) = watch::channel(false);
+        let mut tasks = Vec::new();
+
+        for n in 0..self.config.workers {
+            let worker_id = format!("{}-{}", std::process::id(), n);
+            tasks.push(tokio::spawn(run_worker(
+                worker_id,
+                self.jobs.clone(),
+                self.registry.clone(),
+                self.config.clone(),
+                stop.clone(),
+            )));
+        }
+        tasks.push(tokio::spawn(run_scheduler(
+            self.jobs.clone(),
+            self.config.clone(),
+            stop,
+        )));
+
+        println!("Started {} job workers", self.config.workers);
+        WorkerHandle { shutdown, tasks }
+    }
+}
+
+impl WorkerHandle {
+    // Let running jobs finish, then stop every worker
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        for task in self.tasks {
+            let _ = task.await;
+        }
+        println!("Job workers stopped");
+    }
+}
+
+// Sleep for the poll interval, returning false once shutdown was requested
+async fn idle(config: &JobsConfig, stop: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = time::sleep(std::time::Duration::from_millis(config.poll_interval_ms)) => !*stop.borrow(),
+        _ = stop.changed() => false,
+    }
+}
+
+async fn run_worker(
+    worker_id: String,
+    jobs: Arc<JobService>,
+    registry: Arc<JobRegistry>,
+    config: JobsConfig,
+    mut stop: watch::Receiver<bool>,
+) {
+    while !*stop.borrow() {
+        match jobs.claim_next(&worker_id).await {
+            // Keep draining the queue while there is work
+            Ok(Some(job)) => run_job(&jobs, &registry, &config, &worker_id, job).await,
+            Ok(None) => {
+                if !idle(&config, &mut stop).await {
+                    break;
+                }
+            }
+            Err(e) => {
+                eprintln!("Worker {} failed to claim a job: {}", worker_id, e);
+                if !idle(&config, &mut stop).await {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Running jobs send a heartbeat several times per stale window, so one slow
+// update does not get them requeued while they are still running
+fn heartbeat_interval(config: &JobsConfig) -> std::time::Duration {
+    std::time::Duration::from_secs((config.stale_after_seconds / 4).max(1))
+}
+
+async fn run_job(
+    jobs: &JobService,
+    registry: &JobRegistry,
+    config: &JobsConfig,
+    worker_id: &str,
+    job: Job,
+) {
+    let (id, kind, attempts, max_attempts) =
+        (job.id, job.kind.clone(), job.attempts, job.max_attempts);
+
+    let Some(handler) = registry.get(&kind) else {
+        // Retrying cannot help, fail straight away
+        let error = format!("No handler registered for job kind '{}'", kind);
+        eprintln!("Job {}: {}", id, error);
+        match jobs.fail(id, worker_id, &error, None).await {
+            Ok(true) => {}
+            Ok(false) => eprintln!("Job {}: lease lost before its failure was recorded", id),
+            Err(e) => eprintln!("Failed to record failure of job {}: {}", id, e),
+        }
+        return;
+    };
+
+    // Run in its own task so a panicking handler only fails this job
+    let mut task = tokio::spawn(handler(job));
+    let mut heartbeats = time::interval(heartbeat_interval(config));
+    // The first tick completes straight away, the claim was the first heartbeat
+    heartbeats.tick().await;
+    let joined = loop {
+        tokio::select! {
+            joined = &mut task => break joined,
+            _ = heartbeats.tick() => {
+                if let Err(e) = jobs.heartbeat(id, worker_id).await {
+                    eprintln!("Failed to record heartbeat of job {}: {}", id, e);
+                }
+            }
+        }
+    };
+    let result = match joined {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("Job panicked: {}", e)),
+    };
+
+    let recorded = match result {
+        Ok(()) => jobs.complete(id, worker_id).await,
+        Err(error) => {
+            let retry_at = (attempts < max_attempts).then(|| {
+                Utc::now()
+                    + backoff(
+                        attempts,
+                        config.retry_base_seconds,
+                        config.retry_max_seconds,
+                    )
+            });
+            match retry_at {
+                Some(at) => eprintln!(
+                    "Job {} ({}) attempt {}/{} failed, retrying at {}: {}",
+                    id, kind, attempts, max_attempts, at, error
+                ),
+                None => eprintln!(
+                    "Job {} ({}) failed after {} attempts: {}",
+                    id, kind, attempts, error
+                ),
+            }
+            jobs.fail(id, worker_id, &error, retry_at).await
+        }
+    };
+
+    match recorded {
+        Ok(true) => {}
+        // Requeued as stale while it ran, another worker may be running it now
+        Ok(false) => eprintln!(
+            "Job {} ({}): lease lost, its result was not recorded",
+            id, kind
+        ),
+        Err(e) => eprintln!("Failed to record result of job {}: {}", id, e),
+    }
+}
+
+async fn run_scheduler(jobs: Arc<JobService>, config: JobsConfig, mut stop: watch::Receiver<bool>) {
+    let stale_after = Duration::seconds(config.stale_after_seconds as i64);
+
+    loop {
+        match jobs
+            .enqueue_due_schedules(|schedule, after| next_run(&schedule.cron, after))
+            .await
+        {
+            Ok(fired) if !fired.is_empty() => println!("Scheduled jobs: {}", fired.join(", ")),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to enqueue scheduled jobs: {}", e),
+        }
+
+        match jobs.requeue_stale(stale_after).await {
+            Ok(0) => {}
+            Ok(n) => eprintln!("Requeued {} jobs from unresponsive workers", n),
+            Err(e) => eprintln!("Failed to requeue stale jobs: {}", e),
+        }
+
+        if !idle(&config, &mut stop).await {
+            break;
+        }
+    }
+}
@@ -1,4 +1,19 @@
 pub mod api;
+pub mod calendar;
+pub mod cli;
+pub mod config;
 pub mod db;
+pub mod digests;
+pub mod exports;
+pub mod geo;
+pub mod images;
+pub mod jobs;
+pub mod mail;
+pub mod markdown;
 pub mod middleware;
 pub mod password;
+pub mod realtime;
+pub mod reminders;
+pub mod retention;
+pub mod session;
+pub mod storage;
@@ -0,0 +1,148 @@
+use crate::config::MailConfig;
+use crate::db::job::{JobError, NewJob};
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{
+        Mailbox, MultiPart,
+        header::{HeaderName, HeaderValue},
+    },
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Job kind that delivers an Email, see jobs::registry
+pub const SEND_EMAIL_JOB: &str = "send_email";
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("Invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Invalid message: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+// An email waiting to be sent. Emails are queued as jobs so a slow or failing
+// SMTP server never holds up a request and delivery is retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    // Sent as an alternative to the text body when present
+    pub html: Option<String>,
+    // Link for mail clients' unsubscribe button, which POSTs to it
+    #[serde(default)]
+    pub unsubscribe_url: Option<String>,
+}
+
+impl Email {
+    pub fn job(&self) -> Result<NewJob, JobError> {
+        NewJob::new(SEND_EMAIL_JOB, self)
+    }
+}
+
+pub struct Mailer {
+    from: Mailbox,
+    // None in development: emails are printed instead of sent
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+}
+
+impl Mailer {
+    pub fn from_config(config: &MailConfig) -> Result<Self, MailError> {
+        let transport = match &config.smtp_url {
+            Some(url) => Some(AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build()),
+            None => None,
+        };
+
+        Ok(Self {
+            from: config.from.parse()?,
+            transport,
+        })
+    }
+
+    pub fn message(&self, email: &Email) -> Result<Message, MailError> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(&email.subject);
+        // One-click unsubscribe, RFC 8058
+        if let Some(url) = &email.unsubscribe_url {
+            builder = builder
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                    format!("<{}>", url),
+                ))
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                    "List-Unsubscribe=One-Click".to_string(),
+                ));
+        }
+
+        let message = match &email.html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+                email.text.clone(),
+                html.clone(),
+            ))?,
+            None => builder.body(email.text.clone())?,
+        };
+
+        Ok(message)
+    }
+
+    pub async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = self.message(email)?;
+
+        match &self.transport {
+            Some(transport) => {
+                transport.send(message).await?;
+            }
+            None => println!(
+                "Email to {} (no SMTP configured)\nSubject: {}\n\n{}",
+                email.to, email.subject, email.text
+            ),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builds_multipart_message() {
+        let mailer = Mailer::from_config(&MailConfig::default()).unwrap();
+        let email = Email {
+            to: "ada@example.com".to_string(),
+            subject: "You're invited".to_string(),
+            text: "Join us".to_string(),
+            html: Some("<p>Join us</p>".to_string()),
+            unsubscribe_url: None,
+        };
+
+        let formatted = String::from_utf8(mailer.message(&email).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("To: ada@example.com"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("<p>Join us</p>"));
+        assert!(!formatted.contains("List-Unsubscribe"));
+
+        let digest = Email {
+            unsubscribe_url: Some("https://groups.dev/digest/unsubscribe/abc".to_string()),
+            ..email.clone()
+        };
+        let formatted = String::from_utf8(mailer.message(&digest).unwrap().formatted()).unwrap();
+        assert!(
+            formatted.contains("List-Unsubscribe: <https://groups.dev/digest/unsubscribe/abc>")
+        );
+        assert!(formatted.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+
+        let invalid = Email {
+            to: "not an address".to_string(),
+            ..email
+        };
+        assert!(mailer.message(&invalid).is_err());
+    }
+}
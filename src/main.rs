@@ -1,26 +1,89 @@
 use actix_files as fs;
-use actix_session::{SessionMiddleware, storage::CookieSessionStore};
-use actix_web::{App, HttpResponse, HttpServer, cookie::Key, middleware as actix_middleware, web};
-use db::group::GroupService;
-use db::user::UserService;
-use std::env;
+use actix_web::{App, HttpResponse, HttpServer, middleware as actix_middleware, web};
+use clap::Parser;
+use groups::api::{self, context::create_template_context, hello::AppStateWithCounter};
+use groups::cli::{self, Cli, Command};
+use groups::config::Config;
+use groups::db::{
+    self,
+    admin_action::AdminActionService,
+    attendance::AttendanceService,
+    audit::AuditService,
+    digest::DigestService,
+    event::EventService,
+    event_comment::EventCommentService,
+    export::DataExportService,
+    geo::{GeoError, GeoService},
+    group::GroupService,
+    invitation::InvitationService,
+    job::JobService,
+    join_request::JoinRequestService,
+    member::MemberService,
+    notification::NotificationService,
+    post::PostService,
+    rsvp::RsvpService,
+    tag::TagService,
+    user::UserService,
+    venue::VenueService,
+};
+use groups::jobs;
+use groups::markdown;
+use groups::middleware::notifications::LoadUnreadNotifications;
+use groups::middleware::request_id::AssignRequestId;
+use groups::middleware::security_headers::{CspNonce, SecurityHeaders};
+use groups::middleware::session_key_rotation::SessionKeyRotation;
+use groups::middleware::session_validation::ValidateSession;
+use groups::realtime::Broadcaster;
+use groups::session::SessionSettings;
+use groups::storage::{self, BlobStore};
 use tera::Tera;
-mod api;
-mod db;
-mod middleware;
-mod password;
-
-use api::hello::AppStateWithCounter;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Load environment variables
     dotenvy::dotenv().ok();
 
-    // Initialize database
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let cli = Cli::parse();
+
+    let config = match Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if cli.print_config {
+        print!("{}", config.redacted().to_toml());
+        return Ok(());
+    }
+
+    // Set up logging
+    env_logger::Builder::new()
+        .parse_filters(&config.log_level)
+        .init();
 
-    let pool = db::create_pool(&database_url)
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::Worker => worker(config).await,
+        command => {
+            let pool = db::create_pool(&config.database).await.unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+
+            if let Err(e) = cli::run(command, &pool).await {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn serve(config: Config) -> std::io::Result<()> {
+    // Initialize database
+    let pool = db::create_pool(&config.database)
         .await
         .expect("Failed to create database pool");
 
@@ -29,6 +92,13 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to run migrations");
 
+    // Development databases get the seed data, production ones only with `groups seed`
+    if !config.environment.is_production() {
+        db::run_seed(&pool)
+            .await
+            .expect("Failed to insert seed data");
+    }
+
     // Check database health
     match db::health_check(&pool).await {
         Ok(true) => println!("Database connection: OK"),
@@ -43,7 +113,7 @@ async fn main() -> std::io::Result<()> {
     }
 
     // Set up templating
-    let mut tera = match Tera::new("src/templates/**/*") {
+    let mut tera = match Tera::new(&config.paths.templates_glob()) {
         Ok(t) => t,
         Err(e) => {
             println!("Template parsing error(s): {}", e);
@@ -51,6 +121,7 @@ async fn main() -> std::io::Result<()> {
         }
     };
     tera.autoescape_on(vec!["html"]);
+    markdown::register_filters(&mut tera);
     let tera_data = web::Data::new(tera);
 
     // Initialize services
@@ -59,56 +130,102 @@ async fn main() -> std::io::Result<()> {
     });
     let user_service = web::Data::new(UserService::new(pool.clone()));
     let group_service = web::Data::new(GroupService::new(pool.clone()));
+    let admin_action_service = web::Data::new(AdminActionService::new(pool.clone()));
+    let audit_service = web::Data::new(AuditService::new(pool.clone()));
+    let member_service = web::Data::new(MemberService::new(pool.clone()));
+    let invitation_service = web::Data::new(InvitationService::new(pool.clone()));
+    let join_request_service = web::Data::new(JoinRequestService::new(pool.clone()));
+    let post_service = web::Data::new(PostService::new(pool.clone()));
+    let event_service = web::Data::new(EventService::new(pool.clone()));
+    let event_comment_service = web::Data::new(EventCommentService::new(pool.clone()));
+    let rsvp_service = web::Data::new(RsvpService::new(pool.clone()));
+    let notification_service = web::Data::new(NotificationService::new(pool.clone()));
+    let digest_service = web::Data::new(DigestService::new(pool.clone()));
+    let data_export_service = web::Data::new(DataExportService::new(pool.clone()));
+    let tag_service = web::Data::new(TagService::new(pool.clone()));
+    let geo_service = web::Data::new(GeoService::new(pool.clone()));
+    let venue_service = web::Data::new(VenueService::new(pool.clone()));
+    let attendance_service = web::Data::new(AttendanceService::new(pool.clone()));
+    let features = web::Data::new(config.features.clone());
+    let retention = web::Data::new(config.retention.clone());
+    let server = web::Data::new(config.server.clone());
+    let uploads = web::Data::new(config.uploads.clone());
+    let blob_store: web::Data<dyn BlobStore> =
+        web::Data::from(storage::from_config(&config.uploads));
+    let job_service = web::Data::new(JobService::new(pool.clone()));
 
-    // Get configuration from environment
-    let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port = env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .expect("PORT must be a valid number");
+    // Updates pushed to browsers, shared between instances through the database
+    let broadcaster = web::Data::new(
+        Broadcaster::start(&pool)
+            .await
+            .expect("Failed to listen for updates"),
+    );
+
+    // Background jobs, unless they are handled by a separate `groups worker`
+    let workers = if config.jobs.run_in_process {
+        match jobs::start_workers(&pool, &config).await {
+            Ok(workers) => Some(workers),
+            Err(e) => {
+                eprintln!("Failed to start job workers: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
 
     println!(
         "Number of users: {}",
         user_service.count().await.unwrap_or(0)
     );
-    println!("Server running at http://{}:{}/", host, port);
+    println!("Environment: {}", config.environment);
+    println!(
+        "Server running at http://{}:{}/",
+        config.server.host, config.server.port
+    );
 
-    // Set up logging
-    if env::var("RUST_LOG").is_err() {
-        unsafe {
-            env::set_var("RUST_LOG", "info");
-        }
-    }
-    env_logger::init();
-
-    // Get session secret key from environment or generate one for development
-    let secret_key = match env::var("SESSION_SECRET_KEY") {
-        Ok(key) => {
-            if key.len() >= 64 {
-                Key::from(key.as_bytes())
-            } else {
-                eprintln!(
-                    "Warning: SESSION_SECRET_KEY too short (minimum 64 characters), using generated key"
-                );
-                Key::generate()
-            }
-        }
-        Err(_) => {
-            eprintln!(
-                "Warning: SESSION_SECRET_KEY not set, using generated key (not suitable for production)"
-            );
-            Key::generate()
-        }
-    };
+    let session_settings = SessionSettings::from_config(&config);
+    let security_headers = config.security_headers();
+    let static_dir = config.paths.static_files.clone();
 
     HttpServer::new(move || {
         App::new()
             .app_data(counter.clone())
             .app_data(user_service.clone())
             .app_data(group_service.clone())
+            .app_data(admin_action_service.clone())
+            .app_data(audit_service.clone())
+            .app_data(member_service.clone())
+            .app_data(invitation_service.clone())
+            .app_data(join_request_service.clone())
+            .app_data(post_service.clone())
+            .app_data(event_service.clone())
+            .app_data(event_comment_service.clone())
+            .app_data(rsvp_service.clone())
+            .app_data(notification_service.clone())
+            .app_data(digest_service.clone())
+            .app_data(data_export_service.clone())
+            .app_data(tag_service.clone())
+            .app_data(geo_service.clone())
+            .app_data(venue_service.clone())
+            .app_data(attendance_service.clone())
+            .app_data(job_service.clone())
+            .app_data(broadcaster.clone())
             .app_data(tera_data.clone())
+            .app_data(features.clone())
+            .app_data(retention.clone())
+            .app_data(server.clone())
+            .app_data(uploads.clone())
+            .app_data(blob_store.clone())
             // Static files
-            .service(fs::Files::new("/static", "src/static").show_files_listing())
+            .service({
+                let files = fs::Files::new("/static", &static_dir);
+                if features.static_file_listing {
+                    files.show_files_listing()
+                } else {
+                    files
+                }
+            })
             // HTML Routes
             .service(web::resource("/").to(index))
             .service(web::resource("/groups").to(groups_page))
@@ -120,51 +237,383 @@ async fn main() -> std::io::Result<()> {
             // Protected routes
             .service(
                 web::resource("/groups/new")
-                    .wrap(crate::middleware::auth::RequireAuth)
+                    .wrap(groups::middleware::auth::RequireAuth)
                     .to(new_group_page),
             )
+            .service(
+                web::resource("/groups/trash")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .to(api::groups_html::trash_page),
+            )
+            .service(
+                web::resource("/groups/{id}/invitations")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .to(api::invitations::invitations_page),
+            )
+            // Joining groups
+            .service(
+                web::resource("/groups/{id}/join")
+                    .route(web::get().to(api::join_requests::join_page))
+                    .route(
+                        web::post()
+                            .to(api::join_requests::join_group)
+                            .wrap(groups::middleware::auth::RequireAuth),
+                    ),
+            )
+            .service(
+                web::resource("/groups/{id}/join-requests")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .to(api::join_requests::join_requests_page),
+            )
+            .service(
+                web::resource("/groups/{id}/join-requests/{request_id}/{action}")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::join_requests::decide_join_request)),
+            )
+            .service(
+                web::resource("/groups/{id}/join-settings")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::join_requests::update_join_settings)),
+            )
+            .service(
+                web::resource("/groups/{id}/tags")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::tags::update_group_tags)),
+            )
+            .service(
+                web::resource("/groups/{id}/location")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::geo::update_group_location)),
+            )
+            // Topics
+            .service(web::resource("/tags").route(web::get().to(api::tags::topics_page)))
+            .service(web::resource("/tags/{slug}").route(web::get().to(api::tags::tag_page)))
+            // Discussions
+            .service(
+                web::resource("/groups/{id}/discussions")
+                    .route(web::get().to(api::discussions::discussions_page))
+                    .route(
+                        web::post()
+                            .to(api::discussions::create_thread)
+                            .wrap(groups::middleware::auth::RequireAuth),
+                    ),
+            )
+            .service(
+                web::resource("/groups/{id}/discussions/{thread_id}")
+                    .route(web::get().to(api::discussions::thread_page)),
+            )
+            .service(
+                web::resource("/groups/{id}/discussions/{thread_id}/replies")
+                    .route(web::get().to(api::discussions::replies_fragment))
+                    .route(
+                        web::post()
+                            .to(api::discussions::create_reply)
+                            .wrap(groups::middleware::auth::RequireAuth),
+                    ),
+            )
+            .service(
+                web::resource("/groups/{id}/posts/{post_id}/edit")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::discussions::edit_post_page))
+                    .route(web::post().to(api::discussions::edit_post)),
+            )
+            .service(
+                web::resource("/groups/{id}/posts/{post_id}/delete")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::discussions::delete_post)),
+            )
+            .service(
+                web::resource("/groups/{id}/posts/{post_id}/history")
+                    .route(web::get().to(api::discussions::post_history)),
+            )
+            // Events
+            .service(
+                web::resource("/groups/{id}/events")
+                    .route(web::get().to(api::events::events_page))
+                    .route(
+                        web::post()
+                            .to(api::events::create_event)
+                            .wrap(groups::middleware::auth::RequireAuth),
+                    ),
+            )
+            .service(
+                web::resource("/groups/{id}/calendar")
+                    .route(web::get().to(api::calendar::group_calendar_page)),
+            )
+            .service(
+                web::resource("/groups/{id}/calendar/view")
+                    .route(web::get().to(api::calendar::group_calendar_fragment)),
+            )
+            .service(
+                web::resource("/calendar")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::calendar::my_calendar_page)),
+            )
+            .service(
+                web::resource("/calendar/view")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::calendar::my_calendar_fragment)),
+            )
+            .service(
+                web::resource("/groups/{id}/venues")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::venues::venues_page))
+                    .route(web::post().to(api::venues::create_venue)),
+            )
+            .service(
+                web::resource("/groups/{id}/venues/{venue_id}")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::venues::update_venue)),
+            )
+            .service(
+                web::resource("/groups/{id}/venues/{venue_id}/delete")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::venues::delete_venue)),
+            )
+            .service(web::resource("/events/{id}").route(web::get().to(api::events::event_page)))
+            .service(
+                web::resource("/events/{id}/comments")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::events::create_comment)),
+            )
+            .service(
+                web::resource("/events/{id}/comments/{comment_id}/{action}")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::events::moderate_comment)),
+            )
+            // Check-in and attendance
+            .service(
+                web::resource("/events/{id}/check-in")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::attendance::check_in_page)),
+            )
+            .service(
+                web::resource("/events/{id}/check-in/attendees")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::attendance::attendees_fragment)),
+            )
+            .service(
+                web::resource("/events/{id}/check-in/scan/{token}")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::attendance::scan_page))
+                    .route(web::post().to(api::attendance::check_in_scanned)),
+            )
+            .service(
+                web::resource("/events/{id}/check-in/{user_id}")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::attendance::check_in)),
+            )
+            .service(
+                web::resource("/events/{id}/check-in/{user_id}/undo")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::attendance::undo_check_in)),
+            )
+            .service(
+                web::resource("/events/{id}/check-in-code.svg")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::attendance::check_in_code)),
+            )
+            .service(
+                web::resource("/groups/{id}/attendance")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::attendance::attendance_page)),
+            )
+            .service(
+                web::resource("/events/{id}/rsvp")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::events::rsvp)),
+            )
+            // Notifications
+            .service(
+                web::resource("/notifications")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::notifications::notifications_page)),
+            )
+            .service(
+                web::resource("/notifications/read-all")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::notifications::mark_all_read)),
+            )
+            // Profiles
+            .service(web::resource("/users/{id}").route(web::get().to(api::profiles::profile_page)))
+            .service(
+                web::resource("/profile")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::profiles::edit_profile_page))
+                    .route(web::post().to(api::profiles::update_profile)),
+            )
+            // Account settings
+            .service(
+                web::resource("/account")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::account::account_page)),
+            )
+            .service(
+                web::resource("/account/email")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::account::change_email)),
+            )
+            .service(
+                web::resource("/account/password")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::account::change_password)),
+            )
+            .service(
+                web::resource("/account/delete")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::account::delete_account)),
+            )
+            .service(
+                web::resource("/account/export")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::account::request_export)),
+            )
+            .service(
+                web::resource("/account/export/{token}")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::account::download_export)),
+            )
+            // Confirmation links in emails, usable without logging in
+            .service(
+                web::resource("/account/email/{token}")
+                    .route(web::get().to(api::account::confirm_email_page))
+                    .route(web::post().to(api::account::confirm_email)),
+            )
+            // Uploaded images
+            .service(
+                web::resource("/profile/avatar")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::uploads::upload_avatar)),
+            )
+            .service(
+                web::resource("/profile/avatar/delete")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::uploads::remove_avatar)),
+            )
+            .service(
+                web::resource("/groups/{id}/settings")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::group_settings::group_settings_page)),
+            )
+            .service(
+                web::resource("/groups/{id}/cover")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::uploads::upload_cover)),
+            )
+            .service(
+                web::resource("/groups/{id}/cover/delete")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::uploads::remove_cover)),
+            )
+            .service(web::resource("/media/{key:.*}").route(web::get().to(api::uploads::media)))
+            .service(
+                web::resource("/notifications/settings")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::notifications::settings_page))
+                    .route(web::post().to(api::notifications::update_settings)),
+            )
+            .service(
+                web::resource("/notifications/{id}")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::get().to(api::notifications::open_notification)),
+            )
+            .service(
+                web::resource("/notifications/{id}/read")
+                    .wrap(groups::middleware::auth::RequireAuth)
+                    .route(web::post().to(api::notifications::mark_read)),
+            )
+            // Live updates
+            .service(web::resource("/updates").route(web::get().to(api::updates::updates)))
+            // Unsubscribe links in digest emails, usable without logging in
+            .service(
+                web::resource("/digest/unsubscribe/{token}")
+                    .route(web::get().to(api::digests::unsubscribe_page))
+                    .route(web::post().to(api::digests::unsubscribe)),
+            )
+            // Invitation links
+            .service(
+                web::resource("/invitations/{code}")
+                    .route(web::get().to(api::invitations::invitation_page)),
+            )
+            .service(
+                web::resource("/invitations/{code}/accept")
+                    .route(web::post().to(api::invitations::accept_invitation)),
+            )
+            // Admin area
+            .configure(api::configure_admin_routes)
             // API Routes
-            .service(api::hello_service)
+            .configure(|cfg| {
+                if features.hello_counter {
+                    cfg.service(api::hello_service);
+                }
+            })
             .service(
                 web::scope("/api")
                     .configure(api::configure_groups_routes)
-                    .configure(api::configure_html_routes),
+                    .configure(api::configure_html_routes)
+                    .configure(api::configure_invitation_routes)
+                    .configure(api::configure_tag_routes)
+                    .configure(api::configure_geo_routes),
             )
             // Default 404 handler
             .default_service(web::route().to(not_found))
-            .wrap(actix_middleware::Logger::default())
-            .wrap(
-                SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
-                    .cookie_secure(false) // Set to true in production with HTTPS
-                    .build(),
-            )
+            .wrap(SecurityHeaders::new(security_headers.clone()))
+            .wrap(AssignRequestId)
+            .wrap(actix_middleware::Logger::new(
+                r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{X-Request-Id}o"#,
+            ))
+            // Needs the session, so it is wrapped inside the session middleware
+            .wrap(LoadUnreadNotifications)
+            // Outside LoadUnreadNotifications, so it sees the session cleared
+            .wrap(ValidateSession)
+            .wrap(session_settings.middleware())
+            .wrap(SessionKeyRotation::new(
+                session_settings.cookie_name.clone(),
+                session_settings.key.clone(),
+                session_settings.previous_keys.clone(),
+            ))
     })
-    .bind((host.as_str(), port))?
+    .bind((config.server.host.as_str(), config.server.port))?
     .run()
-    .await
+    .await?;
+
+    if let Some(workers) = workers {
+        workers.shutdown().await;
+    }
+    Ok(())
 }
 
-// Helper function to create template context with session data
-fn create_template_context(session: &actix_session::Session) -> tera::Context {
-    let mut context = tera::Context::new();
+// Process background jobs only, for deployments that keep them out of the web server
+async fn worker(config: Config) -> std::io::Result<()> {
+    let pool = db::create_pool(&config.database)
+        .await
+        .expect("Failed to create database pool");
+
+    db::run_migrations(&pool)
+        .await
+        .expect("Failed to run migrations");
 
-    // Check if user is logged in
-    if let Ok(Some(user_email)) = session.get::<String>("user_email") {
-        context.insert("user_email", &user_email);
-        if let Ok(Some(user_name)) = session.get::<String>("user_name") {
-            context.insert("user_name", &user_name);
+    let workers = match jobs::start_workers(&pool, &config).await {
+        Ok(workers) => workers,
+        Err(e) => {
+            eprintln!("Failed to start job workers: {}", e);
+            std::process::exit(1);
         }
-        context.insert("is_logged_in", &true);
-    } else {
-        context.insert("is_logged_in", &false);
-    }
+    };
 
-    context
+    tokio::signal::ctrl_c().await?;
+    println!("Shutting down, waiting for running jobs");
+    workers.shutdown().await;
+    Ok(())
 }
 
 // Page handlers
-async fn index(tmpl: web::Data<Tera>, session: actix_session::Session) -> HttpResponse {
-    let context = create_template_context(&session);
+async fn index(
+    tmpl: web::Data<Tera>,
+    session: actix_session::Session,
+    nonce: CspNonce,
+) -> HttpResponse {
+    let context = create_template_context(&session, &nonce);
 
     let rendered = tmpl.render("index.html", &context).unwrap_or_else(|e| {
         eprintln!("Template error: {}", e);
@@ -173,8 +622,68 @@ async fn index(tmpl: web::Data<Tera>, session: actix_session::Session) -> HttpRe
     HttpResponse::Ok().content_type("text/html").body(rendered)
 }
 
-async fn groups_page(tmpl: web::Data<Tera>, session: actix_session::Session) -> HttpResponse {
-    let context = create_template_context(&session);
+#[derive(serde::Deserialize)]
+struct GroupsPageQuery {
+    // City or coordinates, to list the groups around it
+    near: Option<String>,
+    radius_km: Option<f64>,
+}
+
+async fn groups_page(
+    query: web::Query<GroupsPageQuery>,
+    tmpl: web::Data<Tera>,
+    session: actix_session::Session,
+    nonce: CspNonce,
+    groups: web::Data<GroupService>,
+    tags: web::Data<TagService>,
+    geo: web::Data<GeoService>,
+) -> HttpResponse {
+    let mut context = create_template_context(&session, &nonce);
+    let near = query.near.as_deref().map(str::trim).unwrap_or_default();
+    let radius = api::geo::radius_km(query.radius_km).unwrap_or(api::geo::DEFAULT_RADIUS_KM);
+    context.insert("near", near);
+    context.insert("radius_km", &radius);
+    context.insert("place", &None::<String>);
+    context.insert("error", &None::<String>);
+
+    let (groups, distances) = if near.is_empty() {
+        match groups.list_active().await {
+            Ok(groups) => (groups, Vec::new()),
+            Err(e) => {
+                return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+            }
+        }
+    } else {
+        let found = match geo.locate(near).await {
+            Ok((place, point)) => {
+                context.insert("place", &place);
+                geo.groups_near(point, radius, &[]).await
+            }
+            Err(e @ (GeoError::InvalidPlace(_) | GeoError::UnknownCity(_))) => {
+                context.insert("error", &e.to_string());
+                Ok(Vec::new())
+            }
+            Err(e) => Err(e),
+        };
+        match found {
+            Ok(found) => found
+                .into_iter()
+                .map(|nearby| (nearby.group, nearby.distance_km))
+                .unzip(),
+            Err(e) => {
+                return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+            }
+        }
+    };
+    match api::tags::with_tags(&tags, groups).await {
+        Ok(mut groups) => {
+            for (group, distance_km) in groups.iter_mut().zip(distances) {
+                group.distance_km = Some(distance_km);
+            }
+            context.insert("groups", &groups);
+        }
+        Err(response) => return response,
+    }
 
     let rendered = tmpl.render("groups.html", &context).unwrap_or_else(|e| {
         eprintln!("Template error: {}", e);
@@ -183,8 +692,12 @@ async fn groups_page(tmpl: web::Data<Tera>, session: actix_session::Session) ->
     HttpResponse::Ok().content_type("text/html").body(rendered)
 }
 
-async fn new_group_page(tmpl: web::Data<Tera>, session: actix_session::Session) -> HttpResponse {
-    let context = create_template_context(&session);
+async fn new_group_page(
+    tmpl: web::Data<Tera>,
+    session: actix_session::Session,
+    nonce: CspNonce,
+) -> HttpResponse {
+    let context = create_template_context(&session, &nonce);
 
     let rendered = tmpl
         .render("groups_new.html", &context)
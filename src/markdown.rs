@@ -0,0 +1,51 @@
+use pulldown_cmark::{Options, Parser, html};
+use std::collections::HashMap;
+use tera::{Tera, Value};
+
+// Render user-written Markdown to HTML that is safe to embed in a page.
+// Raw HTML in the input is allowed through pulldown-cmark and then cleaned
+// by ammonia, which drops scripts, event handlers and unsafe URLs.
+pub fn render(markdown: &str) -> String {
+    let options =
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+
+    ammonia::Builder::default()
+        .link_rel(Some("nofollow noopener noreferrer"))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+// `{{ post.body | markdown | safe }}` in templates
+fn markdown_filter(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = tera::try_get_value!("markdown", "value", String, value);
+    Ok(Value::String(render(&text)))
+}
+
+pub fn register_filters(tera: &mut Tera) {
+    tera.register_filter("markdown", markdown_filter);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_markdown() {
+        let html = render("**bold** and [a link](https://example.com)");
+        assert!(html.contains("<strong>bold</strong>"));
+        assert!(html.contains(r#"href="https://example.com""#));
+        assert!(html.contains(r#"rel="nofollow noopener noreferrer""#));
+    }
+
+    #[test]
+    fn test_strips_unsafe_html() {
+        let html = render(
+            "<script>alert(1)</script>\n\n<img src=x onerror=alert(1)>\n\n[x](javascript:alert(1))",
+        );
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript:"));
+    }
+}
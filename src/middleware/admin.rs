@@ -0,0 +1,85 @@
+use crate::db::user::UserService;
+use crate::middleware::auth::current_user_id;
+use actix_session::SessionExt;
+use actix_web::{
+    Error, HttpResponse,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    web,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{Ready, ready},
+    rc::Rc,
+};
+
+// Whether the user is an active site administrator, checked against the database
+pub async fn is_site_admin(users: &UserService, user_id: i32) -> bool {
+    matches!(
+        users.get_by_id(user_id).await,
+        Ok(Some(user)) if user.is_admin && user.deleted_at.is_none()
+    )
+}
+
+// Only lets site administrators through. The role is checked against the
+// database on every request so revoking it takes effect immediately.
+pub struct RequireAdmin;
+
+impl<S> Transform<S, ServiceRequest> for RequireAdmin
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequireAdminMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAdminMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequireAdminMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S> Service<ServiceRequest> for RequireAdminMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let Some(user_id) = current_user_id(&req.get_session()) else {
+                // Not logged in, send them to the login page
+                let response = HttpResponse::Found()
+                    .append_header(("Location", "/login"))
+                    .finish();
+                return Ok(req.into_response(response));
+            };
+
+            let is_admin = match req.app_data::<web::Data<UserService>>() {
+                Some(users) => is_site_admin(users, user_id).await,
+                None => false,
+            };
+
+            if is_admin {
+                service.call(req).await
+            } else {
+                let response = HttpResponse::Forbidden().body("Administrators only");
+                Ok(req.into_response(response))
+            }
+        })
+    }
+}
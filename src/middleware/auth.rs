@@ -1,4 +1,4 @@
-use actix_session::SessionExt;
+use actix_session::{Session, SessionExt};
 use actix_web::{
     Error, HttpResponse,
     dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
@@ -9,6 +9,11 @@ use std::{
     rc::Rc,
 };
 
+// Id of the logged-in user, if any
+pub fn current_user_id(session: &Session) -> Option<i32> {
+    session.get::<i32>("user_id").ok().flatten()
+}
+
 pub struct RequireAuth;
 
 impl<S> Transform<S, ServiceRequest> for RequireAuth
@@ -52,7 +57,7 @@ where
             let session = req.get_session();
 
             // Check if user is logged in
-            if let Ok(Some(_user_id)) = session.get::<u32>("user_id") {
+            if current_user_id(&session).is_some() {
                 // User is authenticated, proceed with request
                 service.call(req).await
             } else {
@@ -1 +1,7 @@
+pub mod admin;
 pub mod auth;
+pub mod notifications;
+pub mod request_id;
+pub mod security_headers;
+pub mod session_key_rotation;
+pub mod session_validation;
@@ -0,0 +1,87 @@
+use crate::db::notification::NotificationService;
+use crate::middleware::auth::current_user_id;
+use actix_session::{Session, SessionExt};
+use actix_web::{
+    Error,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    http::Method,
+    web,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{Ready, ready},
+    rc::Rc,
+};
+
+// Session key read by create_template_context for the badge in the layout
+pub const UNREAD_NOTIFICATIONS_KEY: &str = "unread_notifications";
+
+pub fn unread_notifications(session: &Session) -> i64 {
+    session
+        .get::<i64>(UNREAD_NOTIFICATIONS_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+}
+
+// Keeps the logged in user's unread notification count in the session so
+// every page can show it. The count is refreshed from the database on page
+// loads and only written back to the session when it changed.
+pub struct LoadUnreadNotifications;
+
+impl<S, B> Transform<S, ServiceRequest> for LoadUnreadNotifications
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LoadUnreadNotificationsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LoadUnreadNotificationsMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct LoadUnreadNotificationsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for LoadUnreadNotificationsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let session = req.get_session();
+            let page_load = req.method() == Method::GET && !req.path().starts_with("/static/");
+
+            if let (true, Some(user_id)) = (page_load, current_user_id(&session)) {
+                if let Some(notifications) = req.app_data::<web::Data<NotificationService>>() {
+                    if let Ok(count) = notifications.unread_count(user_id).await {
+                        if count != unread_notifications(&session) {
+                            let _ = session.insert(UNREAD_NOTIFICATIONS_KEY, count);
+                        }
+                    }
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
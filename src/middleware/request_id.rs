@@ -0,0 +1,177 @@
+use crate::db::audit::AuditContext;
+use crate::middleware::auth::current_user_id;
+use actix_session::SessionExt;
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest,
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    http::header::{HeaderName, HeaderValue},
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{Ready, ready},
+    rc::Rc,
+};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Longest incoming request id that is passed through instead of replaced
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+// Identifier of the current request, echoed in the X-Request-Id response
+// header and recorded with audit events
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().simple().to_string())
+    }
+
+    // Keep an id set by a proxy in front of us so logs can be correlated,
+    // as long as it is short and cannot smuggle anything into logs or headers
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LENGTH
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        valid.then(|| Self(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(RequestId::generate);
+        ready(Ok(id))
+    }
+}
+
+// The logged in user, client address and request id of the current request
+impl FromRequest for AuditContext {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(AuditContext {
+            actor_id: current_user_id(&req.get_session()),
+            ip_address: req
+                .connection_info()
+                .realip_remote_addr()
+                .map(str::to_string),
+            request_id: req
+                .extensions()
+                .get::<RequestId>()
+                .map(|id| id.as_str().to_string()),
+        }))
+    }
+}
+
+// Assigns every request a RequestId and returns it in the X-Request-Id header
+pub struct AssignRequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for AssignRequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AssignRequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AssignRequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AssignRequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AssignRequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(RequestId::from_header)
+            .unwrap_or_else(RequestId::generate);
+        req.extensions_mut().insert(id.clone());
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+                res.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpResponse, test, web};
+
+    async fn echo(id: RequestId) -> HttpResponse {
+        HttpResponse::Ok().body(id.as_str().to_string())
+    }
+
+    #[actix_web::test]
+    async fn test_assigns_and_echoes_request_id() {
+        let app = test::init_service(
+            App::new()
+                .wrap(AssignRequestId)
+                .route("/", web::get().to(echo)),
+        )
+        .await;
+
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let header = resp.headers().get(REQUEST_ID_HEADER).unwrap().clone();
+        let body = test::read_body(resp).await;
+        assert_eq!(header.to_str().unwrap().len(), 32);
+        assert_eq!(header.as_bytes(), &body[..]);
+
+        // A well-formed id from upstream is kept, anything else is replaced
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "edge-42"))
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "edge-42");
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "bad id; rm -rf"))
+            .to_request();
+        assert_ne!(test::call_and_read_body(&app, req).await, "bad id; rm -rf");
+    }
+}
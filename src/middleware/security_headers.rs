@@ -0,0 +1,304 @@
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest,
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    http::header::{HeaderName, HeaderValue},
+};
+use futures_util::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::{Ready, ready},
+    rc::Rc,
+};
+
+// Per-request nonce that inline <script> tags must carry to pass the CSP
+#[derive(Debug, Clone, PartialEq)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    fn generate() -> Self {
+        CspNonce(format!("{:032x}", rand::random::<u128>()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromRequest for CspNonce {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Outside of the middleware (e.g. in tests) fall back to a fresh nonce
+        // so handlers can always render their templates
+        let nonce = req
+            .extensions()
+            .get::<CspNonce>()
+            .cloned()
+            .unwrap_or_else(CspNonce::generate);
+        ready(Ok(nonce))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameOptions {
+    Deny,
+    SameOrigin,
+}
+
+impl FrameOptions {
+    fn header_value(self) -> &'static str {
+        match self {
+            FrameOptions::Deny => "DENY",
+            FrameOptions::SameOrigin => "SAMEORIGIN",
+        }
+    }
+
+    fn frame_ancestors(self) -> &'static str {
+        match self {
+            FrameOptions::Deny => "'none'",
+            FrameOptions::SameOrigin => "'self'",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityHeadersConfig {
+    // Send Content-Security-Policy-Report-Only instead of enforcing the policy
+    pub csp_report_only: bool,
+    // Extra directives appended verbatim to the generated policy
+    pub csp_extra_directives: Option<String>,
+    // HSTS max-age in seconds, None disables the header entirely. Sent on
+    // every response, so only set it where the site is served over HTTPS.
+    pub hsts_max_age: Option<u64>,
+    pub hsts_include_subdomains: bool,
+    // htmz swaps content through a same-origin iframe, so DENY breaks it
+    pub frame_options: FrameOptions,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            csp_report_only: false,
+            csp_extra_directives: None,
+            hsts_max_age: Some(31_536_000),
+            hsts_include_subdomains: true,
+            frame_options: FrameOptions::SameOrigin,
+            referrer_policy: "strict-origin-when-cross-origin".to_string(),
+            permissions_policy: "camera=(), microphone=(), geolocation=(self), payment=()"
+                .to_string(),
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    fn content_security_policy(&self, nonce: &CspNonce) -> String {
+        let mut policy = format!(
+            "default-src 'self'; script-src 'self' 'nonce-{}'; style-src 'self'; \
+             img-src 'self' data:; font-src 'self'; connect-src 'self'; frame-src 'self'; \
+             frame-ancestors {}; form-action 'self'; base-uri 'self'; object-src 'none'",
+            nonce.as_str(),
+            self.frame_options.frame_ancestors()
+        );
+
+        if let Some(extra) = &self.csp_extra_directives {
+            policy.push_str("; ");
+            policy.push_str(extra.trim().trim_end_matches(';'));
+        }
+
+        policy
+    }
+}
+
+pub struct SecurityHeaders {
+    config: Rc<SecurityHeadersConfig>,
+}
+
+impl SecurityHeaders {
+    pub fn new(config: SecurityHeadersConfig) -> Self {
+        Self {
+            config: Rc::new(config),
+        }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SecurityHeadersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service: Rc::new(service),
+            config: self.config.clone(),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+    config: Rc<SecurityHeadersConfig>,
+}
+
+impl<S> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            // Make the nonce available to handlers before they render templates
+            let nonce = CspNonce::generate();
+            req.extensions_mut().insert(nonce.clone());
+
+            let mut res = service.call(req).await?;
+            let headers = res.headers_mut();
+
+            let csp_header = if config.csp_report_only {
+                "content-security-policy-report-only"
+            } else {
+                "content-security-policy"
+            };
+            if let Ok(value) = HeaderValue::from_str(&config.content_security_policy(&nonce)) {
+                headers.insert(HeaderName::from_static(csp_header), value);
+            }
+
+            headers.insert(
+                HeaderName::from_static("x-frame-options"),
+                HeaderValue::from_static(config.frame_options.header_value()),
+            );
+            headers.insert(
+                HeaderName::from_static("x-content-type-options"),
+                HeaderValue::from_static("nosniff"),
+            );
+            if let Ok(value) = HeaderValue::from_str(&config.referrer_policy) {
+                headers.insert(HeaderName::from_static("referrer-policy"), value);
+            }
+            if let Ok(value) = HeaderValue::from_str(&config.permissions_policy) {
+                headers.insert(HeaderName::from_static("permissions-policy"), value);
+            }
+
+            if let Some(max_age) = config.hsts_max_age {
+                let mut value = format!("max-age={}", max_age);
+                if config.hsts_include_subdomains {
+                    value.push_str("; includeSubDomains");
+                }
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    headers.insert(HeaderName::from_static("strict-transport-security"), value);
+                }
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpResponse, test, web};
+
+    async fn echo_nonce(nonce: CspNonce) -> HttpResponse {
+        HttpResponse::Ok().body(nonce.as_str().to_string())
+    }
+
+    #[actix_web::test]
+    async fn test_sets_security_headers_with_request_nonce() {
+        let app = test::init_service(
+            App::new()
+                .wrap(SecurityHeaders::new(SecurityHeadersConfig::default()))
+                .route("/", web::get().to(echo_nonce)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        let headers = resp.headers().clone();
+        let nonce = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+        let csp = headers
+            .get("content-security-policy")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(csp.contains(&format!("'nonce-{}'", nonce)));
+        assert!(csp.contains("frame-ancestors 'self'"));
+        assert_eq!(headers.get("x-frame-options").unwrap(), "SAMEORIGIN");
+        assert_eq!(headers.get("x-content-type-options").unwrap(), "nosniff");
+        assert!(headers.get("referrer-policy").is_some());
+        assert!(headers.get("permissions-policy").is_some());
+        assert_eq!(
+            headers.get("strict-transport-security").unwrap(),
+            "max-age=31536000; includeSubDomains"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_nonce_changes_between_requests() {
+        let app = test::init_service(
+            App::new()
+                .wrap(SecurityHeaders::new(SecurityHeadersConfig::default()))
+                .route("/", web::get().to(echo_nonce)),
+        )
+        .await;
+
+        let first =
+            test::call_and_read_body(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let second =
+            test::call_and_read_body(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_ne!(first, second);
+    }
+
+    #[actix_web::test]
+    async fn test_hsts_ignores_forwarded_scheme() {
+        let config = SecurityHeadersConfig {
+            csp_report_only: true,
+            hsts_max_age: None,
+            frame_options: FrameOptions::Deny,
+            ..SecurityHeadersConfig::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(SecurityHeaders::new(config))
+                .route("/", web::get().to(echo_nonce)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header(("X-Forwarded-Proto", "https"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let headers = resp.headers();
+
+        // A client claiming HTTPS does not turn HSTS on
+        assert!(headers.get("strict-transport-security").is_none());
+        assert!(headers.get("content-security-policy").is_none());
+        let csp = headers
+            .get("content-security-policy-report-only")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(csp.contains("frame-ancestors 'none'"));
+        assert_eq!(headers.get("x-frame-options").unwrap(), "DENY");
+    }
+}
@@ -0,0 +1,225 @@
+use actix_web::{
+    Error,
+    cookie::{Cookie, CookieJar, Key},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    http::header::{COOKIE, HeaderValue},
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{Ready, ready},
+    rc::Rc,
+};
+
+// Accepts session cookies encrypted with a previous key by re-encrypting them
+// with the current key before the session middleware sees them.
+// Must be registered as an outer wrapper of the SessionMiddleware.
+pub struct SessionKeyRotation {
+    inner: Rc<RotationKeys>,
+}
+
+struct RotationKeys {
+    cookie_name: String,
+    current: Key,
+    previous: Vec<Key>,
+}
+
+impl SessionKeyRotation {
+    pub fn new(cookie_name: String, current: Key, previous: Vec<Key>) -> Self {
+        Self {
+            inner: Rc::new(RotationKeys {
+                cookie_name,
+                current,
+                previous,
+            }),
+        }
+    }
+}
+
+impl RotationKeys {
+    // Returns the cookie re-encrypted with the current key, or None when the
+    // cookie is already current or was not produced by any known key
+    fn reencrypt(&self, cookie: &Cookie<'static>) -> Option<String> {
+        let mut jar = CookieJar::new();
+        jar.add_original(cookie.clone());
+
+        if jar.private(&self.current).get(&self.cookie_name).is_some() {
+            return None;
+        }
+
+        let plain = self
+            .previous
+            .iter()
+            .find_map(|key| jar.private(key).get(&self.cookie_name))?;
+
+        let mut fresh = CookieJar::new();
+        fresh.private_mut(&self.current).add(plain);
+        fresh
+            .get(&self.cookie_name)
+            .map(|cookie| cookie.stripped().encoded().to_string())
+    }
+}
+
+// Generic over the body so it can sit outside of body-wrapping middleware like Logger
+impl<S, B> Transform<S, ServiceRequest> for SessionKeyRotation
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SessionKeyRotationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SessionKeyRotationMiddleware {
+            service: Rc::new(service),
+            keys: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct SessionKeyRotationMiddleware<S> {
+    service: Rc<S>,
+    keys: Rc<RotationKeys>,
+}
+
+impl<S, B> Service<ServiceRequest> for SessionKeyRotationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        if !self.keys.previous.is_empty() {
+            // Parse the raw header rather than req.cookies(), which would cache
+            // the original values before we get a chance to rewrite them
+            let cookies: Vec<Cookie<'static>> = req
+                .headers()
+                .get_all(COOKIE)
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'))
+                .filter_map(|pair| Cookie::parse_encoded(pair.trim().to_string()).ok())
+                .collect();
+
+            let mut rotated = false;
+            let rewritten: Vec<String> = cookies
+                .iter()
+                .map(|cookie| {
+                    if cookie.name() == self.keys.cookie_name {
+                        if let Some(value) = self.keys.reencrypt(cookie) {
+                            rotated = true;
+                            return value;
+                        }
+                    }
+                    cookie.stripped().encoded().to_string()
+                })
+                .collect();
+
+            if rotated {
+                if let Ok(value) = HeaderValue::from_str(&rewritten.join("; ")) {
+                    req.headers_mut().insert(COOKIE, value);
+                }
+            }
+        }
+
+        Box::pin(async move { service.call(req).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_session::{Session, SessionMiddleware, storage::CookieSessionStore};
+    use actix_web::{App, HttpResponse, test, web};
+
+    async fn login(session: Session) -> HttpResponse {
+        session.insert("user_id", 42).unwrap();
+        HttpResponse::Ok().finish()
+    }
+
+    async fn whoami(session: Session) -> HttpResponse {
+        match session.get::<i32>("user_id").unwrap() {
+            Some(id) => HttpResponse::Ok().body(id.to_string()),
+            None => HttpResponse::Unauthorized().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_cookie_from_previous_key_is_accepted() {
+        let old_key = Key::generate();
+        let new_key = Key::generate();
+
+        // Issue a session cookie with the old key
+        let old_app = test::init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    old_key.clone(),
+                ))
+                .route("/login", web::post().to(login)),
+        )
+        .await;
+        let resp = test::call_service(
+            &old_app,
+            test::TestRequest::post().uri("/login").to_request(),
+        )
+        .await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+
+        // The rotated app only trusts the new key but still knows the old one
+        let app = test::init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    new_key.clone(),
+                ))
+                .wrap(SessionKeyRotation::new(
+                    "id".to_string(),
+                    new_key,
+                    vec![old_key],
+                ))
+                .route("/whoami", web::get().to(whoami)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/whoami")
+            .cookie(cookie)
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "42");
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_key_is_rejected() {
+        let app = test::init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                ))
+                .wrap(SessionKeyRotation::new(
+                    "id".to_string(),
+                    Key::generate(),
+                    vec![Key::generate()],
+                ))
+                .route("/whoami", web::get().to(whoami)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/whoami")
+            .cookie(Cookie::new("id", "garbage"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::db::user::UserService;
+use crate::middleware::auth::current_user_id;
+use actix_session::{Session, SessionExt};
+use actix_web::{
+    Error,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    web,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{Ready, ready},
+    rc::Rc,
+};
+
+// Session key holding the user's session version at login
+pub const SESSION_VERSION_KEY: &str = "session_version";
+
+// Sessions from before versions were recorded count as version 0
+fn session_version(session: &Session) -> i32 {
+    session
+        .get::<i32>(SESSION_VERSION_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+}
+
+// Ends sessions the user no longer should have. Sessions live in the cookie,
+// so they cannot be revoked on the server: instead each one carries the
+// user's session version, which changing the password bumps, and is cleared
+// when it no longer matches or the account was deleted.
+pub struct ValidateSession;
+
+impl<S, B> Transform<S, ServiceRequest> for ValidateSession
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ValidateSessionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ValidateSessionMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ValidateSessionMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ValidateSessionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let session = req.get_session();
+
+            if let (false, Some(user_id)) = (
+                req.path().starts_with("/static/"),
+                current_user_id(&session),
+            ) {
+                if let Some(users) = req.app_data::<web::Data<UserService>>() {
+                    // A database error lets the request through rather than
+                    // logging everybody out
+                    match users.session_version(user_id).await {
+                        Ok(Some(version)) if version == session_version(&session) => {}
+                        Ok(_) => session.purge(),
+                        Err(e) => eprintln!("Failed to check session of user {}: {}", user_id, e),
+                    }
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use crate::db::connection::DbPool;
+use crate::db::realtime::{CHANNEL, Update};
+use sqlx::postgres::PgListener;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+// Updates kept for slow subscribers before they start missing some
+const BUFFER: usize = 256;
+// Wait before listening again after losing the database connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// Relays the updates published by any server instance, see
+// crate::db::realtime::publish, to the browsers connected to this one
+#[derive(Clone)]
+pub struct Broadcaster {
+    sender: broadcast::Sender<Update>,
+}
+
+impl Broadcaster {
+    // Listen for updates on a dedicated database connection
+    pub async fn start(pool: &DbPool) -> Result<Self, sqlx::Error> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(CHANNEL).await?;
+
+        let (sender, _) = broadcast::channel(BUFFER);
+        let broadcaster = Self { sender };
+        tokio::spawn(broadcaster.clone().relay(listener));
+
+        Ok(broadcaster)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Update> {
+        self.sender.subscribe()
+    }
+
+    async fn relay(self, mut listener: PgListener) {
+        loop {
+            // The listener reconnects by itself on the next call; updates sent
+            // in the meantime are lost, pages catch up when reloaded
+            let notification = match listener.recv().await {
+                Ok(notification) => notification,
+                Err(e) => {
+                    eprintln!("Lost the connection listening for updates: {}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+
+            match serde_json::from_str::<Update>(notification.payload()) {
+                // No one connected is not an error
+                Ok(update) => {
+                    let _ = self.sender.send(update);
+                }
+                Err(e) => eprintln!("Ignoring malformed update: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::audit::AuditContext;
+    use crate::db::connection::create_test_pool;
+    use crate::db::group::GroupService;
+    use crate::db::realtime::publish;
+
+    async fn next(updates: &mut broadcast::Receiver<Update>) -> Update {
+        tokio::time::timeout(Duration::from_secs(5), updates.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_relays_committed_updates() {
+        let db = create_test_pool().await.unwrap();
+        let groups = GroupService::new(db.pool.clone());
+        let broadcaster = Broadcaster::start(&db.pool).await.unwrap();
+        let mut updates = broadcaster.subscribe();
+
+        let group = groups
+            .create("Readers".to_string(), &AuditContext::system())
+            .await
+            .unwrap();
+        assert_eq!(next(&mut updates).await, Update::group_created(&group));
+
+        // Updates of a transaction rolled back are never sent
+        let mut tx = db.pool.begin().await.unwrap();
+        publish(&mut tx, &Update::GroupDeleted { id: 1 })
+            .await
+            .unwrap();
+        tx.rollback().await.unwrap();
+        let mut tx = db.pool.begin().await.unwrap();
+        publish(&mut tx, &Update::GroupDeleted { id: 2 })
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(next(&mut updates).await, Update::GroupDeleted { id: 2 });
+    }
+}
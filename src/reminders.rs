@@ -0,0 +1,427 @@
+use crate::api::notifications::notify;
+use crate::calendar;
+use crate::config::ServerConfig;
+use crate::db::{
+    event::{EventError, EventService},
+    job::{JobError, JobService, NewJob},
+    models::{Event, NotificationKind, RsvpStatus, User},
+    notification::{NewNotification, NotificationService},
+    rsvp::{RsvpError, RsvpService},
+};
+use crate::mail::Email;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+use thiserror::Error;
+
+// Scheduled job queueing the reminders coming up
+pub const SCHEDULE_REMINDERS_JOB: &str = "schedule_event_reminders";
+// Sends one reminder of one event to its attendees
+pub const SEND_REMINDER_JOB: &str = "send_event_reminder";
+
+// Reminders are queued this long before they are due
+const QUEUE_AHEAD: Duration = Duration::hours(1);
+// Reminders due longer ago are skipped, e.g. for an event created an hour
+// before it starts there is no point in a "starts in 24 hours" reminder
+const GRACE: Duration = Duration::minutes(10);
+
+#[derive(Debug, Error)]
+pub enum ReminderError {
+    #[error("Event error: {0}")]
+    Event(#[from] EventError),
+    #[error("RSVP error: {0}")]
+    Rsvp(#[from] RsvpError),
+    #[error("Job error: {0}")]
+    Job(#[from] JobError),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReminderPayload {
+    pub event_id: i32,
+    // When the event starts, as it was when the reminder was queued
+    pub starts_at: DateTime<Utc>,
+    pub offset_minutes: i64,
+}
+
+impl ReminderPayload {
+    // One reminder per event, start and offset, however often the schedule
+    // runs. A rescheduled event gets reminders for its new start.
+    fn unique_key(&self) -> String {
+        format!(
+            "event_reminder:{}:{}:{}",
+            self.event_id,
+            self.starts_at.timestamp(),
+            self.offset_minutes
+        )
+    }
+}
+
+// Starts in (from, to] of the event, or of the occurrences of a recurring one
+fn starts_between(event: &Event, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    calendar::occurrences(event, "", from, to + Duration::seconds(1))
+        .into_iter()
+        .map(|occurrence| occurrence.starts_at)
+        .filter(|starts_at| *starts_at > from && *starts_at <= to)
+        .collect()
+}
+
+// Whether the event, or one of its occurrences, still starts at `at`
+fn starts_at(event: &Event, at: DateTime<Utc>) -> bool {
+    !starts_between(event, at - Duration::seconds(1), at).is_empty()
+}
+
+// Reminders to queue now, with the time they are due
+fn due_reminders(
+    events: &[Event],
+    offsets: &[Duration],
+    now: DateTime<Utc>,
+) -> Vec<(ReminderPayload, DateTime<Utc>)> {
+    events
+        .iter()
+        .flat_map(|event| {
+            offsets.iter().flat_map(move |offset| {
+                let (from, to) = (now - GRACE + *offset, now + QUEUE_AHEAD + *offset);
+                starts_between(event, from, to)
+                    .into_iter()
+                    .map(move |starts_at| {
+                        let payload = ReminderPayload {
+                            event_id: event.id,
+                            starts_at,
+                            offset_minutes: offset.num_minutes(),
+                        };
+                        (payload, starts_at - *offset)
+                    })
+            })
+        })
+        .collect()
+}
+
+// Queue the reminders due within the next hour. Queueing is idempotent
+// thanks to the jobs' unique keys, so running this often, or again after a
+// restart, never sends a reminder twice. Returns the number queued.
+pub async fn schedule_reminders(
+    events: &EventService,
+    jobs: &JobService,
+    offsets: &[Duration],
+    now: DateTime<Utc>,
+) -> Result<usize, ReminderError> {
+    let Some(longest) = offsets.iter().max() else {
+        return Ok(0);
+    };
+    let upcoming = events
+        .list_starting_between(now, now + *longest + QUEUE_AHEAD)
+        .await?;
+
+    let mut queued = 0;
+    for (payload, remind_at) in due_reminders(&upcoming, offsets, now) {
+        let job = NewJob::new(SEND_REMINDER_JOB, &payload)?
+            .run_at(remind_at)
+            .unique_key(payload.unique_key());
+        if jobs.enqueue(job).await?.is_some() {
+            queued += 1;
+        }
+    }
+
+    Ok(queued)
+}
+
+// "Sat 1 Mar 2026 at 18:30 CET", in the user's time zone
+pub fn local_time(at: DateTime<Utc>, user: &User) -> String {
+    at.with_timezone(&user.tz())
+        .format("%a %-d %b %Y at %H:%M %Z")
+        .to_string()
+}
+
+fn reminder_email(
+    tmpl: &Tera,
+    user: &User,
+    event: &Event,
+    starts_at: DateTime<Utc>,
+    url: &str,
+) -> Result<Email, tera::Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("event_title", &event.title);
+    ctx.insert("starts_at", &local_time(starts_at, user));
+    ctx.insert("location", &event.location);
+    // Reminders go to members going, who may see the link
+    ctx.insert("meeting_url", &event.meeting_url);
+    ctx.insert("url", url);
+
+    Ok(Email {
+        to: user.email.clone(),
+        subject: format!("Reminder: {}", event.title),
+        text: tmpl.render("emails/event_reminder.txt", &ctx)?,
+        html: None,
+        unsubscribe_url: None,
+    })
+}
+
+// Remind everyone going to the event, in the app and by email according to
+// their preferences. Events deleted, started or rescheduled since are skipped.
+// RSVPs to a recurring event hold for every occurrence.
+pub struct ReminderSender {
+    pub events: EventService,
+    pub rsvps: RsvpService,
+    pub notifications: NotificationService,
+    pub jobs: JobService,
+    pub tmpl: Tera,
+    pub server: ServerConfig,
+}
+
+impl ReminderSender {
+    pub async fn send(
+        &self,
+        payload: &ReminderPayload,
+        now: DateTime<Utc>,
+    ) -> Result<usize, ReminderError> {
+        let event = match self.events.get(payload.event_id).await? {
+            Some(event) if payload.starts_at > now && starts_at(&event, payload.starts_at) => event,
+            _ => return Ok(0),
+        };
+        let attendees = self.rsvps.attendees(event.id, RsvpStatus::Going).await?;
+
+        // Nothing fails past this point, so a retried job cannot remind
+        // anyone twice
+        let link = format!("/events/{}", event.id);
+        let url = self.server.url(&link);
+        for user in &attendees {
+            let notification = NewNotification {
+                kind: NotificationKind::Reminder,
+                actor_id: None,
+                message: format!(
+                    "Reminder: {} starts {}",
+                    event.title,
+                    local_time(payload.starts_at, user)
+                ),
+                url: link.clone(),
+            };
+            notify(
+                &self.notifications,
+                &self.jobs,
+                &[user.id],
+                &notification,
+                |user| reminder_email(&self.tmpl, user, &event, payload.starts_at, &url),
+            )
+            .await;
+        }
+
+        Ok(attendees.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::audit::AuditContext;
+    use crate::db::connection::create_test_pool;
+    use crate::db::event::NewEvent;
+    use crate::db::group::GroupService;
+    use crate::db::models::{ProfileVisibility, Recurrence};
+
+    fn event(id: i32, starts_at: DateTime<Utc>) -> Event {
+        Event {
+            id,
+            group_id: 1,
+            title: "Meetup".to_string(),
+            starts_at,
+            created_at: starts_at,
+            time_zone: "UTC".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_due_reminders() {
+        let now = "2026-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let offsets = [Duration::hours(24), Duration::hours(1)];
+        let events = [
+            // 24h reminder due in 30 minutes
+            event(1, now + Duration::minutes(24 * 60 + 30)),
+            // 1h reminder due 5 minutes ago, 24h one long gone
+            event(2, now + Duration::minutes(55)),
+            // 1h reminder missed by more than the grace period
+            event(3, now + Duration::minutes(30)),
+        ];
+
+        let due: Vec<(i32, i64)> = due_reminders(&events, &offsets, now)
+            .into_iter()
+            .map(|(payload, _)| (payload.event_id, payload.offset_minutes))
+            .collect();
+        assert_eq!(due, vec![(1, 24 * 60), (2, 60)]);
+    }
+
+    #[test]
+    fn test_due_reminders_of_recurring_events() {
+        let now = "2026-03-10T17:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let offsets = [Duration::hours(24), Duration::hours(1)];
+        let mut weekly = event(1, "2026-03-03T18:00:00Z".parse().unwrap());
+        weekly.recurrence = Some(Recurrence::Weekly);
+
+        let due: Vec<(DateTime<Utc>, i64)> = due_reminders(&[weekly.clone()], &offsets, now)
+            .into_iter()
+            .map(|(payload, _)| (payload.starts_at, payload.offset_minutes))
+            .collect();
+        assert_eq!(due, vec![(now + Duration::hours(1), 60)]);
+
+        // Over once the series ends
+        weekly.recurs_until = Some("2026-03-09T00:00:00Z".parse().unwrap());
+        assert!(due_reminders(&[weekly], &offsets, now).is_empty());
+    }
+
+    #[test]
+    fn test_starts_at_follows_the_event() {
+        let first = "2026-03-03T18:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let mut meetup = event(1, first);
+        assert!(starts_at(&meetup, first));
+        assert!(!starts_at(&meetup, first + Duration::weeks(1)));
+
+        meetup.recurrence = Some(Recurrence::Weekly);
+        assert!(starts_at(&meetup, first + Duration::weeks(1)));
+        assert!(!starts_at(&meetup, first + Duration::days(1)));
+
+        // Rescheduled series no longer take place at the old times
+        meetup.starts_at = first + Duration::hours(1);
+        assert!(!starts_at(&meetup, first + Duration::weeks(1)));
+    }
+
+    #[test]
+    fn test_unique_key_changes_with_the_start() {
+        let starts_at = "2026-03-01T18:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let payload = ReminderPayload {
+            event_id: 4,
+            starts_at,
+            offset_minutes: 60,
+        };
+        let rescheduled = ReminderPayload {
+            starts_at: starts_at + Duration::days(1),
+            ..payload.clone()
+        };
+
+        assert_eq!(payload.unique_key(), "event_reminder:4:1772388000:60");
+        assert_ne!(payload.unique_key(), rescheduled.unique_key());
+    }
+
+    #[test]
+    fn test_local_time() {
+        let at = "2026-03-01T17:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        let user = |time_zone: &str| User {
+            id: 1,
+            email: "ada@example.com".to_string(),
+            name: "Ada".to_string(),
+            password_hash: None,
+            created_at: at,
+            updated_at: at,
+            deleted_at: None,
+            is_admin: false,
+            time_zone: time_zone.to_string(),
+            bio: String::new(),
+            location: String::new(),
+            interests: Vec::new(),
+            profile_visibility: ProfileVisibility::default(),
+            show_location: true,
+            show_groups: true,
+            avatar_key: None,
+            session_version: 0,
+        };
+
+        assert_eq!(
+            local_time(at, &user("Europe/Paris")),
+            "Sun 1 Mar 2026 at 18:30 CET"
+        );
+        assert_eq!(
+            local_time(at, &user("Not/AZone")),
+            "Sun 1 Mar 2026 at 17:30 UTC"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_schedule_reminders_queues_each_once() {
+        let db = create_test_pool().await.unwrap();
+        let ctx = AuditContext::system();
+        let groups = GroupService::new(db.pool.clone());
+        let events = EventService::new(db.pool.clone());
+        let jobs = JobService::new(db.pool.clone());
+
+        let now = "2026-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let offsets = [Duration::hours(24), Duration::hours(1)];
+        let new_event = |starts_at| NewEvent {
+            title: "Meetup".to_string(),
+            starts_at,
+            ..Default::default()
+        };
+        let group = groups.create("Readers".to_string(), &ctx).await.unwrap();
+        let tomorrow = now + Duration::minutes(24 * 60 + 30);
+        let tomorrow = events
+            .create(group.id, new_event(tomorrow), &ctx)
+            .await
+            .unwrap();
+        let soon = now + Duration::minutes(55);
+        let soon = events
+            .create(group.id, new_event(soon), &ctx)
+            .await
+            .unwrap();
+        let gone = groups.create("Hikers".to_string(), &ctx).await.unwrap();
+        let in_gone = now + Duration::minutes(50);
+        events
+            .create(gone.id, new_event(in_gone), &ctx)
+            .await
+            .unwrap();
+        groups.delete(gone.id, &ctx).await.unwrap();
+
+        assert_eq!(
+            schedule_reminders(&events, &jobs, &offsets, now)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            schedule_reminders(&events, &jobs, &offsets, now)
+                .await
+                .unwrap(),
+            0
+        );
+        let mut queued: Vec<(ReminderPayload, DateTime<Utc>)> = jobs
+            .list_recent(None, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|job| {
+                assert_eq!(job.kind, SEND_REMINDER_JOB);
+                (serde_json::from_str(&job.payload).unwrap(), job.run_at)
+            })
+            .collect();
+        queued.sort_by_key(|(payload, _)| payload.event_id);
+        let expected = [
+            (tomorrow.id, tomorrow.starts_at, 24 * 60),
+            (soon.id, soon.starts_at, 60),
+        ];
+        for ((payload, run_at), (event_id, starts_at, offset_minutes)) in
+            queued.iter().zip(expected)
+        {
+            assert_eq!(
+                *payload,
+                ReminderPayload {
+                    event_id,
+                    starts_at,
+                    offset_minutes,
+                }
+            );
+            assert_eq!(*run_at, starts_at - Duration::minutes(offset_minutes));
+        }
+
+        // A rescheduled event is reminded of its new start
+        sqlx::query(
+            "UPDATE events SET starts_at = starts_at + INTERVAL '20 minutes' WHERE id = $1",
+        )
+        .bind(soon.id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            schedule_reminders(&events, &jobs, &offsets, now)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+}
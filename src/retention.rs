@@ -0,0 +1,94 @@
+use crate::db::{
+    audit::AuditContext,
+    group::{GroupError, GroupService},
+    models::Group,
+};
+use chrono::{DateTime, Duration, Utc};
+
+// When a soft-deleted group will be purged, None for groups that are not deleted
+pub fn purge_at(group: &Group, retention: Duration) -> Option<DateTime<Utc>> {
+    group.deleted_at.map(|deleted_at| deleted_at + retention)
+}
+
+// Human readable time left before a purge, e.g. "3 days 4 hours"
+pub fn format_remaining(remaining: Duration) -> String {
+    if remaining <= Duration::zero() {
+        return "purge pending".to_string();
+    }
+
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("1 {}", unit)
+        } else {
+            format!("{} {}s", n, unit)
+        }
+    };
+
+    let days = remaining.num_days();
+    let hours = remaining.num_hours() % 24;
+    let minutes = remaining.num_minutes() % 60;
+
+    match (days, hours) {
+        (0, 0) => plural(minutes.max(1), "minute"),
+        (0, _) => plural(hours, "hour"),
+        (_, 0) => plural(days, "day"),
+        _ => format!("{} {}", plural(days, "day"), plural(hours, "hour")),
+    }
+}
+
+// Hard delete every group that has been in the trash longer than the retention
+pub async fn purge_expired_groups(
+    groups: &GroupService,
+    retention: Duration,
+) -> Result<Vec<Group>, GroupError> {
+    let expired = groups.list_deleted_before(Utc::now() - retention).await?;
+    for group in &expired {
+        groups
+            .hard_delete(group.id, &AuditContext::system())
+            .await?;
+    }
+
+    Ok(expired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::JoinPolicy;
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!(
+            format_remaining(Duration::days(3) + Duration::hours(4)),
+            "3 days 4 hours"
+        );
+        assert_eq!(format_remaining(Duration::days(1)), "1 day");
+        assert_eq!(format_remaining(Duration::minutes(90)), "1 hour");
+        assert_eq!(format_remaining(Duration::seconds(20)), "1 minute");
+        assert_eq!(format_remaining(Duration::seconds(-5)), "purge pending");
+    }
+
+    #[test]
+    fn test_purge_at() {
+        let deleted_at = Utc::now();
+        let mut group = Group {
+            id: 1,
+            name: "Rustaceans".to_string(),
+            created_at: deleted_at,
+            deleted_at: None,
+            owner_id: None,
+            join_policy: JoinPolicy::Open,
+            cover_key: None,
+            location: None,
+            latitude: None,
+            longitude: None,
+        };
+        assert_eq!(purge_at(&group, Duration::days(30)), None);
+
+        group.deleted_at = Some(deleted_at);
+        assert_eq!(
+            purge_at(&group, Duration::days(30)),
+            Some(deleted_at + Duration::days(30))
+        );
+    }
+}
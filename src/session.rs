@@ -0,0 +1,119 @@
+use crate::config::{Config, Environment, SameSitePolicy};
+use actix_session::{
+    SessionMiddleware,
+    config::{PersistentSession, TtlExtensionPolicy},
+    storage::CookieSessionStore,
+};
+use actix_web::cookie::{Key, SameSite, time::Duration};
+
+// Everything needed to build the session middleware
+#[derive(Clone)]
+pub struct SessionSettings {
+    pub environment: Environment,
+    pub key: Key,
+    // Keys that were used before the current one; cookies encrypted with them
+    // are still accepted and transparently re-encrypted with the current key
+    pub previous_keys: Vec<Key>,
+    pub cookie_name: String,
+    pub cookie_secure: bool,
+    pub cookie_same_site: SameSite,
+    // Idle timeout, extended on every request
+    pub ttl: Duration,
+}
+
+impl SessionSettings {
+    // Expects a validated Config: production always has a usable key by then,
+    // development falls back to a generated one
+    pub fn from_config(config: &Config) -> Self {
+        let session = &config.session;
+
+        let key = match session.secret_key.as_deref().map(parse_key) {
+            Some(Some(key)) => key,
+            Some(None) => {
+                eprintln!("Warning: SESSION_SECRET_KEY too short, using generated key");
+                Key::generate()
+            }
+            None => {
+                eprintln!(
+                    "Warning: SESSION_SECRET_KEY not set, using generated key (not suitable for production)"
+                );
+                Key::generate()
+            }
+        };
+
+        Self {
+            environment: config.environment,
+            key,
+            previous_keys: session
+                .previous_keys
+                .iter()
+                .filter_map(|k| parse_key(k))
+                .collect(),
+            cookie_name: session.cookie_name.clone(),
+            cookie_secure: session
+                .cookie_secure
+                .unwrap_or(config.environment.is_production()),
+            cookie_same_site: match session.cookie_same_site {
+                SameSitePolicy::Lax => SameSite::Lax,
+                SameSitePolicy::Strict => SameSite::Strict,
+            },
+            ttl: Duration::seconds(session.ttl_seconds),
+        }
+    }
+
+    pub fn middleware(&self) -> SessionMiddleware<CookieSessionStore> {
+        SessionMiddleware::builder(CookieSessionStore::default(), self.key.clone())
+            .cookie_name(self.cookie_name.clone())
+            .cookie_secure(self.cookie_secure)
+            .cookie_http_only(true)
+            .cookie_same_site(self.cookie_same_site)
+            .session_lifecycle(
+                PersistentSession::default()
+                    .session_ttl(self.ttl)
+                    // Re-issuing the cookie on every request gives a sliding idle
+                    // timeout and migrates cookies encrypted with a previous key
+                    .session_ttl_extension_policy(TtlExtensionPolicy::OnEveryRequest),
+            )
+            .build()
+    }
+}
+
+fn parse_key(secret: &str) -> Option<Key> {
+    Key::try_from(secret.as_bytes()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const KEY_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    #[test]
+    fn test_development_falls_back_to_generated_key() {
+        let mut config = Config::default();
+        config.session.secret_key = Some("short".to_string());
+
+        let settings = SessionSettings::from_config(&config);
+        assert_eq!(settings.environment, Environment::Development);
+        assert!(!settings.cookie_secure);
+    }
+
+    #[test]
+    fn test_production_defaults() {
+        let mut config = Config {
+            environment: Environment::Production,
+            ..Config::default()
+        };
+        config.session.secret_key = Some(KEY_A.to_string());
+        config.session.previous_keys = vec![KEY_B.to_string()];
+        config.session.ttl_seconds = 3600;
+
+        let settings = SessionSettings::from_config(&config);
+        assert!(settings.key == Key::from(KEY_A.as_bytes()));
+        assert!(settings.cookie_secure);
+        assert_eq!(settings.cookie_same_site, SameSite::Lax);
+        assert_eq!(settings.previous_keys.len(), 1);
+        assert_eq!(settings.ttl, Duration::hours(1));
+    }
+}
@@ -0,0 +1,16 @@
+// Wire the htmz iframe from a static script instead of an inline onload
+// handler, so the page keeps working under a nonce-based Content-Security-Policy.
+document.addEventListener('DOMContentLoaded', () => {
+    const frame = document.querySelector('iframe[name="htmz"]');
+    if (!frame) {
+        return;
+    }
+
+    frame.addEventListener('load', () => {
+        setTimeout(() =>
+            document
+                .querySelector(frame.contentWindow.location.hash || null)
+                ?.replaceWith(...frame.contentDocument.body.childNodes)
+        );
+    });
+});
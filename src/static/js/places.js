@@ -0,0 +1,48 @@
+// Suggest cities from the offline geocoding table while typing a place, and
+// fill in the browser's position on request.
+document.addEventListener('DOMContentLoaded', () => {
+    for (const input of document.querySelectorAll('[data-city-input]')) {
+        const list = input.list;
+        if (!list) {
+            continue;
+        }
+
+        let pending;
+        input.addEventListener('input', () => {
+            clearTimeout(pending);
+            pending = setTimeout(async () => {
+                const typed = input.value.trim();
+                // Coordinates need no suggestions
+                if (!typed || /^-?\d/.test(typed)) {
+                    list.replaceChildren();
+                    return;
+                }
+
+                const response = await fetch(`/api/cities?q=${encodeURIComponent(typed)}`);
+                if (!response.ok) {
+                    return;
+                }
+                const options = (await response.json()).map((city) => {
+                    const option = document.createElement('option');
+                    option.value = `${city.name}, ${city.country_code}`;
+                    return option;
+                });
+                list.replaceChildren(...options);
+            }, 150);
+        });
+    }
+
+    const locate = document.querySelector('[data-locate]');
+    const near = locate?.form.elements.near;
+    if (!locate || !near || !navigator.geolocation) {
+        return;
+    }
+    locate.hidden = false;
+    locate.addEventListener('click', () => {
+        navigator.geolocation.getCurrentPosition((position) => {
+            const { latitude, longitude } = position.coords;
+            near.value = `${latitude.toFixed(4)}, ${longitude.toFixed(4)}`;
+            locate.form.submit();
+        });
+    });
+});
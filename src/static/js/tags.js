@@ -0,0 +1,39 @@
+// Suggest existing tags while typing in a comma separated tag input. The
+// browser matches datalist options against the whole value, so each option
+// is the tags already typed followed by a suggestion for the last one.
+document.addEventListener('DOMContentLoaded', () => {
+    for (const input of document.querySelectorAll('[data-tag-input]')) {
+        const list = input.list;
+        if (!list) {
+            continue;
+        }
+
+        let pending;
+        input.addEventListener('input', () => {
+            clearTimeout(pending);
+            pending = setTimeout(async () => {
+                const terms = input.value.split(',');
+                const last = terms.pop().trim();
+                if (!last) {
+                    list.replaceChildren();
+                    return;
+                }
+
+                const response = await fetch(`/api/tags?q=${encodeURIComponent(last)}`);
+                if (!response.ok) {
+                    return;
+                }
+                const typed = terms.map((term) => term.trim()).filter(Boolean);
+                const options = (await response.json())
+                    .filter((tag) => !typed.includes(tag.name))
+                    .map((tag) => {
+                        const option = document.createElement('option');
+                        option.value = [...typed, tag.name].join(', ');
+                        option.label = `${tag.name} (${tag.group_count})`;
+                        return option;
+                    });
+                list.replaceChildren(...options);
+            }, 150);
+        });
+    }
+});
@@ -0,0 +1,75 @@
+// Live updates from the server-sent events at /updates: the group list, the
+// RSVP counts of the event page and the unread notifications badge. Updates
+// from every server instance arrive here through PostgreSQL LISTEN/NOTIFY.
+document.addEventListener('DOMContentLoaded', () => {
+    if (!window.EventSource) {
+        return;
+    }
+
+    const groups = document.querySelector('[data-live-groups]');
+    const event = document.querySelector('[data-live-event]');
+    const badge = document.querySelector('.notifications-link .badge');
+    if (!groups && !event && !badge) {
+        return;
+    }
+
+    const url = event ? `/updates?event=${event.dataset.liveEvent}` : '/updates';
+    const source = new EventSource(url);
+    const data = (message) => JSON.parse(message.data);
+
+    const showEmptyMessage = () => {
+        const empty = document.querySelector('.group-list-empty');
+        if (empty) {
+            empty.hidden = groups.children.length > 0;
+        }
+    };
+
+    source.addEventListener('group_created', (message) => {
+        const group = data(message);
+        if (!groups || document.getElementById(`group-${group.id}`)) {
+            return;
+        }
+
+        const item = document.createElement('div');
+        item.className = 'group-item';
+        item.id = `group-${group.id}`;
+        const title = document.createElement('h3');
+        const link = document.createElement('a');
+        link.href = `/groups/${group.id}/join`;
+        link.textContent = group.name;
+        title.append(link);
+        const created = document.createElement('p');
+        created.textContent = `Created: ${group.created_at.slice(0, 16).replace('T', ' ')}`;
+        item.append(title, created);
+
+        groups.prepend(item);
+        showEmptyMessage();
+    });
+
+    source.addEventListener('group_deleted', (message) => {
+        if (!groups) {
+            return;
+        }
+        document.getElementById(`group-${data(message).id}`)?.remove();
+        showEmptyMessage();
+    });
+
+    source.addEventListener('rsvp_counts', (message) => {
+        const counts = data(message);
+        for (const status of ['going', 'maybe', 'not_going']) {
+            const count = event?.querySelector(`[data-rsvp="${status}"]`);
+            if (count) {
+                count.textContent = counts[status];
+            }
+        }
+    });
+
+    source.addEventListener('notifications', (message) => {
+        if (!badge) {
+            return;
+        }
+        const unread = data(message).unread;
+        badge.textContent = unread;
+        badge.hidden = unread === 0;
+    });
+});
@@ -0,0 +1,86 @@
+use super::{Blob, BlobError, BlobStore, check_key, content_type};
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::fs;
+
+// Files in a directory of the server; the content type follows the extension
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, BlobError> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, blob: Blob) -> Result<(), BlobError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // Readers never see a partly written file
+        let partial = path.with_extension(format!("partial-{:016x}", rand::random::<u64>()));
+        fs::write(&partial, &blob.bytes).await?;
+        if let Err(e) = fs::rename(&partial, &path).await {
+            let _ = fs::remove_file(&partial).await;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Blob>, BlobError> {
+        match fs::read(self.path(key)?).await {
+            Ok(bytes) => Ok(Some(Blob {
+                bytes: bytes.into(),
+                content_type: content_type(key).to_string(),
+            })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let root = std::env::temp_dir().join(format!("groups-blobs-{:x}", rand::random::<u64>()));
+        let store = LocalBlobStore::new(root.clone());
+        let blob = Blob {
+            bytes: "fake image".into(),
+            content_type: "image/png".to_string(),
+        };
+
+        store
+            .put("covers/abc-small.png", blob.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.get("covers/abc-small.png").await.unwrap(), Some(blob));
+        store.delete("covers/abc-small.png").await.unwrap();
+        assert_eq!(store.get("covers/abc-small.png").await.unwrap(), None);
+        // Already gone
+        store.delete("covers/abc-small.png").await.unwrap();
+        assert!(store.get("../escape.png").await.is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
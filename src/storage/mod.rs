@@ -0,0 +1,101 @@
+mod local;
+mod s3;
+
+use crate::config::{StorageBackend, UploadsConfig};
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub use local::LocalBlobStore;
+pub use s3::S3BlobStore;
+
+#[derive(Debug, Error)]
+pub enum BlobError {
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Storage responded {status}: {body}")]
+    Status { status: u16, body: String },
+}
+
+// A stored file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blob {
+    pub bytes: Bytes,
+    pub content_type: String,
+}
+
+// Where uploaded files live. Keys are relative paths such as
+// "avatars/3f2a...-small.jpg", see valid_key.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, blob: Blob) -> Result<(), BlobError>;
+
+    async fn get(&self, key: &str) -> Result<Option<Blob>, BlobError>;
+
+    // Deleting a missing key is not an error
+    async fn delete(&self, key: &str) -> Result<(), BlobError>;
+}
+
+// Lowercase letters, digits, dashes and dots, in segments separated by
+// slashes, so keys are safe both as file paths and in URLs
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 200
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('.')
+                && segment
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'.')
+        })
+}
+
+fn check_key(key: &str) -> Result<(), BlobError> {
+    if valid_key(key) {
+        Ok(())
+    } else {
+        Err(BlobError::InvalidKey(key.to_string()))
+    }
+}
+
+// Content type of a key, from its extension
+pub fn content_type(key: &str) -> &'static str {
+    match key.rsplit('.').next() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+// The store selected in the configuration
+pub fn from_config(config: &UploadsConfig) -> Arc<dyn BlobStore> {
+    match config.backend {
+        StorageBackend::Local => Arc::new(LocalBlobStore::new(config.local_dir.clone())),
+        StorageBackend::S3 => Arc::new(S3BlobStore::new(config.s3.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_key() {
+        assert!(valid_key("avatars/3f2a-small.jpg"));
+        assert!(!valid_key(""));
+        assert!(!valid_key("avatars/../config.toml"));
+        assert!(!valid_key("/etc/passwd"));
+        assert!(!valid_key("avatars//a.jpg"));
+        assert!(!valid_key("avatars/.hidden"));
+        assert!(!valid_key("Avatars/a.jpg"));
+        assert!(!valid_key("avatars/a b.jpg"));
+    }
+}
@@ -0,0 +1,321 @@
+use super::{Blob, BlobError, BlobStore, check_key};
+use crate::config::S3Config;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode, header};
+use sha2::{Digest, Sha256};
+
+// S3 or an S3-compatible service, through its REST API with requests signed
+// with AWS Signature Version 4
+pub struct S3BlobStore {
+    config: S3Config,
+    client: reqwest::Client,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// A request to sign: `headers` are the signed ones, lowercase and sorted
+struct SigningRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    headers: &'a [(&'a str, &'a str)],
+    payload_hash: &'a str,
+    // YYYYMMDD'T'HHMMSS'Z'
+    amz_date: &'a str,
+}
+
+// The Authorization header of a request
+fn authorization(
+    request: &SigningRequest,
+    access_key_id: &str,
+    secret_access_key: &str,
+    region: &str,
+    service: &str,
+) -> String {
+    let canonical_headers: String = request
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = request
+        .headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        request.method, request.path, canonical_headers, signed_headers, request.payload_hash
+    );
+
+    let date = &request.amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        request.amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let key = hmac_sha256(format!("AWS4{}", secret_access_key).as_bytes(), date);
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, service);
+    let key = hmac_sha256(&key, "aws4_request");
+    let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id, scope, signed_headers, signature
+    )
+}
+
+impl S3BlobStore {
+    pub fn new(config: S3Config) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    // Path-style URL of a key
+    fn url(&self, key: &str) -> Result<reqwest::Url, BlobError> {
+        check_key(key)?;
+        let url = format!(
+            "{}/{}/{}",
+            self.config.endpoint.trim_end_matches('/'),
+            self.config.bucket,
+            key
+        );
+        reqwest::Url::parse(&url).map_err(|_| BlobError::InvalidKey(url))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        body: Option<Blob>,
+    ) -> Result<reqwest::Response, BlobError> {
+        let url = self.url(key)?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(BlobError::InvalidKey(url.to_string())),
+        };
+        let payload = body.as_ref().map(|b| b.bytes.clone()).unwrap_or_default();
+        let payload_hash = sha256_hex(&payload);
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let signing = SigningRequest {
+            method: method.as_str(),
+            path: url.path(),
+            headers: &[
+                ("host", &host),
+                ("x-amz-content-sha256", &payload_hash),
+                ("x-amz-date", &amz_date),
+            ],
+            payload_hash: &payload_hash,
+            amz_date: &amz_date,
+        };
+        let authorization = authorization(
+            &signing,
+            &self.config.access_key_id,
+            &self.config.secret_access_key,
+            &self.config.region,
+            "s3",
+        );
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header(header::AUTHORIZATION, authorization)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date);
+        if let Some(blob) = body {
+            request = request
+                .header(header::CONTENT_TYPE, blob.content_type)
+                .body(payload);
+        }
+
+        Ok(request.send().await?)
+    }
+
+    async fn error(response: reqwest::Response) -> BlobError {
+        BlobError::Status {
+            status: response.status().as_u16(),
+            body: response.text().await.unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(&self, key: &str, blob: Blob) -> Result<(), BlobError> {
+        let response = self.send(Method::PUT, key, Some(blob)).await?;
+        if !response.status().is_success() {
+            return Err(Self::error(response).await);
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Blob>, BlobError> {
+        let response = self.send(Method::GET, key, None).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let content_type = response
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("application/octet-stream")
+                    .to_string();
+                Ok(Some(Blob {
+                    bytes: response.bytes().await?,
+                    content_type,
+                }))
+            }
+            _ => Err(Self::error(response).await),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        let response = self.send(Method::DELETE, key, None).await?;
+        if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+            return Err(Self::error(response).await);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    // "get-vanilla" from the AWS Signature Version 4 test suite
+    #[test]
+    fn test_signature_matches_aws_test_suite() {
+        let request = SigningRequest {
+            method: "GET",
+            path: "/",
+            headers: &[
+                ("host", "example.amazonaws.com"),
+                ("x-amz-date", "20150830T123600Z"),
+            ],
+            payload_hash: &sha256_hex(b""),
+            amz_date: "20150830T123600Z",
+        };
+
+        assert_eq!(
+            authorization(
+                &request,
+                "AKIDEXAMPLE",
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "us-east-1",
+                "service",
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    type Objects = web::Data<Mutex<HashMap<String, (String, web::Bytes)>>>;
+
+    // A minimal in-memory stand-in for an S3 bucket. It checks that requests
+    // are signed for the payload they carry.
+    async fn stand_in(request: HttpRequest, body: web::Bytes, objects: Objects) -> HttpResponse {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        if !header("authorization").starts_with("AWS4-HMAC-SHA256 Credential=test-key/")
+            || header("x-amz-content-sha256") != sha256_hex(&body)
+        {
+            return HttpResponse::Forbidden().finish();
+        }
+
+        let path = request.path().to_string();
+        let mut objects = objects.lock().unwrap();
+        match request.method().as_str() {
+            "PUT" => {
+                objects.insert(path, (header("content-type"), body));
+                HttpResponse::Ok().finish()
+            }
+            "GET" => match objects.get(&path) {
+                Some((content_type, bytes)) => HttpResponse::Ok()
+                    .content_type(content_type.as_str())
+                    .body(bytes.clone()),
+                None => HttpResponse::NotFound().finish(),
+            },
+            "DELETE" => {
+                objects.remove(&path);
+                HttpResponse::NoContent().finish()
+            }
+            _ => HttpResponse::MethodNotAllowed().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_round_trip_against_stand_in() {
+        let objects: Objects = web::Data::new(Mutex::new(HashMap::new()));
+        let data = objects.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .default_service(web::to(stand_in))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let store = S3BlobStore::new(S3Config {
+            endpoint: format!("http://{}", address),
+            bucket: "uploads".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "test-key".to_string(),
+            secret_access_key: "test-secret".to_string(),
+        });
+        let blob = Blob {
+            bytes: "fake image".into(),
+            content_type: "image/jpeg".to_string(),
+        };
+
+        store
+            .put("avatars/abc-small.jpg", blob.clone())
+            .await
+            .unwrap();
+        assert!(
+            objects
+                .lock()
+                .unwrap()
+                .contains_key("/uploads/avatars/abc-small.jpg")
+        );
+        assert_eq!(
+            store.get("avatars/abc-small.jpg").await.unwrap(),
+            Some(blob)
+        );
+        store.delete("avatars/abc-small.jpg").await.unwrap();
+        assert_eq!(store.get("avatars/abc-small.jpg").await.unwrap(), None);
+
+        handle.stop(false).await;
+    }
+}
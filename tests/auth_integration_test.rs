@@ -1,17 +1,16 @@
 use actix_session::{SessionMiddleware, storage::CookieSessionStore};
 use actix_web::{App, cookie::Key, http::StatusCode, test, web};
-use groups::db;
 
 #[actix_web::test]
 async fn test_login_page_accessible() {
-    let mut app = test::init_service(App::new().service(
+    let app = test::init_service(App::new().service(
         web::resource("/login").to(|| async { actix_web::HttpResponse::Ok().body("Login Page") }),
     ))
     .await;
 
     let req = test::TestRequest::get().uri("/login").to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::OK);
 }
 
@@ -19,7 +18,7 @@ async fn test_login_page_accessible() {
 async fn test_logout_redirects() {
     let secret_key = Key::generate();
 
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .wrap(
                 SessionMiddleware::builder(CookieSessionStore::default(), secret_key)
@@ -32,7 +31,7 @@ async fn test_logout_redirects() {
 
     let req = test::TestRequest::get().uri("/logout").to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::FOUND);
 
     let location = resp.headers().get("Location").unwrap();
@@ -43,7 +42,7 @@ async fn test_logout_redirects() {
 async fn test_protected_route_redirects_when_not_authenticated() {
     let secret_key = Key::generate();
 
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .wrap(
                 SessionMiddleware::builder(CookieSessionStore::default(), secret_key)
@@ -60,9 +59,54 @@ async fn test_protected_route_redirects_when_not_authenticated() {
 
     let req = test::TestRequest::get().uri("/protected").to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::FOUND);
 
     let location = resp.headers().get("Location").unwrap();
     assert_eq!(location, "/login");
 }
+
+#[actix_web::test]
+async fn test_admin_area_requires_admin() {
+    let secret_key = Key::generate();
+
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                SessionMiddleware::builder(CookieSessionStore::default(), secret_key)
+                    .cookie_secure(false)
+                    .build(),
+            )
+            .route(
+                "/fake-login",
+                web::post().to(|session: actix_session::Session| async move {
+                    session.insert("user_id", 1).unwrap();
+                    actix_web::HttpResponse::Ok().finish()
+                }),
+            )
+            .service(
+                web::resource("/admin")
+                    .wrap(groups::middleware::admin::RequireAdmin)
+                    .to(|| async { actix_web::HttpResponse::Ok().body("Admin") }),
+            ),
+    )
+    .await;
+
+    // Anonymous visitors are sent to the login page
+    let req = test::TestRequest::get().uri("/admin").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers().get("Location").unwrap(), "/login");
+
+    // Logged in users that cannot be confirmed as admins are refused
+    let req = test::TestRequest::post().uri("/fake-login").to_request();
+    let resp = test::call_service(&app, req).await;
+    let cookie = resp.response().cookies().next().unwrap().into_owned();
+
+    let req = test::TestRequest::get()
+        .uri("/admin")
+        .cookie(cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
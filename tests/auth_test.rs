@@ -1,4 +1,3 @@
-use actix_web::{App, http::StatusCode, test};
 use groups::password::{hash_password, verify_password};
 
 #[actix_web::test]